@@ -0,0 +1,93 @@
+//! Who may use the bot: `allowed_users` and `allowed_usergroup` limit whom
+//! it answers, and `write_users` and `write_usergroup` who may use the
+//! tools that change something. Admins may always do both, and while
+//! neither setting of a pair is set, anyone may.
+
+use crate::commands;
+use crate::store::{get, set, Expire, ExpireKind};
+use crate::tools::http::{self, Method};
+use serde_json::{json, Value};
+use std::env;
+
+const USERGROUP_USERS_URL: &str = "https://slack.com/api/usergroups.users.list";
+/// How long a user group's members are kept before they're looked up again.
+const MEMBERS_TTL_SECS: i64 = 10 * 60;
+
+/// The reply to users the bot may not answer.
+pub const NOT_AUTHORIZED: &str = "Sorry, you're not authorized to use this bot.";
+
+/// Whether the bot answers `user`.
+pub async fn may_trigger(user: &str) -> bool {
+    admitted(user, "allowed_users", "allowed_usergroup").await
+}
+
+/// Whether `user` may use the tools in [`WRITE_TOOLS`](crate::tools::WRITE_TOOLS).
+pub async fn may_write(user: &str) -> bool {
+    admitted(user, "write_users", "write_usergroup").await
+}
+
+/// Whether `user` is an admin, listed in `users_var` or a member of the
+/// Slack user group in `group_var`, or neither is set.
+async fn admitted(user: &str, users_var: &str, group_var: &str) -> bool {
+    if commands::is_admin(user) {
+        return true;
+    }
+    let users = env::var(users_var).ok().filter(|v| !v.trim().is_empty());
+    let group = env::var(group_var).ok().filter(|v| !v.trim().is_empty());
+    if users.is_none() && group.is_none() {
+        return true;
+    }
+    if users.is_some_and(|users| users.split(',').any(|id| id.trim() == user)) {
+        return true;
+    }
+    match group {
+        Some(group) => members(group.trim()).await.iter().any(|id| id == user),
+        None => false,
+    }
+}
+
+/// The user IDs in the Slack user group `group`, looked up with
+/// `SLACK_BOT_TOKEN` and kept for a while. Empty when Slack won't say.
+async fn members(group: &str) -> Vec<String> {
+    let key = format!("usergroup:{group}");
+    if let Some(members) = get(&key).and_then(|v| serde_json::from_value(v).ok()) {
+        return members;
+    }
+    let members = match look_up(group).await {
+        Ok(members) => members,
+        Err(e) => {
+            log::warn!("Failed to look up the members of {}: {}", group, e);
+            return Vec::new();
+        }
+    };
+    set(
+        &key,
+        json!(members),
+        Some(Expire {
+            kind: ExpireKind::Ex,
+            value: MEMBERS_TTL_SECS,
+        }),
+    );
+    members
+}
+
+async fn look_up(group: &str) -> anyhow::Result<Vec<String>> {
+    let token = env::var("SLACK_BOT_TOKEN")?;
+    let url = format!("{USERGROUP_USERS_URL}?usergroup={group}");
+    let authorization = format!("Bearer {token}");
+    let reply: Value = http::send(
+        Method::GET,
+        &url,
+        &[("Authorization", &authorization)],
+        None,
+    )
+    .await?
+    .json()?;
+    match reply["users"].as_array() {
+        Some(users) => Ok(users
+            .iter()
+            .filter_map(|id| id.as_str().map(str::to_string))
+            .collect()),
+        None => anyhow::bail!("Slack said {}", reply["error"].as_str().unwrap_or("no")),
+    }
+}
@@ -0,0 +1,113 @@
+use crate::store::{get, set};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::time::Duration;
+
+/// How many of a tool's latest calls are kept for working out latencies.
+const RECENT_CALLS: usize = 100;
+
+/// One call of a tool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invocation {
+    /// Tells calls with the same arguments apart without storing them.
+    pub args_hash: String,
+    pub latency_ms: u64,
+    pub ok: bool,
+    /// Unix seconds.
+    pub at: i64,
+}
+
+/// How a tool has been used, stored under `tool_stats:<name>`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolStats {
+    pub calls: u64,
+    pub failures: u64,
+    /// The latest calls, oldest first.
+    pub recent: Vec<Invocation>,
+}
+
+impl ToolStats {
+    pub fn load(tool: &str) -> Self {
+        get(&stats_key(tool))
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, tool: &str) {
+        match serde_json::to_value(self) {
+            Ok(value) => set(&stats_key(tool), value, None),
+            Err(e) => log::warn!("Failed to save stats for {}: {}", tool, e),
+        }
+    }
+
+    /// The latency 95% of the recent calls stayed within.
+    pub fn p95_latency_ms(&self) -> Option<u64> {
+        let mut latencies: Vec<u64> = self.recent.iter().map(|i| i.latency_ms).collect();
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort_unstable();
+        let rank = (latencies.len() * 95).div_ceil(100).max(1);
+        Some(latencies[rank - 1])
+    }
+}
+
+/// Adds a call of `tool` with `arguments` that took `latency`.
+pub fn record(tool: &str, arguments: &str, latency: Duration, ok: bool) {
+    let mut stats = ToolStats::load(tool);
+    stats.calls += 1;
+    if !ok {
+        stats.failures += 1;
+    }
+    stats.recent.push(Invocation {
+        args_hash: format!("{:016x}", fnv1a(arguments.as_bytes())),
+        latency_ms: latency.as_millis() as u64,
+        ok,
+        at: Utc::now().timestamp(),
+    });
+    if stats.recent.len() > RECENT_CALLS {
+        let excess = stats.recent.len() - RECENT_CALLS;
+        stats.recent.drain(..excess);
+    }
+    stats.save(tool);
+}
+
+/// The tools in `names` by how often they've been called, most used first,
+/// with their failures and p95 latency.
+pub fn leaderboard<'a>(names: impl Iterator<Item = &'a str>) -> String {
+    let mut rows: Vec<(&str, ToolStats)> = names
+        .map(|name| (name, ToolStats::load(name)))
+        .filter(|(_, stats)| stats.calls > 0)
+        .collect();
+    if rows.is_empty() {
+        return "No tools have been called yet.".to_string();
+    }
+    rows.sort_by_key(|(_, stats)| Reverse(stats.calls));
+    let lines: Vec<String> = rows
+        .iter()
+        .map(|(name, stats)| {
+            let p95 = stats
+                .p95_latency_ms()
+                .map(|ms| format!(", p95 {ms} ms"))
+                .unwrap_or_default();
+            format!(
+                "• {name}: {} calls, {} failed{p95}",
+                stats.calls, stats.failures
+            )
+        })
+        .collect();
+    format!("Tool calls:\n{}", lines.join("\n"))
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` stays the same between
+/// builds.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn stats_key(tool: &str) -> String {
+    format!("tool_stats:{tool}")
+}
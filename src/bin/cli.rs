@@ -0,0 +1,74 @@
+//! A REPL that drives `chat_inner` from the terminal, for trying tools out
+//! without Slack or flows.network. Settings come from env and `.env` as in a
+//! flow, and conversations, preferences and counters are kept in memory
+//! until the REPL exits.
+//!
+//! `/reset` starts the conversation afresh and `/quit` leaves.
+
+use dotenv::dotenv;
+use gpt_function_call_demo::client::ChatClient;
+use gpt_function_call_demo::core::prompt;
+use gpt_function_call_demo::core::session::{SessionKey, SessionManager};
+use gpt_function_call_demo::core::{ToolContext, ToolRegistry};
+use gpt_function_call_demo::store::{self, MemoryStore};
+use gpt_function_call_demo::{chat_inner, Config};
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+
+/// What the CLI's conversation is kept under.
+const CHANNEL: &str = "cli";
+const USER: &str = "cli";
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    dotenv().ok();
+    store::install(Arc::new(MemoryStore::default()));
+    let config = Config::from_env();
+    let client = ChatClient::from_env();
+    let registry = ToolRegistry::with_builtin_tools(&client, &config.model);
+    let sessions = SessionManager::new(prompt::template_from_env());
+    let key = SessionKey::new(CHANNEL, USER);
+    let context = ToolContext::new(CHANNEL, CHANNEL, USER).in_session(key.clone());
+
+    println!(
+        "Chatting with {} and {} tools. /reset starts over, /quit leaves.",
+        config.model,
+        registry.names().count()
+    );
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+        let input = line.trim();
+        match input {
+            "" => continue,
+            "/quit" | "/exit" => break,
+            "/reset" => {
+                sessions.reset(&key);
+                println!("Started a new conversation.");
+                continue;
+            }
+            _ => {}
+        }
+
+        let mut session = sessions.load(key.clone());
+        let result = chat_inner(
+            input.to_string(),
+            &mut session.messages,
+            &client,
+            &registry,
+            &config,
+            &context,
+        )
+        .await;
+        sessions.save(&mut session);
+        match result {
+            Ok(Some(answer)) => println!("{answer}\n"),
+            Ok(None) => println!(),
+            Err(e) => eprintln!("{}\n", e.user_message()),
+        }
+    }
+}
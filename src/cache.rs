@@ -0,0 +1,85 @@
+//! Answers reused for repeated questions.
+//!
+//! With `answer_cache_minutes` set, an answer is kept per channel under its
+//! normalized question, and the same question asked again within that time
+//! gets it without a completion. Starting a message with `!nocache` asks
+//! afresh.
+
+use crate::analytics::fnv1a;
+use crate::store::{get, set, Expire, ExpireKind};
+use serde_json::json;
+use std::env;
+
+const NO_CACHE_PREFIX: &str = "!nocache";
+
+/// How long answers are reused, read from `answer_cache_minutes`. Off by
+/// default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnswerCache {
+    pub minutes: i64,
+}
+
+impl AnswerCache {
+    pub fn from_env() -> Self {
+        Self {
+            minutes: env::var("answer_cache_minutes")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.minutes > 0
+    }
+
+    /// The answer given in `channel` to `question`, if it's still fresh.
+    pub fn get(&self, channel: &str, question: &str) -> Option<String> {
+        if !self.is_enabled() {
+            return None;
+        }
+        get(&cache_key(channel, question)).and_then(|v| v.as_str().map(str::to_string))
+    }
+
+    pub fn put(&self, channel: &str, question: &str, answer: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+        let expire = Expire {
+            kind: ExpireKind::Ex,
+            value: self.minutes * 60,
+        };
+        set(&cache_key(channel, question), json!(answer), Some(expire));
+    }
+}
+
+/// Whether `input` starts with `!nocache`, and the question without it.
+pub fn strip_no_cache(input: &str) -> (bool, String) {
+    let trimmed = input.trim_start();
+    match trimmed.strip_prefix(NO_CACHE_PREFIX) {
+        Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => {
+            (true, rest.trim().to_string())
+        }
+        _ => (false, input.to_string()),
+    }
+}
+
+/// Lowercased, with punctuation dropped and whitespace collapsed, so "What's
+/// the weather in Tokyo?" and "whats the weather in tokyo" match.
+fn normalize(question: &str) -> String {
+    question
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn cache_key(channel: &str, question: &str) -> String {
+    format!(
+        "answer_cache:{channel}:{:016x}",
+        fnv1a(normalize(question).as_bytes())
+    )
+}
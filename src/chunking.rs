@@ -0,0 +1,72 @@
+//! Splitting answers too long for one chat message into several.
+
+/// Room kept in each piece for closing and reopening a code block.
+const FENCE_ALLOWANCE: usize = 8;
+
+/// `text` in pieces of at most `max_chars` characters, broken at paragraph
+/// breaks where possible, then at line breaks, and only then mid-line. A
+/// code block split between pieces is closed at the end of one and
+/// reopened at the start of the next, so each piece renders on its own.
+pub fn split(text: &str, max_chars: usize) -> Vec<String> {
+    let budget = max_chars.saturating_sub(FENCE_ALLOWANCE).max(1);
+    if text.chars().count() <= budget {
+        return vec![text.to_string()];
+    }
+
+    // Each unit with what separates it from the one before.
+    let mut units: Vec<(String, &str)> = Vec::new();
+    for (i, paragraph) in text.split("\n\n").enumerate() {
+        let separator = if i == 0 { "" } else { "\n\n" };
+        if paragraph.chars().count() <= budget {
+            units.push((paragraph.to_string(), separator));
+            continue;
+        }
+        for (j, line) in paragraph.split('\n').enumerate() {
+            let separator = if j == 0 { separator } else { "\n" };
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                units.push((String::new(), separator));
+            }
+            for (k, chunk) in chars.chunks(budget).enumerate() {
+                let separator = if k == 0 { separator } else { "" };
+                units.push((chunk.iter().collect(), separator));
+            }
+        }
+    }
+
+    let mut pieces = Vec::new();
+    let mut piece = String::new();
+    let mut length = 0;
+    for (unit, separator) in units {
+        let unit_length = unit.chars().count();
+        if length > 0 && length + separator.len() + unit_length > budget {
+            pieces.push(std::mem::take(&mut piece));
+            length = 0;
+        }
+        if length > 0 {
+            piece.push_str(separator);
+            length += separator.len();
+        }
+        piece.push_str(&unit);
+        length += unit_length;
+    }
+    if !piece.is_empty() {
+        pieces.push(piece);
+    }
+
+    let mut in_code = false;
+    for piece in &mut pieces {
+        if in_code {
+            piece.insert_str(0, "```\n");
+        }
+        let fences = piece
+            .lines()
+            .filter(|line| line.trim_start().starts_with("```"))
+            .count();
+        in_code = fences % 2 == 1;
+        if in_code {
+            piece.push_str("\n```");
+        }
+    }
+    pieces
+}
@@ -0,0 +1,88 @@
+//! The sources an answer came from: the pages the scraper, summary and
+//! crawl tools read, the documents `searchDocs` quoted, and the web search
+//! results, listed under the answer unless it already links to them.
+
+use crate::config::Config;
+use crate::tools::is_untrusted;
+use async_openai::types::FunctionCall;
+use serde_json::Value;
+
+/// Tools that read the page at their `url` argument.
+const PAGE_TOOLS: &[&str] = &["scraper", "summarizeUrl", "crawlSite"];
+const SEARCH_TOOL: &str = "searchWeb";
+/// Names the document each passage it returns is from.
+const DOCS_TOOL: &str = "searchDocs";
+/// How many search results are cited when the answer doesn't say which it
+/// used and no page was read.
+const MAX_SEARCH_SOURCES: usize = 3;
+
+/// What the tools of one turn read.
+#[derive(Debug, Default)]
+pub struct Sources {
+    pages: Vec<String>,
+    results: Vec<String>,
+}
+
+impl Sources {
+    /// Notes where the call `function` got what it returned in `content`,
+    /// the envelope the model is sent. Failed calls and pages that couldn't
+    /// be read add nothing.
+    pub fn record(&mut self, function: &FunctionCall, content: &str) {
+        let Ok(envelope) = serde_json::from_str::<Value>(content) else {
+            return;
+        };
+        if envelope["ok"] != Value::Bool(true) {
+            return;
+        }
+        let data = envelope["data"].as_str().unwrap_or_default();
+        if PAGE_TOOLS.contains(&function.name.as_str()) && is_untrusted(data) {
+            let url = serde_json::from_str::<Value>(&function.arguments)
+                .ok()
+                .and_then(|args| args["url"].as_str().map(str::to_string));
+            if let Some(url) = url {
+                push_new(&mut self.pages, url);
+            }
+        } else if function.name == DOCS_TOOL {
+            for source in data
+                .lines()
+                .filter_map(|line| line.strip_prefix("Source: "))
+            {
+                push_new(&mut self.pages, source.trim().to_string());
+            }
+        } else if function.name == SEARCH_TOOL {
+            for url in data
+                .lines()
+                .map(str::trim)
+                .filter(|line| line.starts_with("https://") || line.starts_with("http://"))
+            {
+                push_new(&mut self.results, url.to_string());
+            }
+        }
+    }
+
+    /// `answer` with a "Sources:" line naming the sources it doesn't link
+    /// to already, when `citations` is on and answers aren't held to a
+    /// schema. Search results only count when no page was read and the
+    /// answer links to none of them.
+    pub fn cite(&self, config: &Config, answer: String) -> String {
+        if !config.citations || config.response_schema.is_some() {
+            return answer;
+        }
+        let mut sources: Vec<&String> = self.pages.iter().collect();
+        if sources.is_empty() && !self.results.iter().any(|url| answer.contains(url.as_str())) {
+            sources.extend(self.results.iter().take(MAX_SEARCH_SOURCES));
+        }
+        sources.retain(|url| !answer.contains(url.as_str()));
+        if sources.is_empty() {
+            return answer;
+        }
+        let sources: Vec<&str> = sources.iter().map(|url| url.as_str()).collect();
+        format!("{}\n\nSources: {}", answer.trim_end(), sources.join(", "))
+    }
+}
+
+fn push_new(urls: &mut Vec<String>, url: String) {
+    if !urls.contains(&url) {
+        urls.push(url);
+    }
+}
@@ -0,0 +1,469 @@
+use crate::status::StatusSink;
+use crate::telemetry;
+use crate::tools::http::{self, Method};
+use crate::trace::Trace;
+use crate::usage;
+use async_openai::{
+    config::{AzureConfig, Config, OpenAIConfig},
+    error::{ApiError, OpenAIError},
+    types::{
+        CompletionUsage, CreateChatCompletionRequest, CreateChatCompletionResponse,
+        CreateEmbeddingRequest, CreateEmbeddingResponse, CreateImageRequest,
+        CreateModerationRequest, CreateModerationResponse, CreateSpeechRequest,
+        CreateSpeechResponse, ImagesResponse,
+    },
+    Client,
+};
+use async_trait::async_trait;
+use backoff::{backoff::Backoff, ExponentialBackoff, ExponentialBackoffBuilder};
+use serde::Deserialize;
+use serde_json::{json, Map};
+use std::env;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+const DEFAULT_AZURE_API_VERSION: &str = "2023-12-01-preview";
+/// Long recordings take a while to transcribe.
+const TRANSCRIPTION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Something that creates chat completions, such as an async-openai client,
+/// or a [`ScriptedBackend`](crate::mock::ScriptedBackend) in tests.
+#[async_trait]
+pub trait ChatBackend: Send + Sync {
+    async fn create_chat(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<CreateChatCompletionResponse, OpenAIError>;
+}
+
+#[async_trait]
+impl<C: Config + Send + Sync> ChatBackend for Client<C> {
+    async fn create_chat(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<CreateChatCompletionResponse, OpenAIError> {
+        self.chat().create(request).await
+    }
+}
+
+#[derive(Clone)]
+enum Backend {
+    OpenAI(Client<OpenAIConfig>),
+    /// Azure OpenAI, where requests go to a deployment and the request's
+    /// `model` is ignored.
+    Azure(Client<AzureConfig>),
+    /// Chat completions only.
+    Custom(Arc<dyn ChatBackend>),
+}
+
+/// Retries are handled by `ChatClient`, so the client's own rate limit
+/// backoff is turned off.
+fn no_backoff() -> ExponentialBackoff {
+    ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(Duration::ZERO))
+        .build()
+}
+
+/// How often and how patiently failed requests are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first one.
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Reads `retry_max_attempts` from env.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            max_attempts: env::var("retry_max_attempts")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.max_attempts)
+                .max(1),
+            ..default
+        }
+    }
+
+    fn backoff(&self) -> ExponentialBackoff {
+        ExponentialBackoffBuilder::new()
+            .with_initial_interval(self.initial_delay)
+            .with_max_interval(self.max_delay)
+            .with_max_elapsed_time(None)
+            .build()
+    }
+}
+
+/// The chat completion service behind the bot.
+#[derive(Clone)]
+pub struct ChatClient {
+    backend: Backend,
+    retry: RetryPolicy,
+    status: Option<Arc<dyn StatusSink>>,
+}
+
+impl ChatClient {
+    /// Uses Azure OpenAI when `AZURE_OPENAI_ENDPOINT` is set, and OpenAI
+    /// (keyed by `OPENAI_API_KEY`) otherwise. `OPENAI_API_BASE` points the
+    /// latter at any OpenAI-compatible server, such as Ollama or vLLM.
+    ///
+    /// Azure also reads `AZURE_OPENAI_KEY`, `AZURE_OPENAI_DEPLOYMENT` and the
+    /// optional `AZURE_OPENAI_API_VERSION`.
+    pub fn from_env() -> Self {
+        let backend = match env::var("AZURE_OPENAI_ENDPOINT") {
+            Ok(endpoint) => {
+                let config = AzureConfig::new()
+                    .with_api_base(endpoint.trim_end_matches('/'))
+                    .with_api_key(env::var("AZURE_OPENAI_KEY").unwrap_or_default())
+                    .with_deployment_id(env::var("AZURE_OPENAI_DEPLOYMENT").unwrap_or_default())
+                    .with_api_version(
+                        env::var("AZURE_OPENAI_API_VERSION")
+                            .unwrap_or(DEFAULT_AZURE_API_VERSION.to_string()),
+                    );
+                Backend::Azure(Client::with_config(config).with_backoff(no_backoff()))
+            }
+            Err(_) => {
+                let mut config = OpenAIConfig::new();
+                if let Ok(api_base) = env::var("OPENAI_API_BASE") {
+                    config = config.with_api_base(api_base.trim_end_matches('/'));
+                }
+                Backend::OpenAI(Client::with_config(config).with_backoff(no_backoff()))
+            }
+        };
+
+        Self {
+            backend,
+            retry: RetryPolicy::from_env(),
+            status: None,
+        }
+    }
+
+    /// Uses OpenAI with `api_key`, without reading any env.
+    pub fn openai(api_key: &str) -> Self {
+        let config = OpenAIConfig::new().with_api_key(api_key);
+        Self {
+            backend: Backend::OpenAI(Client::with_config(config).with_backoff(no_backoff())),
+            retry: RetryPolicy::default(),
+            status: None,
+        }
+    }
+
+    /// Sends chat completions to `backend`. Images, moderation, speech and
+    /// transcription aren't available.
+    pub fn from_backend(backend: Arc<dyn ChatBackend>) -> Self {
+        Self {
+            backend: Backend::Custom(backend),
+            retry: RetryPolicy::default(),
+            status: None,
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Where to report that a throttled request is being retried.
+    pub fn with_status(mut self, status: Arc<dyn StatusSink>) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Creates a chat completion, retrying rate limits and server errors
+    /// with jittered exponential backoff. Its token usage is recorded and
+    /// the request traced.
+    pub async fn create_chat(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<CreateChatCompletionResponse, OpenAIError> {
+        let span = telemetry::start("chat.completion");
+        let started = Instant::now();
+        let result = self
+            .retrying("Chat completion", || async {
+                match &self.backend {
+                    Backend::OpenAI(client) => client.create_chat(request.clone()).await,
+                    Backend::Azure(client) => client.create_chat(request.clone()).await,
+                    Backend::Custom(backend) => backend.create_chat(request.clone()).await,
+                }
+            })
+            .await;
+        let trace = Trace::from_env();
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                trace.failure(&request, &e, started.elapsed()).await;
+                let mut attributes = Map::new();
+                attributes.insert("model".to_string(), json!(request.model));
+                span.end(false, attributes);
+                return Err(e);
+            }
+        };
+        let mut attributes = Map::new();
+        attributes.insert("model".to_string(), json!(response.model));
+        if let Some(usage) = &response.usage {
+            attributes.insert("prompt_tokens".to_string(), json!(usage.prompt_tokens));
+            attributes.insert(
+                "completion_tokens".to_string(),
+                json!(usage.completion_tokens),
+            );
+        }
+        if let Some(reason) = response.choices.first().and_then(|c| c.finish_reason) {
+            attributes.insert("finish_reason".to_string(), json!(reason));
+        }
+        span.end(true, attributes);
+        trace
+            .completion(&request, &response, started.elapsed())
+            .await;
+        if let Some(usage) = &response.usage {
+            usage::record(&response.model, usage);
+        }
+        Ok(response)
+    }
+
+    /// Generates images, retrying like [`create_chat`](Self::create_chat).
+    pub async fn create_image(
+        &self,
+        request: CreateImageRequest,
+    ) -> Result<ImagesResponse, OpenAIError> {
+        self.retrying("Image generation", || async {
+            match &self.backend {
+                Backend::OpenAI(client) => client.images().create(request.clone()).await,
+                Backend::Azure(client) => client.images().create(request.clone()).await,
+                Backend::Custom(_) => Err(unsupported("Image generation")),
+            }
+        })
+        .await
+    }
+
+    /// Embeds text as vectors, retrying like [`create_chat`](Self::create_chat).
+    /// Its tokens count toward usage like a completion's prompt.
+    pub async fn create_embeddings(
+        &self,
+        request: CreateEmbeddingRequest,
+    ) -> Result<CreateEmbeddingResponse, OpenAIError> {
+        let response = self
+            .retrying("Embedding", || async {
+                match &self.backend {
+                    Backend::OpenAI(client) => client.embeddings().create(request.clone()).await,
+                    Backend::Azure(client) => client.embeddings().create(request.clone()).await,
+                    Backend::Custom(_) => Err(unsupported("Embedding")),
+                }
+            })
+            .await?;
+        usage::record(
+            &response.model,
+            &CompletionUsage {
+                prompt_tokens: response.usage.prompt_tokens,
+                completion_tokens: 0,
+                total_tokens: response.usage.total_tokens,
+            },
+        );
+        Ok(response)
+    }
+
+    /// Classifies text against the usage policies, retrying like
+    /// [`create_chat`](Self::create_chat).
+    pub async fn moderate(
+        &self,
+        request: CreateModerationRequest,
+    ) -> Result<CreateModerationResponse, OpenAIError> {
+        self.retrying("Moderation", || async {
+            match &self.backend {
+                Backend::OpenAI(client) => client.moderations().create(request.clone()).await,
+                Backend::Azure(client) => client.moderations().create(request.clone()).await,
+                Backend::Custom(_) => Err(unsupported("Moderation")),
+            }
+        })
+        .await
+    }
+
+    /// Reads text aloud, retrying like [`create_chat`](Self::create_chat).
+    pub async fn create_speech(
+        &self,
+        request: CreateSpeechRequest,
+    ) -> Result<CreateSpeechResponse, OpenAIError> {
+        self.retrying("Speech", || async {
+            match &self.backend {
+                Backend::OpenAI(client) => client.audio().speech(request.clone()).await,
+                Backend::Azure(client) => client.audio().speech(request.clone()).await,
+                Backend::Custom(_) => Err(unsupported("Speech")),
+            }
+        })
+        .await
+    }
+
+    /// Transcribes speech with `model`, retrying like
+    /// [`create_chat`](Self::create_chat). `file_name` tells the API what
+    /// format the audio is in.
+    ///
+    /// The client library only uploads audio from files, which flows don't
+    /// have, so the form is posted here.
+    pub async fn transcribe(
+        &self,
+        audio: &[u8],
+        file_name: &str,
+        model: &str,
+    ) -> Result<String, OpenAIError> {
+        let (url, headers) = match &self.backend {
+            Backend::OpenAI(client) => endpoint(client.config(), "/audio/transcriptions"),
+            Backend::Azure(client) => endpoint(client.config(), "/audio/transcriptions"),
+            Backend::Custom(_) => Err(unsupported("Transcription")),
+        }?;
+        let boundary = format!(
+            "----transcription{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+        );
+        let mut body = Vec::new();
+        body.extend(
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\n{model}\r\n\
+                 --{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+                 Content-Type: application/octet-stream\r\n\r\n",
+                file_name.replace('"', "")
+            )
+            .as_bytes(),
+        );
+        body.extend(audio);
+        body.extend(format!("\r\n--{boundary}--\r\n").as_bytes());
+        let content_type = format!("multipart/form-data; boundary={boundary}");
+
+        let mut request_headers: Vec<(&str, &str)> = headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        request_headers.push(("Content-Type", &content_type));
+
+        self.retrying("Transcription", || async {
+            let res = http::send_timed(
+                Method::POST,
+                &url,
+                &request_headers,
+                body.clone(),
+                TRANSCRIPTION_TIMEOUT,
+            )
+            .await
+            .map_err(|e| OpenAIError::StreamError(format!("transcription request failed: {e}")))?;
+            let status = res.status;
+            if res.is_success() {
+                return serde_json::from_slice::<Transcription>(&res.body)
+                    .map(|t| t.text)
+                    .map_err(OpenAIError::JSONDeserialize);
+            }
+            Err(OpenAIError::ApiError(
+                serde_json::from_slice::<WrappedError>(&res.body)
+                    .map(|w| w.error)
+                    .unwrap_or_else(|_| ApiError {
+                        message: format!("{status}: {}", String::from_utf8_lossy(&res.body).trim()),
+                        r#type: match status {
+                            429 => Some("requests".to_string()),
+                            500.. => Some("server_error".to_string()),
+                            _ => None,
+                        },
+                        param: None,
+                        code: None,
+                    }),
+            ))
+        })
+        .await
+    }
+
+    async fn retrying<T, F, Fut>(&self, what: &str, mut call: F) -> Result<T, OpenAIError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, OpenAIError>>,
+    {
+        let mut backoff = self.retry.backoff();
+        let mut attempt = 1;
+        loop {
+            let err = match call().await {
+                Err(e) if attempt < self.retry.max_attempts && is_transient(&e) => e,
+                result => return result,
+            };
+
+            let delay = backoff.next_backoff().unwrap_or(self.retry.max_delay);
+            log::warn!(
+                "{} attempt {} failed, retrying in {:?}: {}",
+                what,
+                attempt,
+                delay,
+                err
+            );
+            if let Some(status) = &self.status {
+                status
+                    .status(&format!(
+                        "OpenAI is busy, retrying in {} seconds...",
+                        delay.as_secs().max(1)
+                    ))
+                    .await;
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Transcription {
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct WrappedError {
+    error: ApiError,
+}
+
+/// The URL, with any query the backend needs, and the auth headers for an
+/// API path.
+fn endpoint<C: Config>(
+    config: &C,
+    path: &str,
+) -> Result<(String, Vec<(String, String)>), OpenAIError> {
+    let url = Url::parse_with_params(&config.url(path), config.query())
+        .map_err(|e| OpenAIError::InvalidArgument(e.to_string()))?;
+    let headers = config
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    Ok((url.to_string(), headers))
+}
+
+fn unsupported(what: &str) -> OpenAIError {
+    OpenAIError::InvalidArgument(format!("{what} isn't available with a custom backend"))
+}
+
+/// Whether `err` is worth retrying: rate limits (other than an exhausted
+/// quota), server errors and network failures.
+fn is_transient(err: &OpenAIError) -> bool {
+    match err {
+        OpenAIError::ApiError(e) => {
+            let kind = e.r#type.as_deref().unwrap_or_default();
+            let code = e.code.as_ref().and_then(|c| c.as_str()).unwrap_or_default();
+            kind != "insufficient_quota"
+                && (matches!(kind, "requests" | "tokens" | "server_error")
+                    || code == "rate_limit_exceeded")
+        }
+        OpenAIError::Reqwest(e) => match e.status() {
+            Some(status) => status.as_u16() == 429 || status.is_server_error(),
+            None => e.is_timeout() || e.is_request(),
+        },
+        _ => false,
+    }
+}
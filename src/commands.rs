@@ -0,0 +1,432 @@
+use crate::analytics;
+use crate::client::ChatClient;
+use crate::config::Config;
+use crate::core::session::{SessionKey, SessionManager};
+use crate::feedback;
+use crate::health;
+use crate::persona;
+use crate::prefs::ChannelPrefs;
+use crate::store::{del, get, set};
+use crate::tools::{self, ToolRegistry};
+use crate::usage::{self, Budget, ChannelUsage};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::env;
+
+const ACTIVE_MODEL_KEY: &str = "active_model";
+const DISABLED_TOOLS_KEY: &str = "disabled_tools";
+const HELP: &str = "Commands: !reset [@user], !model [name|default], \
+                    !tools [on|off|enable|disable name], !toolstats, !feedback, \
+                    !persona [name|default], !dryrun [on|off], !ephemeral [on|off], !usage, !budget [override], !ping, !ingest [url]";
+
+/// A message starting with `!`. Only admins may send most of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Forgets the conversation of the given user, or of the admin when
+    /// none is given, in the channel.
+    Reset {
+        user: Option<String>,
+    },
+    /// Switches every conversation to another model, back to the configured
+    /// one with `default`, or shows the current one.
+    Model {
+        name: Option<String>,
+    },
+    /// Turns a tool on or off everywhere, or lists the tools.
+    Tools {
+        toggle: Option<(bool, String)>,
+    },
+    /// Turns a tool on or off in the channel only.
+    ChannelTool {
+        on: bool,
+        name: String,
+    },
+    /// Reports how often each tool has been called and how long it takes.
+    ToolStats,
+    /// Reports how answers in the channel have been rated.
+    Feedback,
+    /// Switches the channel to a persona, back to none with `default`, or
+    /// lists the personas.
+    Persona {
+        name: Option<String>,
+    },
+    /// Has tool calls in the channel described instead of run, or not, or
+    /// tells which.
+    DryRun {
+        on: Option<bool>,
+    },
+    /// Has answers in the channel shown only to the asker, or not, or tells
+    /// which.
+    Ephemeral {
+        on: Option<bool>,
+    },
+    /// Reports the channel's token usage and its estimated cost.
+    Usage,
+    /// Reports use against the token budget, or with `override` lifts the
+    /// budget until midnight UTC.
+    Budget {
+        lift: bool,
+    },
+    /// Checks that OpenAI, the weather API and the store can be reached.
+    Ping,
+    /// Adds the page at the URL, or on Slack the files shared along with
+    /// the command, to the documents `searchDocs` searches.
+    Ingest {
+        url: Option<String>,
+    },
+    Unknown(String),
+}
+
+impl Command {
+    /// Reads a command, or `None` when `text` is an ordinary message.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim().strip_prefix('!')?;
+        let mut words = text.split_whitespace();
+        let name = words.next()?.to_lowercase();
+        Some(match name.as_str() {
+            // Questions asked past the answer cache or in private, not
+            // commands.
+            "nocache" | "private" => return None,
+            "reset" => Command::Reset {
+                // Slack sends mentions as `<@U0123ABCD>`.
+                user: words
+                    .next()
+                    .map(|w| w.trim_start_matches("<@").trim_end_matches('>').to_string()),
+            },
+            "model" => Command::Model {
+                name: words.next().map(str::to_string),
+            },
+            "tools" => match (words.next(), words.next()) {
+                (Some("on"), Some(tool)) => Command::Tools {
+                    toggle: Some((true, tool.to_string())),
+                },
+                (Some("off"), Some(tool)) => Command::Tools {
+                    toggle: Some((false, tool.to_string())),
+                },
+                (Some("enable"), Some(tool)) => Command::ChannelTool {
+                    on: true,
+                    name: tool.to_string(),
+                },
+                (Some("disable"), Some(tool)) => Command::ChannelTool {
+                    on: false,
+                    name: tool.to_string(),
+                },
+                _ => Command::Tools { toggle: None },
+            },
+            "toolstats" => Command::ToolStats,
+            "feedback" => Command::Feedback,
+            "persona" => Command::Persona {
+                name: words.next().map(str::to_lowercase),
+            },
+            "dryrun" => Command::DryRun {
+                on: match words.next() {
+                    Some("on") => Some(true),
+                    Some("off") => Some(false),
+                    _ => None,
+                },
+            },
+            "ephemeral" => Command::Ephemeral {
+                on: match words.next() {
+                    Some("on") => Some(true),
+                    Some("off") => Some(false),
+                    _ => None,
+                },
+            },
+            "usage" => Command::Usage,
+            "ping" => Command::Ping,
+            // Slack sends links as `<https://...>` or `<https://...|label>`.
+            "ingest" => Command::Ingest {
+                url: words.next().map(|w| {
+                    let w = w.trim_start_matches('<').trim_end_matches('>');
+                    w.split('|').next().unwrap_or(w).to_string()
+                }),
+            },
+            "budget" => Command::Budget {
+                lift: words.next() == Some("override"),
+            },
+            _ => Command::Unknown(name),
+        })
+    }
+
+    /// Whether anyone may send the command: resetting one's own
+    /// conversation.
+    pub fn is_public(&self) -> bool {
+        matches!(self, Command::Reset { user: None })
+    }
+
+    /// Runs the command sent in the conversation `key`, returning the
+    /// reply.
+    pub async fn run(
+        &self,
+        key: &SessionKey,
+        registry: &ToolRegistry,
+        sessions: &SessionManager,
+        client: &ChatClient,
+        config: &Config,
+    ) -> String {
+        let channel = key.channel.as_str();
+        match self {
+            Command::Reset { user: target } => {
+                let target = target.as_deref().unwrap_or(&key.user);
+                sessions.reset(&SessionKey::new(channel, target).in_thread(key.thread.clone()));
+                format!("Cleared the conversation with <@{target}>.")
+            }
+            Command::Model { name: None } => match active_model() {
+                Some(model) => format!("Conversations use {model}, switched to by an admin."),
+                None => "Conversations use the configured model.".to_string(),
+            },
+            Command::Model { name: Some(name) } if name == "default" => {
+                del(ACTIVE_MODEL_KEY);
+                "Switched back to the configured model.".to_string()
+            }
+            Command::Model { name: Some(name) } => {
+                set(ACTIVE_MODEL_KEY, json!(name), None);
+                format!("Conversations now use {name}.")
+            }
+            Command::Tools { toggle: None } => {
+                let disabled = disabled_tools();
+                let prefs = ChannelPrefs::load(channel);
+                let lines: Vec<String> = registry
+                    .names()
+                    .map(|name| {
+                        if prefs.disabled_tools.contains(name) {
+                            format!("• {name} (off here)")
+                        } else if prefs.enabled_tools.contains(name) {
+                            format!("• {name} (on here)")
+                        } else if disabled.contains(name) {
+                            format!("• {name} (off)")
+                        } else {
+                            format!("• {name}")
+                        }
+                    })
+                    .collect();
+                format!("Registered tools:\n{}", lines.join("\n"))
+            }
+            Command::Tools {
+                toggle: Some((on, name)),
+            } => {
+                if registry.get(name).is_none() {
+                    return format!("There is no tool called {name}. Try !tools.");
+                }
+                let mut disabled = disabled_tools();
+                if *on {
+                    disabled.remove(name);
+                } else {
+                    disabled.insert(name.clone());
+                }
+                set(DISABLED_TOOLS_KEY, json!(disabled), None);
+                format!("Turned {name} {}.", if *on { "on" } else { "off" })
+            }
+            Command::ChannelTool { on, name } => {
+                if registry.get(name).is_none() {
+                    return format!("There is no tool called {name}. Try !tools.");
+                }
+                let mut prefs = ChannelPrefs::load(channel);
+                prefs.toggle_tool(name, *on);
+                prefs.save(channel);
+                format!(
+                    "Turned {name} {} in {channel}.",
+                    if *on { "on" } else { "off" }
+                )
+            }
+            Command::ToolStats => analytics::leaderboard(registry.names()),
+            Command::Feedback => feedback::summary(channel),
+            Command::Persona { name: None } => {
+                let current = ChannelPrefs::load(channel).persona;
+                let lines: Vec<String> = persona::all()
+                    .into_keys()
+                    .map(|name| {
+                        if current.as_ref() == Some(&name) {
+                            format!("• {name} (current)")
+                        } else {
+                            format!("• {name}")
+                        }
+                    })
+                    .collect();
+                format!("Personas:\n{}", lines.join("\n"))
+            }
+            Command::Persona { name: Some(name) } => {
+                let mut prefs = ChannelPrefs::load(channel);
+                if name == "default" {
+                    prefs.persona = None;
+                    prefs.save(channel);
+                    return format!(
+                        "Switched {channel} back to the default tools, and new conversations \
+                         to the default prompt."
+                    );
+                }
+                if persona::get(name).is_none() {
+                    return format!("There is no persona called {name}. Try !persona.");
+                }
+                prefs.persona = Some(name.clone());
+                prefs.save(channel);
+                format!(
+                    "Switched {channel} to the {name} persona: its tools now, and its prompt \
+                     in new conversations."
+                )
+            }
+            Command::DryRun { on: None } => {
+                if config.dry_run {
+                    format!("Tool calls in {channel} are described, not run.")
+                } else {
+                    format!("Tool calls in {channel} are run.")
+                }
+            }
+            Command::DryRun { on: Some(on) } => {
+                let mut prefs = ChannelPrefs::load(channel);
+                prefs.dry_run = Some(*on);
+                prefs.save(channel);
+                if *on {
+                    format!("Tool calls in {channel} will be described instead of run.")
+                } else {
+                    format!("Tool calls in {channel} will be run again.")
+                }
+            }
+            Command::Ephemeral { on: None } => {
+                if ChannelPrefs::load(channel).answers_ephemeral() {
+                    format!("Answers in {channel} are shown only to whoever asked.")
+                } else {
+                    format!("Answers in {channel} are posted for everyone.")
+                }
+            }
+            Command::Ephemeral { on: Some(on) } => {
+                let mut prefs = ChannelPrefs::load(channel);
+                prefs.ephemeral = Some(*on);
+                prefs.save(channel);
+                if *on {
+                    format!("Answers in {channel} will be shown only to whoever asked.")
+                } else {
+                    format!("Answers in {channel} will be posted for everyone again.")
+                }
+            }
+            Command::Usage => ChannelUsage::load(channel).report(channel),
+            Command::Budget { lift: true } => {
+                usage::override_budget();
+                "The token budget is lifted until midnight UTC.".to_string()
+            }
+            Command::Budget { lift: false } => Budget::from_env().report(),
+            Command::Ping => health::report(&health::run(client, &config.model).await),
+            Command::Ingest { url: Some(url) } => tools::ingest_url(client, url).await,
+            Command::Ingest { url: None } => {
+                "Use !ingest with the URL of a page, or share the files to ingest along with it."
+                    .to_string()
+            }
+            Command::Unknown(name) => format!("Unknown command !{name}. {HELP}"),
+        }
+    }
+}
+
+/// Whether `user` is one of the Slack user IDs in the comma-separated
+/// `admin_users`. Nobody is when it's unset, least of all a missing user.
+pub fn is_admin(user: &str) -> bool {
+    let user = user.trim();
+    !user.is_empty()
+        && env::var("admin_users")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|admin| !admin.is_empty())
+            .any(|admin| admin == user)
+}
+
+/// The model an admin switched to with `!model`, which replaces `model`.
+pub fn active_model() -> Option<String> {
+    get(ACTIVE_MODEL_KEY).and_then(|v| v.as_str().map(str::to_string))
+}
+
+/// The tools admins turned off with `!tools off`.
+pub fn disabled_tools() -> BTreeSet<String> {
+    get(DISABLED_TOOLS_KEY)
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordinary_messages_are_not_commands() {
+        assert_eq!(Command::parse("what's the weather?"), None);
+        assert_eq!(Command::parse("!"), None);
+        assert_eq!(Command::parse("!nocache what's new?"), None);
+        assert_eq!(Command::parse("!private hello"), None);
+    }
+
+    #[test]
+    fn reset_reads_a_slack_mention() {
+        assert_eq!(
+            Command::parse("  !RESET <@U0123ABCD>"),
+            Some(Command::Reset {
+                user: Some("U0123ABCD".to_string())
+            })
+        );
+        assert_eq!(
+            Command::parse("!reset"),
+            Some(Command::Reset { user: None })
+        );
+        assert!(Command::parse("!reset").unwrap().is_public());
+        assert!(!Command::parse("!reset <@U1>").unwrap().is_public());
+    }
+
+    #[test]
+    fn tools_toggles_everywhere_or_in_the_channel() {
+        assert_eq!(
+            Command::parse("!tools off getWeather"),
+            Some(Command::Tools {
+                toggle: Some((false, "getWeather".to_string()))
+            })
+        );
+        assert_eq!(
+            Command::parse("!tools enable getWeather"),
+            Some(Command::ChannelTool {
+                on: true,
+                name: "getWeather".to_string()
+            })
+        );
+        assert_eq!(
+            Command::parse("!tools off"),
+            Some(Command::Tools { toggle: None })
+        );
+    }
+
+    #[test]
+    fn switches_read_on_and_off() {
+        assert_eq!(
+            Command::parse("!dryrun on"),
+            Some(Command::DryRun { on: Some(true) })
+        );
+        assert_eq!(
+            Command::parse("!ephemeral maybe"),
+            Some(Command::Ephemeral { on: None })
+        );
+        assert_eq!(
+            Command::parse("!budget override"),
+            Some(Command::Budget { lift: true })
+        );
+    }
+
+    #[test]
+    fn ingest_unwraps_slack_links() {
+        assert_eq!(
+            Command::parse("!ingest <https://example.com/doc|the doc>"),
+            Some(Command::Ingest {
+                url: Some("https://example.com/doc".to_string())
+            })
+        );
+        assert_eq!(
+            Command::parse("!ingest <https://example.com/doc>"),
+            Some(Command::Ingest {
+                url: Some("https://example.com/doc".to_string())
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_commands_keep_their_name() {
+        assert_eq!(
+            Command::parse("!Frobnicate now"),
+            Some(Command::Unknown("frobnicate".to_string()))
+        );
+    }
+}
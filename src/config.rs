@@ -0,0 +1,275 @@
+use crate::core::session::ChatEnd;
+use crate::history::Compaction;
+use crate::response_format;
+use async_openai::types::{
+    ChatCompletionNamedToolChoice, ChatCompletionToolChoiceOption, ChatCompletionToolType,
+    FunctionName,
+};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How tools are offered to the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToolMode {
+    /// Through the tools API.
+    #[default]
+    Native,
+    /// Described in the system prompt, for models without the tools API.
+    Prompt,
+}
+
+impl FromStr for ToolMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "native" => Ok(ToolMode::Native),
+            "prompt" => Ok(ToolMode::Prompt),
+            other => Err(format!("unknown tool mode: {other}")),
+        }
+    }
+}
+
+/// Generation settings for a conversation.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub model: String,
+    /// The model used instead of `model` for messages with images.
+    pub vision_model: String,
+    /// The speech-to-text model voice clips are transcribed with.
+    pub transcription_model: String,
+    /// The text-to-speech model and voice answers are read out with, for
+    /// users who asked for spoken answers.
+    pub speech_model: String,
+    pub speech_voice: String,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: u16,
+    /// `None` leaves the choice to the API default.
+    pub tool_choice: Option<ChatCompletionToolChoiceOption>,
+    pub max_tool_rounds: usize,
+    pub tool_mode: ToolMode,
+    /// Whether tools' example requests are added to the system prompt.
+    pub tool_examples: bool,
+    /// How long a tool may run when neither `tool_timeouts` nor the tool
+    /// itself says otherwise.
+    pub tool_timeout: Duration,
+    /// Per-tool deadlines, keyed by tool name.
+    pub tool_timeouts: HashMap<String, Duration>,
+    /// Tool output longer than this many characters is also shared as a
+    /// file, so the answer can point to it instead of repeating it. `0`
+    /// never shares it.
+    pub tool_upload_chars: usize,
+    pub compaction: Compaction,
+    pub chat_end: ChatEnd,
+    /// The language replies are written in, set per channel. `None` lets the
+    /// model follow the user.
+    pub reply_language: Option<String>,
+    /// A JSON schema final answers must match, from `response_format`.
+    pub response_schema: Option<Value>,
+    /// How many times an answer that doesn't match `response_schema` is sent
+    /// back to the model to be fixed.
+    pub response_format_retries: usize,
+    /// Whether answers end with the pages and search results the tools
+    /// found them in, from `citations`.
+    pub citations: bool,
+    /// Tools left out of requests, and refused if the model calls them anyway.
+    pub disabled_tools: BTreeSet<String>,
+    /// Whether tool calls are described to the user instead of run.
+    pub dry_run: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            model: "gpt-3.5-turbo-1106".to_string(),
+            vision_model: "gpt-4o".to_string(),
+            transcription_model: "whisper-1".to_string(),
+            speech_model: "tts-1".to_string(),
+            speech_voice: "alloy".to_string(),
+            temperature: None,
+            top_p: None,
+            max_tokens: 512,
+            tool_choice: None,
+            max_tool_rounds: 3,
+            tool_mode: ToolMode::Native,
+            tool_examples: false,
+            tool_timeout: Duration::from_secs(30),
+            tool_timeouts: HashMap::new(),
+            tool_upload_chars: 4000,
+            compaction: Compaction::Truncate,
+            chat_end: ChatEnd::Bye,
+            reply_language: None,
+            response_schema: None,
+            response_format_retries: 2,
+            citations: true,
+            disabled_tools: BTreeSet::new(),
+            dry_run: false,
+        }
+    }
+}
+
+impl Config {
+    /// The same settings, but with `tool` called before the model answers.
+    pub fn forcing(self, tool: &str) -> Self {
+        Self {
+            tool_choice: Some(named_tool_choice(tool)),
+            ..self
+        }
+    }
+
+    /// Reads the settings from env, keeping the default for anything unset
+    /// or unparsable.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            model: env::var("model").unwrap_or(default.model),
+            vision_model: env::var("vision_model").unwrap_or(default.vision_model),
+            transcription_model: env::var("transcription_model")
+                .unwrap_or(default.transcription_model),
+            speech_model: env::var("speech_model").unwrap_or(default.speech_model),
+            speech_voice: env::var("speech_voice").unwrap_or(default.speech_voice),
+            temperature: parse_env("temperature").or(default.temperature),
+            top_p: parse_env("top_p").or(default.top_p),
+            max_tokens: parse_env("max_tokens").unwrap_or(default.max_tokens),
+            tool_choice: env::var("tool_choice")
+                .ok()
+                .map(|v| parse_tool_choice(&v))
+                .or(default.tool_choice),
+            max_tool_rounds: parse_env("max_tool_rounds").unwrap_or(default.max_tool_rounds),
+            tool_mode: parse_env("tool_mode").unwrap_or(default.tool_mode),
+            tool_examples: parse_env("tool_examples").unwrap_or(default.tool_examples),
+            tool_timeout: parse_env("tool_timeout_secs")
+                .map(Duration::from_secs)
+                .unwrap_or(default.tool_timeout),
+            tool_timeouts: env::var("tool_timeouts")
+                .map(|v| parse_tool_timeouts(&v))
+                .unwrap_or(default.tool_timeouts),
+            tool_upload_chars: parse_env("tool_upload_chars").unwrap_or(default.tool_upload_chars),
+            compaction: parse_env("history_compaction").unwrap_or(default.compaction),
+            chat_end: parse_env("chat_end").unwrap_or(default.chat_end),
+            reply_language: default.reply_language,
+            response_schema: parse_env::<Value>("response_format")
+                .filter(|schema| match response_format::compiles(schema) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        log::warn!("Ignoring invalid response_format schema: {}", e);
+                        false
+                    }
+                })
+                .or(default.response_schema),
+            response_format_retries: parse_env("response_format_retries")
+                .unwrap_or(default.response_format_retries),
+            citations: parse_env("citations").unwrap_or(default.citations),
+            disabled_tools: default.disabled_tools,
+            dry_run: parse_env("dry_run").unwrap_or(default.dry_run),
+        }
+    }
+}
+
+/// Parses `name=seconds` pairs separated by commas, e.g. `scraper=20,getWeather=5`.
+fn parse_tool_timeouts(value: &str) -> HashMap<String, Duration> {
+    value
+        .split(',')
+        .filter_map(|pair| {
+            let (name, secs) = pair.split_once('=')?;
+            let secs = secs.trim().parse().ok()?;
+            Some((name.trim().to_string(), Duration::from_secs(secs)))
+        })
+        .collect()
+}
+
+fn parse_env<T: FromStr>(key: &str) -> Option<T> {
+    let value = env::var(key).ok()?;
+    match value.parse() {
+        Ok(v) => Some(v),
+        Err(_) => {
+            log::warn!("Ignoring invalid value for {}: {}", key, value);
+            None
+        }
+    }
+}
+
+/// Parses `auto`, `none`, or the name of a function to force.
+pub fn parse_tool_choice(value: &str) -> ChatCompletionToolChoiceOption {
+    match value.trim() {
+        "auto" => ChatCompletionToolChoiceOption::Auto,
+        "none" => ChatCompletionToolChoiceOption::None,
+        name => named_tool_choice(name),
+    }
+}
+
+fn named_tool_choice(name: &str) -> ChatCompletionToolChoiceOption {
+    ChatCompletionToolChoiceOption::Named(ChatCompletionNamedToolChoice {
+        r#type: ChatCompletionToolType::Function,
+        function: FunctionName {
+            name: name.to_string(),
+        },
+    })
+}
+
+/// Splits a message of the form `tool_calls <tool>: <message>` into the tool
+/// as the user wrote it and the message. `None` for other messages.
+pub fn parse_forced_tool(text: &str) -> Option<(&str, &str)> {
+    let rest = text.trim_start().strip_prefix("tool_calls")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let (tool, message) = rest.split_once(':')?;
+    let tool = tool.trim();
+    if tool.is_empty() || tool.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((tool, message.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tool_choice_reads_auto_and_none() {
+        assert!(matches!(
+            parse_tool_choice("auto"),
+            ChatCompletionToolChoiceOption::Auto
+        ));
+        assert!(matches!(
+            parse_tool_choice(" none "),
+            ChatCompletionToolChoiceOption::None
+        ));
+    }
+
+    #[test]
+    fn tool_choice_forces_any_other_name() {
+        match parse_tool_choice("get_weather") {
+            ChatCompletionToolChoiceOption::Named(choice) => {
+                assert_eq!(choice.function.name, "get_weather")
+            }
+            other => panic!("expected a named choice, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn forced_tool_splits_the_tool_from_the_message() {
+        assert_eq!(
+            parse_forced_tool("  tool_calls weather: Paris tomorrow "),
+            Some(("weather", "Paris tomorrow"))
+        );
+        assert_eq!(
+            parse_forced_tool("tool_calls   search :"),
+            Some(("search", ""))
+        );
+    }
+
+    #[test]
+    fn forced_tool_needs_one_word_and_a_colon() {
+        assert_eq!(parse_forced_tool("tool_calls: hello"), None);
+        assert_eq!(parse_forced_tool("tool_callsweather: hi"), None);
+        assert_eq!(parse_forced_tool("tool_calls weather Paris"), None);
+        assert_eq!(parse_forced_tool("tool_calls get weather: Paris"), None);
+        assert_eq!(parse_forced_tool("what is tool_calls weather: x"), None);
+    }
+}
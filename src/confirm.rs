@@ -0,0 +1,68 @@
+use crate::core::session::SessionKey;
+use crate::store::{del, get, set, Expire, ExpireKind};
+use serde::{Deserialize, Serialize};
+
+/// How long a confirmation request stays open.
+const PENDING_TTL_SECS: i64 = 15 * 60;
+
+/// A tool call waiting for the user's approval.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingAction {
+    pub tool: String,
+    pub arguments: String,
+}
+
+/// How a user answered a confirmation request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    Approve,
+    Reject,
+    /// Anything else, which leaves the question unanswered.
+    Other,
+}
+
+impl Reply {
+    /// Reads a reply such as `:+1:` (how Slack sends 👍), `yes` or `cancel`.
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim().trim_end_matches(['.', '!']).to_lowercase();
+        match text.as_str() {
+            ":+1:" | ":thumbsup:" | "👍" | "yes" | "y" | "ok" | "approve" | "confirm"
+            | "go ahead" => Reply::Approve,
+            ":-1:" | ":thumbsdown:" | "👎" | "no" | "n" | "cancel" | "reject" | "stop" => {
+                Reply::Reject
+            }
+            _ => Reply::Other,
+        }
+    }
+}
+
+fn store_key(key: &SessionKey) -> String {
+    match &key.thread {
+        Some(thread) => format!("pending:{}:{}:{}", key.channel, key.user, thread),
+        None => format!("pending:{}:{}", key.channel, key.user),
+    }
+}
+
+/// Remembers `action` until the user answers or the request expires,
+/// replacing any earlier request.
+pub fn request(key: &SessionKey, action: &PendingAction) {
+    match serde_json::to_value(action) {
+        Ok(value) => set(
+            &store_key(key),
+            value,
+            Some(Expire {
+                kind: ExpireKind::Ex,
+                value: PENDING_TTL_SECS,
+            }),
+        ),
+        Err(e) => log::warn!("Failed to save pending {} call: {}", action.tool, e),
+    }
+}
+
+pub fn pending(key: &SessionKey) -> Option<PendingAction> {
+    get(&store_key(key)).and_then(|v| serde_json::from_value(v).ok())
+}
+
+pub fn clear(key: &SessionKey) {
+    del(&store_key(key));
+}
@@ -0,0 +1,461 @@
+use super::chat::{chat_inner, complete_follow_up, force_tool, run_approved};
+use super::prompt;
+use super::session::{self, SessionKey, SessionManager};
+use super::{Tool, ToolContext, ToolRegistry, DEFAULT_SYSTEM_PROMPT};
+use crate::cache::{self, AnswerCache};
+use crate::client::ChatClient;
+use crate::config::Config;
+use crate::confirm::{self, Reply};
+use crate::debug;
+use crate::error::Result;
+use crate::follow_up;
+use crate::history;
+use crate::moderation::{self, Moderation};
+use crate::usage::Budget;
+use async_openai::types::{
+    ChatCompletionRequestMessageContentPart, ChatCompletionRequestMessageContentPartImageArgs,
+    ChatCompletionRequestMessageContentPartTextArgs, ChatCompletionRequestUserMessageContent,
+    ImageUrlArgs,
+};
+use log::Level;
+use std::time::Duration;
+
+/// Where agents keep their conversations unless told otherwise.
+const DEFAULT_CHANNEL: &str = "agent";
+
+/// A chat assistant with tools and a conversation per user, for flows that
+/// use this crate as a library:
+///
+/// ```ignore
+/// let agent = Agent::new(Config::from_env());
+/// let answer = agent.chat("alice", "What time is it in Tokyo?").await?;
+/// ```
+///
+/// [`Agent::builder`] assembles one in code instead.
+pub struct Agent {
+    config: Config,
+    client: ChatClient,
+    registry: ToolRegistry,
+    sessions: SessionManager,
+    channel: String,
+}
+
+impl Agent {
+    /// An agent with the built-in tools, talking to the service configured
+    /// in env.
+    pub fn new(config: Config) -> Self {
+        Self::builder()
+            .config(config)
+            .system_prompt(&prompt::template_from_env())
+            .builtin_tools()
+            .idle_expiry(session::idle_expiry_from_env())
+            .build()
+    }
+
+    pub fn builder() -> AgentBuilder {
+        AgentBuilder::default()
+    }
+
+    /// Keeps the agent's conversations, usage and preferences under
+    /// `channel`, so agents in different places don't share them.
+    pub fn in_channel(mut self, channel: &str) -> Self {
+        self.channel = channel.to_string();
+        self
+    }
+
+    pub fn with_client(mut self, client: ChatClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Fills `{name}` in the system prompt of conversations started from
+    /// now on with `value`.
+    pub fn with_prompt_variable(mut self, name: &str, value: &str) -> Self {
+        self.sessions = self.sessions.with_variable(name, value);
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn sessions(&self) -> &SessionManager {
+        &self.sessions
+    }
+
+    /// The conversation `user` is having with the agent.
+    pub fn session_key(&self, user: &str) -> SessionKey {
+        SessionKey::new(&self.channel, user)
+    }
+
+    /// Answers `message` from `user`, continuing their conversation. `None`
+    /// when the model has nothing to say.
+    pub async fn chat(&self, user: &str, message: &str) -> Result<Option<String>> {
+        let context =
+            ToolContext::new(&self.channel, &self.channel, user).in_session(self.session_key(user));
+        match force_tool(message, &self.registry, &self.config) {
+            Some(Ok((config, input))) => self.converse(input, &config, &context).await,
+            Some(Err(reply)) => Ok(Some(reply)),
+            None => {
+                self.converse(message.to_string(), &self.config, &context)
+                    .await
+            }
+        }
+    }
+
+    /// Answers a message on a platform without Slack's threads, files and
+    /// commands: an answer to a confirmation request or follow-up question,
+    /// or else `input`, the message as the model should read it. `None` when
+    /// there's nothing to say, including when `input` is `None` because the
+    /// message isn't for the bot.
+    pub async fn answer(
+        &self,
+        message: &str,
+        input: Option<String>,
+        context: &ToolContext,
+    ) -> Option<String> {
+        match self.reply(Incoming::new(message, input), context).await {
+            Outcome::Answered { answer, .. } => Some(answer),
+            Outcome::Said(text) => Some(text),
+            Outcome::Silent => None,
+        }
+    }
+
+    /// Like [`answer`](Self::answer), for platforms that treat the model's
+    /// answers differently from what else is said, or whose messages carry
+    /// images.
+    pub async fn reply(&self, incoming: Incoming, context: &ToolContext) -> Outcome {
+        let message = incoming.text.as_str();
+        let key = &context.session;
+        let pending = confirm::pending(key);
+        if pending.is_some() {
+            confirm::clear(key);
+        }
+        let follow_up = follow_up::pending(key);
+        if follow_up.is_some() {
+            follow_up::clear(key);
+        }
+        let reply = Reply::parse(message);
+        let replying = (pending.is_some() && reply != Reply::Other) || follow_up.is_some();
+        let user_input = match (pending, reply, follow_up) {
+            (Some(action), Reply::Approve, _) => {
+                match run_approved(&self.registry, &self.config, context, &action).await {
+                    Ok(result) => {
+                        format!("Approved. The {} tool returned: {}", action.tool, result)
+                    }
+                    Err(e) => format!("Approved, but the {} tool failed: {}", action.tool, e),
+                }
+            }
+            (Some(action), Reply::Reject, _) => {
+                format!("Cancelled. Don't run the {} tool.", action.tool)
+            }
+            (_, Reply::Reject, Some(call)) => {
+                format!("Cancelled. Don't run the {} tool.", call.tool)
+            }
+            (_, _, Some(call)) => {
+                complete_follow_up(&self.registry, &self.config, context, &call, message).await
+            }
+            _ => match incoming.input {
+                Some(input) => input,
+                None => return Outcome::Silent,
+            },
+        };
+        let (config, user_input) = match force_tool(&user_input, &self.registry, &self.config) {
+            Some(Ok(forced)) => forced,
+            Some(Err(reply)) => return Outcome::Said(reply),
+            None => (self.config.clone(), user_input),
+        };
+
+        if incoming.budgeted {
+            if let Some(notice) = Budget::from_env().exhausted() {
+                return Outcome::Said(notice);
+            }
+        }
+        if !replying {
+            if let Some(reason) = Moderation::from_env()
+                .check(&self.client, &user_input)
+                .await
+            {
+                debug::emit(
+                    Level::Info,
+                    &format!("Refused a message from {} because {}", context.user, reason),
+                )
+                .await;
+                return Outcome::Said(moderation::refusal());
+            }
+        }
+
+        // A repeated question gets the answer it got before, unless it's
+        // asked with `!nocache`.
+        let answer_cache = AnswerCache::from_env();
+        let (fresh, question) = cache::strip_no_cache(&user_input);
+        let cacheable = !replying && incoming.cacheable;
+        if cacheable && !fresh {
+            if let Some(answer) = answer_cache.get(&self.channel, &question) {
+                let mut session = self.sessions.load(context.session.clone());
+                session.push_exchange(&question, &answer);
+                self.sessions.save(&mut session);
+                return Outcome::Said(answer);
+            }
+        }
+
+        // Images are sent along to a vision model.
+        let (user_input, config) = if incoming.images.is_empty() {
+            (question.clone().into(), config)
+        } else {
+            match image_message(question.clone(), incoming.images) {
+                Ok(content) => (
+                    content,
+                    Config {
+                        model: config.vision_model.clone(),
+                        ..config
+                    },
+                ),
+                Err(e) => {
+                    log::warn!("Failed to attach images: {}", e);
+                    (question.clone().into(), config)
+                }
+            }
+        };
+        match self.converse(user_input, &config, context).await {
+            Ok(Some(answer)) => {
+                if cacheable {
+                    answer_cache.put(&self.channel, &question, &answer);
+                }
+                Outcome::Answered {
+                    question,
+                    answer,
+                    model: config.model,
+                }
+            }
+            Ok(None) => Outcome::Silent,
+            Err(e) => {
+                log::error!("Failed to answer {}: {}", context.user, e);
+                Outcome::Said(e.user_message())
+            }
+        }
+    }
+
+    /// Runs one turn of the conversation `context` is in and saves it.
+    async fn converse(
+        &self,
+        input: impl Into<ChatCompletionRequestUserMessageContent>,
+        config: &Config,
+        context: &ToolContext,
+    ) -> Result<Option<String>> {
+        let mut session = self.sessions.load(context.session.clone());
+        let result = chat_inner(
+            input,
+            &mut session.messages,
+            &self.client,
+            &self.registry,
+            config,
+            context,
+        )
+        .await;
+        history::strip_images(&mut session.messages);
+        self.sessions.save(&mut session);
+        result
+    }
+}
+
+/// A message for [`Agent::reply`], and how the platform it came from wants
+/// it handled.
+#[derive(Debug, Clone)]
+pub struct Incoming {
+    /// What the user sent, read for answers to confirmation requests and
+    /// follow-up questions.
+    pub text: String,
+    /// The message as the model should read it, or `None` when it isn't for
+    /// the bot.
+    pub input: Option<String>,
+    /// URLs of images shared with the message, which is then answered by
+    /// the vision model.
+    pub images: Vec<String>,
+    /// Whether the token budget holds the message back once it's used up.
+    pub budgeted: bool,
+    /// Whether the answer may come from, and be kept in, the answer cache.
+    pub cacheable: bool,
+}
+
+impl Incoming {
+    /// `text`, read by the model as `input`, held to the budget and cached.
+    pub fn new(text: &str, input: Option<String>) -> Self {
+        Self {
+            text: text.to_string(),
+            input,
+            images: Vec::new(),
+            budgeted: true,
+            cacheable: true,
+        }
+    }
+
+    pub fn with_images(mut self, images: Vec<String>) -> Self {
+        self.images = images;
+        self
+    }
+
+    /// Answers even once the token budget is used up.
+    pub fn unbudgeted(mut self) -> Self {
+        self.budgeted = false;
+        self
+    }
+
+    /// Answers afresh, and doesn't keep the answer for next time.
+    pub fn uncached(mut self) -> Self {
+        self.cacheable = false;
+        self
+    }
+}
+
+/// What came of [`Agent::reply`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The model answered `question`, the message as it read it, with
+    /// `model`.
+    Answered {
+        question: String,
+        answer: String,
+        model: String,
+    },
+    /// Something other than a fresh answer from the model: a notice, a
+    /// refusal, an answer given before, or what went wrong.
+    Said(String),
+    /// Nothing to say.
+    Silent,
+}
+
+/// `text` followed by the images at `image_urls`.
+fn image_message(
+    text: String,
+    image_urls: Vec<String>,
+) -> Result<ChatCompletionRequestUserMessageContent> {
+    let mut parts: Vec<ChatCompletionRequestMessageContentPart> =
+        vec![ChatCompletionRequestMessageContentPartTextArgs::default()
+            .text(text)
+            .build()?
+            .into()];
+    for url in image_urls {
+        parts.push(
+            ChatCompletionRequestMessageContentPartImageArgs::default()
+                .image_url(ImageUrlArgs::default().url(url).build()?)
+                .build()?
+                .into(),
+        );
+    }
+    Ok(ChatCompletionRequestUserMessageContent::Array(parts))
+}
+
+/// Builds an [`Agent`] without env or statics:
+///
+/// ```ignore
+/// let agent = Agent::builder()
+///     .client(ChatClient::openai(&key))
+///     .model("gpt-4o-mini")
+///     .system_prompt("You are a weather assistant")
+///     .tool(WeatherTool)
+///     .max_rounds(3)
+///     .build();
+/// ```
+///
+/// It starts from `Config::default()` and the default system prompt, with no
+/// tools. Without a client, `build` falls back to `ChatClient::from_env`.
+#[derive(Default)]
+pub struct AgentBuilder {
+    config: Config,
+    client: Option<ChatClient>,
+    registry: ToolRegistry,
+    builtin_tools: bool,
+    system_prompt: Option<String>,
+    idle_expiry: Option<Duration>,
+    channel: Option<String>,
+}
+
+impl AgentBuilder {
+    /// Replaces every setting, including any set on the builder before.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn model(mut self, model: &str) -> Self {
+        self.config.model = model.to_string();
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.config.temperature = Some(temperature);
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u16) -> Self {
+        self.config.max_tokens = max_tokens;
+        self
+    }
+
+    /// How many rounds of tool calls the model gets before it has to answer.
+    pub fn max_rounds(mut self, max_rounds: usize) -> Self {
+        self.config.max_tool_rounds = max_rounds;
+        self
+    }
+
+    /// What new conversations start with, a template that may use
+    /// `{today}`, `{channel}`, `{user}` and `{user_name}`.
+    pub fn system_prompt(mut self, system_prompt: &str) -> Self {
+        self.system_prompt = Some(system_prompt.to_string());
+        self
+    }
+
+    /// Adds `tool`, replacing any tool of the same name.
+    pub fn tool<T: Tool + 'static>(mut self, tool: T) -> Self {
+        self.registry.register(tool);
+        self
+    }
+
+    /// Adds the built-in tools, leaving out those needing a service that
+    /// isn't configured. They're made by `build`, with the builder's client
+    /// and model, and tools added with [`tool`](Self::tool) replace any of
+    /// the same name.
+    pub fn builtin_tools(mut self) -> Self {
+        self.builtin_tools = true;
+        self
+    }
+
+    pub fn client(mut self, client: ChatClient) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Starts conversations unused for longer than `idle_expiry` afresh.
+    pub fn idle_expiry(mut self, idle_expiry: Option<Duration>) -> Self {
+        self.idle_expiry = idle_expiry;
+        self
+    }
+
+    /// See [`Agent::in_channel`].
+    pub fn channel(mut self, channel: &str) -> Self {
+        self.channel = Some(channel.to_string());
+        self
+    }
+
+    pub fn build(self) -> Agent {
+        let client = self.client.unwrap_or_else(ChatClient::from_env);
+        let mut registry = if self.builtin_tools {
+            ToolRegistry::with_builtin_tools(&client, &self.config.model)
+        } else {
+            ToolRegistry::new()
+        };
+        registry.extend(self.registry);
+        Agent {
+            config: self.config,
+            client,
+            registry,
+            sessions: SessionManager::new(
+                self.system_prompt
+                    .unwrap_or(DEFAULT_SYSTEM_PROMPT.to_string()),
+            )
+            .with_idle_expiry(self.idle_expiry),
+            channel: self.channel.unwrap_or(DEFAULT_CHANNEL.to_string()),
+        }
+    }
+}
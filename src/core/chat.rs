@@ -0,0 +1,669 @@
+use crate::access;
+use crate::analytics;
+use crate::citations::Sources;
+use crate::client::ChatClient;
+use crate::config::{self, Config, ToolMode};
+use crate::confirm::{self, PendingAction};
+use crate::core::session::{ChatEnd, SessionState};
+use crate::debug;
+use crate::error::{Error, Result};
+use crate::follow_up::{self, PendingCall};
+use crate::history::{self, Compaction};
+use crate::response_format;
+use crate::secrets;
+use crate::telemetry;
+use crate::tool_prompt;
+use crate::tools::{NeedsInput, Tool, ToolContext, ToolRegistry, WRITE_TOOLS};
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestToolMessageArgs,
+    ChatCompletionRequestUserMessageArgs, ChatCompletionRequestUserMessageContent,
+    ChatCompletionResponseFormat, ChatCompletionResponseFormatType, ChatCompletionToolChoiceOption,
+    CreateChatCompletionRequestArgs, FinishReason, FunctionCall,
+};
+use log::Level;
+use serde_json::{json, Map, Value};
+use std::time::Instant;
+
+/// For a message of the form `tool_calls <tool>: <message>`, settings that
+/// force the tool and the message to send. `Err` holds what to tell the user
+/// when no enabled tool matches. `None` for other messages.
+pub(crate) fn force_tool(
+    input: &str,
+    registry: &ToolRegistry,
+    config: &Config,
+) -> Option<std::result::Result<(Config, String), String>> {
+    let (name, message) = config::parse_forced_tool(input)?;
+    Some(match registry.find(name) {
+        Some(tool) if !config.disabled_tools.contains(tool.name()) => {
+            Ok((config.clone().forcing(tool.name()), message.to_string()))
+        }
+        _ => Err(format!("There is no tool called {name} to use here.")),
+    })
+}
+
+/// Runs one user turn, letting the model call tools for up to
+/// `config.max_tool_rounds` rounds before it has to answer in plain text.
+pub async fn chat_inner(
+    user_input: impl Into<ChatCompletionRequestUserMessageContent>,
+    messages: &mut Vec<ChatCompletionRequestMessage>,
+    client: &ChatClient,
+    registry: &ToolRegistry,
+    config: &Config,
+    context: &ToolContext,
+) -> Result<Option<String>> {
+    let span = telemetry::start_turn("chat.turn");
+    let result = run_turn(user_input, messages, client, registry, config, context).await;
+    let mut attributes = Map::new();
+    attributes.insert("channel".to_string(), json!(context.channel));
+    attributes.insert("user".to_string(), json!(context.user));
+    attributes.insert("model".to_string(), json!(config.model));
+    span.end(result.is_ok(), attributes);
+    telemetry::finish_turn().await;
+    result
+}
+
+async fn run_turn(
+    user_input: impl Into<ChatCompletionRequestUserMessageContent>,
+    messages: &mut Vec<ChatCompletionRequestMessage>,
+    client: &ChatClient,
+    registry: &ToolRegistry,
+    config: &Config,
+    context: &ToolContext,
+) -> Result<Option<String>> {
+    let user_msg_obj = ChatCompletionRequestUserMessageArgs::default()
+        .content(user_input.into())
+        .build()?
+        .into();
+
+    messages.push(user_msg_obj);
+
+    let mut sources = Sources::default();
+    for round in 0..=config.max_tool_rounds {
+        // Once the budget is spent, withhold the tools so the model has to answer.
+        let tools = if round < config.max_tool_rounds && !registry.is_empty() {
+            registry
+                .schemas()
+                .into_iter()
+                .filter(|tool| !config.disabled_tools.contains(&tool.function.name))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let budget = history::context_window(&config.model)
+            .saturating_sub(config.max_tokens as usize + history::tools_tokens(&tools));
+        let dropped = history::trim_to_budget(messages, budget);
+        if !dropped.is_empty() {
+            debug::emit(
+                Level::Info,
+                &format!("Trimmed {} old messages to fit the context", dropped.len()),
+            )
+            .await;
+            if config.compaction == Compaction::Summarize {
+                if let Err(e) =
+                    history::summarize_dropped(client, &config.model, messages, &dropped).await
+                {
+                    log::warn!("Failed to summarize trimmed messages: {}", e);
+                }
+            }
+        }
+
+        let mut request_messages = messages.clone();
+        if let Some(language) = &config.reply_language {
+            // Tools mostly return English, so without this the model tends to
+            // answer in English too.
+            insert_after_system(
+                &mut request_messages,
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content(format!(
+                        "Always reply in {language}, translating any tool results or other \
+                         content in other languages."
+                    ))
+                    .build()?
+                    .into(),
+            );
+        }
+        let prompted_tools = config.tool_mode == ToolMode::Prompt && !tools.is_empty();
+        if prompted_tools {
+            // Without tool_choice, a forced tool is the only one described.
+            let offered: Vec<_> = match &config.tool_choice {
+                Some(ChatCompletionToolChoiceOption::Named(choice)) if round == 0 => tools
+                    .iter()
+                    .filter(|tool| tool.function.name == choice.function.name)
+                    .cloned()
+                    .collect(),
+                _ => tools.clone(),
+            };
+            insert_after_system(
+                &mut request_messages,
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content(tool_prompt::instructions(&offered))
+                    .build()?
+                    .into(),
+            );
+        }
+
+        if config.tool_examples {
+            let examples: Vec<_> = tools
+                .iter()
+                .filter_map(|tool| registry.get(&tool.function.name))
+                .flat_map(|tool| {
+                    tool.examples()
+                        .into_iter()
+                        .map(|example| (tool.name().to_string(), example))
+                })
+                .collect();
+            if let Some(text) = tool_prompt::examples(&examples) {
+                insert_after_system(
+                    &mut request_messages,
+                    ChatCompletionRequestSystemMessageArgs::default()
+                        .content(text)
+                        .build()?
+                        .into(),
+                );
+            }
+        }
+
+        if let Some(schema) = &config.response_schema {
+            insert_after_system(
+                &mut request_messages,
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content(response_format::instructions(schema))
+                    .build()?
+                    .into(),
+            );
+        }
+
+        let mut request = base_request(config, request_messages.clone());
+        if !tools.is_empty() && config.tool_mode == ToolMode::Native {
+            request.tools(tools);
+            match &config.tool_choice {
+                // Forcing a function every round would never let the model answer.
+                Some(ChatCompletionToolChoiceOption::Named(_)) if round > 0 => {}
+                Some(tool_choice) => {
+                    request.tool_choice(tool_choice.clone());
+                }
+                None => {}
+            }
+        }
+
+        let chat = client.create_chat(request.build()?).await?;
+
+        let choice = chat.choices.first().ok_or(Error::EmptyResponse)?;
+
+        if prompted_tools {
+            let content = choice.message.content.clone().unwrap_or_default();
+            if let Some(function) = tool_prompt::parse_call(&content) {
+                if config.dry_run {
+                    return dry_run(registry, config, messages, &[function]);
+                }
+                messages.push(
+                    ChatCompletionRequestAssistantMessageArgs::default()
+                        .content(content)
+                        .build()?
+                        .into(),
+                );
+                let result = run_tool(registry, config, context, &function).await;
+                sources.record(&function, &result);
+                messages.push(
+                    ChatCompletionRequestUserMessageArgs::default()
+                        .content(tool_prompt::result_message(&function.name, &result))
+                        .build()?
+                        .into(),
+                );
+                continue;
+            }
+        }
+
+        let tool_calls = match &choice.message.tool_calls {
+            Some(tool_calls) if choice.finish_reason == Some(FinishReason::ToolCalls) => tool_calls,
+            _ => {
+                let content = match (&config.response_schema, &choice.message.content) {
+                    (Some(schema), Some(content)) => Some(
+                        conform(client, config, request_messages, schema, content.clone()).await?,
+                    ),
+                    (_, content) => content.clone(),
+                };
+                if let Some(content) = &content {
+                    messages.push(
+                        ChatCompletionRequestAssistantMessageArgs::default()
+                            .content(content.clone())
+                            .build()?
+                            .into(),
+                    );
+                }
+                return Ok(content.map(|content| sources.cite(config, content)));
+            }
+        };
+
+        if config.dry_run {
+            let calls: Vec<_> = tool_calls.iter().map(|c| c.function.clone()).collect();
+            return dry_run(registry, config, messages, &calls);
+        }
+
+        messages.push(
+            ChatCompletionRequestAssistantMessageArgs::default()
+                .tool_calls(tool_calls.clone())
+                .build()?
+                .into(),
+        );
+
+        for tool_call in tool_calls {
+            let content = run_tool(registry, config, context, &tool_call.function).await;
+            sources.record(&tool_call.function, &content);
+
+            messages.push(
+                ChatCompletionRequestToolMessageArgs::default()
+                    .tool_call_id(tool_call.id.clone())
+                    .content(content)
+                    .build()?
+                    .into(),
+            );
+        }
+    }
+
+    Ok(None)
+}
+
+/// Ends the turn by telling the user which calls the model asked for,
+/// without running them.
+fn dry_run(
+    registry: &ToolRegistry,
+    config: &Config,
+    messages: &mut Vec<ChatCompletionRequestMessage>,
+    calls: &[FunctionCall],
+) -> Result<Option<String>> {
+    let lines: Vec<String> = calls
+        .iter()
+        .map(|call| {
+            let planned = format!("I would call {}({})", call.name, call.arguments);
+            match registry.get(&call.name) {
+                None => format!("{planned}, but there is no such tool."),
+                Some(_) if config.disabled_tools.contains(&call.name) => {
+                    format!("{planned}, but it has been turned off.")
+                }
+                Some(tool) => match tool.check_arguments(&call.arguments) {
+                    Ok(()) => format!("{planned}."),
+                    Err(problems) => {
+                        format!("{planned}, but the arguments are invalid: {problems}")
+                    }
+                },
+            }
+        })
+        .collect();
+    let plan = format!("Dry run, nothing was run.\n{}", lines.join("\n"));
+    messages.push(
+        ChatCompletionRequestAssistantMessageArgs::default()
+            .content(plan.clone())
+            .build()?
+            .into(),
+    );
+    Ok(Some(plan))
+}
+
+/// Inserts `message` after the system messages at the start of `messages`,
+/// ahead of the conversation.
+fn insert_after_system(
+    messages: &mut Vec<ChatCompletionRequestMessage>,
+    message: ChatCompletionRequestMessage,
+) {
+    let at = messages
+        .iter()
+        .take_while(|m| matches!(m, ChatCompletionRequestMessage::System(_)))
+        .count();
+    messages.insert(at, message);
+}
+
+/// A completion request for `messages` with the configured generation
+/// settings.
+fn base_request(
+    config: &Config,
+    messages: Vec<ChatCompletionRequestMessage>,
+) -> CreateChatCompletionRequestArgs {
+    let mut request = CreateChatCompletionRequestArgs::default();
+    request
+        .max_tokens(config.max_tokens)
+        .model(&config.model)
+        .messages(messages);
+    if let Some(temperature) = config.temperature {
+        request.temperature(temperature);
+    }
+    if let Some(top_p) = config.top_p {
+        request.top_p(top_p);
+    }
+    if config.response_schema.is_some() {
+        request.response_format(ChatCompletionResponseFormat {
+            r#type: ChatCompletionResponseFormatType::JsonObject,
+        });
+    }
+    request
+}
+
+/// Checks a final answer against the `response_format` schema. An answer
+/// that doesn't match is sent back with the problems found, up to
+/// `config.response_format_retries` times.
+async fn conform(
+    client: &ChatClient,
+    config: &Config,
+    mut messages: Vec<ChatCompletionRequestMessage>,
+    schema: &Value,
+    mut answer: String,
+) -> Result<String> {
+    let mut retries = 0;
+    loop {
+        let problems = match response_format::validate(schema, &answer) {
+            Ok(answer) => return Ok(answer),
+            Err(problems) => problems,
+        };
+        if retries == config.response_format_retries {
+            return Err(Error::InvalidResponse(problems));
+        }
+        retries += 1;
+        debug::emit(
+            Level::Info,
+            &format!("Answer didn't match the response format, retrying: {problems}"),
+        )
+        .await;
+
+        messages.push(
+            ChatCompletionRequestAssistantMessageArgs::default()
+                .content(answer)
+                .build()?
+                .into(),
+        );
+        messages.push(
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(format!(
+                    "That answer doesn't match the JSON schema:\n{problems}\nReply again with \
+                     only JSON that matches it."
+                ))
+                .build()?
+                .into(),
+        );
+        let chat = client
+            .create_chat(base_request(config, messages.clone()).build()?)
+            .await?;
+        answer = chat
+            .choices
+            .first()
+            .and_then(|c| c.message.content.clone())
+            .ok_or(Error::EmptyResponse)?;
+    }
+}
+
+/// Runs the tool the model asked for, or asks the user to approve the call
+/// first when the tool wants confirmation. A call missing something only the
+/// user can say is held while the model asks them. A failed tool is reported
+/// to the model rather than ending the conversation.
+async fn run_tool(
+    registry: &ToolRegistry,
+    config: &Config,
+    context: &ToolContext,
+    function: &FunctionCall,
+) -> String {
+    let tool = match registry.get(&function.name) {
+        Some(_) if config.disabled_tools.contains(&function.name) => {
+            return envelope(Err(format!("{} has been turned off", function.name)))
+        }
+        Some(tool) => tool,
+        None => return envelope(Err(format!("Unknown function: {}", function.name))),
+    };
+    if WRITE_TOOLS.contains(&tool.name()) && !access::may_write(&context.user).await {
+        return envelope(Err(format!(
+            "The user is not authorized to use {}. Tell them so.",
+            function.name
+        )));
+    }
+    if let Some(param) = tool.missing_argument(&function.arguments) {
+        let description = tool.parameters()["properties"][&param]["description"]
+            .as_str()
+            .map(|d| format!(" ({})", d.trim_end_matches('.')))
+            .unwrap_or_default();
+        let question = format!("What should the {param} be{description}?");
+        return hold_for_input(context, function, NeedsInput::new(&param, question));
+    }
+    if let Err(problems) = tool.check_arguments(&function.arguments) {
+        debug::emit(
+            Level::Info,
+            &format!("Invalid arguments for {}: {}", function.name, problems),
+        )
+        .await;
+        return envelope(Err(format!(
+            "The arguments are invalid:\n{problems}\nFix them and call {} again.",
+            function.name
+        )));
+    }
+    // Only the chat of whoever the tool ran for ends.
+    if config.chat_end == ChatEnd::Tool {
+        SessionState::Idle.save(&context.session);
+    }
+
+    if let Some(question) = tool.confirmation(&function.arguments) {
+        let action = PendingAction {
+            tool: function.name.clone(),
+            arguments: function.arguments.clone(),
+        };
+        confirm::request(&context.session, &action);
+        if let Some(status) = &context.status {
+            status.confirm(&context.session, &question).await;
+        }
+        return envelope(Ok(format!(
+            "Nothing has been done yet. The user has been asked to approve this {} call, \
+             and it will run once they reply with a thumbs up. Tell them you're waiting \
+             for their approval.",
+            function.name
+        )));
+    }
+
+    if let Some(status) = &context.status {
+        status
+            .progress(&format!("🛠 running {}...", function.name))
+            .await;
+    }
+    let result = match execute_tool(tool, config, context, function).await {
+        Ok(output) => Ok(share_long_output(config, context, &function.name, output).await),
+        Err(Error::Tool { source, .. }) => match source.downcast::<NeedsInput>() {
+            Ok(needs) => return hold_for_input(context, function, needs),
+            Err(source) => Err(secrets::scrub(&format!("{source:#}"))),
+        },
+        Err(e) => Err(secrets::scrub(&e.to_string())),
+    };
+    if let Err(e) = &result {
+        debug::emit(
+            Level::Warn,
+            &format!("Tool {} failed: {}", function.name, e),
+        )
+        .await;
+    }
+    if let Some(status) = &context.status {
+        status
+            .progress(&format!(
+                "🛠 {} is done, working on the answer...",
+                function.name
+            ))
+            .await;
+    }
+    envelope(result)
+}
+
+/// Shares output longer than `config.tool_upload_chars` as a file where the
+/// platform allows it, and asks the model to point to the file rather than
+/// repeat what's in it.
+async fn share_long_output(
+    config: &Config,
+    context: &ToolContext,
+    tool: &str,
+    output: String,
+) -> String {
+    let length = output.chars().count();
+    if config.tool_upload_chars == 0 || length <= config.tool_upload_chars {
+        return output;
+    }
+    let Some(status) = &context.status else {
+        return output;
+    };
+    let name = format!("{tool}-output.txt");
+    let Some(file) = status.share_file(&name, &output).await else {
+        return output;
+    };
+    let place = match file.permalink {
+        Some(link) => format!("uploaded as {name}: {link}"),
+        None => format!("uploaded to the channel as {name}"),
+    };
+    format!(
+        "{output}\n\n[This output, {length} characters, has been {place}. Answer with a short \
+         summary and point the user to the file instead of repeating it.]"
+    )
+}
+
+/// Keeps `function` until the user answers `needs.question`, and tells the
+/// model to ask it.
+fn hold_for_input(context: &ToolContext, function: &FunctionCall, needs: NeedsInput) -> String {
+    let call = PendingCall {
+        tool: function.name.clone(),
+        arguments: function.arguments.clone(),
+        param: needs.param,
+        question: needs.question,
+    };
+    follow_up::ask(&context.session, &call);
+    envelope(Ok(format!(
+        "Nothing has been done yet. The {} call needs the user's answer first. \
+         Ask them this and nothing else: {}\nTheir next message will be used as the {}.",
+        call.tool, call.question, call.param
+    )))
+}
+
+/// Finishes a held call with `answer`, the user's reply to its question,
+/// and says how it went in a message for the model.
+pub(crate) async fn complete_follow_up(
+    registry: &ToolRegistry,
+    config: &Config,
+    context: &ToolContext,
+    call: &PendingCall,
+    answer: &str,
+) -> String {
+    let answer = answer.trim();
+    let mut arguments: Map<String, Value> =
+        serde_json::from_str(&call.arguments).unwrap_or_default();
+    // Anything but a string parameter takes the answer as JSON, so "3" can
+    // fill in a number.
+    let is_string = registry.get(&call.tool).is_none_or(|tool| {
+        tool.parameters()["properties"][&call.param]["type"]
+            .as_str()
+            .is_none_or(|kind| kind == "string")
+    });
+    let value = if is_string {
+        Value::String(answer.to_string())
+    } else {
+        serde_json::from_str(answer).unwrap_or_else(|_| Value::String(answer.to_string()))
+    };
+    arguments.insert(call.param.clone(), value);
+    let function = FunctionCall {
+        name: call.tool.clone(),
+        arguments: Value::Object(arguments).to_string(),
+    };
+    let result = run_tool(registry, config, context, &function).await;
+    format!(
+        "Asked \"{}\", the user answered: {answer}\n\
+         The {} call with that as the {} returned: {result}\n\
+         If the answer doesn't fit, call {} again with arguments that do.",
+        call.question, call.tool, call.param, call.tool
+    )
+}
+
+/// How a tool's outcome is handed to the model: `{"ok": true, "data": ...}`
+/// or `{"ok": false, "error": "..."}`, so a failure can't be mistaken for
+/// content. Output that is itself JSON is embedded as is.
+fn envelope(result: std::result::Result<String, String>) -> String {
+    match result {
+        Ok(output) => {
+            let data = match output.trim_start().chars().next() {
+                Some('{' | '[') => serde_json::from_str(&output).unwrap_or(Value::String(output)),
+                _ => Value::String(output),
+            };
+            json!({ "ok": true, "data": data })
+        }
+        Err(error) => json!({ "ok": false, "error": error }),
+    }
+    .to_string()
+}
+
+/// Runs a call the user has approved, held to the same checks as
+/// `run_tool`, as things may have changed since it was asked for.
+pub(crate) async fn run_approved(
+    registry: &ToolRegistry,
+    config: &Config,
+    context: &ToolContext,
+    action: &PendingAction,
+) -> Result<String> {
+    let function = FunctionCall {
+        name: action.tool.clone(),
+        arguments: action.arguments.clone(),
+    };
+    let refused = |reason: &str| Error::Tool {
+        name: function.name.clone(),
+        source: anyhow::anyhow!("{reason}"),
+    };
+    let tool = match registry.get(&function.name) {
+        Some(_) if config.disabled_tools.contains(&function.name) => {
+            return Err(refused("it has been turned off"))
+        }
+        Some(tool) => tool,
+        None => return Ok(format!("Unknown function: {}", function.name)),
+    };
+    if WRITE_TOOLS.contains(&tool.name()) && !access::may_write(&context.user).await {
+        return Err(refused("the user is not authorized to use it"));
+    }
+    if config.dry_run {
+        return Ok(format!(
+            "Dry run, nothing was run. I would call {}({}).",
+            function.name, function.arguments
+        ));
+    }
+    execute_tool(tool, config, context, &function).await
+}
+
+/// Runs `tool` within its deadline.
+///
+/// A tool that overruns its deadline is abandoned at its next await point
+/// and fails with a timeout, so the conversation can go on.
+async fn execute_tool(
+    tool: &dyn Tool,
+    config: &Config,
+    context: &ToolContext,
+    function: &FunctionCall,
+) -> Result<String> {
+    let deadline = config
+        .tool_timeouts
+        .get(&function.name)
+        .copied()
+        .or_else(|| tool.timeout())
+        .unwrap_or(config.tool_timeout);
+    let span = telemetry::start("tool.execute");
+    let started = Instant::now();
+    let result =
+        match tokio::time::timeout(deadline, tool.execute(&function.arguments, context)).await {
+            Ok(result) => result.map_err(|source| Error::Tool {
+                name: function.name.clone(),
+                source,
+            }),
+            Err(_) => {
+                log::warn!("Tool {} timed out after {:?}", function.name, deadline);
+                Err(Error::Tool {
+                    name: function.name.clone(),
+                    source: anyhow::anyhow!("timed out after {} seconds", deadline.as_secs()),
+                })
+            }
+        };
+    analytics::record(
+        &function.name,
+        &function.arguments,
+        started.elapsed(),
+        result.is_ok(),
+    );
+    let mut attributes = Map::new();
+    attributes.insert("tool".to_string(), json!(function.name));
+    span.end(result.is_ok(), attributes);
+    result
+}
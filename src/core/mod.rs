@@ -0,0 +1,13 @@
+mod agent;
+mod chat;
+pub mod prompt;
+pub mod session;
+
+pub use crate::tools::{Tool, ToolContext, ToolExample, ToolRegistry};
+pub use agent::{Agent, AgentBuilder, Incoming, Outcome};
+pub use chat::chat_inner;
+#[cfg(feature = "flows")]
+pub(crate) use chat::run_approved;
+
+/// The system prompt conversations start with unless told otherwise.
+pub const DEFAULT_SYSTEM_PROMPT: &str = "Perform function requests for the user";
@@ -0,0 +1,36 @@
+use super::DEFAULT_SYSTEM_PROMPT;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+
+/// The system prompt template, from `system_prompt` or else the file named
+/// by `system_prompt_file`.
+pub fn template_from_env() -> String {
+    if let Ok(template) = env::var("system_prompt") {
+        return template;
+    }
+    if let Ok(path) = env::var("system_prompt_file") {
+        match fs::read_to_string(&path) {
+            Ok(template) => return template,
+            Err(e) => log::warn!("Failed to read system_prompt_file {}: {}", path, e),
+        }
+    }
+    DEFAULT_SYSTEM_PROMPT.to_string()
+}
+
+/// Fills `{today}` and `{name}` for each of `variables` into `template`.
+/// Anything else in braces is left as it is.
+pub fn render(template: &str, variables: &BTreeMap<String, String>) -> String {
+    let mut prompt = template.replace("{today}", &Utc::now().format("%A, %Y-%m-%d").to_string());
+    for (name, value) in variables {
+        prompt = prompt.replace(&format!("{{{name}}}"), value);
+    }
+    prompt
+}
+
+/// Whether `template` uses `{name}`, so callers can skip looking up values
+/// it doesn't need.
+pub fn uses(template: &str, name: &str) -> bool {
+    template.contains(&format!("{{{name}}}"))
+}
@@ -0,0 +1,337 @@
+use super::prompt;
+use crate::store::{del, get, set, Expire, ExpireKind};
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::time::Duration;
+
+/// Bumped whenever the stored layout of a session changes.
+const SESSION_VERSION: u32 = 1;
+const DEFAULT_IDLE_MINUTES: u64 = 60;
+
+/// Identifies one conversation: a Slack user talking in a channel, or
+/// everyone talking in a thread.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SessionKey {
+    pub channel: String,
+    pub user: String,
+    /// The timestamp of the thread's first message.
+    pub thread: Option<String>,
+}
+
+impl SessionKey {
+    pub fn new(channel: &str, user: &str) -> Self {
+        Self {
+            channel: channel.to_string(),
+            user: user.to_string(),
+            thread: None,
+        }
+    }
+
+    pub fn in_thread(mut self, thread: Option<String>) -> Self {
+        self.thread = thread;
+        self
+    }
+
+    fn store_key(&self) -> String {
+        match &self.thread {
+            Some(thread) => format!("session:{}:thread:{}", self.channel, thread),
+            None => format!("session:{}:{}", self.channel, self.user),
+        }
+    }
+
+    fn state_key(&self) -> String {
+        format!("{}:state", self.store_key())
+    }
+}
+
+/// Whether the bot is chatting in a conversation, so that messages there
+/// are answered without the trigger word. Kept per conversation, so one
+/// ending leaves the others be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    /// Only messages with the trigger word are answered.
+    #[default]
+    Idle,
+    /// Every message is answered, until `clear`, `!reset`, or
+    /// `session_idle_minutes` without one.
+    Chatting,
+}
+
+/// When a chat outside a thread ends, from `chat_end`. Either way it also
+/// ends with `clear`, `!reset`, or `session_idle_minutes` without a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChatEnd {
+    /// Once a tool runs.
+    Tool,
+    /// When the user says goodbye, as [`Farewell`](crate::farewell::Farewell)
+    /// tells.
+    #[default]
+    Bye,
+}
+
+impl std::str::FromStr for ChatEnd {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "tool" => Ok(ChatEnd::Tool),
+            "bye" => Ok(ChatEnd::Bye),
+            other => Err(format!("unknown chat end: {other}")),
+        }
+    }
+}
+
+impl SessionState {
+    /// The state of `key`'s conversation. Anything unreadable counts as
+    /// idle.
+    pub fn load(key: &SessionKey) -> Self {
+        get(&key.state_key())
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default()
+    }
+
+    /// Puts `key`'s conversation in this state. Chatting lapses after
+    /// `session_idle_minutes`, so it's saved again with every message.
+    pub fn save(self, key: &SessionKey) {
+        match self {
+            SessionState::Idle => {
+                del(&key.state_key());
+            }
+            SessionState::Chatting => set(
+                &key.state_key(),
+                json!(self),
+                idle_expiry_from_env().map(|expiry| Expire {
+                    kind: ExpireKind::Ex,
+                    value: expiry.as_secs() as i64,
+                }),
+            ),
+        }
+    }
+}
+
+/// The message history of one conversation.
+pub struct Session {
+    pub key: SessionKey,
+    pub messages: Vec<ChatCompletionRequestMessage>,
+    /// Unix timestamp of the last save, `None` for a session never saved.
+    pub updated_at: Option<i64>,
+}
+
+impl Session {
+    /// Adds `question` and `answer` as if the model had just answered it.
+    pub fn push_exchange(&mut self, question: &str, answer: &str) {
+        self.messages.push(
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(question)
+                .build()
+                .expect("Failed to build user message")
+                .into(),
+        );
+        self.messages.push(
+            ChatCompletionRequestAssistantMessageArgs::default()
+                .content(answer)
+                .build()
+                .expect("Failed to build assistant message")
+                .into(),
+        );
+    }
+}
+
+/// The form a session takes in the [store](crate::store).
+#[derive(Serialize, Deserialize)]
+struct StoredSession {
+    version: u32,
+    updated_at: i64,
+    messages: Value,
+}
+
+/// Loads and saves sessions in the [store](crate::store), seeding new
+/// ones with the system prompt.
+pub struct SessionManager {
+    /// A template rendered when a session starts, see [`prompt::render`].
+    system_prompt: String,
+    /// Values for the template beyond `{channel}` and `{user}`, which come
+    /// from the session's key.
+    variables: BTreeMap<String, String>,
+    /// How long a session may sit unused before it's started afresh.
+    idle_expiry: Option<Duration>,
+}
+
+impl SessionManager {
+    pub fn new(system_prompt: impl Into<String>) -> Self {
+        Self {
+            system_prompt: system_prompt.into(),
+            variables: BTreeMap::new(),
+            idle_expiry: None,
+        }
+    }
+
+    /// Fills `{name}` in the system prompt with `value`.
+    pub fn with_variable(mut self, name: &str, value: &str) -> Self {
+        self.variables.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// The system prompt, before it's rendered.
+    pub fn system_prompt(&self) -> &str {
+        &self.system_prompt
+    }
+
+    /// Starts sessions unused for longer than `idle_expiry` afresh, so stale
+    /// context doesn't carry over into unrelated questions.
+    pub fn with_idle_expiry(mut self, idle_expiry: Option<Duration>) -> Self {
+        self.idle_expiry = idle_expiry;
+        self
+    }
+
+    /// Returns the stored session for `key`, or a fresh one if there is none
+    /// or the stored one can't be read back.
+    pub fn load(&self, key: SessionKey) -> Session {
+        let stored = get(&key.store_key()).and_then(|v| match decode_session(v) {
+            Ok(stored) => Some(stored),
+            Err(e) => {
+                log::warn!("Discarding unreadable session {:?}: {}", key, e);
+                None
+            }
+        });
+
+        let now = Utc::now().timestamp();
+        let expired = |updated_at: Option<i64>| match (self.idle_expiry, updated_at) {
+            (Some(expiry), Some(updated_at)) => now - updated_at > expiry.as_secs() as i64,
+            _ => false,
+        };
+        match stored {
+            Some((_, updated_at)) if expired(updated_at) => {
+                log::info!("Starting {:?} afresh after it sat idle", key);
+                Session {
+                    messages: self.initial_messages(&key),
+                    key,
+                    updated_at: None,
+                }
+            }
+            Some((messages, updated_at)) if !messages.is_empty() => Session {
+                key,
+                messages,
+                updated_at,
+            },
+            _ => Session {
+                messages: self.initial_messages(&key),
+                key,
+                updated_at: None,
+            },
+        }
+    }
+
+    /// Writes the session back to the store, stamping it with the current time.
+    pub fn save(&self, session: &mut Session) {
+        let now = Utc::now().timestamp();
+        let messages = match serde_json::to_value(&session.messages) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("Failed to serialize session {:?}: {}", session.key, e);
+                return;
+            }
+        };
+        let stored = StoredSession {
+            version: SESSION_VERSION,
+            updated_at: now,
+            messages,
+        };
+
+        match serde_json::to_value(stored) {
+            Ok(v) => {
+                set(&session.key.store_key(), v, None);
+                session.updated_at = Some(now);
+            }
+            Err(e) => log::error!("Failed to serialize session {:?}: {}", session.key, e),
+        }
+    }
+
+    /// Whether a session has been saved for `key`.
+    pub fn exists(&self, key: &SessionKey) -> bool {
+        get(&key.store_key()).is_some()
+    }
+
+    /// Forgets `key`'s conversation, which also ends the chat.
+    pub fn reset(&self, key: &SessionKey) {
+        del(&key.store_key());
+        SessionState::Idle.save(key);
+    }
+
+    fn initial_messages(&self, key: &SessionKey) -> Vec<ChatCompletionRequestMessage> {
+        let mut variables = BTreeMap::from([
+            ("channel".to_string(), key.channel.clone()),
+            ("user".to_string(), key.user.clone()),
+            // Left empty rather than guessed when the platform can't name
+            // the user.
+            ("user_name".to_string(), String::new()),
+        ]);
+        variables.extend(self.variables.clone());
+        vec![ChatCompletionRequestSystemMessageArgs::default()
+            .content(prompt::render(&self.system_prompt, &variables))
+            .build()
+            .expect("Failed to build system message")
+            .into()]
+    }
+}
+
+/// Reads `session_idle_minutes`, where `0` keeps sessions however long
+/// they sit unused.
+pub fn idle_expiry_from_env() -> Option<Duration> {
+    let minutes = env::var("session_idle_minutes")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_IDLE_MINUTES);
+    (minutes > 0).then(|| Duration::from_secs(minutes * 60))
+}
+
+/// Decodes a stored session into its messages and last update time.
+///
+/// Sessions saved before versioning was introduced are a bare message list.
+fn decode_session(
+    value: Value,
+) -> serde_json::Result<(Vec<ChatCompletionRequestMessage>, Option<i64>)> {
+    if value.is_array() {
+        return Ok((decode_messages(value)?, None));
+    }
+
+    let stored: StoredSession = serde_json::from_value(value)?;
+    if stored.version != SESSION_VERSION {
+        return Err(serde::de::Error::custom(format!(
+            "unsupported session version {}",
+            stored.version
+        )));
+    }
+    Ok((decode_messages(stored.messages)?, Some(stored.updated_at)))
+}
+
+/// Decodes a stored message list.
+///
+/// `ChatCompletionRequestMessage` is an untagged enum whose variants overlap,
+/// so each message is dispatched on its `role` rather than left to serde.
+pub fn decode_messages(value: Value) -> serde_json::Result<Vec<ChatCompletionRequestMessage>> {
+    let items: Vec<Value> = serde_json::from_value(value)?;
+    items.into_iter().map(decode_message).collect()
+}
+
+fn decode_message(value: Value) -> serde_json::Result<ChatCompletionRequestMessage> {
+    use ChatCompletionRequestMessage::*;
+
+    let message = match value.get("role").and_then(Value::as_str) {
+        Some("system") => System(serde_json::from_value(value)?),
+        Some("user") => User(serde_json::from_value(value)?),
+        Some("assistant") => Assistant(serde_json::from_value(value)?),
+        Some("tool") => Tool(serde_json::from_value(value)?),
+        Some("function") => Function(serde_json::from_value(value)?),
+        _ => return Err(serde::de::Error::custom("message without a known role")),
+    };
+    Ok(message)
+}
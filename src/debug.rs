@@ -0,0 +1,120 @@
+use crate::secrets;
+use log::Level;
+use std::env;
+use std::str::FromStr;
+
+/// Where diagnostic output goes: traces of completions, trimmed histories,
+/// refused messages and failing tools.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DebugSink {
+    Off,
+    /// The flow's log.
+    #[default]
+    Log,
+    /// The log, and a Slack channel too. Whatever is posted there can be
+    /// read by everyone in it, including what users asked.
+    Slack {
+        workspace: String,
+        channel: String,
+    },
+}
+
+impl FromStr for DebugSink {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(target) = s.strip_prefix("slack:") {
+            return match target.split_once('/') {
+                Some((workspace, channel)) if !workspace.is_empty() && !channel.is_empty() => {
+                    Ok(DebugSink::Slack {
+                        workspace: workspace.trim().to_string(),
+                        channel: channel.trim().to_string(),
+                    })
+                }
+                _ => Err(format!("expected slack:workspace/channel, got {s}")),
+            };
+        }
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(DebugSink::Off),
+            "log" => Ok(DebugSink::Log),
+            other => Err(format!("unknown debug sink: {other}")),
+        }
+    }
+}
+
+impl DebugSink {
+    /// Reads `debug_sink`, logging when it's unset or invalid.
+    pub fn from_env() -> Self {
+        match env::var("debug_sink") {
+            Ok(value) => value.parse().unwrap_or_else(|e| {
+                log::warn!("Ignoring debug_sink: {}", e);
+                DebugSink::default()
+            }),
+            Err(_) => DebugSink::default(),
+        }
+    }
+
+    /// Sends `text`, with any credentials in it masked.
+    pub async fn emit(&self, level: Level, text: &str) {
+        if *self == DebugSink::Off {
+            return;
+        }
+        let text = secrets::scrub(text);
+        match self {
+            DebugSink::Off => {}
+            DebugSink::Log => log::log!(level, "{}", text),
+            DebugSink::Slack { workspace, channel } => {
+                log::log!(level, "{}", text);
+                post_to_slack(workspace, channel, &text).await;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "flows")]
+async fn post_to_slack(workspace: &str, channel: &str, text: &str) {
+    slack_flows::send_message_to_channel(workspace, channel, format!("```{text}```")).await;
+}
+
+/// Outside a flow, the log is all there is.
+#[cfg(not(feature = "flows"))]
+async fn post_to_slack(_workspace: &str, _channel: &str, _text: &str) {}
+
+/// Sends `text` to the sink configured in env.
+pub async fn emit(level: Level, text: &str) {
+    DebugSink::from_env().emit(level, text).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sinks_parse_in_any_case() {
+        assert_eq!("off".parse(), Ok(DebugSink::Off));
+        assert_eq!(" LOG ".parse(), Ok(DebugSink::Log));
+    }
+
+    #[test]
+    fn slack_sinks_name_a_workspace_and_channel() {
+        assert_eq!(
+            "slack:acme/bot-debug".parse(),
+            Ok(DebugSink::Slack {
+                workspace: "acme".to_string(),
+                channel: "bot-debug".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn incomplete_or_unknown_sinks_are_refused() {
+        assert!("slack:acme".parse::<DebugSink>().is_err());
+        assert!("slack:/bot-debug".parse::<DebugSink>().is_err());
+        assert!("slack:acme/".parse::<DebugSink>().is_err());
+        assert_eq!(
+            "file".parse::<DebugSink>(),
+            Err("unknown debug sink: file".to_string())
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use async_openai::error::OpenAIError;
+
+/// Everything that can go wrong while answering a message.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("OpenAI request failed: {0}")]
+    OpenAI(#[from] OpenAIError),
+
+    #[error("tool `{name}` failed: {source}")]
+    Tool {
+        name: String,
+        #[source]
+        source: anyhow::Error,
+    },
+
+    #[error("the model returned no answer")]
+    EmptyResponse,
+
+    #[error("the answer didn't match the response format: {0}")]
+    InvalidResponse(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// A short explanation suitable for posting back to the user.
+    pub fn user_message(&self) -> String {
+        match self {
+            Error::OpenAI(OpenAIError::ApiError(e)) => {
+                format!("Sorry, OpenAI rejected the request: {}", e.message)
+            }
+            Error::OpenAI(_) => {
+                "Sorry, I couldn't reach OpenAI right now. Please try again later.".to_string()
+            }
+            Error::Tool { name, .. } => {
+                format!("Sorry, the `{name}` tool failed while working on your request.")
+            }
+            Error::EmptyResponse => {
+                "Sorry, I didn't get an answer back. Please try again.".to_string()
+            }
+            Error::InvalidResponse(_) => {
+                "Sorry, I couldn't put my answer in the required format. Please try again."
+                    .to_string()
+            }
+        }
+    }
+}
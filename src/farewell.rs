@@ -0,0 +1,132 @@
+//! Telling when the user is done: a short message with a phrase such as
+//! "bye" or "thanks, that's all" ends the conversation, and with
+//! `farewell_check` set to `model`, the chat model is asked about other
+//! short messages, such as a lone "thanks".
+
+use crate::client::ChatClient;
+use async_openai::types::{
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+    CreateChatCompletionRequestArgs,
+};
+use std::env;
+
+/// Phrases that end a conversation, besides those in `farewell_phrases`.
+const PHRASES: &[&str] = &[
+    "bye",
+    "bye bye",
+    "goodbye",
+    "good bye",
+    "see ya",
+    "cya",
+    "that's all",
+    "thats all",
+    "that is all",
+    "that's it",
+    "nothing else",
+    "i'm done",
+    "im done",
+    "we're done",
+    "all done",
+    "end chat",
+];
+/// Longer messages go on to ask something, even when they start with a
+/// goodbye.
+const MAX_WORDS: usize = 8;
+
+const MODEL_PROMPT: &str = "You decide whether a chat message ends the conversation. \
+     Answer yes if the user is saying goodbye or has nothing more to ask, \
+     and no if they're asking or telling the assistant something.";
+
+/// How goodbyes are recognised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Farewell {
+    phrases: Vec<String>,
+    /// Whether the model is asked about short messages no phrase matches.
+    model_check: bool,
+}
+
+impl Farewell {
+    /// Reads the extra comma-separated `farewell_phrases`, and
+    /// `farewell_check` (`phrases` or `model`).
+    pub fn from_env() -> Self {
+        let extra = env::var("farewell_phrases").unwrap_or_default();
+        Farewell {
+            phrases: PHRASES
+                .iter()
+                .copied()
+                .chain(extra.split(','))
+                .map(normalize)
+                .filter(|phrase| !phrase.is_empty())
+                .collect(),
+            model_check: env::var("farewell_check").as_deref() == Ok("model"),
+        }
+    }
+
+    /// Whether `text` ends the conversation. Questions never do. The model
+    /// is only asked when no phrase matches, with `model`, and counts as a
+    /// no when it can't be reached.
+    pub async fn is_goodbye(&self, client: &ChatClient, model: &str, text: &str) -> bool {
+        if text.contains('?') {
+            return false;
+        }
+        let text = normalize(text);
+        let words = text.split(' ').filter(|word| !word.is_empty()).count();
+        if words == 0 || words > MAX_WORDS {
+            return false;
+        }
+        let padded = format!(" {text} ");
+        if self
+            .phrases
+            .iter()
+            .any(|phrase| padded.contains(&format!(" {phrase} ")))
+        {
+            return true;
+        }
+        if !self.model_check {
+            return false;
+        }
+        match ask_model(client, model, &text).await {
+            Ok(answer) => answer,
+            Err(e) => {
+                log::warn!("Failed to ask whether a message is a goodbye: {}", e);
+                false
+            }
+        }
+    }
+}
+
+async fn ask_model(client: &ChatClient, model: &str, text: &str) -> anyhow::Result<bool> {
+    let request = CreateChatCompletionRequestArgs::default()
+        .model(model)
+        .max_tokens(2u16)
+        .temperature(0.0)
+        .messages(vec![
+            ChatCompletionRequestSystemMessageArgs::default()
+                .content(MODEL_PROMPT)
+                .build()?
+                .into(),
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(text)
+                .build()?
+                .into(),
+        ])
+        .build()?;
+    let chat = client.create_chat(request).await?;
+    let answer = chat
+        .choices
+        .first()
+        .and_then(|c| c.message.content.clone())
+        .unwrap_or_default();
+    Ok(answer.trim().to_lowercase().starts_with("yes"))
+}
+
+/// Lowercases `text` and reduces it to words separated by single spaces,
+/// keeping apostrophes so "that's" stays one word.
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .replace('\u{2019}', "'")
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
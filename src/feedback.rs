@@ -0,0 +1,126 @@
+//! What users think of the bot's answers: the turns answers were posted
+//! for, so a reaction on one can be traced back to what was asked, and the
+//! ratings given, kept per channel for later analysis.
+
+use crate::store::{get, set, Expire, ExpireKind};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+/// How long an answer can still be rated or regenerated.
+const TURN_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+/// How many ratings are kept per channel.
+const MAX_RATINGS: usize = 500;
+
+/// A question and the answer posted for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Turn {
+    pub workspace: String,
+    pub channel: String,
+    pub user: String,
+    /// The thread the conversation is held in.
+    pub thread: Option<String>,
+    pub question: String,
+    pub answer: String,
+    pub model: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+/// A rating of one answer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Feedback {
+    pub rating: Rating,
+    /// Who rated it.
+    pub user: String,
+    pub question: String,
+    pub answer: String,
+    pub model: String,
+    /// Unix seconds.
+    pub at: i64,
+}
+
+fn turn_key(channel_id: &str, ts: &str) -> String {
+    format!("turn:{channel_id}:{ts}")
+}
+
+fn ratings_key(channel: &str) -> String {
+    format!("feedback:{channel}")
+}
+
+/// Remembers that `turn`'s answer was posted as the message `ts` in the
+/// channel with ID `channel_id`.
+pub fn remember(channel_id: &str, ts: &str, turn: &Turn) {
+    match serde_json::to_value(turn) {
+        Ok(value) => set(
+            &turn_key(channel_id, ts),
+            value,
+            Some(Expire {
+                kind: ExpireKind::Ex,
+                value: TURN_TTL_SECS,
+            }),
+        ),
+        Err(e) => log::warn!("Failed to save the turn for {}: {}", ts, e),
+    }
+}
+
+/// The turn whose answer is the message `ts`, if it's recent enough.
+pub fn turn(channel_id: &str, ts: &str) -> Option<Turn> {
+    get(&turn_key(channel_id, ts)).and_then(|v| serde_json::from_value(v).ok())
+}
+
+/// The ratings given in `channel`, oldest first.
+pub fn ratings(channel: &str) -> Vec<Feedback> {
+    get(&ratings_key(channel))
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+/// Adds `user`'s rating of `turn`'s answer, dropping the oldest once a
+/// channel has `MAX_RATINGS`.
+pub fn record(turn: &Turn, rating: Rating, user: &str) {
+    let mut ratings = ratings(&turn.channel);
+    ratings.push(Feedback {
+        rating,
+        user: user.to_string(),
+        question: turn.question.clone(),
+        answer: turn.answer.clone(),
+        model: turn.model.clone(),
+        at: Utc::now().timestamp(),
+    });
+    if ratings.len() > MAX_RATINGS {
+        let excess = ratings.len() - MAX_RATINGS;
+        ratings.drain(..excess);
+    }
+    match serde_json::to_value(&ratings) {
+        Ok(value) => set(&ratings_key(&turn.channel), value, None),
+        Err(e) => log::warn!("Failed to save feedback in {}: {}", turn.channel, e),
+    }
+}
+
+/// How answers in `channel` have been rated, with the latest questions
+/// whose answers got a thumbs down.
+pub fn summary(channel: &str) -> String {
+    let ratings = ratings(channel);
+    if ratings.is_empty() {
+        return format!("No answers in {channel} have been rated yet.");
+    }
+    let up = ratings.iter().filter(|f| f.rating == Rating::Up).count();
+    let down = ratings.len() - up;
+    let mut summary = format!("Answers in {channel}: {up} :+1:, {down} :-1:");
+    let disliked: Vec<String> = ratings
+        .iter()
+        .rev()
+        .filter(|f| f.rating == Rating::Down)
+        .take(5)
+        .map(|f| format!("• {}", f.question.lines().next().unwrap_or_default()))
+        .collect();
+    if !disliked.is_empty() {
+        summary.push_str(&format!("\nLatest disliked:\n{}", disliked.join("\n")));
+    }
+    summary
+}
@@ -0,0 +1,52 @@
+//! Tool calls held until the user supplies an argument the model couldn't,
+//! such as which of several cities they meant. The question is asked in the
+//! bot's answer, and the user's next message completes the call.
+
+use crate::core::session::SessionKey;
+use crate::store::{del, get, set, Expire, ExpireKind};
+use serde::{Deserialize, Serialize};
+
+/// How long a follow-up question stays open.
+const PENDING_TTL_SECS: i64 = 15 * 60;
+
+/// A tool call waiting for the user to answer a question.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingCall {
+    pub tool: String,
+    /// The arguments given so far, as a JSON object.
+    pub arguments: String,
+    /// The parameter the answer fills in.
+    pub param: String,
+    pub question: String,
+}
+
+fn store_key(key: &SessionKey) -> String {
+    match &key.thread {
+        Some(thread) => format!("follow_up:{}:{}:{}", key.channel, key.user, thread),
+        None => format!("follow_up:{}:{}", key.channel, key.user),
+    }
+}
+
+/// Remembers `call` until the user answers or the question expires,
+/// replacing any earlier one.
+pub fn ask(key: &SessionKey, call: &PendingCall) {
+    match serde_json::to_value(call) {
+        Ok(value) => set(
+            &store_key(key),
+            value,
+            Some(Expire {
+                kind: ExpireKind::Ex,
+                value: PENDING_TTL_SECS,
+            }),
+        ),
+        Err(e) => log::warn!("Failed to save pending {} call: {}", call.tool, e),
+    }
+}
+
+pub fn pending(key: &SessionKey) -> Option<PendingCall> {
+    get(&store_key(key)).and_then(|v| serde_json::from_value(v).ok())
+}
+
+pub fn clear(key: &SessionKey) {
+    del(&store_key(key));
+}
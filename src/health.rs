@@ -0,0 +1,99 @@
+//! Self-tests of the services the bot depends on, for checking a deploy.
+
+use crate::client::ChatClient;
+use crate::store::{get, set};
+use crate::tools::check_weather;
+use async_openai::types::{ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs};
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::time::Instant;
+
+const STORE_CHECK_KEY: &str = "health_check";
+
+/// The outcome of one check: what was found, or what went wrong.
+pub struct Check {
+    pub name: &'static str,
+    pub result: Result<String, String>,
+}
+
+/// Asks `model` for a one-token completion, and checks the weather provider
+/// and that the store reads back what was written.
+pub async fn run(client: &ChatClient, model: &str) -> Vec<Check> {
+    vec![
+        Check {
+            name: "OpenAI",
+            result: check_openai(client, model).await,
+        },
+        Check {
+            name: "Weather API",
+            result: check_weather().await.map_err(|e| e.to_string()),
+        },
+        Check {
+            name: "Store",
+            result: check_store(),
+        },
+    ]
+}
+
+/// A line per check, for posting to the channel.
+pub fn report(checks: &[Check]) -> String {
+    let lines: Vec<String> = checks
+        .iter()
+        .map(|check| match &check.result {
+            Ok(detail) => format!("• {}: ok, {detail}", check.name),
+            Err(e) => format!("• {}: failed, {e}", check.name),
+        })
+        .collect();
+    format!("Health check:\n{}", lines.join("\n"))
+}
+
+/// The checks as `{"ok": ..., "checks": {name: {"ok": ..., "detail": ...}}}`.
+pub fn to_json(checks: &[Check]) -> Value {
+    let results: serde_json::Map<String, Value> = checks
+        .iter()
+        .map(|check| {
+            let result = match &check.result {
+                Ok(detail) => json!({ "ok": true, "detail": detail }),
+                Err(e) => json!({ "ok": false, "detail": e }),
+            };
+            (check.name.to_string(), result)
+        })
+        .collect();
+    json!({
+        "ok": checks.iter().all(|check| check.result.is_ok()),
+        "checks": results,
+    })
+}
+
+async fn check_openai(client: &ChatClient, model: &str) -> Result<String, String> {
+    let started = Instant::now();
+    let request = CreateChatCompletionRequestArgs::default()
+        .model(model)
+        .max_tokens(1u16)
+        .messages([ChatCompletionRequestUserMessageArgs::default()
+            .content("ping")
+            .build()
+            .map_err(|e| e.to_string())?
+            .into()])
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = client
+        .create_chat(request)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(format!(
+        "{} answered in {} ms",
+        response.model,
+        started.elapsed().as_millis()
+    ))
+}
+
+fn check_store() -> Result<String, String> {
+    let stamp = Utc::now().timestamp_millis();
+    set(STORE_CHECK_KEY, json!(stamp), None);
+    match get(STORE_CHECK_KEY).and_then(|v| v.as_i64()) {
+        Some(read) if read == stamp => Ok("read back what was written".to_string()),
+        Some(_) => Err("read back a different value".to_string()),
+        None => Err("couldn't read back what was written".to_string()),
+    }
+}
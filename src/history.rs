@@ -0,0 +1,398 @@
+use crate::client::ChatClient;
+use async_openai::{
+    error::OpenAIError,
+    types::{
+        ChatCompletionRequestMessage, ChatCompletionRequestMessageContentPart,
+        ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+        ChatCompletionRequestUserMessageContent, ChatCompletionTool,
+        CreateChatCompletionRequestArgs,
+    },
+};
+
+/// Fixed cost the API adds around every message.
+const TOKENS_PER_MESSAGE: usize = 4;
+/// What a 1024px image costs a vision model at high detail.
+const TOKENS_PER_IMAGE: usize = 765;
+
+/// Roughly counts the tokens in `text`.
+///
+/// English BPE averages about four ASCII characters per token, while most
+/// non-ASCII characters (CJK in particular) come out as a token or more each.
+pub fn estimate_tokens(text: &str) -> usize {
+    let (ascii, other) = text.chars().fold((0usize, 0usize), |(ascii, other), c| {
+        if c.is_ascii() {
+            (ascii + 1, other)
+        } else {
+            (ascii, other + 1)
+        }
+    });
+    ascii.div_ceil(4) + other
+}
+
+/// Images are counted at a flat rate, as their base64 data says nothing
+/// about what they cost.
+pub fn message_tokens(message: &ChatCompletionRequestMessage) -> usize {
+    if let ChatCompletionRequestMessage::User(m) = message {
+        if let Some(ChatCompletionRequestUserMessageContent::Array(parts)) = &m.content {
+            return parts
+                .iter()
+                .map(|part| match part {
+                    ChatCompletionRequestMessageContentPart::Text(t) => estimate_tokens(&t.text),
+                    ChatCompletionRequestMessageContentPart::Image(_) => TOKENS_PER_IMAGE,
+                })
+                .sum::<usize>()
+                + TOKENS_PER_MESSAGE;
+        }
+    }
+    let text = serde_json::to_string(message).unwrap_or_default();
+    estimate_tokens(&text) + TOKENS_PER_MESSAGE
+}
+
+/// Replaces the images in user messages with a note that there was one, so
+/// the history stays small and readable by models without vision.
+pub fn strip_images(messages: &mut [ChatCompletionRequestMessage]) {
+    for message in messages {
+        let ChatCompletionRequestMessage::User(m) = message else {
+            continue;
+        };
+        let Some(ChatCompletionRequestUserMessageContent::Array(parts)) = &m.content else {
+            continue;
+        };
+        let text = parts
+            .iter()
+            .map(|part| match part {
+                ChatCompletionRequestMessageContentPart::Text(t) => t.text.clone(),
+                ChatCompletionRequestMessageContentPart::Image(_) => "[image]".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        m.content = Some(ChatCompletionRequestUserMessageContent::Text(text));
+    }
+}
+
+pub fn messages_tokens(messages: &[ChatCompletionRequestMessage]) -> usize {
+    messages.iter().map(message_tokens).sum()
+}
+
+pub fn tools_tokens(tools: &[ChatCompletionTool]) -> usize {
+    let text = serde_json::to_string(tools).unwrap_or_default();
+    estimate_tokens(&text)
+}
+
+/// The context window of `model`, falling back to the smallest current one.
+pub fn context_window(model: &str) -> usize {
+    match model {
+        m if m.starts_with("gpt-4o") || m.starts_with("gpt-4-turbo") => 128_000,
+        m if m.starts_with("gpt-4-1106") || m.starts_with("gpt-4-0125") => 128_000,
+        m if m.starts_with("gpt-4-32k") => 32_768,
+        m if m.starts_with("gpt-4") => 8_192,
+        m if m.starts_with("gpt-3.5-turbo-16k") => 16_385,
+        "gpt-3.5-turbo-0613" | "gpt-3.5-turbo-0301" => 4_096,
+        m if m.starts_with("gpt-3.5-turbo") => 16_385,
+        _ => 4_096,
+    }
+}
+
+/// Drops the oldest turns until `messages` fits in `budget` tokens.
+///
+/// Leading system messages are always kept, and messages are removed a whole
+/// turn at a time (up to the next user message) so an assistant tool call is
+/// never separated from its results. The removed messages are returned in
+/// their original order.
+pub fn trim_to_budget(
+    messages: &mut Vec<ChatCompletionRequestMessage>,
+    budget: usize,
+) -> Vec<ChatCompletionRequestMessage> {
+    let start = messages
+        .iter()
+        .take_while(|m| matches!(m, ChatCompletionRequestMessage::System(_)))
+        .count();
+
+    let mut total = messages_tokens(messages);
+    let mut end = start;
+    while total > budget && end < messages.len() {
+        // Never drop the turn in progress: it holds the question being answered.
+        let next_turn = match messages[end + 1..]
+            .iter()
+            .position(|m| matches!(m, ChatCompletionRequestMessage::User(_)))
+        {
+            Some(i) => end + 1 + i,
+            None => break,
+        };
+        total -= messages_tokens(&messages[end..next_turn]);
+        end = next_turn;
+    }
+
+    messages.drain(start..end).collect()
+}
+
+/// Takes back the last turn when it ended with `answer`, so the question can
+/// be answered afresh. Returns whether it did.
+pub fn take_back(messages: &mut Vec<ChatCompletionRequestMessage>, answer: &str) -> bool {
+    let ends_with_answer = matches!(
+        messages.last(),
+        Some(ChatCompletionRequestMessage::Assistant(m)) if m.content.as_deref() == Some(answer)
+    );
+    let question = messages
+        .iter()
+        .rposition(|m| matches!(m, ChatCompletionRequestMessage::User(_)));
+    match question {
+        Some(question) if ends_with_answer => {
+            messages.truncate(question);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Prefix of the system note that stands in for summarized turns.
+pub const SUMMARY_PREFIX: &str = "Conversation so far: ";
+
+/// Longest stretch of a single message fed to the summarizer.
+const SUMMARY_INPUT_CHARS: usize = 1_000;
+
+/// What happens to turns that no longer fit in the context window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compaction {
+    /// Drop them.
+    #[default]
+    Truncate,
+    /// Fold them into a running summary kept as a system note.
+    Summarize,
+}
+
+impl std::str::FromStr for Compaction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "truncate" => Ok(Compaction::Truncate),
+            "summarize" => Ok(Compaction::Summarize),
+            other => Err(format!("unknown compaction mode: {other}")),
+        }
+    }
+}
+
+/// Replaces `dropped` turns with a summary note placed after the leading
+/// system messages, merging in any summary written by an earlier compaction.
+pub async fn summarize_dropped(
+    client: &ChatClient,
+    model: &str,
+    messages: &mut Vec<ChatCompletionRequestMessage>,
+    dropped: &[ChatCompletionRequestMessage],
+) -> Result<(), OpenAIError> {
+    let previous = messages.iter().position(is_summary);
+    let mut transcript = String::new();
+    if let Some(i) = previous {
+        transcript.push_str(&message_text(&messages[i]));
+        transcript.push('\n');
+    }
+    for message in dropped {
+        let text = message_text(message);
+        if !text.is_empty() {
+            transcript.push_str(&text);
+            transcript.push('\n');
+        }
+    }
+
+    let request = CreateChatCompletionRequestArgs::default()
+        .max_tokens(256u16)
+        .model(model)
+        .messages(vec![
+            ChatCompletionRequestSystemMessageArgs::default()
+                .content(
+                    "Summarize the following conversation in a few sentences. \
+                     Keep names, places, numbers and any open questions.",
+                )
+                .build()?
+                .into(),
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(transcript)
+                .build()?
+                .into(),
+        ])
+        .build()?;
+    let chat = client.create_chat(request).await?;
+    let summary = match chat.choices.first().and_then(|c| c.message.content.clone()) {
+        Some(summary) => summary,
+        None => return Ok(()),
+    };
+
+    let note = ChatCompletionRequestSystemMessageArgs::default()
+        .content(format!("{SUMMARY_PREFIX}{summary}"))
+        .build()?
+        .into();
+    match previous {
+        Some(i) => messages[i] = note,
+        None => {
+            let at = messages
+                .iter()
+                .take_while(|m| matches!(m, ChatCompletionRequestMessage::System(_)))
+                .count();
+            messages.insert(at, note);
+        }
+    }
+    Ok(())
+}
+
+fn is_summary(message: &ChatCompletionRequestMessage) -> bool {
+    matches!(
+        message,
+        ChatCompletionRequestMessage::System(m)
+            if m.content.as_deref().is_some_and(|c| c.starts_with(SUMMARY_PREFIX))
+    )
+}
+
+/// A one-line `role: text` rendering of a message for the summarizer.
+fn message_text(message: &ChatCompletionRequestMessage) -> String {
+    use ChatCompletionRequestMessage::*;
+
+    let (role, text) = match message {
+        System(m) => ("system", m.content.clone().unwrap_or_default()),
+        User(m) => match &m.content {
+            Some(ChatCompletionRequestUserMessageContent::Text(text)) => ("user", text.clone()),
+            _ => ("user", String::new()),
+        },
+        Assistant(m) => match (&m.content, &m.tool_calls) {
+            (Some(content), _) => ("assistant", content.clone()),
+            (None, Some(calls)) => (
+                "assistant",
+                calls
+                    .iter()
+                    .map(|c| format!("called {}({})", c.function.name, c.function.arguments))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            (None, None) => ("assistant", String::new()),
+        },
+        Tool(m) => ("tool", m.content.clone().unwrap_or_default()),
+        Function(m) => ("function", m.content.clone().unwrap_or_default()),
+    };
+    if text.is_empty() {
+        return String::new();
+    }
+    let text: String = text.chars().take(SUMMARY_INPUT_CHARS).collect();
+    format!("{role}: {text}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::types::{
+        ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs,
+        ChatCompletionRequestToolMessageArgs, ChatCompletionToolType, FunctionCall,
+    };
+
+    fn system(text: &str) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestSystemMessageArgs::default()
+            .content(text)
+            .build()
+            .unwrap()
+            .into()
+    }
+
+    fn user(text: &str) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestUserMessageArgs::default()
+            .content(text)
+            .build()
+            .unwrap()
+            .into()
+    }
+
+    fn assistant(text: &str) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestAssistantMessageArgs::default()
+            .content(text)
+            .build()
+            .unwrap()
+            .into()
+    }
+
+    fn tool_call(id: &str) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestAssistantMessageArgs::default()
+            .tool_calls(vec![ChatCompletionMessageToolCall {
+                id: id.to_string(),
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionCall {
+                    name: "getWeather".to_string(),
+                    arguments: r#"{"city":"Paris"}"#.to_string(),
+                },
+            }])
+            .build()
+            .unwrap()
+            .into()
+    }
+
+    fn tool_result(id: &str) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestToolMessageArgs::default()
+            .content("Sunny, 21°C")
+            .tool_call_id(id)
+            .build()
+            .unwrap()
+            .into()
+    }
+
+    fn conversation() -> Vec<ChatCompletionRequestMessage> {
+        vec![
+            system("You are a helpful assistant."),
+            user("What's the weather in Paris?"),
+            tool_call("call_1"),
+            tool_result("call_1"),
+            assistant("It's sunny and 21°C in Paris."),
+            user("And tomorrow?"),
+            assistant("Much the same."),
+            user("Thanks! What about Berlin?"),
+        ]
+    }
+
+    #[test]
+    fn leaves_messages_that_fit_alone() {
+        let mut messages = conversation();
+        let budget = messages_tokens(&messages);
+        assert!(trim_to_budget(&mut messages, budget).is_empty());
+        assert_eq!(messages.len(), 8);
+    }
+
+    #[test]
+    fn drops_whole_turns_with_their_tool_calls() {
+        let mut messages = conversation();
+        let budget = messages_tokens(&messages) - 1;
+        let dropped = trim_to_budget(&mut messages, budget);
+        // The first turn goes with its tool call and result.
+        assert_eq!(dropped.len(), 4);
+        assert!(matches!(dropped[0], ChatCompletionRequestMessage::User(_)));
+        assert!(matches!(
+            dropped[3],
+            ChatCompletionRequestMessage::Assistant(_)
+        ));
+        assert_eq!(messages.len(), 4);
+        assert!(matches!(
+            messages[0],
+            ChatCompletionRequestMessage::System(_)
+        ));
+        assert!(matches!(messages[1], ChatCompletionRequestMessage::User(_)));
+        assert!(messages_tokens(&messages) <= budget);
+    }
+
+    #[test]
+    fn keeps_the_system_prompt_and_the_turn_in_progress() {
+        let mut messages = conversation();
+        let dropped = trim_to_budget(&mut messages, 0);
+        assert_eq!(dropped.len(), 6);
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(
+            messages[0],
+            ChatCompletionRequestMessage::System(_)
+        ));
+        assert_eq!(
+            message_text(&messages[1]),
+            "user: Thanks! What about Berlin?"
+        );
+    }
+
+    #[test]
+    fn counts_non_ascii_characters_one_each() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(estimate_tokens("天气"), 2);
+    }
+}
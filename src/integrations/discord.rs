@@ -0,0 +1,131 @@
+use crate::chunking;
+use crate::client::ChatClient;
+use crate::commands;
+use crate::config::Config;
+use crate::core::Agent;
+use crate::postprocess::{Markup, PostProcess};
+use crate::status::{ChatPlatform, StatusSink};
+use crate::tools::{ToolContext, SLACK_ONLY_TOOLS};
+use crate::trigger::Trigger;
+use crate::usage;
+use async_trait::async_trait;
+use discord_flows::{get_client, http::Http, listen_to_event, model::Message, Bot};
+use serde_json::json;
+use std::env;
+use std::sync::Arc;
+
+/// Discord rejects messages longer than this many characters.
+const MAX_MESSAGE_CHARS: usize = 2000;
+
+/// A Discord channel, as a place to answer in.
+pub struct DiscordChannel {
+    client: Http,
+    channel_id: u64,
+}
+
+impl DiscordChannel {
+    pub fn new(channel_id: u64) -> Self {
+        Self {
+            client: get_client(bot()),
+            channel_id,
+        }
+    }
+}
+
+#[async_trait]
+impl StatusSink for DiscordChannel {
+    async fn status(&self, text: &str) {
+        self.post(text).await;
+    }
+}
+
+#[async_trait]
+impl ChatPlatform for DiscordChannel {
+    /// Prefixed so Discord channels don't share stored state with Slack
+    /// channels.
+    fn channel_key(&self) -> String {
+        format!("discord:{}", self.channel_id)
+    }
+
+    /// Posts `text`, split into as many messages as Discord needs.
+    async fn post(&self, text: &str) {
+        for content in chunking::split(text, MAX_MESSAGE_CHARS) {
+            if let Err(e) = self
+                .client
+                .send_message(self.channel_id, &json!({ "content": content }))
+                .await
+            {
+                log::warn!(
+                    "Failed to post to Discord channel {}: {}",
+                    self.channel_id,
+                    e
+                );
+                return;
+            }
+        }
+    }
+}
+
+/// The bot given by `DISCORD_TOKEN`, or the one flows.network provides.
+fn bot() -> Bot {
+    env::var("DISCORD_TOKEN").ok().into()
+}
+
+/// Registers the Discord listener, or answers the Discord message being
+/// handled. Runs alongside the Slack listeners in `run`.
+pub async fn run_discord() {
+    listen_to_event(bot(), handler).await;
+}
+
+/// Answers a Discord message the way the Slack handler answers one, without
+/// Slack's threads, files and admin commands. A chat starts with the
+/// trigger and goes on until it's cleared or sits idle.
+async fn handler(msg: Message) {
+    if msg.author.bot {
+        return;
+    }
+    // Without `discord_channel_id`, the bot answers in every channel it's in.
+    if let Ok(channel_id) = env::var("discord_channel_id") {
+        if channel_id.trim() != msg.channel_id.0.to_string() {
+            return;
+        }
+    }
+    let discord = Arc::new(DiscordChannel::new(msg.channel_id.0));
+    let channel = discord.channel_key();
+    let user = format!("discord:{}", msg.author.id.0);
+    let key = discord.session_key(&user);
+
+    let env_config = Config::from_env();
+    let mut disabled_tools = commands::disabled_tools();
+    disabled_tools.extend(SLACK_ONLY_TOOLS.iter().map(|tool| tool.to_string()));
+    let config = Config {
+        model: commands::active_model().unwrap_or(env_config.model.clone()),
+        disabled_tools,
+        ..env_config
+    };
+    let status: Arc<dyn StatusSink> = discord.clone();
+    let agent = Agent::new(config)
+        .in_channel(&channel)
+        .with_prompt_variable("user_name", &msg.author.name)
+        .with_client(ChatClient::from_env().with_status(status.clone()));
+    let context = ToolContext::new("discord", &channel, &user)
+        .in_session(key.clone())
+        .with_status(status);
+    usage::set_scope(&channel, &user);
+
+    let in_chat = agent.sessions().exists(&key);
+    if in_chat && msg.content.trim().eq_ignore_ascii_case("clear") {
+        agent.sessions().reset(&key);
+        discord.post("Cleared our conversation. What's next?").await;
+        return;
+    }
+
+    let input = Trigger::from_env()
+        .input(&msg.content)
+        .or_else(|| in_chat.then(|| msg.content.clone()));
+    let Some(out) = agent.answer(&msg.content, input, &context).await else {
+        return;
+    };
+    let answer = PostProcess::from_env().apply(&out, Markup::Markdown);
+    discord.post(&answer.text).await;
+}
@@ -0,0 +1,44 @@
+use crate::tools;
+use dotenv::dotenv;
+use flowsnet_platform_sdk::logger;
+use schedule_flows::schedule_cron_job;
+use std::cell::Cell;
+use std::env;
+use webhook_flows::create_endpoint;
+
+#[cfg(feature = "discord")]
+pub mod discord;
+pub mod slack;
+pub mod webhook;
+
+/// The flow's entry point, which registers every listener or answers the
+/// event being handled. Slack messages, Discord messages and reminder
+/// checks all arrive here; webhook requests go straight to
+/// `webhook::request_received`.
+#[no_mangle]
+#[tokio::main(flavor = "current_thread")]
+async fn run() {
+    logger::init();
+    dotenv().ok();
+    // The schedule connector only accepts a fixed minute, so reminders are
+    // checked hourly by default.
+    let reminder_cron = env::var("reminder_cron").unwrap_or("0 * * * *".to_string());
+
+    // The callback can't await, and it also runs for Slack events, so it only
+    // notes that the flow is handling an event. Due reminders are posted
+    // either way.
+    let woken = Cell::new(false);
+    schedule_cron_job(reminder_cron, "reminders".to_string(), |_| woken.set(true));
+    if woken.get() {
+        tools::post_due_reminders().await;
+    }
+
+    // Discord messages don't read as Slack events, nor the other way round,
+    // so each listener only answers its own.
+    #[cfg(feature = "discord")]
+    discord::run_discord().await;
+    if slack::event::registering() {
+        create_endpoint().await;
+    }
+    slack::listen().await;
+}
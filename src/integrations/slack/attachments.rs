@@ -0,0 +1,105 @@
+use crate::tools::http::{self, Method};
+use anyhow::{anyhow, bail};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use std::env;
+
+/// Files larger than this are not downloaded.
+const MAX_FILE_BYTES: u64 = 20 * 1024 * 1024;
+const MAX_REDIRECTS: usize = 3;
+
+/// A file shared along with a Slack message.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Attachment {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub mimetype: String,
+    /// Slack's short name for the format, such as `png` or `webm`.
+    #[serde(default)]
+    pub filetype: String,
+    /// `slack_audio` for voice clips recorded in Slack.
+    #[serde(default)]
+    pub subtype: String,
+    #[serde(default)]
+    pub size: u64,
+    url_private_download: Option<String>,
+    url_private: Option<String>,
+}
+
+impl Attachment {
+    /// Images in the formats vision models accept.
+    pub fn is_image(&self) -> bool {
+        matches!(
+            self.mimetype.as_str(),
+            "image/png" | "image/jpeg" | "image/gif" | "image/webp"
+        )
+    }
+
+    /// Voice clips and audio files, which are transcribed.
+    pub fn is_audio(&self) -> bool {
+        self.mimetype.starts_with("audio/") || self.subtype == "slack_audio"
+    }
+
+    /// A file name whose extension the transcription API recognizes the
+    /// format by. Clips recorded in Slack are named after their time.
+    pub fn audio_file_name(&self) -> String {
+        match self.filetype.as_str() {
+            "" => self.name.clone(),
+            filetype => format!("audio.{filetype}"),
+        }
+    }
+
+    /// Downloads the file with the bot token in `SLACK_BOT_TOKEN`, which
+    /// needs the `files:read` scope.
+    pub async fn download(&self) -> anyhow::Result<Vec<u8>> {
+        if self.size > MAX_FILE_BYTES {
+            bail!("{} is too large to download", self.name);
+        }
+        let token = env::var("SLACK_BOT_TOKEN")
+            .map_err(|_| anyhow!("SLACK_BOT_TOKEN is needed to read files shared in Slack"))?;
+        let authorization = format!("Bearer {token}");
+        let mut url = self
+            .url_private_download
+            .clone()
+            .or_else(|| self.url_private.clone())
+            .ok_or_else(|| anyhow!("{} has no download url", self.name))?;
+
+        // Redirects are followed here, as the client would drop the token
+        // on the way to Slack's file servers.
+        for _ in 0..=MAX_REDIRECTS {
+            let res =
+                http::send_unfollowed(Method::GET, &url, &[("Authorization", &authorization)])
+                    .await?;
+            if (300..400).contains(&res.status) {
+                if let Some(location) = res.location {
+                    url = location;
+                    continue;
+                }
+            }
+            if !res.is_success() {
+                bail!("Slack returned {} for {}", res.status, self.name);
+            }
+            // Without the right scope Slack answers with its sign-in page.
+            if res.content_type.starts_with("text/html") && !self.mimetype.starts_with("text/html")
+            {
+                bail!(
+                    "Slack didn't allow downloading {}; check the bot token's files:read scope",
+                    self.name
+                );
+            }
+            return Ok(res.body);
+        }
+        bail!("too many redirects downloading {}", self.name)
+    }
+
+    /// The file as a `data:` URL, the form the chat API takes images in.
+    pub async fn data_url(&self) -> anyhow::Result<String> {
+        let bytes = self.download().await?;
+        Ok(format!(
+            "data:{};base64,{}",
+            self.mimetype,
+            STANDARD.encode(bytes)
+        ))
+    }
+}
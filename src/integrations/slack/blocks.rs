@@ -0,0 +1,207 @@
+//! Renders answers as Block Kit blocks, so that lists of readings such as
+//! a weather report show as a grid of fields and the sources of a summary
+//! sit in a context block under it, with buttons to open them.
+
+use serde_json::{json, Value};
+
+/// Slack's limits on a message's blocks.
+const MAX_BLOCKS: usize = 50;
+const MAX_SECTION_CHARS: usize = 3000;
+const MAX_FIELDS: usize = 10;
+const MAX_FIELD_CHARS: usize = 2000;
+const MAX_BUTTON_LABEL_CHARS: usize = 75;
+/// How many source links get a button.
+const MAX_SOURCE_BUTTONS: usize = 5;
+/// Labels longer than this are taken to be prose rather than a field name.
+const MAX_LABEL_CHARS: usize = 30;
+
+/// The blocks showing `answer`, in mrkdwn.
+pub fn render(answer: &str) -> Vec<Value> {
+    let mut blocks = Vec::new();
+    let mut sources = Vec::new();
+    for paragraph in paragraphs(answer) {
+        if paragraph.iter().all(|line| is_source(line)) {
+            sources.extend(paragraph.iter().flat_map(|line| urls(line)));
+            blocks.push(json!({
+                "type": "context",
+                "elements": paragraph
+                    .iter()
+                    .map(|line| json!({ "type": "mrkdwn", "text": clip(line, MAX_FIELD_CHARS) }))
+                    .collect::<Vec<_>>(),
+            }));
+            continue;
+        }
+        blocks.extend(section(&paragraph));
+    }
+
+    sources.dedup();
+    if !sources.is_empty() {
+        let buttons: Vec<Value> = sources
+            .iter()
+            .take(MAX_SOURCE_BUTTONS)
+            .map(|url| {
+                json!({
+                    "type": "button",
+                    "text": { "type": "plain_text", "text": clip(&host(url), MAX_BUTTON_LABEL_CHARS) },
+                    "url": url,
+                })
+            })
+            .collect();
+        blocks.push(json!({ "type": "actions", "elements": buttons }));
+    }
+    blocks.truncate(MAX_BLOCKS);
+    blocks
+}
+
+/// A paragraph as a section: a heading over a grid of fields when its
+/// lines read "Label: value", or else its text.
+fn section(paragraph: &[String]) -> Vec<Value> {
+    let (heading, rest) = match paragraph.split_first() {
+        Some((first, rest)) if field(first).is_none() => (Some(first), rest),
+        _ => (None, paragraph),
+    };
+    let fields: Vec<(String, String)> = rest.iter().filter_map(|line| field(line)).collect();
+    if fields.len() < 2 || fields.len() < rest.len() {
+        return text_sections(&paragraph.join("\n"));
+    }
+
+    let mut sections = Vec::new();
+    for (i, chunk) in fields.chunks(MAX_FIELDS).enumerate() {
+        let mut section = json!({
+            "type": "section",
+            "fields": chunk
+                .iter()
+                .map(|(label, value)| json!({
+                    "type": "mrkdwn",
+                    "text": clip(&format!("*{label}*\n{value}"), MAX_FIELD_CHARS),
+                }))
+                .collect::<Vec<_>>(),
+        });
+        if let Some(heading) = heading.filter(|_| i == 0) {
+            section["text"] = json!({ "type": "mrkdwn", "text": clip(heading, MAX_SECTION_CHARS) });
+        }
+        sections.push(section);
+    }
+    sections
+}
+
+/// `answer` split into pages of about `max_chars` at paragraph breaks, for
+/// answers too long to read in one go. A paragraph longer than a page gets
+/// one to itself.
+pub fn pages(answer: &str, max_chars: usize) -> Vec<String> {
+    let mut pages = Vec::new();
+    let mut page = String::new();
+    for paragraph in paragraphs(answer) {
+        let paragraph = paragraph.join("\n");
+        if !page.is_empty() && page.chars().count() + paragraph.chars().count() > max_chars {
+            pages.push(std::mem::take(&mut page));
+        }
+        if !page.is_empty() {
+            page.push_str("\n\n");
+        }
+        page.push_str(&paragraph);
+    }
+    if !page.is_empty() {
+        pages.push(page);
+    }
+    pages
+}
+
+/// Sections holding `text`, split where it's too long for one.
+fn text_sections(text: &str) -> Vec<Value> {
+    let mut sections = Vec::new();
+    let mut chunk = String::new();
+    for line in text.lines() {
+        if !chunk.is_empty() && chunk.chars().count() + line.chars().count() >= MAX_SECTION_CHARS {
+            sections.push(std::mem::take(&mut chunk));
+        }
+        if !chunk.is_empty() {
+            chunk.push('\n');
+        }
+        chunk.push_str(&clip(line, MAX_SECTION_CHARS - 1));
+    }
+    if !chunk.trim().is_empty() {
+        sections.push(chunk);
+    }
+    sections
+        .into_iter()
+        .map(|text| json!({ "type": "section", "text": { "type": "mrkdwn", "text": text } }))
+        .collect()
+}
+
+/// The lines of `text` grouped by the blank lines between them. Code blocks
+/// stay whole.
+fn paragraphs(text: &str) -> Vec<Vec<String>> {
+    let mut paragraphs = Vec::new();
+    let mut current = Vec::new();
+    let mut in_code = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+        }
+        if line.trim().is_empty() && !in_code {
+            if !current.is_empty() {
+                paragraphs.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        current.push(line.to_string());
+    }
+    if !current.is_empty() {
+        paragraphs.push(current);
+    }
+    paragraphs
+}
+
+/// A "Label: value" line, such as "Wind: 12 km/h" or "- *Low*: 4 °C".
+fn field(line: &str) -> Option<(String, String)> {
+    // "**Low:** 4 °C" has become "*Low:* 4 °C".
+    let line = line.replace(":* ", "*: ");
+    let line = line
+        .trim()
+        .trim_start_matches(['-', '•'])
+        .trim_start_matches("* ")
+        .trim();
+    let (label, value) = line.split_once(": ")?;
+    let label = label.trim().trim_matches('*').trim();
+    let value = value.trim().trim_end_matches(',').trim();
+    let plain = !label.is_empty()
+        && label.chars().count() <= MAX_LABEL_CHARS
+        && !label.contains(['`', '<', '>'])
+        && !value.is_empty();
+    plain.then(|| (label.to_string(), value.to_string()))
+}
+
+/// Whether `line` names where an answer came from, like "Source: <url|...>".
+fn is_source(line: &str) -> bool {
+    let line = line.trim().trim_start_matches(['_', '*']).to_lowercase();
+    line.starts_with("source") && !urls(&line).is_empty()
+}
+
+/// The links in a line of mrkdwn.
+fn urls(line: &str) -> Vec<String> {
+    line.split(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '|'))
+        .filter(|word| word.starts_with("https://") || word.starts_with("http://"))
+        .map(|url| url.trim_end_matches(['.', ',', ')', ';']).to_string())
+        .collect()
+}
+
+/// The host of `url`, to label its button.
+fn host(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split(['/', '?', '#'])
+        .next()
+        .unwrap_or(rest)
+        .trim_start_matches("www.")
+        .to_string()
+}
+
+/// `text` cut to at most `max` characters.
+fn clip(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut clipped: String = text.chars().take(max - 1).collect();
+    clipped.push('…');
+    clipped
+}
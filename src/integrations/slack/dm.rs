@@ -0,0 +1,40 @@
+//! Direct messages to the bot, which it always answers, with no trigger
+//! word, keeping a conversation per DM. `slack_flows` only listens to
+//! channels, so the Slack app's event subscriptions send `message.im`
+//! events to the webhook endpoint with `?slack_events`.
+
+use super::event::MessageEvent;
+use super::routes::{self, Route};
+
+/// Starts the names DMs go by in session, usage and preference keys.
+const PREFIX: &str = "dm:";
+
+/// The name the DM with ID `channel_id` goes by.
+pub fn channel_key(channel_id: &str) -> String {
+    format!("{PREFIX}{channel_id}")
+}
+
+/// Whether `channel` names a DM.
+pub fn is_dm(channel: &str) -> bool {
+    channel.starts_with(PREFIX)
+}
+
+/// The settings DMs are answered with: the workspace of the first route,
+/// with the default prompt and tools.
+pub fn route(channel: &str) -> Option<Route> {
+    let workspace = routes::from_env().into_iter().next()?.workspace;
+    Some(Route::new(&workspace, channel))
+}
+
+/// Answers a direct message. Edits, and messages from bots including this
+/// one, are left alone.
+pub async fn handle(event: MessageEvent) {
+    if event.subtype.is_some() || event.bot_id.is_some() || event.user.is_empty() {
+        return;
+    }
+    let Some(route) = route(&channel_key(&event.channel)) else {
+        return;
+    };
+    let (user, text) = (event.user.clone(), event.text.clone());
+    super::respond(&route, event, user, text).await;
+}
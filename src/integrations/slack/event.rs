@@ -0,0 +1,242 @@
+use super::attachments::Attachment;
+use crate::store::{get, set, Expire, ExpireKind};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How old a signed request may be before it's taken for a replay.
+const SIGNATURE_MAX_AGE_SECS: i64 = 5 * 60;
+
+extern "C" {
+    // Provided by the flows runtime; `slack_flows` reads the event the same
+    // way but keeps only its text.
+    fn is_listening() -> i32;
+    fn get_event_body_length() -> i32;
+    fn get_event_body(p: *mut u8) -> i32;
+}
+
+/// The parts of a Slack message event that `slack_flows` leaves out.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MessageEvent {
+    /// `message`, or `app_mention` for messages that mention the bot.
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    /// The channel's ID, which the Web API takes rather than its name.
+    #[serde(default)]
+    pub channel: String,
+    /// `im` for direct messages to the bot.
+    #[serde(default)]
+    pub channel_type: String,
+    /// Set for edits, deletions and other changes rather than new messages.
+    pub subtype: Option<String>,
+    /// Who sent the message. Only read for events sent to the webhook
+    /// endpoint, as `slack_flows` passes it on itself.
+    #[serde(default)]
+    pub user: String,
+    /// The message's text, read like `user`.
+    #[serde(default)]
+    pub text: String,
+    /// The message's timestamp, which doubles as its ID.
+    #[serde(default)]
+    pub ts: String,
+    /// The timestamp of the thread's first message, for messages in a thread.
+    pub thread_ts: Option<String>,
+    /// Set for messages posted by bots, including this one.
+    pub bot_id: Option<String>,
+    #[serde(default)]
+    pub files: Vec<Attachment>,
+    /// The bot's own user ID, from `slack_bot_user_id` or the users the
+    /// event was delivered for.
+    #[serde(skip)]
+    pub bot_user_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+    #[serde(default)]
+    token: String,
+    event: Option<MessageEvent>,
+    #[serde(default)]
+    authorizations: Vec<Authorization>,
+}
+
+#[derive(Deserialize)]
+struct Authorization {
+    user_id: Option<String>,
+    #[serde(default)]
+    is_bot: bool,
+}
+
+impl MessageEvent {
+    /// Whether the message was sent to the bot directly rather than in a
+    /// channel.
+    pub fn is_direct(&self) -> bool {
+        self.channel_type == "im"
+    }
+
+    /// Whether `text`, the message's text, is addressed to the bot with an
+    /// @mention.
+    pub fn mentions_bot(&self, text: &str) -> bool {
+        match &self.bot_user_id {
+            Some(id) => text.contains(&format!("<@{id}>")) || text.contains(&format!("<@{id}|")),
+            None => self.kind == "app_mention",
+        }
+    }
+
+    /// `text` without the bot's @mentions. Without the bot's ID, the
+    /// mention an `app_mention` event starts with is dropped.
+    pub fn strip_mentions(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        match &self.bot_user_id {
+            Some(id) => {
+                let open = format!("<@{id}");
+                while let Some(start) = text.find(&open) {
+                    let end = text[start..]
+                        .find('>')
+                        .map_or(text.len(), |i| start + i + 1);
+                    text.replace_range(start..end, "");
+                }
+            }
+            None if self.kind == "app_mention" => {
+                if let Some(rest) = text
+                    .trim_start()
+                    .strip_prefix("<@")
+                    .and_then(|rest| rest.split_once('>'))
+                    .map(|(_, rest)| rest.to_string())
+                {
+                    text = rest;
+                }
+            }
+            None => {}
+        }
+        text.trim().to_string()
+    }
+}
+
+/// Whether the flow is registering its listeners rather than handling an
+/// event.
+pub fn registering() -> bool {
+    unsafe { is_listening() == 1 }
+}
+
+/// The Slack message being handled. Must only be called while handling a
+/// Slack event.
+pub fn current() -> Option<MessageEvent> {
+    let body = unsafe {
+        let length = get_event_body_length();
+        if length <= 0 {
+            return None;
+        }
+        let mut body = Vec::<u8>::with_capacity(length as usize);
+        let read = get_event_body(body.as_mut_ptr());
+        body.set_len(read.clamp(0, length) as usize);
+        body
+    };
+    let envelope = serde_json::from_slice::<Envelope>(&body).ok()?;
+    read(envelope)
+}
+
+/// The message event the Slack app's event subscriptions sent to the
+/// webhook endpoint, or `None` for other events and unverified ones.
+pub fn from_webhook(body: &[u8]) -> Option<MessageEvent> {
+    let envelope = serde_json::from_slice::<Envelope>(body).ok()?;
+    if !verified(&envelope.token) {
+        return None;
+    }
+    read(envelope).filter(|event| event.kind == "message")
+}
+
+fn read(envelope: Envelope) -> Option<MessageEvent> {
+    let mut event = envelope.event?;
+    event.bot_user_id = env::var("slack_bot_user_id").ok().or_else(|| {
+        envelope
+            .authorizations
+            .into_iter()
+            .find(|a| a.is_bot)
+            .and_then(|a| a.user_id)
+    });
+    Some(event)
+}
+
+/// Whether the message with timestamp `ts` was handled already, which
+/// happens when Slack sends a mention both as a message and as an
+/// `app_mention`, or retries a delivery. Marks it handled.
+pub fn seen_before(channel: &str, ts: &str) -> bool {
+    if ts.is_empty() {
+        return false;
+    }
+    let key = format!("seen:{channel}:{ts}");
+    if get(&key).is_some() {
+        return true;
+    }
+    set(
+        &key,
+        json!(true),
+        Some(Expire {
+            kind: ExpireKind::Ex,
+            value: 10 * 60,
+        }),
+    );
+    false
+}
+
+/// Whether `token`, from a request Slack sent to the webhook endpoint, is
+/// the app's verification token. Without `SLACK_VERIFICATION_TOKEN`, only
+/// requests whose signature `signed` checked are let through, so nothing
+/// is when neither it nor `SLACK_SIGNING_SECRET` is set.
+pub fn verified(token: &str) -> bool {
+    match env::var("SLACK_VERIFICATION_TOKEN") {
+        Ok(expected) => !expected.is_empty() && token == expected,
+        Err(_) => env::var("SLACK_SIGNING_SECRET").is_ok_and(|secret| !secret.is_empty()),
+    }
+}
+
+/// Whether a request Slack sent to the webhook endpoint carries a valid
+/// `X-Slack-Signature`, made with `SLACK_SIGNING_SECRET` in the last five
+/// minutes. Without the secret, `verified` checks the token instead.
+pub fn signed(headers: &[(String, String)], body: &[u8]) -> bool {
+    let Ok(secret) = env::var("SLACK_SIGNING_SECRET") else {
+        return true;
+    };
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+    let (Some(timestamp), Some(signature)) = (
+        header("x-slack-request-timestamp"),
+        header("x-slack-signature"),
+    ) else {
+        return false;
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    match timestamp.parse::<i64>() {
+        Ok(sent) if (now - sent).abs() <= SIGNATURE_MAX_AGE_SECS => {}
+        _ => return false,
+    }
+    let Some(signature) = signature.strip_prefix("v0=").and_then(from_hex) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(format!("v0:{timestamp}:").as_bytes());
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
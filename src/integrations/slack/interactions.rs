@@ -0,0 +1,327 @@
+//! Buttons on the bot's messages: "Run it" and "Cancel" on requests to
+//! approve a tool call, and "Next page" under long answers. Slack sends
+//! clicks to the webhook endpoint with `?slack_interactions`.
+
+use super::event;
+use super::status::{self as slack_status, SlackStatus, Thread};
+use super::{Resumed, REGISTRY};
+use crate::confirm;
+use crate::core::run_approved;
+use crate::core::session::SessionKey;
+use crate::postprocess::{Answer, PostProcess};
+use crate::store::{del, get, set, Expire, ExpireKind};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+const RUN: &str = "confirm_run";
+const CANCEL: &str = "confirm_cancel";
+const NEXT_PAGE: &str = "next_page";
+/// Marks the blocks holding buttons, so they can be taken off once clicked.
+const BUTTONS_BLOCK: &str = "bot_buttons";
+/// How long a confirmation button works, as long as the request it answers.
+const CONFIRM_TTL_SECS: i64 = 15 * 60;
+/// How long the rest of a long answer is kept.
+const PAGES_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// What a button does, stored under the random value it carries so that a
+/// click can only act on what the bot offered.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Button {
+    /// Answers the confirmation request held in a conversation.
+    Confirm {
+        workspace: String,
+        channel: String,
+        channel_id: String,
+        user: String,
+        thread: Option<String>,
+        /// Whether the conversation is shown only to `user`.
+        #[serde(default)]
+        private: bool,
+    },
+    /// Posts the rest of an answer, a page at a time.
+    NextPage {
+        workspace: String,
+        channel: String,
+        channel_id: String,
+        thread: Option<String>,
+        rest: String,
+        /// The user who alone sees the answer, if any.
+        #[serde(default)]
+        visible_to: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    token: String,
+    user: User,
+    response_url: Option<String>,
+    #[serde(default)]
+    message: Value,
+    #[serde(default)]
+    actions: Vec<Action>,
+}
+
+#[derive(Deserialize)]
+struct User {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct Action {
+    action_id: String,
+    #[serde(default)]
+    value: String,
+}
+
+/// The question with "Run it" and "Cancel" buttons, or `None` when the
+/// buttons can't be set up. A `private` conversation carries on out of
+/// sight of the channel once they're clicked.
+pub fn confirm_blocks(
+    workspace: &str,
+    channel_id: &str,
+    session: &SessionKey,
+    question: &str,
+    private: bool,
+) -> Option<Vec<Value>> {
+    let value = remember(
+        &Button::Confirm {
+            workspace: workspace.to_string(),
+            channel: session.channel.clone(),
+            channel_id: channel_id.to_string(),
+            user: session.user.clone(),
+            thread: session.thread.clone(),
+            private,
+        },
+        CONFIRM_TTL_SECS,
+    )?;
+    Some(vec![
+        json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": question },
+        }),
+        json!({
+            "type": "actions",
+            "block_id": BUTTONS_BLOCK,
+            "elements": [
+                button(RUN, "Run it", &value, Some("primary")),
+                button(CANCEL, "Cancel", &value, Some("danger")),
+            ],
+        }),
+    ])
+}
+
+/// A "Next page" button that posts `rest`, or `None` when it can't be set
+/// up.
+pub fn next_page_block(status: &SlackStatus, channel_id: &str, rest: &str) -> Option<Value> {
+    let value = remember(
+        &Button::NextPage {
+            workspace: status.workspace.clone(),
+            channel: status.channel.clone(),
+            channel_id: channel_id.to_string(),
+            thread: status.thread.as_ref().map(|t| t.ts.clone()),
+            rest: rest.to_string(),
+            visible_to: status.visible_to.clone(),
+        },
+        PAGES_TTL_SECS,
+    )?;
+    Some(json!({
+        "type": "actions",
+        "block_id": BUTTONS_BLOCK,
+        "elements": [button(NEXT_PAGE, "Next page", &value, None)],
+    }))
+}
+
+fn button(action_id: &str, label: &str, value: &str, style: Option<&str>) -> Value {
+    let mut button = json!({
+        "type": "button",
+        "action_id": action_id,
+        "text": { "type": "plain_text", "text": label },
+        "value": value,
+    });
+    if let Some(style) = style {
+        button["style"] = json!(style);
+    }
+    button
+}
+
+fn store_key(value: &str) -> String {
+    format!("slack_button:{value}")
+}
+
+/// Stores `button` under a new random value and returns the value.
+fn remember(button: &Button, ttl_secs: i64) -> Option<String> {
+    let mut bytes = [0u8; 16];
+    if let Err(e) = getrandom::getrandom(&mut bytes) {
+        log::warn!("Failed to make a button value: {}", e);
+        return None;
+    }
+    let value: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    set(
+        &store_key(&value),
+        serde_json::to_value(button).ok()?,
+        Some(Expire {
+            kind: ExpireKind::Ex,
+            value: ttl_secs,
+        }),
+    );
+    Some(value)
+}
+
+/// Answers a click, sent as a form with the JSON in its `payload` field.
+/// Clicks that aren't verified are dropped.
+pub async fn handle(body: &[u8]) {
+    let payload = url::form_urlencoded::parse(body)
+        .find(|(name, _)| name == "payload")
+        .map(|(_, payload)| payload.into_owned());
+    let payload: Payload = match payload.map(|p| serde_json::from_str(&p)) {
+        Some(Ok(payload)) => payload,
+        Some(Err(e)) => {
+            log::warn!("Failed to read a Slack interaction: {}", e);
+            return;
+        }
+        None => {
+            log::warn!("A Slack interaction came without a payload");
+            return;
+        }
+    };
+    if !event::verified(&payload.token) {
+        log::warn!("Ignored a Slack interaction with the wrong token");
+        return;
+    }
+    if payload.kind != "block_actions" {
+        return;
+    }
+    for action in &payload.actions {
+        let button =
+            get(&store_key(&action.value)).and_then(|v| serde_json::from_value::<Button>(v).ok());
+        match (action.action_id.as_str(), button) {
+            (RUN | CANCEL, Some(button @ Button::Confirm { .. })) => {
+                answer_confirmation(&payload, &action.value, button, action.action_id == RUN).await
+            }
+            (NEXT_PAGE, Some(button @ Button::NextPage { .. })) => {
+                del(&store_key(&action.value));
+                remove_buttons(&payload, None).await;
+                post_next_page(button).await;
+            }
+            (RUN | CANCEL | NEXT_PAGE, _) => {
+                remove_buttons(&payload, Some("This has expired.")).await;
+            }
+            (other, _) => log::warn!("Unknown Slack action {}", other),
+        }
+    }
+}
+
+/// Runs or cancels the held call the way a typed :+1: or :-1: would, and
+/// carries on the conversation with the outcome.
+async fn answer_confirmation(payload: &Payload, value: &str, button: Button, approved: bool) {
+    let Button::Confirm {
+        workspace,
+        channel,
+        channel_id,
+        user,
+        thread,
+        private,
+    } = button
+    else {
+        return;
+    };
+    if payload.user.id != user {
+        return respond(
+            payload,
+            json!({
+                "response_type": "ephemeral",
+                "replace_original": false,
+                "text": "Only the person who asked can answer this.",
+            }),
+        )
+        .await;
+    }
+    let key = SessionKey::new(&channel, &user).in_thread(thread);
+    let Some(resumed) = Resumed::open(&workspace, &channel_id, &key, &user, private) else {
+        return;
+    };
+    del(&store_key(value));
+    let Some(action) = confirm::pending(&key) else {
+        return remove_buttons(payload, Some("This has expired.")).await;
+    };
+    confirm::clear(&key);
+    let verdict = if approved { "Approved" } else { "Cancelled" };
+    remove_buttons(payload, Some(&format!("{verdict} by <@{user}>."))).await;
+
+    let user_input = if approved {
+        match run_approved(&REGISTRY, &resumed.config, &resumed.context, &action).await {
+            Ok(result) => format!("Approved. The {} tool returned: {}", action.tool, result),
+            Err(e) => format!("Approved, but the {} tool failed: {}", action.tool, e),
+        }
+    } else {
+        format!("Cancelled. Don't run the {} tool.", action.tool)
+    };
+    resumed.answer(user_input, None).await;
+}
+
+async fn post_next_page(button: Button) {
+    let Button::NextPage {
+        workspace,
+        channel,
+        channel_id,
+        thread,
+        rest,
+        visible_to,
+    } = button
+    else {
+        return;
+    };
+    SlackStatus::new(&workspace, &channel)
+        .with_channel_id(&channel_id)
+        .in_thread(thread.map(|ts| Thread {
+            channel_id: channel_id.clone(),
+            ts,
+        }))
+        .visible_only_to(visible_to.as_deref())
+        .post_processed(Answer {
+            unfurl: PostProcess::from_env().unfurls(&rest),
+            text: rest,
+        })
+        .await;
+}
+
+/// Takes the buttons off the clicked message so they can't be clicked
+/// again, noting `outcome` in their place.
+async fn remove_buttons(payload: &Payload, outcome: Option<&str>) {
+    let mut blocks: Vec<Value> = payload.message["blocks"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|block| block["block_id"] != BUTTONS_BLOCK)
+        .cloned()
+        .collect();
+    if let Some(outcome) = outcome {
+        blocks.push(json!({
+            "type": "context",
+            "elements": [{ "type": "mrkdwn", "text": outcome }],
+        }));
+    }
+    respond(
+        payload,
+        json!({
+            "replace_original": true,
+            "text": payload.message["text"],
+            "blocks": blocks,
+        }),
+    )
+    .await;
+}
+
+async fn respond(payload: &Payload, message: Value) {
+    let Some(url) = &payload.response_url else {
+        return;
+    };
+    if let Err(e) = slack_status::post_json(url, &message).await {
+        log::warn!("Failed to answer a Slack interaction: {}", e);
+    }
+}
@@ -0,0 +1,550 @@
+use async_openai::types::{CreateSpeechRequestArgs, SpeechModel, SpeechResponseFormat, Voice};
+use once_cell::sync::Lazy;
+use slack_flows::{listen_to_channel, upload_file, SlackMessage};
+use std::sync::Arc;
+
+mod attachments;
+mod blocks;
+pub mod dm;
+pub mod event;
+pub mod interactions;
+pub mod reactions;
+pub mod routes;
+pub mod slash;
+pub mod status;
+mod users;
+
+use crate::access;
+use crate::client::ChatClient;
+use crate::commands::{self, Command};
+use crate::config::Config;
+use crate::confirm;
+use crate::core::session::{self, SessionKey, SessionManager, SessionState};
+use crate::core::{chat_inner, prompt, Agent, Incoming, Outcome, ToolContext, ToolRegistry};
+use crate::farewell::Farewell;
+use crate::feedback::{self, Turn};
+use crate::follow_up;
+use crate::history;
+use crate::persona::{self, Persona};
+use crate::prefs::{ChannelPrefs, Prefs};
+use crate::status::{ChatPlatform, StatusSink};
+use crate::tools;
+use crate::trigger::Trigger;
+use crate::usage::{self, Budget};
+use attachments::Attachment;
+use event::MessageEvent;
+use routes::Route;
+use status::{SlackStatus, Thread};
+
+/// The speech API reads at most this many characters.
+const MAX_SPEECH_CHARS: usize = 4096;
+
+static REGISTRY: Lazy<ToolRegistry> = Lazy::new(|| {
+    ToolRegistry::with_builtin_tools(&ChatClient::from_env(), &Config::from_env().model)
+});
+
+/// Registers a listener for every route, or hands the Slack message being
+/// handled to the route it came in on, as each listener would otherwise
+/// answer it.
+pub async fn listen() {
+    let routes = routes::from_env();
+    if event::registering() {
+        for route in &routes {
+            listen_to_channel(&route.workspace, &route.channel, |sm| handler(route, sm)).await;
+        }
+        return;
+    }
+    let Some(MessageEvent {
+        channel: channel_id,
+        ..
+    }) = event::current()
+    else {
+        return;
+    };
+    match routes::for_channel(&routes, &channel_id).await {
+        Some(route) => {
+            listen_to_channel(&route.workspace, &route.channel, |sm| handler(route, sm)).await
+        }
+        None => log::warn!("No route for an event from channel {}", channel_id),
+    }
+}
+
+/// The settings and system prompt template of `route`'s channel, with any
+/// persona an admin switched it to.
+fn setup(route: &Route) -> (Config, String) {
+    let env_config = Config::from_env();
+    let channel_prefs = ChannelPrefs::load(&route.channel);
+    // A persona an admin switched to takes over from the route's prompt and
+    // tools.
+    let persona = channel_prefs.persona.as_deref().and_then(persona::get);
+    let mut disabled_tools = commands::disabled_tools();
+    match &persona {
+        Some(Persona {
+            tools: Some(tools), ..
+        }) => disabled_tools.extend(REGISTRY.names_outside(tools)),
+        Some(_) => {}
+        None => disabled_tools.extend(route.excluded_tools(&REGISTRY)),
+    }
+    channel_prefs.restrict_tools(&mut disabled_tools);
+    let config = Config {
+        model: commands::active_model().unwrap_or(env_config.model.clone()),
+        dry_run: channel_prefs.dry_run.unwrap_or(env_config.dry_run),
+        reply_language: channel_prefs.language,
+        disabled_tools,
+        ..env_config
+    };
+    let template = match persona {
+        Some(persona) => persona.system_prompt,
+        None => route
+            .system_prompt
+            .clone()
+            .unwrap_or_else(prompt::template_from_env),
+    };
+    (config, template)
+}
+
+#[no_mangle]
+async fn handler(route: &Route, sm: SlackMessage) {
+    let event = event::current().unwrap_or_default();
+    respond(route, event, sm.user, sm.text).await;
+}
+
+/// Handles an event the Slack app's event subscriptions sent to the
+/// webhook endpoint: a direct message, or a reaction to an answer.
+pub async fn handle_event(body: &[u8]) {
+    match event::from_webhook(body) {
+        Some(event) if event.is_direct() => dm::handle(event).await,
+        _ => reactions::handle(body).await,
+    }
+}
+
+/// Answers `text`, sent by `user` in `route`'s channel or in a DM. The
+/// agent answers the message; what's left here is Slack's: threads, files,
+/// commands and leaving the chat.
+async fn respond(route: &Route, event: MessageEvent, user: String, text: String) {
+    let (workspace, channel) = (route.workspace.as_str(), route.channel.as_str());
+    // Answers posted in threads come back as events.
+    if event.bot_id.is_some() {
+        return;
+    }
+    if event::seen_before(channel, &event.ts) {
+        return;
+    }
+    let mut msg = text;
+    let mentioned = event.mentions_bot(&msg);
+    // Everything sent in a DM is for the bot.
+    let direct = event.is_direct();
+    // `!private` before a question has the answer shown only to the asker.
+    let (private, stripped) = status::strip_private(&event.strip_mentions(&msg));
+    if private {
+        msg = stripped;
+    }
+    let trigger = Trigger::from_env();
+    let triggered = direct || mentioned || trigger.strip(&msg).is_some();
+
+    // With threads, a trigger message starts a thread that holds the
+    // conversation, so several can go on in one channel at once.
+    let threads = !direct && status::threads_enabled() && !event.channel.is_empty();
+    let thread = match &event.thread_ts {
+        Some(thread_ts) if threads => Some(thread_ts.clone()),
+        None if threads && triggered => Some(event.ts.clone()),
+        _ => None,
+    };
+    let slack = Arc::new(
+        SlackStatus::new(workspace, channel)
+            .with_channel_id(&event.channel)
+            .in_thread(thread.map(|ts| Thread {
+                channel_id: event.channel.clone(),
+                ts,
+            }))
+            .visible_only_to((private || status::ephemeral_in(channel)).then_some(user.as_str())),
+    );
+    let key = slack.session_key(&user);
+    let status: Arc<dyn StatusSink> = slack.clone();
+    let client = ChatClient::from_env().with_status(status.clone());
+    let context = ToolContext::new(workspace, channel, &user)
+        .in_session(key.clone())
+        .with_status(status);
+    usage::set_scope(channel, &user);
+    let (config, template) = setup(route);
+    let mut agent = Agent::builder()
+        .config(config)
+        .client(client.clone())
+        .system_prompt(&template)
+        .builtin_tools()
+        .idle_expiry(session::idle_expiry_from_env())
+        .channel(channel)
+        .build();
+    if prompt::uses(agent.sessions().system_prompt(), "user_name") {
+        if let Some(name) = users::display_name(&user).await {
+            agent = agent.with_prompt_variable("user_name", &name);
+        }
+    }
+
+    if let Some(command) = Command::parse(&msg) {
+        if !access::may_trigger(&user).await {
+            slack.post(access::NOT_AUTHORIZED).await;
+            return;
+        }
+        let reply = if command.is_public() || commands::is_admin(&user) {
+            match command {
+                Command::Ingest { url: None } if !event.files.is_empty() => {
+                    ingest_files(&event.files, &client).await
+                }
+                command => {
+                    command
+                        .run(&key, &REGISTRY, agent.sessions(), &client, agent.config())
+                        .await
+                }
+            }
+        } else {
+            "Sorry, only admins can use commands.".to_string()
+        };
+        slack.post(&reply).await;
+        return;
+    }
+
+    // An answer to a confirmation request or follow-up question counts
+    // whether or not the bot is in a chat.
+    let awaited = confirm::pending(&key).is_some() || follow_up::pending(&key).is_some();
+    // In a thread the bot is chatting once it has answered there.
+    let in_chat = if direct {
+        true
+    } else if threads {
+        event.thread_ts.is_some() && agent.sessions().exists(&key)
+    } else {
+        SessionState::load(&key) == SessionState::Chatting
+    };
+
+    if in_chat && msg.trim().eq_ignore_ascii_case("clear") {
+        agent.sessions().reset(&key);
+        slack.post("Cleared our conversation. What's next?").await;
+        return;
+    }
+    if in_chat
+        && Farewell::from_env()
+            .is_goodbye(&client, &agent.config().model, &event.strip_mentions(&msg))
+            .await
+    {
+        agent.sessions().reset(&key);
+        let farewell = if direct {
+            "Bye! Message me whenever you need me again."
+        } else if threads {
+            "Bye! Mention me in this thread if you need me again."
+        } else {
+            "Bye! Use the trigger word when you need me again."
+        };
+        slack.post(farewell).await;
+        return;
+    }
+
+    let answering = awaited || in_chat || triggered;
+
+    // Only messages addressed to the bot are told so, rather than every one
+    // sent while it's chatting.
+    if answering && !access::may_trigger(&user).await {
+        if triggered {
+            slack.post(access::NOT_AUTHORIZED).await;
+        }
+        return;
+    }
+
+    // Admins aren't held to the budget, so they can still look into things.
+    // Others are held to it before their voice clips are transcribed.
+    if answering && !commands::is_admin(&user) {
+        if let Some(notice) = Budget::from_env().exhausted() {
+            slack.post(&notice).await;
+            return;
+        }
+    }
+
+    // Voice clips usually come without text, so what was said becomes the
+    // message. Files the bot wouldn't answer aren't downloaded.
+    let mut images = Vec::new();
+    if answering {
+        let transcript = transcribe_audio(&event.files, &client, agent.config(), &context).await;
+        if !transcript.is_empty() {
+            msg = if msg.trim().is_empty() {
+                transcript
+            } else {
+                format!("{msg}\n{transcript}")
+            };
+        }
+        images = image_urls(&event.files, &context).await;
+    }
+
+    let stripped = event.strip_mentions(&msg);
+    let input = if triggered {
+        Some(trigger.input(&stripped).unwrap_or(stripped.clone()))
+    } else {
+        in_chat.then_some(msg)
+    };
+    if input.is_some() && !threads && !direct {
+        SessionState::Chatting.save(&key);
+    }
+    // Messages with files are always answered afresh.
+    let mut incoming = Incoming::new(&stripped, input)
+        .with_images(images)
+        .unbudgeted();
+    if !event.files.is_empty() {
+        incoming = incoming.uncached();
+    }
+
+    match agent.reply(incoming, &context).await {
+        Outcome::Answered {
+            question,
+            answer,
+            model,
+        } => {
+            let ts = slack.post_answer(&answer).await;
+            remember_answer(&slack, &key, ts, &question, &answer, &model);
+            // `slack_flows` can't upload the audio to a DM.
+            if !direct && Prefs::load(&user).speech {
+                post_speech(&answer, &client, agent.config(), workspace, channel).await;
+            }
+        }
+        Outcome::Said(text) => slack.post(&text).await,
+        Outcome::Silent => slack.clear_progress().await,
+    }
+}
+
+/// Remembers the answer posted as the message `ts`, so that reactions to it
+/// can be traced back to the question.
+fn remember_answer(
+    slack: &SlackStatus,
+    key: &SessionKey,
+    ts: Option<String>,
+    question: &str,
+    answer: &str,
+    model: &str,
+) {
+    let (Some(channel_id), Some(ts)) = (slack.channel_id(), ts.filter(|ts| !ts.is_empty())) else {
+        return;
+    };
+    let turn = Turn {
+        workspace: slack.workspace.clone(),
+        channel: key.channel.clone(),
+        user: key.user.clone(),
+        thread: key.thread.clone(),
+        question: question.to_string(),
+        answer: answer.to_string(),
+        model: model.to_string(),
+    };
+    feedback::remember(channel_id, &ts, &turn);
+}
+
+/// A conversation carried on outside a message event, such as after a
+/// button click or a reaction.
+pub(crate) struct Resumed {
+    pub config: Config,
+    sessions: SessionManager,
+    pub slack: Arc<SlackStatus>,
+    client: ChatClient,
+    pub context: ToolContext,
+}
+
+impl Resumed {
+    /// `key`'s conversation, held on behalf of `user` in the channel with ID
+    /// `channel_id`. A `private` conversation, or one in a channel where
+    /// answers are ephemeral, is shown only to `user`. `None` when no route
+    /// listens on the channel.
+    pub fn open(
+        workspace: &str,
+        channel_id: &str,
+        key: &SessionKey,
+        user: &str,
+        private: bool,
+    ) -> Option<Self> {
+        let route = if dm::is_dm(&key.channel) {
+            dm::route(&key.channel)
+        } else {
+            routes::from_env()
+                .into_iter()
+                .find(|r| r.workspace == workspace && r.channel == key.channel)
+        };
+        let Some(route) = route else {
+            log::warn!("No route for {}/{}", workspace, key.channel);
+            return None;
+        };
+        let (config, template) = setup(&route);
+        let sessions =
+            SessionManager::new(template).with_idle_expiry(session::idle_expiry_from_env());
+        let slack = Arc::new(
+            SlackStatus::new(workspace, &key.channel)
+                .with_channel_id(channel_id)
+                .in_thread(key.thread.clone().map(|ts| Thread {
+                    channel_id: channel_id.to_string(),
+                    ts,
+                }))
+                .visible_only_to((private || status::ephemeral_in(&key.channel)).then_some(user)),
+        );
+        let status: Arc<dyn StatusSink> = slack.clone();
+        let client = ChatClient::from_env().with_status(status.clone());
+        let context = ToolContext::new(workspace, &key.channel, user)
+            .in_session(key.clone())
+            .with_status(status);
+        usage::set_scope(&key.channel, user);
+        Some(Self {
+            config,
+            sessions,
+            slack,
+            client,
+            context,
+        })
+    }
+
+    /// Runs a turn with `input` and posts the answer. With `replacing`, an
+    /// answer that ended the conversation is taken back first, along with
+    /// the message it answered. An earlier one stays, and the model is asked
+    /// for a different answer.
+    pub async fn answer(&self, input: String, replacing: Option<&str>) {
+        let mut session = self.sessions.load(self.context.session.clone());
+        let input = match replacing {
+            Some(previous) if !history::take_back(&mut session.messages, previous) => {
+                format!("Answer this again, in a different way: {input}")
+            }
+            _ => input,
+        };
+        let result = chat_inner(
+            input.clone(),
+            &mut session.messages,
+            &self.client,
+            &REGISTRY,
+            &self.config,
+            &self.context,
+        )
+        .await;
+        self.sessions.save(&mut session);
+        match result {
+            Ok(Some(output)) => {
+                let ts = self.slack.post_answer(&output).await;
+                remember_answer(
+                    &self.slack,
+                    &session.key,
+                    ts,
+                    &input,
+                    &output,
+                    &self.config.model,
+                );
+            }
+            Ok(None) => self.slack.clear_progress().await,
+            Err(e) => {
+                log::error!("Failed to answer {}: {}", self.context.user, e);
+                self.slack.post(&e.user_message()).await;
+            }
+        }
+    }
+}
+
+/// Reads `text` aloud and uploads the audio to the channel.
+async fn post_speech(
+    text: &str,
+    client: &ChatClient,
+    config: &Config,
+    workspace: &str,
+    channel: &str,
+) {
+    let model = match config.speech_model.as_str() {
+        "tts-1" => SpeechModel::Tts1,
+        "tts-1-hd" => SpeechModel::Tts1Hd,
+        other => SpeechModel::Other(other.to_string()),
+    };
+    let voice = match config.speech_voice.as_str() {
+        "alloy" => Voice::Alloy,
+        "echo" => Voice::Echo,
+        "fable" => Voice::Fable,
+        "onyx" => Voice::Onyx,
+        "nova" => Voice::Nova,
+        "shimmer" => Voice::Shimmer,
+        other => Voice::Other(other.to_string()),
+    };
+    let request = CreateSpeechRequestArgs::default()
+        .input(text.chars().take(MAX_SPEECH_CHARS).collect::<String>())
+        .model(model)
+        .voice(voice)
+        .response_format(SpeechResponseFormat::Mp3)
+        .build();
+    let speech = match request {
+        Ok(request) => client.create_speech(request).await,
+        Err(e) => Err(e),
+    };
+    match speech {
+        Ok(speech) => {
+            upload_file(
+                workspace,
+                channel,
+                "answer.mp3",
+                "mp3",
+                speech.bytes.to_vec(),
+            )
+            .await
+        }
+        Err(e) => log::warn!("Failed to read the answer aloud: {}", e),
+    }
+}
+
+/// Adds the files shared with `!ingest` to the documents, with a line on
+/// how each went.
+async fn ingest_files(files: &[Attachment], client: &ChatClient) -> String {
+    let mut reports = Vec::new();
+    for file in files {
+        reports.push(match file.download().await {
+            Ok(body) => tools::ingest_file(client, &file.name, &file.mimetype, body).await,
+            Err(e) => format!("Couldn't download {}: {}", file.name, e),
+        });
+    }
+    reports.join("\n")
+}
+
+/// The images among `files`, as `data:` URLs. Files that can't be
+/// downloaded are skipped and the user is told why.
+async fn image_urls(files: &[Attachment], context: &ToolContext) -> Vec<String> {
+    let mut urls = Vec::new();
+    for file in files.iter().filter(|f| f.is_image()) {
+        match file.data_url().await {
+            Ok(url) => urls.push(url),
+            Err(e) => {
+                log::warn!("Failed to download {}: {}", file.name, e);
+                if let Some(status) = &context.status {
+                    status
+                        .status(&format!("I couldn't look at {}: {}", file.name, e))
+                        .await;
+                }
+            }
+        }
+    }
+    urls
+}
+
+/// What was said in the audio among `files`, one file per line. Files that
+/// can't be transcribed are skipped and the user is told why.
+async fn transcribe_audio(
+    files: &[Attachment],
+    client: &ChatClient,
+    config: &Config,
+    context: &ToolContext,
+) -> String {
+    let mut transcripts = Vec::new();
+    for file in files.iter().filter(|f| f.is_audio()) {
+        let transcript = match file.download().await {
+            Ok(audio) => client
+                .transcribe(&audio, &file.audio_file_name(), &config.transcription_model)
+                .await
+                .map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        };
+        match transcript {
+            Ok(text) if !text.trim().is_empty() => transcripts.push(text.trim().to_string()),
+            Ok(_) => {}
+            Err(e) => {
+                log::warn!("Failed to transcribe {}: {}", file.name, e);
+                if let Some(status) = &context.status {
+                    status
+                        .status(&format!("I couldn't listen to {}: {}", file.name, e))
+                        .await;
+                }
+            }
+        }
+    }
+    transcripts.join("\n")
+}
@@ -0,0 +1,110 @@
+//! Reactions to the bot's answers: :+1: and :-1: rate an answer, and
+//! :repeat: has it answered again. The Slack app's event subscriptions send
+//! `reaction_added` events to the webhook endpoint with `?slack_events`.
+
+use super::event;
+use super::Resumed;
+use crate::access;
+use crate::commands;
+use crate::core::session::SessionKey;
+use crate::feedback::{self, Rating};
+use crate::moderation::{self, Moderation};
+use crate::status::ChatPlatform;
+use crate::usage::Budget;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Envelope {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    token: String,
+    challenge: Option<String>,
+    event: Option<Reaction>,
+}
+
+#[derive(Deserialize)]
+struct Reaction {
+    #[serde(rename = "type")]
+    kind: String,
+    user: String,
+    reaction: String,
+    item: Item,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    #[serde(default)]
+    channel: String,
+    #[serde(default)]
+    ts: String,
+}
+
+/// The challenge Slack sends when the request URL is set, which has to be
+/// answered with itself.
+pub fn challenge(body: &[u8]) -> Option<String> {
+    let envelope: Envelope = serde_json::from_slice(body).ok()?;
+    (envelope.kind == "url_verification" && event::verified(&envelope.token))
+        .then_some(envelope.challenge)
+        .flatten()
+}
+
+/// Records a rating, or answers again, for a reaction to one of the bot's
+/// answers. Reactions to anything else are ignored.
+pub async fn handle(body: &[u8]) {
+    let envelope: Envelope = match serde_json::from_slice(body) {
+        Ok(envelope) => envelope,
+        Err(e) => {
+            log::warn!("Failed to read a Slack event: {}", e);
+            return;
+        }
+    };
+    if !event::verified(&envelope.token) {
+        log::warn!("Ignored a Slack event with the wrong token");
+        return;
+    }
+    let Some(reaction) = envelope.event.filter(|e| e.kind == "reaction_added") else {
+        return;
+    };
+    let Some(turn) = feedback::turn(&reaction.item.channel, &reaction.item.ts) else {
+        return;
+    };
+    // Skin tones come as `+1::skin-tone-2`.
+    let name = reaction.reaction.split("::").next().unwrap_or_default();
+    match name {
+        "+1" | "thumbsup" => feedback::record(&turn, Rating::Up, &reaction.user),
+        "-1" | "thumbsdown" => feedback::record(&turn, Rating::Down, &reaction.user),
+        "repeat" => {
+            let key = SessionKey::new(&turn.channel, &turn.user).in_thread(turn.thread.clone());
+            let Some(resumed) = Resumed::open(
+                &turn.workspace,
+                &reaction.item.channel,
+                &key,
+                &reaction.user,
+                false,
+            ) else {
+                return;
+            };
+            // Asking again is asking, so it's held to what asking is.
+            if !access::may_trigger(&reaction.user).await {
+                return resumed.slack.post(access::NOT_AUTHORIZED).await;
+            }
+            if !commands::is_admin(&reaction.user) {
+                if let Some(notice) = Budget::from_env().exhausted() {
+                    return resumed.slack.post(&notice).await;
+                }
+            }
+            if Moderation::from_env()
+                .check(&resumed.client, &turn.question)
+                .await
+                .is_some()
+            {
+                return resumed.slack.post(&moderation::refusal()).await;
+            }
+            resumed
+                .answer(turn.question.clone(), Some(&turn.answer))
+                .await;
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,127 @@
+use crate::tools::http::{self, Method};
+use crate::tools::ToolRegistry;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::env;
+
+const CONVERSATIONS_INFO_URL: &str = "https://slack.com/api/conversations.info";
+
+/// A Slack channel the bot listens on, with its own prompt and tools.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Route {
+    pub workspace: String,
+    pub channel: String,
+    /// The channel's ID, for telling which route an event came in on when
+    /// there are several. Without one, the channel's name is looked up with
+    /// `SLACK_BOT_TOKEN`.
+    pub channel_id: Option<String>,
+    /// Replaces the default system prompt for conversations started in the
+    /// channel.
+    pub system_prompt: Option<String>,
+    /// The only tools offered in the channel. `None` offers every tool.
+    pub tools: Option<BTreeSet<String>>,
+}
+
+impl Route {
+    pub fn new(workspace: &str, channel: &str) -> Self {
+        Self {
+            workspace: workspace.to_string(),
+            channel: channel.to_string(),
+            channel_id: None,
+            system_prompt: None,
+            tools: None,
+        }
+    }
+
+    /// The registered tools the route doesn't offer.
+    pub fn excluded_tools(&self, registry: &ToolRegistry) -> BTreeSet<String> {
+        match &self.tools {
+            Some(tools) => registry.names_outside(tools),
+            None => BTreeSet::new(),
+        }
+    }
+}
+
+/// Reads `slack_channels`, either a JSON array of routes or `workspace/channel`
+/// pairs separated by commas. Without it, the bot listens on the single
+/// channel named by `slack_workspace` and `slack_channel`.
+pub fn from_env() -> Vec<Route> {
+    let routes = env::var("slack_channels")
+        .map(|v| parse(&v))
+        .unwrap_or_default();
+    if !routes.is_empty() {
+        return routes;
+    }
+    vec![Route::new(
+        &env::var("slack_workspace").unwrap_or("secondstate".to_string()),
+        &env::var("slack_channel").unwrap_or("test-flow".to_string()),
+    )]
+}
+
+fn parse(value: &str) -> Vec<Route> {
+    let value = value.trim();
+    if value.starts_with('[') {
+        return match serde_json::from_str(value) {
+            Ok(routes) => routes,
+            Err(e) => {
+                log::warn!("Ignoring invalid slack_channels: {}", e);
+                Vec::new()
+            }
+        };
+    }
+    value
+        .split(',')
+        .filter_map(|pair| match pair.trim().split_once('/') {
+            Some((workspace, channel)) => Some(Route::new(workspace.trim(), channel.trim())),
+            None => {
+                log::warn!(
+                    "Ignoring slack_channels entry without a workspace: {}",
+                    pair
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+/// The route an event from the channel with ID `channel_id` came in on.
+pub async fn for_channel<'a>(routes: &'a [Route], channel_id: &str) -> Option<&'a Route> {
+    if let [route] = routes {
+        return Some(route);
+    }
+    if let Some(route) = routes
+        .iter()
+        .find(|r| r.channel_id.as_deref() == Some(channel_id) || r.channel == channel_id)
+    {
+        return Some(route);
+    }
+    let name = match channel_name(channel_id).await {
+        Ok(name) => name,
+        Err(e) => {
+            log::warn!("Failed to look up channel {}: {}", channel_id, e);
+            return None;
+        }
+    };
+    routes
+        .iter()
+        .find(|r| r.channel.trim_start_matches('#') == name)
+}
+
+async fn channel_name(channel_id: &str) -> anyhow::Result<String> {
+    let token = env::var("SLACK_BOT_TOKEN")?;
+    let url = format!("{CONVERSATIONS_INFO_URL}?channel={channel_id}");
+    let authorization = format!("Bearer {token}");
+    let reply: Value = http::send(
+        Method::GET,
+        &url,
+        &[("Authorization", &authorization)],
+        None,
+    )
+    .await?
+    .json()?;
+    match reply["channel"]["name"].as_str() {
+        Some(name) => Ok(name.to_string()),
+        None => anyhow::bail!("Slack said {}", reply["error"].as_str().unwrap_or("no")),
+    }
+}
@@ -0,0 +1,134 @@
+//! Slash commands, a way to ask the bot without a trigger word: `/ask`
+//! has the question answered as any other, and a command named after a
+//! tool, such as `/weather Tokyo` or `/scrape <url>`, has that tool called
+//! for it. Slack sends them to the webhook endpoint with `?slack_commands`.
+
+use super::event;
+use super::routes;
+use super::status;
+use super::{Resumed, REGISTRY};
+use crate::access;
+use crate::commands;
+use crate::core::session::SessionKey;
+use crate::moderation::{self, Moderation};
+use crate::status::ChatPlatform;
+use crate::usage::Budget;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// The command handing a question to the whole agent.
+const ASK: &str = "/ask";
+
+/// A slash command, sent as a form.
+#[derive(Deserialize)]
+pub struct SlashCommand {
+    #[serde(default)]
+    token: String,
+    command: String,
+    #[serde(default)]
+    text: String,
+    user_id: String,
+    channel_id: String,
+}
+
+impl SlashCommand {
+    /// The command in `body`, or `None` when it can't be read or isn't
+    /// verified.
+    pub fn parse(body: &[u8]) -> Option<Self> {
+        let command: Self = match from_form(body) {
+            Ok(command) => command,
+            Err(e) => {
+                log::warn!("Failed to read a Slack command: {}", e);
+                return None;
+            }
+        };
+        if !event::verified(&command.token) {
+            log::warn!("Ignored a Slack command with the wrong token");
+            return None;
+        }
+        Some(command)
+    }
+
+    /// The immediate reply, which shows the command in the channel unless
+    /// answers there are ephemeral or it's missing its text.
+    pub async fn acknowledgement(&self) -> Value {
+        if self.text.trim().is_empty() {
+            return json!({
+                "response_type": "ephemeral",
+                "text": format!("Try {} followed by what you'd like to know.", self.command),
+            });
+        }
+        let ephemeral = routes::for_channel(&routes::from_env(), &self.channel_id)
+            .await
+            .is_some_and(|route| status::ephemeral_in(&route.channel));
+        if ephemeral {
+            return json!({ "response_type": "ephemeral", "text": "Working on it..." });
+        }
+        json!({ "response_type": "in_channel" })
+    }
+
+    /// Answers the command in the channel it was sent from, with that
+    /// channel's settings and the sender's conversation there.
+    pub async fn run(self) {
+        let text = self.text.trim();
+        if text.is_empty() {
+            return;
+        }
+        let routes = routes::from_env();
+        let Some(route) = routes::for_channel(&routes, &self.channel_id).await else {
+            log::warn!("No route for a command from channel {}", self.channel_id);
+            return;
+        };
+        let key = SessionKey::new(&route.channel, &self.user_id);
+        let Some(mut resumed) = Resumed::open(
+            &route.workspace,
+            &self.channel_id,
+            &key,
+            &self.user_id,
+            false,
+        ) else {
+            return;
+        };
+
+        if !access::may_trigger(&self.user_id).await {
+            return resumed.slack.post(access::NOT_AUTHORIZED).await;
+        }
+        if !commands::is_admin(&self.user_id) {
+            if let Some(notice) = Budget::from_env().exhausted() {
+                return resumed.slack.post(&notice).await;
+            }
+        }
+        if Moderation::from_env()
+            .check(&resumed.client, text)
+            .await
+            .is_some()
+        {
+            return resumed.slack.post(&moderation::refusal()).await;
+        }
+
+        let name = self.command.trim_start_matches('/');
+        if self.command != ASK {
+            match REGISTRY
+                .find(name)
+                .filter(|tool| !resumed.config.disabled_tools.contains(tool.name()))
+            {
+                Some(tool) => resumed.config = resumed.config.clone().forcing(tool.name()),
+                None => {
+                    return resumed
+                        .slack
+                        .post(&format!("There is no tool called {name} to use here."))
+                        .await
+                }
+            }
+        }
+        resumed.answer(text.to_string(), None).await;
+    }
+}
+
+/// Reads the fields of a form into `SlashCommand`.
+fn from_form(body: &[u8]) -> serde_json::Result<SlashCommand> {
+    let fields: serde_json::Map<String, Value> = url::form_urlencoded::parse(body)
+        .map(|(name, value)| (name.into_owned(), json!(value)))
+        .collect();
+    serde_json::from_value(Value::Object(fields))
+}
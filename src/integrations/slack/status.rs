@@ -0,0 +1,601 @@
+use super::{blocks, dm, interactions};
+use crate::chunking;
+use crate::core::session::SessionKey;
+use crate::postprocess::{Answer, Markup, PostProcess};
+use crate::prefs::ChannelPrefs;
+use crate::status::{confirmation_prompt, ChatPlatform, SharedFile, StatusSink};
+use crate::tools::http::{self, Method};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use slack_flows::{send_message_to_channel, upload_file};
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+const POST_MESSAGE_URL: &str = "https://slack.com/api/chat.postMessage";
+const POST_EPHEMERAL_URL: &str = "https://slack.com/api/chat.postEphemeral";
+const UPDATE_MESSAGE_URL: &str = "https://slack.com/api/chat.update";
+const DELETE_MESSAGE_URL: &str = "https://slack.com/api/chat.delete";
+const UPLOAD_URL_URL: &str = "https://slack.com/api/files.getUploadURLExternal";
+const COMPLETE_UPLOAD_URL: &str = "https://slack.com/api/files.completeUploadExternal";
+/// Where the `response_url` of a click points.
+const RESPONSE_URL_PREFIX: &str = "https://hooks.slack.com/";
+const DEFAULT_PAGE_CHARS: usize = 3000;
+/// Slack cuts messages off well past this, but they get hard to read.
+const MAX_MESSAGE_CHARS: usize = 4000;
+/// Text that would take more messages than this is uploaded as a file.
+const MAX_MESSAGES: usize = 4;
+/// Starts a question whose answer only the asker sees.
+const PRIVATE_PREFIX: &str = "!private";
+
+/// A Slack thread, named by its channel's ID and its first message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thread {
+    pub channel_id: String,
+    pub ts: String,
+}
+
+/// Posts status updates to a Slack channel, or to a thread in it.
+pub struct SlackStatus {
+    pub workspace: String,
+    pub channel: String,
+    /// The channel's ID, which posting with the bot token takes.
+    pub channel_id: Option<String>,
+    pub thread: Option<Thread>,
+    /// The user who alone sees what's posted, as ephemeral messages.
+    pub visible_to: Option<String>,
+    /// The timestamp of the message showing progress, until the answer
+    /// replaces it.
+    progress: Mutex<Option<String>>,
+    /// Whether Slack previews the links in what's posted, which answers
+    /// listing many links turn off.
+    unfurl: AtomicBool,
+}
+
+impl SlackStatus {
+    pub fn new(workspace: &str, channel: &str) -> Self {
+        Self {
+            workspace: workspace.to_string(),
+            channel: channel.to_string(),
+            channel_id: None,
+            thread: None,
+            visible_to: None,
+            progress: Mutex::new(None),
+            unfurl: AtomicBool::new(true),
+        }
+    }
+
+    pub fn with_channel_id(mut self, channel_id: &str) -> Self {
+        self.channel_id = Some(channel_id.to_string()).filter(|id| !id.is_empty());
+        self
+    }
+
+    pub fn in_thread(mut self, thread: Option<Thread>) -> Self {
+        self.thread = thread;
+        self
+    }
+
+    /// Shows everything posted only to `user`, when given.
+    pub fn visible_only_to(mut self, user: Option<&str>) -> Self {
+        self.visible_to = user.map(str::to_string);
+        self
+    }
+
+    /// The channel's ID, when known.
+    pub fn channel_id(&self) -> Option<&String> {
+        match &self.thread {
+            Some(thread) => Some(&thread.channel_id),
+            None => self.channel_id.as_ref(),
+        }
+    }
+
+    /// Whether only the bot token can post what's posted here: in a DM, or
+    /// for one user's eyes, as `slack_flows` only posts to channels.
+    fn token_only(&self) -> bool {
+        dm::is_dm(&self.channel) || self.visible_to.is_some()
+    }
+
+    /// The channel ID to post blocks to, when `reply_blocks` allows them.
+    fn blocks_channel(&self) -> Option<&String> {
+        self.channel_id().filter(|_| blocks_enabled())
+    }
+
+    /// Posts `blocks`, with `text` for notifications, in the channel or
+    /// thread, returning the message's timestamp. `None` when they couldn't
+    /// be posted.
+    async fn post_blocks(
+        &self,
+        channel_id: &str,
+        text: &str,
+        blocks: Vec<Value>,
+    ) -> Option<String> {
+        let mut message = self.message(channel_id, text);
+        message["blocks"] = json!(blocks);
+        match self.send_message(message).await {
+            Ok(ts) => Some(ts),
+            Err(e) => {
+                log::warn!("Failed to post blocks in {}: {}", channel_id, e);
+                None
+            }
+        }
+    }
+
+    /// A `chat.postMessage` body saying `text` in the channel or thread.
+    fn message(&self, channel_id: &str, text: &str) -> Value {
+        let mut message = json!({ "channel": channel_id, "text": text });
+        if let Some(thread) = &self.thread {
+            message["thread_ts"] = json!(thread.ts);
+        }
+        if !self.unfurl.load(Ordering::Relaxed) {
+            message["unfurl_links"] = json!(false);
+            message["unfurl_media"] = json!(false);
+        }
+        message
+    }
+
+    /// Posts `message` with the bot token, shown only to `visible_to` when
+    /// set, and returns its timestamp. Ephemeral messages can't be edited
+    /// or reacted to, so theirs is left empty.
+    async fn send_message(&self, mut message: Value) -> anyhow::Result<String> {
+        let Some(user) = &self.visible_to else {
+            return post_message(&message).await;
+        };
+        message["user"] = json!(user);
+        call_api(
+            POST_EPHEMERAL_URL,
+            "application/json; charset=utf-8",
+            message.to_string(),
+        )
+        .await?;
+        Ok(String::new())
+    }
+
+    /// Edits the progress message, if there is one, to say `text`. Returns
+    /// whether it was edited.
+    async fn edit_progress(&self, text: &str) -> bool {
+        let (Some(channel_id), Some(ts)) = (self.channel_id(), self.progress_ts()) else {
+            return false;
+        };
+        match update_message(channel_id, &ts, text, None).await {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("Failed to edit the progress message {}: {}", ts, e);
+                false
+            }
+        }
+    }
+
+    /// Turns the progress message, if there is one, into the message
+    /// `text` with `blocks`, and returns its timestamp. A message that can't
+    /// be edited is deleted instead, and so is one whose links mustn't be
+    /// previewed, as an edit can't say so.
+    async fn replace_progress(&self, text: &str, blocks: Option<Vec<Value>>) -> Option<String> {
+        if !self.unfurl.load(Ordering::Relaxed) {
+            self.clear_progress().await;
+            return None;
+        }
+        let ts = self.progress.lock().ok()?.take()?;
+        let channel_id = self.channel_id()?;
+        match update_message(channel_id, &ts, text, blocks).await {
+            Ok(()) => return Some(ts),
+            Err(e) => log::warn!("Failed to replace the progress message {}: {}", ts, e),
+        }
+        self.delete_message(channel_id, &ts).await;
+        None
+    }
+
+    /// The timestamp of the progress message, if there is one.
+    fn progress_ts(&self) -> Option<String> {
+        self.progress
+            .lock()
+            .ok()
+            .and_then(|progress| progress.clone())
+    }
+
+    /// Deletes the progress message, if there is one, for something that
+    /// can't take its place or when there's no answer to post.
+    pub async fn clear_progress(&self) {
+        let Some(ts) = self.progress.lock().ok().and_then(|mut p| p.take()) else {
+            return;
+        };
+        if let Some(channel_id) = self.channel_id() {
+            self.delete_message(channel_id, &ts).await;
+        }
+    }
+
+    async fn delete_message(&self, channel_id: &str, ts: &str) {
+        let message = json!({ "channel": channel_id, "ts": ts });
+        if let Err(e) = call_api(
+            DELETE_MESSAGE_URL,
+            "application/json; charset=utf-8",
+            message.to_string(),
+        )
+        .await
+        {
+            log::warn!("Failed to delete the message {}: {}", ts, e);
+        }
+    }
+
+    /// Posts `text` in as many messages as it takes, split at paragraphs.
+    /// Text too long for a few messages is uploaded as a file, with its
+    /// start posted to say so. Text that fits one message takes the place of
+    /// the progress message. Returns the first message's timestamp, when
+    /// known.
+    async fn post_text(&self, text: &str) -> Option<String> {
+        let pieces = chunking::split(text, MAX_MESSAGE_CHARS);
+        if pieces.len() == 1 {
+            if let Some(ts) = self.replace_progress(text, None).await {
+                return Some(ts);
+            }
+        } else {
+            self.clear_progress().await;
+        }
+        // Files are seen by the whole channel, and `slack_flows` can't upload
+        // them to DMs.
+        if pieces.len() > MAX_MESSAGES && !self.token_only() {
+            let ts = self
+                .post_message_text(&format!(
+                    "{}\n\n_That's the start. The whole answer is too long to post, so it's \
+                     attached as answer.txt._",
+                    pieces[0]
+                ))
+                .await;
+            upload_file(
+                &self.workspace,
+                &self.channel,
+                "answer.txt",
+                "text",
+                text.as_bytes().to_vec(),
+            )
+            .await;
+            return ts;
+        }
+        let mut first = None;
+        for piece in &pieces {
+            let ts = self.post_message_text(piece).await;
+            first = first.or(ts);
+        }
+        first
+    }
+
+    /// Posts `text` as one message, falling back to the channel itself if the
+    /// thread can't be posted to. The message's timestamp is only known when
+    /// it was posted with the bot token. A message only one user may see is
+    /// never posted to the channel.
+    async fn post_message_text(&self, text: &str) -> Option<String> {
+        // Only the bot token can post in threads, DMs, or to one user.
+        let with_token = self.thread.is_some() || self.token_only();
+        if let Some(channel_id) = self.channel_id().filter(|_| with_token) {
+            match self.send_message(self.message(channel_id, text)).await {
+                Ok(ts) => return Some(ts),
+                Err(e) => log::warn!("Failed to post in {}: {}", channel_id, e),
+            }
+        }
+        if self.token_only() {
+            return None;
+        }
+        send_message_to_channel(&self.workspace, &self.channel, text.to_string()).await;
+        None
+    }
+
+    /// Posts an answer, once it's been through `reply_postprocess`. Returns
+    /// the timestamp of the message posted, when known.
+    pub async fn post_answer(&self, text: &str) -> Option<String> {
+        let answer = PostProcess::from_env().apply(text, Markup::Slack);
+        self.post_processed(answer).await
+    }
+
+    /// Posts an answer as Block Kit blocks when `reply_blocks` allows it,
+    /// falling back to plain text. A long answer is posted a page at a time,
+    /// with a button for the next.
+    pub async fn post_processed(&self, answer: Answer) -> Option<String> {
+        self.unfurl.store(answer.unfurl, Ordering::Relaxed);
+        let text = answer.text.as_str();
+        if let Some(channel_id) = self.blocks_channel() {
+            let pages = blocks::pages(text, page_chars());
+            let next_page = match pages.get(1..) {
+                Some(rest) if !rest.is_empty() => {
+                    interactions::next_page_block(self, channel_id, &rest.join("\n\n"))
+                }
+                _ => None,
+            };
+            let (text, blocks) = match next_page {
+                Some(button) => {
+                    let mut blocks = blocks::render(&pages[0]);
+                    blocks.push(button);
+                    (pages[0].as_str(), blocks)
+                }
+                None => (text, blocks::render(text)),
+            };
+            if let Some(ts) = self.replace_progress(text, Some(blocks.clone())).await {
+                return Some(ts);
+            }
+            if let Some(ts) = self.post_blocks(channel_id, text, blocks).await {
+                return Some(ts);
+            }
+        }
+        self.post_text(text).await
+    }
+}
+
+#[async_trait]
+impl ChatPlatform for SlackStatus {
+    fn channel_key(&self) -> String {
+        self.channel.clone()
+    }
+
+    /// In a thread, everyone shares the thread's conversation.
+    fn session_key(&self, user: &str) -> SessionKey {
+        SessionKey::new(&self.channel, user).in_thread(self.thread.as_ref().map(|t| t.ts.clone()))
+    }
+
+    async fn post(&self, text: &str) {
+        self.post_text(text).await;
+    }
+}
+
+#[async_trait]
+impl StatusSink for SlackStatus {
+    /// Shown in the progress message while there is one.
+    async fn status(&self, text: &str) {
+        if !self.edit_progress(text).await {
+            self.post(text).await;
+        }
+    }
+
+    /// Offers "Run it" and "Cancel" buttons along with the typed reply.
+    async fn confirm(&self, session: &SessionKey, question: &str) {
+        // The request stays put, so it can't be replaced by the answer.
+        self.clear_progress().await;
+        if let Some(channel_id) = self.blocks_channel() {
+            let prompt = confirmation_prompt(question);
+            if let Some(blocks) = interactions::confirm_blocks(
+                &self.workspace,
+                channel_id,
+                session,
+                &prompt,
+                self.visible_to.is_some(),
+            ) {
+                if self
+                    .post_blocks(channel_id, &prompt, blocks)
+                    .await
+                    .is_some()
+                {
+                    return;
+                }
+            }
+        }
+        self.status(&confirmation_prompt(question)).await;
+    }
+
+    /// Posts a message when the first tool starts running, and keeps it up
+    /// to date until the answer takes its place. Ephemeral messages can't be
+    /// edited, so none is posted for them.
+    async fn progress(&self, text: &str) {
+        let Some(channel_id) = self
+            .channel_id()
+            .filter(|_| progress_enabled() && self.visible_to.is_none())
+        else {
+            return;
+        };
+        if let Some(ts) = self.progress_ts() {
+            if let Err(e) = update_message(channel_id, &ts, text, None).await {
+                log::warn!("Failed to edit the progress message {}: {}", ts, e);
+            }
+            return;
+        }
+        match post_message(&self.message(channel_id, text)).await {
+            Ok(ts) => {
+                if let Ok(mut progress) = self.progress.lock() {
+                    *progress = Some(ts);
+                }
+            }
+            Err(e) => log::warn!("Failed to post progress in {}: {}", channel_id, e),
+        }
+    }
+
+    /// Uploads `content` to the thread with the bot token, which gives a
+    /// link to it, or else to the channel through `slack_flows`. Nothing is
+    /// shared when only one user is to see the answer.
+    async fn share_file(&self, name: &str, content: &str) -> Option<SharedFile> {
+        if self.visible_to.is_some() {
+            return None;
+        }
+        if let Some(channel_id) = self.channel_id() {
+            if env::var("SLACK_BOT_TOKEN").is_ok() {
+                let thread_ts = self.thread.as_ref().map(|t| t.ts.as_str());
+                match upload(channel_id, thread_ts, name, content).await {
+                    Ok(permalink) => {
+                        return Some(SharedFile {
+                            permalink: Some(permalink),
+                        })
+                    }
+                    Err(e) => log::warn!("Failed to upload {}: {}", name, e),
+                }
+            }
+        }
+        if dm::is_dm(&self.channel) {
+            return None;
+        }
+        upload_file(
+            &self.workspace,
+            &self.channel,
+            name,
+            "text",
+            content.as_bytes().to_vec(),
+        )
+        .await;
+        Some(SharedFile { permalink: None })
+    }
+}
+
+/// Whether answers go in threads, which takes posting with the bot token in
+/// `SLACK_BOT_TOKEN`, as `slack_flows` only posts to channels. Turned off
+/// by setting `reply_in_threads` to `false`.
+pub fn threads_enabled() -> bool {
+    env::var("SLACK_BOT_TOKEN").is_ok() && env::var("reply_in_threads").as_deref() != Ok("false")
+}
+
+/// Whether answers are laid out with Block Kit, which also takes the bot
+/// token. Turned off by setting `reply_blocks` to `false`.
+pub fn blocks_enabled() -> bool {
+    env::var("SLACK_BOT_TOKEN").is_ok() && env::var("reply_blocks").as_deref() != Ok("false")
+}
+
+/// Whether a message showing which tool is running is posted and kept up
+/// to date until the answer replaces it, which also takes the bot token.
+/// Turned off by setting `reply_progress` to `false`.
+pub fn progress_enabled() -> bool {
+    env::var("SLACK_BOT_TOKEN").is_ok() && env::var("reply_progress").as_deref() != Ok("false")
+}
+
+/// Whether answers in `channel` are shown only to the user who asked, as
+/// set with `!ephemeral` or else by `reply_ephemeral`. Ephemeral messages
+/// take the bot token.
+pub fn ephemeral_in(channel: &str) -> bool {
+    env::var("SLACK_BOT_TOKEN").is_ok() && ChannelPrefs::load(channel).answers_ephemeral()
+}
+
+/// Whether `text` starts with `!private`, and the text without it.
+pub fn strip_private(text: &str) -> (bool, String) {
+    let trimmed = text.trim_start();
+    match trimmed.strip_prefix(PRIVATE_PREFIX) {
+        Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => {
+            (true, rest.trim().to_string())
+        }
+        _ => (false, text.to_string()),
+    }
+}
+
+/// How many characters of an answer are posted at a time, from
+/// `reply_page_chars`.
+fn page_chars() -> usize {
+    env::var("reply_page_chars")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&chars| chars > 0)
+        .unwrap_or(DEFAULT_PAGE_CHARS)
+}
+
+/// Posts `message`, a `chat.postMessage` body, with the bot token, and
+/// returns the new message's timestamp.
+async fn post_message(message: &Value) -> anyhow::Result<String> {
+    let reply = call_api(
+        POST_MESSAGE_URL,
+        "application/json; charset=utf-8",
+        message.to_string(),
+    )
+    .await?;
+    Ok(reply["ts"].as_str().unwrap_or_default().to_string())
+}
+
+/// Edits the message `ts` to say `text`, laid out with `blocks` if given.
+async fn update_message(
+    channel_id: &str,
+    ts: &str,
+    text: &str,
+    blocks: Option<Vec<Value>>,
+) -> anyhow::Result<()> {
+    let mut message = json!({ "channel": channel_id, "ts": ts, "text": text });
+    if let Some(blocks) = blocks {
+        message["blocks"] = json!(blocks);
+    }
+    call_api(
+        UPDATE_MESSAGE_URL,
+        "application/json; charset=utf-8",
+        message.to_string(),
+    )
+    .await
+    .map(|_| ())
+}
+
+/// Uploads `content` as a file called `name` to the channel, or the thread
+/// when `thread_ts` is given, and returns its permalink.
+async fn upload(
+    channel_id: &str,
+    thread_ts: Option<&str>,
+    name: &str,
+    content: &str,
+) -> anyhow::Result<String> {
+    let form = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("filename", name)
+        .append_pair("length", &content.len().to_string())
+        .finish();
+    let reply = call_api(UPLOAD_URL_URL, "application/x-www-form-urlencoded", form).await?;
+    let (Some(upload_url), Some(file_id)) =
+        (reply["upload_url"].as_str(), reply["file_id"].as_str())
+    else {
+        anyhow::bail!("Slack gave no upload URL");
+    };
+    send(
+        upload_url,
+        "text/plain; charset=utf-8",
+        content.as_bytes().to_vec(),
+        None,
+    )
+    .await?;
+
+    let mut complete = json!({
+        "files": [{ "id": file_id, "title": name }],
+        "channel_id": channel_id,
+    });
+    if let Some(thread_ts) = thread_ts {
+        complete["thread_ts"] = json!(thread_ts);
+    }
+    let reply = call_api(
+        COMPLETE_UPLOAD_URL,
+        "application/json; charset=utf-8",
+        complete.to_string(),
+    )
+    .await?;
+    reply["files"][0]["permalink"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("Slack gave no link to the file"))
+}
+
+/// Calls a Web API method with the bot token.
+async fn call_api(url: &str, content_type: &str, body: String) -> anyhow::Result<Value> {
+    let token = env::var("SLACK_BOT_TOKEN")?;
+    let reply = send(url, content_type, body.into_bytes(), Some(&token)).await?;
+    // Slack answers 200 with `ok: false` for most failures.
+    let reply: Value = serde_json::from_slice(&reply)?;
+    if reply["ok"].as_bool() != Some(true) {
+        anyhow::bail!("Slack said {}", reply["error"].as_str().unwrap_or("no"));
+    }
+    Ok(reply)
+}
+
+/// Posts `message` to the `response_url` of a click, which needs no token.
+/// URLs anywhere but Slack's are refused, as the click says where to post.
+pub async fn post_json(url: &str, message: &Value) -> anyhow::Result<()> {
+    if !url.starts_with(RESPONSE_URL_PREFIX) {
+        anyhow::bail!("{url} isn't a Slack response URL");
+    }
+    send(
+        url,
+        "application/json; charset=utf-8",
+        message.to_string().into_bytes(),
+        None,
+    )
+    .await
+    .map(|_| ())
+}
+
+async fn send(
+    url: &str,
+    content_type: &str,
+    body: Vec<u8>,
+    token: Option<&str>,
+) -> anyhow::Result<Vec<u8>> {
+    let authorization = token.map(|token| format!("Bearer {token}"));
+    let mut headers = vec![("Content-Type", content_type)];
+    if let Some(authorization) = &authorization {
+        headers.push(("Authorization", authorization));
+    }
+    let response = http::send(Method::POST, url, &headers, Some(body)).await?;
+    if !response.is_success() {
+        anyhow::bail!("Slack returned {}", response.status);
+    }
+    Ok(response.body)
+}
@@ -0,0 +1,60 @@
+use crate::store::{get, set, Expire, ExpireKind};
+use crate::tools::http::{self, Method};
+use serde_json::{json, Value};
+use std::env;
+
+const USERS_INFO_URL: &str = "https://slack.com/api/users.info";
+
+/// The name a Slack user goes by, looked up with `SLACK_BOT_TOKEN` and kept
+/// for a day. `None` without a token or when Slack won't say.
+pub async fn display_name(user_id: &str) -> Option<String> {
+    let key = format!("user_name:{user_id}");
+    if let Some(name) = get(&key).and_then(|v| v.as_str().map(str::to_string)) {
+        return Some(name);
+    }
+    let name = match look_up(user_id).await {
+        Ok(name) => name,
+        Err(e) => {
+            log::warn!("Failed to look up the name of {}: {}", user_id, e);
+            return None;
+        }
+    };
+    set(
+        &key,
+        json!(name),
+        Some(Expire {
+            kind: ExpireKind::Ex,
+            value: 24 * 60 * 60,
+        }),
+    );
+    Some(name)
+}
+
+async fn look_up(user_id: &str) -> anyhow::Result<String> {
+    let token = env::var("SLACK_BOT_TOKEN")?;
+    let url = format!("{USERS_INFO_URL}?user={user_id}");
+    let authorization = format!("Bearer {token}");
+    let reply: Value = http::send(
+        Method::GET,
+        &url,
+        &[("Authorization", &authorization)],
+        None,
+    )
+    .await?
+    .json()?;
+    let user = &reply["user"];
+    // Display names are optional in Slack, real names less so.
+    let name = [
+        &user["profile"]["display_name"],
+        &user["profile"]["real_name"],
+        &user["name"],
+    ]
+    .into_iter()
+    .filter_map(|name| name.as_str())
+    .find(|name| !name.is_empty())
+    .map(str::to_string);
+    match name {
+        Some(name) => Ok(name),
+        None => anyhow::bail!("Slack said {}", reply["error"].as_str().unwrap_or("no")),
+    }
+}
@@ -0,0 +1,135 @@
+use crate::client::ChatClient;
+use crate::commands;
+use crate::config::Config;
+use crate::core::Agent;
+use crate::health;
+use crate::integrations::slack;
+use crate::postprocess::{Markup, PostProcess};
+use crate::tools::{ToolContext, SLACK_ONLY_TOOLS};
+use crate::usage;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use webhook_flows::{request_handler, send_response};
+
+/// Names the webhook in session, usage and preference keys, which callers'
+/// users share with no Slack channel.
+const CHANNEL: &str = "webhook";
+
+#[derive(Deserialize)]
+struct WebhookRequest {
+    user: String,
+    message: String,
+}
+
+/// Answers `{"user": ..., "message": ...}` with `{"answer": ...}`, keeping a
+/// conversation per user like the Slack handler does. With `WEBHOOK_TOKEN`
+/// set, callers have to send it as a bearer token. With a `health` query
+/// parameter, the health checks are run instead. With `slack_interactions`,
+/// `slack_events` or `slack_commands`, a click on one of the bot's Slack
+/// buttons, a reaction to one of its answers or a direct message to it, or
+/// a slash command is handled. Slack can't send a token with those, so
+/// they have to be signed with `SLACK_SIGNING_SECRET` or carry
+/// `SLACK_VERIFICATION_TOKEN` instead.
+#[request_handler]
+async fn request_received(
+    headers: Vec<(String, String)>,
+    query: HashMap<String, Value>,
+    body: Vec<u8>,
+) {
+    let from_slack = ["slack_interactions", "slack_commands", "slack_events"]
+        .iter()
+        .any(|name| query.contains_key(*name));
+    if from_slack && !slack::event::signed(&headers, &body) {
+        return respond(401, json!({ "error": "missing or wrong Slack signature" }));
+    }
+    if query.contains_key("slack_interactions") {
+        // Slack only waits 3 seconds, so the click is acknowledged first.
+        send_response(200, Vec::new(), Vec::new());
+        return slack::interactions::handle(&body).await;
+    }
+    if query.contains_key("slack_commands") {
+        let Some(command) = slack::slash::SlashCommand::parse(&body) else {
+            return respond(401, json!({ "error": "unreadable or unverified command" }));
+        };
+        // As with clicks, Slack only waits 3 seconds for the reply.
+        respond(200, command.acknowledgement().await);
+        return command.run().await;
+    }
+    if query.contains_key("slack_events") {
+        if let Some(challenge) = slack::reactions::challenge(&body) {
+            return respond(200, json!({ "challenge": challenge }));
+        }
+        send_response(200, Vec::new(), Vec::new());
+        // A retry means the event arrived before, and was answered then.
+        let retry = headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("x-slack-retry-num"));
+        if !retry {
+            slack::handle_event(&body).await;
+        }
+        return;
+    }
+    if let Ok(token) = env::var("WEBHOOK_TOKEN") {
+        let expected = format!("Bearer {token}");
+        let authorized = headers
+            .iter()
+            .any(|(name, value)| name.eq_ignore_ascii_case("authorization") && *value == expected);
+        if !authorized {
+            return respond(401, json!({ "error": "missing or wrong token" }));
+        }
+    }
+    if query.contains_key("health") {
+        let config = Config::from_env();
+        let checks = health::run(&ChatClient::from_env(), &config.model).await;
+        let status = if checks.iter().all(|check| check.result.is_ok()) {
+            200
+        } else {
+            503
+        };
+        return respond(status, health::to_json(&checks));
+    }
+    let request: WebhookRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            return respond(
+                400,
+                json!({ "error": format!("expected {{\"user\": ..., \"message\": ...}}: {e}") }),
+            )
+        }
+    };
+
+    let user = format!("{CHANNEL}:{}", request.user);
+    let env_config = Config::from_env();
+    let mut disabled_tools = commands::disabled_tools();
+    disabled_tools.extend(SLACK_ONLY_TOOLS.iter().map(|tool| tool.to_string()));
+    let config = Config {
+        model: commands::active_model().unwrap_or(env_config.model.clone()),
+        disabled_tools,
+        ..env_config
+    };
+    let agent = Agent::new(config)
+        .in_channel(CHANNEL)
+        .with_prompt_variable("user_name", &request.user);
+    let context = ToolContext::new(CHANNEL, CHANNEL, &user).in_session(agent.session_key(&user));
+    usage::set_scope(CHANNEL, &user);
+
+    if request.message.trim().eq_ignore_ascii_case("clear") {
+        agent.sessions().reset(&context.session);
+        return respond(200, json!({ "answer": "Cleared our conversation." }));
+    }
+    let out = agent
+        .answer(&request.message, Some(request.message.clone()), &context)
+        .await
+        .map(|out| PostProcess::from_env().apply(&out, Markup::Markdown).text);
+    respond(200, json!({ "answer": out }));
+}
+
+fn respond(status: u16, body: Value) {
+    send_response(
+        status,
+        vec![("Content-Type".to_string(), "application/json".to_string())],
+        body.to_string().into_bytes(),
+    );
+}
@@ -0,0 +1,331 @@
+//! A chat backend that answers from a script, for exercising agents and the
+//! tool-call loop without network access:
+//!
+//! ```ignore
+//! let backend = Arc::new(
+//!     ScriptedBackend::new()
+//!         .then_call("getWeather", r#"{"city": "Tokyo"}"#)
+//!         .then_answer("It's sunny in Tokyo."),
+//! );
+//! let agent = Agent::builder()
+//!     .client(ChatClient::from_backend(backend.clone()))
+//!     .tool(WeatherTool)
+//!     .build();
+//! agent.chat("alice", "Weather in Tokyo?").await?;
+//! assert_eq!(backend.requests().len(), 2);
+//! ```
+
+use crate::client::ChatBackend;
+use async_openai::error::OpenAIError;
+use async_openai::types::{
+    CreateChatCompletionRequest, CreateChatCompletionResponse, FinishReason,
+};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+enum Step {
+    Answer {
+        content: String,
+        finish_reason: FinishReason,
+    },
+    Calls(Vec<(String, String)>),
+    Fail(String),
+}
+
+/// Answers chat completions with the replies it was given, in order, and
+/// keeps the requests it got.
+#[derive(Default)]
+pub struct ScriptedBackend {
+    script: Mutex<VecDeque<Step>>,
+    requests: Mutex<Vec<CreateChatCompletionRequest>>,
+}
+
+impl ScriptedBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replies with `content`, finishing normally.
+    pub fn then_answer(self, content: &str) -> Self {
+        self.then_finish(content, FinishReason::Stop)
+    }
+
+    /// Replies with `content`, finishing for `finish_reason`, such as
+    /// `Length` for a cut-off answer.
+    pub fn then_finish(self, content: &str, finish_reason: FinishReason) -> Self {
+        self.then(Step::Answer {
+            content: content.to_string(),
+            finish_reason,
+        })
+    }
+
+    /// Calls the tool `name` with `arguments`, which needn't be valid JSON.
+    pub fn then_call(self, name: &str, arguments: &str) -> Self {
+        self.then_calls(&[(name, arguments)])
+    }
+
+    /// Calls several tools at once.
+    pub fn then_calls(self, calls: &[(&str, &str)]) -> Self {
+        self.then(Step::Calls(
+            calls
+                .iter()
+                .map(|(name, arguments)| (name.to_string(), arguments.to_string()))
+                .collect(),
+        ))
+    }
+
+    /// Fails the request with an API error saying `message`.
+    pub fn then_fail(self, message: &str) -> Self {
+        self.then(Step::Fail(message.to_string()))
+    }
+
+    /// The requests made so far, oldest first.
+    pub fn requests(&self) -> Vec<CreateChatCompletionRequest> {
+        self.requests
+            .lock()
+            .map(|requests| requests.clone())
+            .unwrap_or_default()
+    }
+
+    fn then(self, step: Step) -> Self {
+        if let Ok(mut script) = self.script.lock() {
+            script.push_back(step);
+        }
+        self
+    }
+}
+
+#[async_trait]
+impl ChatBackend for ScriptedBackend {
+    async fn create_chat(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<CreateChatCompletionResponse, OpenAIError> {
+        let n = match self.requests.lock() {
+            Ok(mut requests) => {
+                requests.push(request.clone());
+                requests.len()
+            }
+            Err(_) => 0,
+        };
+        let step = self.script.lock().ok().and_then(|mut s| s.pop_front());
+        let (message, finish_reason) = match step {
+            Some(Step::Answer {
+                content,
+                finish_reason,
+            }) => (
+                json!({ "role": "assistant", "content": content }),
+                json!(finish_reason),
+            ),
+            Some(Step::Calls(calls)) => {
+                let tool_calls: Vec<Value> = calls
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (name, arguments))| {
+                        json!({
+                            "id": format!("call_{n}_{i}"),
+                            "type": "function",
+                            "function": { "name": name, "arguments": arguments },
+                        })
+                    })
+                    .collect();
+                (
+                    json!({ "role": "assistant", "content": null, "tool_calls": tool_calls }),
+                    json!("tool_calls"),
+                )
+            }
+            Some(Step::Fail(message)) => return Err(api_error(&message)),
+            None => return Err(api_error("the script has run out of replies")),
+        };
+        serde_json::from_value(json!({
+            "id": format!("scripted-{n}"),
+            "object": "chat.completion",
+            "created": 0,
+            "model": request.model,
+            "choices": [{ "index": 0, "message": message, "finish_reason": finish_reason }],
+            "usage": { "prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0 },
+        }))
+        .map_err(OpenAIError::JSONDeserialize)
+    }
+}
+
+fn api_error(message: &str) -> OpenAIError {
+    OpenAIError::ApiError(async_openai::error::ApiError {
+        message: message.to_string(),
+        r#type: None,
+        param: None,
+        code: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ChatClient;
+    use crate::config::Config;
+    use crate::core::{chat_inner, Tool, ToolContext, ToolRegistry};
+    use async_openai::types::{
+        ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
+    };
+    use std::sync::Arc;
+
+    /// Returns the `text` it's called with, and keeps it.
+    struct Echo(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl Tool for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn description(&self) -> &str {
+            "Repeats text"
+        }
+
+        fn parameters(&self) -> Value {
+            json!({
+                "type": "object",
+                "properties": { "text": { "type": "string" } },
+                "required": ["text"],
+            })
+        }
+
+        async fn execute(&self, arguments: &str, _: &ToolContext) -> anyhow::Result<String> {
+            let text = serde_json::from_str::<Value>(arguments)?["text"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            self.0.lock().unwrap().push(text.clone());
+            Ok(text)
+        }
+    }
+
+    struct Run {
+        answer: Option<String>,
+        requests: Vec<CreateChatCompletionRequest>,
+        calls: Vec<String>,
+    }
+
+    async fn run(script: ScriptedBackend) -> Run {
+        // Tool calls are counted and usage recorded in the store.
+        crate::store::use_memory_for_thread();
+        let backend = Arc::new(script);
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut registry = ToolRegistry::new();
+        registry.register(Echo(calls.clone()));
+        let mut messages = vec![ChatCompletionRequestSystemMessageArgs::default()
+            .content("Be brief.")
+            .build()
+            .unwrap()
+            .into()];
+        let answer = chat_inner(
+            "Say something",
+            &mut messages,
+            &ChatClient::from_backend(backend.clone()),
+            &registry,
+            &Config::default(),
+            &ToolContext::new("test", "test", "alice"),
+        )
+        .await
+        .unwrap();
+        let calls = calls.lock().unwrap().clone();
+        Run {
+            answer,
+            requests: backend.requests(),
+            calls,
+        }
+    }
+
+    /// The tool results sent with `request`, by call ID.
+    fn tool_results(request: &CreateChatCompletionRequest) -> Vec<(String, String)> {
+        request
+            .messages
+            .iter()
+            .filter_map(|m| match m {
+                ChatCompletionRequestMessage::Tool(m) => Some((
+                    m.tool_call_id.clone(),
+                    m.content.clone().unwrap_or_default(),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn answers_without_tools() {
+        let run = run(ScriptedBackend::new().then_answer("Hello.")).await;
+        assert_eq!(run.answer.as_deref(), Some("Hello."));
+        assert_eq!(run.requests.len(), 1);
+        assert!(run.calls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn runs_every_call_of_a_round() {
+        let run = run(ScriptedBackend::new()
+            .then_calls(&[
+                ("echo", r#"{"text": "one"}"#),
+                ("echo", r#"{"text": "two"}"#),
+            ])
+            .then_answer("one, two"))
+        .await;
+        assert_eq!(run.answer.as_deref(), Some("one, two"));
+        assert_eq!(run.calls, ["one", "two"]);
+        let results = tool_results(&run.requests[1]);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, "call_1_0");
+        assert!(results[0].1.contains("one"));
+        assert_eq!(results[1].0, "call_1_1");
+        assert!(results[1].1.contains("two"));
+    }
+
+    #[tokio::test]
+    async fn sends_malformed_arguments_back() {
+        let run = run(ScriptedBackend::new()
+            .then_call("echo", "{text: one")
+            .then_call("echo", r#"{"text": "one"}"#)
+            .then_answer("one"))
+        .await;
+        assert_eq!(run.answer.as_deref(), Some("one"));
+        // Only the corrected call runs.
+        assert_eq!(run.calls, ["one"]);
+        let results = tool_results(&run.requests[1]);
+        assert!(results[0].1.contains("aren't JSON"), "{}", results[0].1);
+    }
+
+    #[tokio::test]
+    async fn reports_unknown_tools() {
+        let run = run(ScriptedBackend::new()
+            .then_call("nope", "{}")
+            .then_answer("Sorry."))
+        .await;
+        assert_eq!(run.answer.as_deref(), Some("Sorry."));
+        let results = tool_results(&run.requests[1]);
+        assert!(results[0].1.contains("Unknown function: nope"));
+    }
+
+    #[tokio::test]
+    async fn keeps_answers_cut_short() {
+        for reason in [FinishReason::Length, FinishReason::ContentFilter] {
+            let run = run(ScriptedBackend::new().then_finish("It was", reason)).await;
+            assert_eq!(run.answer.as_deref(), Some("It was"));
+            assert_eq!(run.requests.len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn withholds_tools_after_the_last_round() {
+        let rounds = Config::default().max_tool_rounds;
+        let mut script = ScriptedBackend::new();
+        for _ in 0..rounds {
+            script = script.then_call("echo", r#"{"text": "again"}"#);
+        }
+        let run = run(script.then_answer("Done.")).await;
+        assert_eq!(run.answer.as_deref(), Some("Done."));
+        assert_eq!(run.calls.len(), rounds);
+        assert_eq!(run.requests.len(), rounds + 1);
+        assert!(run.requests[rounds - 1].tools.is_some());
+        assert!(run.requests[rounds].tools.is_none());
+    }
+}
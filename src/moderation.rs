@@ -0,0 +1,94 @@
+use crate::client::ChatClient;
+use async_openai::types::CreateModerationRequestArgs;
+use std::env;
+
+const DEFAULT_REFUSAL: &str =
+    "Sorry, I can't help with that message. Please keep requests within this community's guidelines.";
+
+/// How incoming messages are screened before they reach the chat model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Moderation {
+    Off,
+    /// OpenAI's moderation endpoint, which Azure OpenAI doesn't offer.
+    OpenAI,
+    /// Refuses messages containing any of these words or phrases.
+    Local(Vec<String>),
+}
+
+impl Moderation {
+    /// Reads `moderation` (`off`, `openai` or `local`) and, for the local
+    /// filter, the comma-separated `moderation_blocklist`.
+    pub fn from_env() -> Self {
+        match env::var("moderation").as_deref() {
+            Ok("openai") => Moderation::OpenAI,
+            Ok("local") => Moderation::Local(
+                env::var("moderation_blocklist")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(normalize)
+                    .filter(|phrase| !phrase.is_empty())
+                    .collect(),
+            ),
+            _ => Moderation::Off,
+        }
+    }
+
+    /// Why `text` shouldn't be answered, or `None` when it may be.
+    ///
+    /// Messages are let through when the moderation endpoint can't be
+    /// reached, so an outage doesn't silence the bot.
+    pub async fn check(&self, client: &ChatClient, text: &str) -> Option<String> {
+        match self {
+            Moderation::Off => None,
+            Moderation::Local(blocklist) => {
+                let text = format!(" {} ", normalize(text));
+                blocklist
+                    .iter()
+                    .find(|phrase| text.contains(&format!(" {phrase} ")))
+                    .map(|phrase| format!("it contains \"{phrase}\""))
+            }
+            Moderation::OpenAI => {
+                let request = match CreateModerationRequestArgs::default().input(text).build() {
+                    Ok(request) => request,
+                    Err(e) => {
+                        log::warn!("Failed to build a moderation request: {}", e);
+                        return None;
+                    }
+                };
+                let response = match client.moderate(request).await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        log::warn!("Moderation failed, letting the message through: {}", e);
+                        return None;
+                    }
+                };
+                let result = response.results.into_iter().find(|r| r.flagged)?;
+                let categories: Vec<String> = serde_json::to_value(&result.categories)
+                    .ok()
+                    .and_then(|v| v.as_object().cloned())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|(_, flagged)| flagged.as_bool() == Some(true))
+                    .map(|(category, _)| category)
+                    .collect();
+                Some(format!("it was flagged for {}", categories.join(", ")))
+            }
+        }
+    }
+}
+
+/// What the user is told when their message is refused, from
+/// `moderation_message`.
+pub fn refusal() -> String {
+    env::var("moderation_message").unwrap_or(DEFAULT_REFUSAL.to_string())
+}
+
+/// Lowercases `text` and reduces it to words separated by single spaces, so
+/// phrases match whatever punctuation surrounds them.
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,85 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+
+/// A role the bot can take on in a channel: what conversations start with
+/// and which tools it offers.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Persona {
+    pub system_prompt: String,
+    /// The only tools offered. `None` offers every tool.
+    #[serde(default)]
+    pub tools: Option<BTreeSet<String>>,
+}
+
+impl Persona {
+    fn new(system_prompt: &str, tools: &[&str]) -> Self {
+        Self {
+            system_prompt: system_prompt.to_string(),
+            tools: Some(tools.iter().map(|tool| tool.to_string()).collect()),
+        }
+    }
+}
+
+/// The built-in personas, plus those in `personas`, a JSON object of
+/// personas by name that adds to or replaces them.
+pub fn all() -> BTreeMap<String, Persona> {
+    let mut personas = BTreeMap::from([
+        (
+            "ops".to_string(),
+            Persona::new(
+                "You help engineers look into incidents. Check sites, DNS and registrations \
+                 before answering, and say exactly what you checked.",
+                &[
+                    "checkUrl",
+                    "dnsLookup",
+                    "whois",
+                    "exec",
+                    "getTimeOfDay",
+                    "searchWeb",
+                ],
+            ),
+        ),
+        (
+            "researcher".to_string(),
+            Persona::new(
+                "You research questions on the web. Read and summarize sources before \
+                 answering, and cite the pages you used.",
+                &[
+                    "searchWeb",
+                    "scraper",
+                    "summarizeUrl",
+                    "crawlSite",
+                    "translate",
+                ],
+            ),
+        ),
+        (
+            "support".to_string(),
+            Persona::new(
+                "You help users with their problems. Look up the issues and tickets they \
+                 mention, and file an issue when they find a bug.",
+                &[
+                    "getIssue",
+                    "listOpenIssues",
+                    "createIssue",
+                    "getTicket",
+                    "searchTickets",
+                    "getRepoInfo",
+                ],
+            ),
+        ),
+    ]);
+    if let Ok(configured) = env::var("personas") {
+        match serde_json::from_str::<BTreeMap<String, Persona>>(&configured) {
+            Ok(configured) => personas.extend(configured),
+            Err(e) => log::warn!("Ignoring invalid personas: {}", e),
+        }
+    }
+    personas
+}
+
+/// The persona called `name`, if there is one.
+pub fn get(name: &str) -> Option<Persona> {
+    all().remove(name)
+}
@@ -0,0 +1,229 @@
+//! What's done to an answer before it's posted, in the order the steps are
+//! listed in `reply_postprocess`: `preamble` drops the reasoning some
+//! models write before their answer, `mrkdwn` converts Markdown to Slack's
+//! mrkdwn, and `unfurl` keeps Slack from previewing the links of an answer
+//! that lists more than `unfurl_max_links` of them.
+
+use std::env;
+
+const DEFAULT_STEPS: &[Step] = &[Step::Preamble, Step::Mrkdwn, Step::Unfurl];
+const DEFAULT_MAX_LINKS: usize = 3;
+
+/// How a paragraph of reasoning written before the answer starts.
+const PREAMBLES: &[&str] = &[
+    "thought:",
+    "thoughts:",
+    "thinking:",
+    "reasoning:",
+    "let me think",
+    "let's think",
+    "let me see",
+    "okay, so the user",
+    "ok, so the user",
+    "so the user",
+    "the user is asking",
+    "the user wants",
+    "the user asked",
+];
+/// Tags some models wrap their reasoning in.
+const THINKING_TAGS: &[(&str, &str)] = &[("<think>", "</think>"), ("<thinking>", "</thinking>")];
+/// Labels put before the answer once the reasoning is done.
+const ANSWER_LABELS: &[&str] = &["final answer:", "answer:"];
+
+/// One step of the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Preamble,
+    Mrkdwn,
+    Unfurl,
+}
+
+impl std::str::FromStr for Step {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "preamble" => Ok(Step::Preamble),
+            "mrkdwn" => Ok(Step::Mrkdwn),
+            "unfurl" => Ok(Step::Unfurl),
+            other => Err(format!("unknown post-processing step: {other}")),
+        }
+    }
+}
+
+/// The markup an answer is posted in. Steps for another platform's markup
+/// are skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    /// Discord and the webhook, which take the Markdown models write.
+    Markdown,
+    Slack,
+}
+
+/// An answer ready to post.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Answer {
+    pub text: String,
+    /// Whether Slack may preview its links.
+    pub unfurl: bool,
+}
+
+/// The chain of steps answers go through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PostProcess {
+    steps: Vec<Step>,
+    max_links: usize,
+}
+
+impl PostProcess {
+    /// Reads the comma-separated `reply_postprocess`, which is
+    /// `preamble,mrkdwn,unfurl` when unset and `off` for none, and
+    /// `unfurl_max_links`. Unknown steps are skipped.
+    pub fn from_env() -> Self {
+        let steps = match env::var("reply_postprocess") {
+            Ok(steps) if steps.trim() == "off" => Vec::new(),
+            Ok(steps) => steps
+                .split(',')
+                .filter(|step| !step.trim().is_empty())
+                .filter_map(|step| match step.parse() {
+                    Ok(step) => Some(step),
+                    Err(e) => {
+                        log::warn!("Ignoring {}", e);
+                        None
+                    }
+                })
+                .collect(),
+            Err(_) => DEFAULT_STEPS.to_vec(),
+        };
+        PostProcess {
+            steps,
+            max_links: env::var("unfurl_max_links")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_LINKS),
+        }
+    }
+
+    /// `text` put through each step that applies to `markup`.
+    pub fn apply(&self, text: &str, markup: Markup) -> Answer {
+        let mut answer = Answer {
+            text: text.to_string(),
+            unfurl: true,
+        };
+        for step in &self.steps {
+            match (step, markup) {
+                (Step::Preamble, _) => answer.text = strip_preamble(&answer.text),
+                (Step::Mrkdwn, Markup::Slack) => answer.text = to_mrkdwn(&answer.text),
+                (Step::Unfurl, Markup::Slack) => answer.unfurl = self.unfurls(&answer.text),
+                (Step::Mrkdwn | Step::Unfurl, Markup::Markdown) => {}
+            }
+        }
+        answer
+    }
+
+    /// Whether the links in `text` may be previewed: always, unless the
+    /// `unfurl` step is on and there are more than `max_links` of them.
+    pub fn unfurls(&self, text: &str) -> bool {
+        if !self.steps.contains(&Step::Unfurl) {
+            return true;
+        }
+        let mut links: Vec<&str> = text
+            .split(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '|' | '(' | ')'))
+            .filter(|word| word.starts_with("https://") || word.starts_with("http://"))
+            .collect();
+        links.sort_unstable();
+        links.dedup();
+        links.len() <= self.max_links
+    }
+}
+
+/// `text` without the reasoning a model wrote before its answer: anything
+/// in thinking tags, and opening paragraphs that think out loud, as long as
+/// something is left after them.
+fn strip_preamble(text: &str) -> String {
+    let mut text = text.to_string();
+    for (open, close) in THINKING_TAGS {
+        while let Some(start) = text.find(open) {
+            let Some(end) = text[start..].find(close) else {
+                break;
+            };
+            text.replace_range(start..start + end + close.len(), "");
+        }
+    }
+
+    let mut rest = text.trim();
+    while starts_with_any(rest, PREAMBLES) {
+        match rest.split_once("\n\n") {
+            Some((_, after)) if !after.trim().is_empty() => rest = after.trim_start(),
+            _ => break,
+        }
+    }
+    if let Some(label) = ANSWER_LABELS.iter().find(|label| {
+        rest.get(..label.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(label))
+    }) {
+        rest = rest[label.len()..].trim_start();
+    }
+    rest.to_string()
+}
+
+fn starts_with_any(paragraph: &str, prefixes: &[&str]) -> bool {
+    let paragraph = paragraph.trim_start().to_lowercase();
+    prefixes.iter().any(|prefix| paragraph.starts_with(prefix))
+}
+
+/// Converts the Markdown models write to Slack's mrkdwn: bold, links and
+/// headings. Code blocks are left alone.
+pub fn to_mrkdwn(markdown: &str) -> String {
+    let mut in_code = false;
+    markdown
+        .lines()
+        .map(|line| {
+            if line.trim_start().starts_with("```") {
+                in_code = !in_code;
+                return line.to_string();
+            }
+            if in_code {
+                return line.to_string();
+            }
+            let line = line
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace("**", "*")
+                .replace("__", "_");
+            let line = links(&line);
+            let heading = line.trim_start_matches('#');
+            if heading.len() < line.len() && heading.starts_with(' ') {
+                format!("*{}*", heading.trim().replace('*', ""))
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Rewrites Markdown links, `[text](url)`, as Slack's `<url|text>`.
+fn links(line: &str) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find('[') {
+        let Some((text, after)) = rest[start + 1..].split_once("](") else {
+            break;
+        };
+        let Some((url, tail)) = after.split_once(')') else {
+            break;
+        };
+        if text.contains(']') || url.contains(char::is_whitespace) {
+            out.push_str(&rest[..=start]);
+            rest = &rest[start + 1..];
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        out.push_str(&format!("<{url}|{text}>"));
+        rest = tail;
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,137 @@
+use crate::store::{get, set};
+use crate::tools::ToolParam;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::env;
+
+/// The measurement system tools report in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    /// °C, km/h and mm.
+    #[default]
+    Metric,
+    /// °F, mph and inches.
+    Imperial,
+}
+
+impl Units {
+    /// The name OpenWeatherMap and the tool schemas use.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Units::Metric => "metric",
+            Units::Imperial => "imperial",
+        }
+    }
+}
+
+impl ToolParam for Units {
+    fn schema() -> Value {
+        json!({ "type": "string", "enum": ["metric", "imperial"] })
+    }
+}
+
+/// Per-user settings, stored under `prefs:<user>`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Prefs {
+    #[serde(default)]
+    pub units: Units,
+    /// Whether answers are also posted as audio.
+    #[serde(default)]
+    pub speech: bool,
+    /// The city weather questions are about when they don't name one.
+    #[serde(default)]
+    pub home_city: Option<String>,
+}
+
+impl Prefs {
+    /// The user's settings, or the defaults when none are stored.
+    pub fn load(user: &str) -> Self {
+        get(&prefs_key(user))
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, user: &str) {
+        match serde_json::to_value(self) {
+            Ok(value) => set(&prefs_key(user), value, None),
+            Err(e) => log::warn!("Failed to save preferences for {}: {}", user, e),
+        }
+    }
+}
+
+fn prefs_key(user: &str) -> String {
+    format!("prefs:{user}")
+}
+
+/// Per-channel settings, stored under `channel_prefs:<channel>`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChannelPrefs {
+    /// The language the bot replies in, whatever language tools return.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// The persona an admin switched the channel to with `!persona`.
+    #[serde(default)]
+    pub persona: Option<String>,
+    /// Tools turned on here with `!tools enable`, even where they're off
+    /// for everyone else.
+    #[serde(default)]
+    pub enabled_tools: BTreeSet<String>,
+    /// Tools turned off here with `!tools disable`.
+    #[serde(default)]
+    pub disabled_tools: BTreeSet<String>,
+    /// Whether `!dryrun` has tool calls described instead of run, overriding
+    /// `dry_run`.
+    #[serde(default)]
+    pub dry_run: Option<bool>,
+    /// Whether `!ephemeral` has answers shown only to the asker, overriding
+    /// `reply_ephemeral`.
+    #[serde(default)]
+    pub ephemeral: Option<bool>,
+}
+
+impl ChannelPrefs {
+    /// The channel's settings, or the defaults when none are stored.
+    pub fn load(channel: &str) -> Self {
+        get(&channel_prefs_key(channel))
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default()
+    }
+
+    /// Adds the tools turned off in the channel to `disabled`, the tools
+    /// turned off elsewhere, and takes out those turned on in it.
+    pub fn restrict_tools(&self, disabled: &mut BTreeSet<String>) {
+        disabled.retain(|name| !self.enabled_tools.contains(name));
+        disabled.extend(self.disabled_tools.iter().cloned());
+    }
+
+    /// Turns `tool` on or off in the channel.
+    pub fn toggle_tool(&mut self, tool: &str, on: bool) {
+        let (add, remove) = if on {
+            (&mut self.enabled_tools, &mut self.disabled_tools)
+        } else {
+            (&mut self.disabled_tools, &mut self.enabled_tools)
+        };
+        remove.remove(tool);
+        add.insert(tool.to_string());
+    }
+
+    /// Whether answers are shown only to whoever asked, from `!ephemeral`
+    /// or else `reply_ephemeral`.
+    pub fn answers_ephemeral(&self) -> bool {
+        self.ephemeral
+            .unwrap_or(env::var("reply_ephemeral").as_deref() == Ok("true"))
+    }
+
+    pub fn save(&self, channel: &str) {
+        match serde_json::to_value(self) {
+            Ok(value) => set(&channel_prefs_key(channel), value, None),
+            Err(e) => log::warn!("Failed to save preferences for {}: {}", channel, e),
+        }
+    }
+}
+
+fn channel_prefs_key(channel: &str) -> String {
+    format!("channel_prefs:{channel}")
+}
@@ -0,0 +1,49 @@
+use jsonschema::JSONSchema;
+use serde_json::Value;
+
+/// The system prompt asking for answers that match `schema`. JSON mode
+/// requires the prompt to mention JSON.
+pub fn instructions(schema: &Value) -> String {
+    format!(
+        "Answer with only a JSON value, without Markdown or any other text, that matches this \
+         JSON schema:\n{schema}"
+    )
+}
+
+/// Whether `schema` is a JSON schema answers can be checked against.
+pub fn compiles(schema: &Value) -> Result<(), String> {
+    JSONSchema::compile(schema)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Checks `answer` against `schema` and returns it without any Markdown
+/// fence, or describes every problem found so the model can correct them.
+pub fn validate(schema: &Value, answer: &str) -> Result<String, String> {
+    let compiled =
+        JSONSchema::compile(schema).map_err(|e| format!("the schema is invalid: {e}"))?;
+    // Models sometimes fence JSON even when told not to.
+    let answer = answer
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    let value: Value =
+        serde_json::from_str(answer).map_err(|e| format!("the answer is not valid JSON: {e}"))?;
+    check(&compiled, &value)?;
+    Ok(answer.to_string())
+}
+
+/// Checks `value` against `schema`, describing every problem found.
+pub fn check(schema: &JSONSchema, value: &Value) -> Result<(), String> {
+    schema.validate(value).map_err(|errors| {
+        errors
+            .map(|e| match e.instance_path.to_string() {
+                path if path.is_empty() => e.to_string(),
+                path => format!("{path}: {e}"),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+}
@@ -0,0 +1,53 @@
+use crate::core::session::SessionKey;
+use async_trait::async_trait;
+
+/// A file shared in the conversation.
+pub struct SharedFile {
+    /// Where it can be opened, when the platform says.
+    pub permalink: Option<String>,
+}
+
+/// Somewhere to tell the user what the bot is up to while they wait.
+#[async_trait]
+pub trait StatusSink: Send + Sync {
+    async fn status(&self, text: &str);
+
+    /// Asks the user to approve a tool call held in `session`. Platforms
+    /// with buttons can offer them instead of a typed reply.
+    async fn confirm(&self, _session: &SessionKey, question: &str) {
+        self.status(&confirmation_prompt(question)).await;
+    }
+
+    /// Shows what the bot is doing, such as which tool it's running. Where
+    /// messages can be edited, one message is kept up to date and then
+    /// replaced by the answer. Elsewhere this does nothing, rather than post
+    /// a message for every step.
+    async fn progress(&self, _text: &str) {}
+
+    /// Shares `content` as a file called `name`. `None` on platforms that
+    /// can't share files.
+    async fn share_file(&self, _name: &str, _content: &str) -> Option<SharedFile> {
+        None
+    }
+}
+
+/// `question` with how to answer it by typing.
+pub fn confirmation_prompt(question: &str) -> String {
+    format!("{question}\nReply :+1: to go ahead or :-1: to cancel.")
+}
+
+/// A chat service the bot answers on, which decides how conversations are
+/// keyed and where answers go.
+#[async_trait]
+pub trait ChatPlatform: StatusSink {
+    /// Names the channel in session, usage and preference keys. Channels on
+    /// different platforms mustn't share names.
+    fn channel_key(&self) -> String;
+
+    /// The conversation `user` is having in the channel.
+    fn session_key(&self, user: &str) -> SessionKey {
+        SessionKey::new(&self.channel_key(), user)
+    }
+
+    async fn post(&self, text: &str);
+}
@@ -0,0 +1,158 @@
+//! Where conversations, preferences, counters and caches are kept. A flow
+//! keeps them in the flows.network store; the CLI and tests keep them in
+//! memory.
+
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// When a value is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpireKind {
+    /// `value` seconds from now.
+    Ex,
+    /// At the Unix time `value`, in seconds.
+    ExAt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Expire {
+    pub kind: ExpireKind,
+    pub value: i64,
+}
+
+/// A key-value store of JSON values.
+pub trait Store: Send + Sync {
+    fn get(&self, key: &str) -> Option<Value>;
+
+    /// Stores `value` under `key`, replacing any value there.
+    fn set(&self, key: &str, value: Value, expire: Option<Expire>);
+
+    /// Removes `key`, returning the value it held.
+    fn del(&self, key: &str) -> Option<Value>;
+}
+
+/// The flows.network store, which only answers inside a flow.
+#[cfg(feature = "flows")]
+pub struct FlowsStore;
+
+#[cfg(feature = "flows")]
+impl Store for FlowsStore {
+    fn get(&self, key: &str) -> Option<Value> {
+        store_flows::get(key)
+    }
+
+    fn set(&self, key: &str, value: Value, expire: Option<Expire>) {
+        let expire = expire.map(|expire| store_flows::Expire {
+            kind: match expire.kind {
+                ExpireKind::Ex => store_flows::ExpireKind::Ex,
+                ExpireKind::ExAt => store_flows::ExpireKind::ExAt,
+            },
+            value: expire.value,
+        });
+        store_flows::set(key, value, expire);
+    }
+
+    fn del(&self, key: &str) -> Option<Value> {
+        store_flows::del(key)
+    }
+}
+
+/// A store that lasts as long as the process.
+#[derive(Default)]
+pub struct MemoryStore {
+    /// Values with the Unix time they expire at.
+    entries: Mutex<HashMap<String, (Value, Option<i64>)>>,
+}
+
+impl Store for MemoryStore {
+    fn get(&self, key: &str) -> Option<Value> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        match entries.get(key) {
+            Some((_, Some(expires_at))) if *expires_at <= now() => {
+                entries.remove(key);
+                None
+            }
+            Some((value, _)) => Some(value.clone()),
+            None => None,
+        }
+    }
+
+    fn set(&self, key: &str, value: Value, expire: Option<Expire>) {
+        let expires_at = expire.map(|expire| match expire.kind {
+            ExpireKind::Ex => now() + expire.value,
+            ExpireKind::ExAt => expire.value,
+        });
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key.to_string(), (value, expires_at));
+    }
+
+    fn del(&self, key: &str) -> Option<Value> {
+        let value = self.get(key);
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+        value
+    }
+}
+
+static STORE: Lazy<RwLock<Arc<dyn Store>>> = Lazy::new(|| RwLock::new(default_store()));
+
+thread_local! {
+    /// A store for the current thread only, so tests running side by side
+    /// don't see each other's keys.
+    static THREAD_STORE: RefCell<Option<Arc<dyn Store>>> = const { RefCell::new(None) };
+}
+
+#[cfg(feature = "flows")]
+fn default_store() -> Arc<dyn Store> {
+    Arc::new(FlowsStore)
+}
+
+#[cfg(not(feature = "flows"))]
+fn default_store() -> Arc<dyn Store> {
+    Arc::new(MemoryStore::default())
+}
+
+/// Keeps everything in `store` from now on, instead of the flows.network
+/// store, or memory when built without the `flows` feature.
+pub fn install(store: Arc<dyn Store>) {
+    *STORE.write().unwrap_or_else(|e| e.into_inner()) = store;
+}
+
+/// Gives the current thread a fresh in-memory store, which a test's
+/// single-threaded runtime then uses throughout.
+#[cfg(test)]
+pub(crate) fn use_memory_for_thread() {
+    THREAD_STORE.with(|store| *store.borrow_mut() = Some(Arc::new(MemoryStore::default())));
+}
+
+fn current() -> Arc<dyn Store> {
+    THREAD_STORE
+        .with(|store| store.borrow().clone())
+        .unwrap_or_else(|| STORE.read().unwrap_or_else(|e| e.into_inner()).clone())
+}
+
+pub fn get(key: &str) -> Option<Value> {
+    current().get(key)
+}
+
+pub fn set(key: &str, value: Value, expire: Option<Expire>) {
+    current().set(key, value, expire);
+}
+
+pub fn del(key: &str) -> Option<Value> {
+    current().del(key)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
@@ -0,0 +1,165 @@
+//! Timed spans around turns, chat completions and tool calls, in the shape
+//! of OpenTelemetry spans.
+//!
+//! Spans are collected while a turn runs and posted to `trace_export_url`
+//! as JSON lines when it ends. Nothing is collected without a URL.
+
+use crate::tools::http::{self, Method};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The turn being traced and the spans ended so far. A flow handles one
+/// event per run, so there's at most one turn at a time.
+static CURRENT: Lazy<Mutex<Option<Turn>>> = Lazy::new(|| Mutex::new(None));
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+struct Turn {
+    trace_id: String,
+    root_span_id: String,
+    spans: Vec<Span>,
+}
+
+/// A finished span, as exported.
+#[derive(Debug, Clone, Serialize)]
+pub struct Span {
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub start_time_unix_nano: u128,
+    pub end_time_unix_nano: u128,
+    pub duration_ms: u128,
+    /// `ok` or `error`.
+    pub status: &'static str,
+    pub attributes: Map<String, Value>,
+}
+
+/// A span that is still running. Dropping it without [`end`](Self::end)
+/// discards it.
+pub struct SpanTimer {
+    name: String,
+    span_id: String,
+    root: bool,
+    started_at: SystemTime,
+    started: Instant,
+}
+
+impl SpanTimer {
+    /// Ends the span, keeping it for the export when a turn is being traced.
+    pub fn end(self, ok: bool, attributes: Map<String, Value>) {
+        let Ok(mut current) = CURRENT.lock() else {
+            return;
+        };
+        let Some(turn) = current.as_mut() else {
+            return;
+        };
+        let start = self
+            .started_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let duration = self.started.elapsed();
+        turn.spans.push(Span {
+            trace_id: turn.trace_id.clone(),
+            span_id: self.span_id,
+            parent_span_id: (!self.root).then(|| turn.root_span_id.clone()),
+            name: self.name,
+            start_time_unix_nano: start,
+            end_time_unix_nano: start + duration.as_nanos(),
+            duration_ms: duration.as_millis(),
+            status: if ok { "ok" } else { "error" },
+            attributes,
+        });
+    }
+}
+
+/// Starts tracing a turn with its root span, when `trace_export_url` is
+/// set.
+pub fn start_turn(name: &str) -> SpanTimer {
+    let mut timer = start(name);
+    if export_url().is_some() {
+        if let Ok(mut current) = CURRENT.lock() {
+            *current = Some(Turn {
+                trace_id: format!("{}{}", new_id(), new_id()),
+                root_span_id: timer.span_id.clone(),
+                spans: Vec::new(),
+            });
+            timer.root = true;
+        }
+    }
+    timer
+}
+
+/// Starts a span inside the current turn.
+pub fn start(name: &str) -> SpanTimer {
+    SpanTimer {
+        name: name.to_string(),
+        span_id: new_id(),
+        root: false,
+        started_at: SystemTime::now(),
+        started: Instant::now(),
+    }
+}
+
+/// Ends tracing the turn and posts its spans to `trace_export_url`, one JSON
+/// object per line.
+pub async fn finish_turn() {
+    let Some(turn) = CURRENT.lock().ok().and_then(|mut current| current.take()) else {
+        return;
+    };
+    let Some(url) = export_url() else {
+        return;
+    };
+    let body = turn
+        .spans
+        .iter()
+        .filter_map(|span| serde_json::to_string(span).ok())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if let Err(e) = export(&url, body).await {
+        log::warn!("Failed to export {} spans: {}", turn.spans.len(), e);
+    }
+}
+
+async fn export(url: &str, body: String) -> anyhow::Result<()> {
+    let token = env::var("TRACE_EXPORT_TOKEN").unwrap_or_default();
+    let authorization = format!("Bearer {token}");
+    let mut headers = vec![("Content-Type", "application/x-ndjson")];
+    if !token.is_empty() {
+        headers.push(("Authorization", &authorization));
+    }
+    let res = tokio::time::timeout(
+        EXPORT_TIMEOUT,
+        http::send(Method::POST, url, &headers, Some(body.into_bytes())),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("the collector didn't answer in time"))??;
+    if !res.is_success() {
+        anyhow::bail!("the collector answered {}", res.status);
+    }
+    Ok(())
+}
+
+fn export_url() -> Option<String> {
+    env::var("trace_export_url")
+        .ok()
+        .filter(|url| !url.is_empty())
+}
+
+/// A 16 hex digit ID, unique within the run and unlikely to repeat across
+/// runs.
+fn new_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    let count = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    format!("{:016x}", nanos.rotate_left(16) ^ count)
+}
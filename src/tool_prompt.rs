@@ -0,0 +1,72 @@
+//! Tool calling for models without the tools API.
+//!
+//! The tool definitions are described in a system message and the model is
+//! asked to answer with a bare JSON object whenever it wants to call one.
+//! Tools' few-shot examples are described here too, and are offered in
+//! either mode.
+
+use crate::tools::ToolExample;
+use async_openai::types::{ChatCompletionTool, FunctionCall};
+use serde_json::Value;
+
+/// The system message describing `tools` and how to call them.
+pub fn instructions(tools: &[ChatCompletionTool]) -> String {
+    let mut text = String::from(
+        "You can call the following functions. To call one, reply with nothing but a \
+         JSON object of the form {\"tool\": \"<function name>\", \"arguments\": {...}}. \
+         The result will be sent back to you. Otherwise, answer the user normally.\n",
+    );
+    for tool in tools {
+        let function = &tool.function;
+        text.push_str(&format!(
+            "\n- {}: {}\n  parameters: {}",
+            function.name,
+            function.description.as_deref().unwrap_or_default(),
+            function.parameters
+        ));
+    }
+    text
+}
+
+/// The system message showing which call answers each example request, or
+/// `None` when there are no examples.
+pub fn examples(examples: &[(String, ToolExample)]) -> Option<String> {
+    if examples.is_empty() {
+        return None;
+    }
+    let mut text = String::from("Examples of requests and the function call that answers them:\n");
+    for (name, example) in examples {
+        text.push_str(&format!(
+            "\nUser: {}\nCall: {name} {}",
+            example.request, example.arguments
+        ));
+    }
+    Some(text)
+}
+
+/// Extracts a tool call from a reply, if the reply is one.
+///
+/// Accepts the JSON object on its own or wrapped in a Markdown code fence.
+pub fn parse_call(content: &str) -> Option<FunctionCall> {
+    let trimmed = content.trim();
+    let json = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(trimmed)
+        .trim();
+
+    let value: Value = serde_json::from_str(json).ok()?;
+    let name = value.get("tool")?.as_str()?.to_string();
+    let arguments = match value.get("arguments") {
+        Some(Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+        None => "{}".to_string(),
+    };
+    Some(FunctionCall { name, arguments })
+}
+
+/// The user message that hands a tool result back to the model.
+pub fn result_message(name: &str, content: &str) -> String {
+    format!("Result of {name}:\n{content}")
+}
@@ -0,0 +1,502 @@
+use crate::tool;
+use crate::tools::http;
+use anyhow::{anyhow, bail};
+use chrono::{prelude::*, Duration};
+use chrono_tz::Tz;
+use ical::parser::ical::component::IcalEvent;
+use ical::property::Property;
+use ical::IcalParser;
+use std::collections::HashSet;
+use std::env;
+
+const DEFAULT_WORK_HOURS: &str = "09:00-17:00";
+/// How far back a recurring event's first occurrence may lie, in days.
+const MAX_RECURRENCE_DAYS: i64 = 10 * 366;
+
+tool! {
+    /// Check the user's calendar for a day: which meetings are booked and when they
+    /// are free during working hours
+    pub struct CalendarTool as "checkCalendar";
+
+    async fn check_calendar(
+        /// The day to check, as YYYY-MM-DD, "today" or "tomorrow"
+        date: String,
+        /// An IANA timezone name such as "Europe/Paris" to show times in
+        timezone: Option<String>,
+    ) -> anyhow::Result<String> {
+        let tz = match timezone.or_else(|| env::var("calendar_timezone").ok()) {
+            Some(name) => name
+                .parse::<Tz>()
+                .map_err(|_| anyhow!("unknown timezone \"{name}\""))?,
+            None => Tz::UTC,
+        };
+        let today = Utc::now().with_timezone(&tz).date_naive();
+        let day = match date.trim().to_lowercase().as_str() {
+            "today" => today,
+            "tomorrow" => today + Duration::days(1),
+            other => NaiveDate::parse_from_str(other, "%Y-%m-%d")
+                .map_err(|_| anyhow!("\"{date}\" is not a date like 2024-03-01"))?,
+        };
+
+        let events = fetch_events(tz).await?;
+        Ok(day_report(&events, day, tz))
+    }
+}
+
+/// Whether `CALENDAR_ICS_URL` points at a calendar to read.
+pub fn calendar_configured() -> bool {
+    env::var("CALENDAR_ICS_URL").is_ok()
+}
+
+struct Event {
+    uid: String,
+    summary: String,
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    all_day: bool,
+    rule: Option<Rule>,
+    /// Days on which a recurring event is cancelled or moved.
+    skipped: HashSet<NaiveDate>,
+}
+
+async fn fetch_events(tz: Tz) -> anyhow::Result<Vec<Event>> {
+    let url = env::var("CALENDAR_ICS_URL").map_err(|_| anyhow!("no calendar is configured"))?;
+    // Calendar apps hand out webcal:// links for the same https feed.
+    let url = match url.strip_prefix("webcal://") {
+        Some(rest) => format!("https://{rest}"),
+        None => url,
+    };
+    // The feed URL is a secret, so it is never saved as a fixture.
+    let res = http::send(http::Method::GET, &url, &[], None).await?;
+    if !res.is_success() {
+        bail!("the calendar feed returned {}", res.status);
+    }
+
+    let mut events = Vec::new();
+    let mut moved = Vec::new();
+    for calendar in IcalParser::new(res.body.as_slice()) {
+        for event in calendar?.events {
+            if let Some(recurrence_id) = property(&event, "RECURRENCE-ID") {
+                if let Some((start, _)) = parse_time(recurrence_id, tz) {
+                    moved.push((uid(&event), start.date_naive()));
+                }
+            }
+            if let Some(event) = to_event(&event, tz) {
+                events.push(event);
+            }
+        }
+    }
+    // A moved occurrence is its own event, so the series must not also
+    // claim the original slot.
+    for (uid, day) in moved {
+        for event in events
+            .iter_mut()
+            .filter(|e| e.uid == uid && e.rule.is_some())
+        {
+            event.skipped.insert(day);
+        }
+    }
+    Ok(events)
+}
+
+/// Cancelled events and those marked as free don't make anyone busy.
+fn to_event(event: &IcalEvent, tz: Tz) -> Option<Event> {
+    let value = |name| property(event, name).and_then(|p| p.value.as_deref());
+    if value("STATUS") == Some("CANCELLED") || value("TRANSP") == Some("TRANSPARENT") {
+        return None;
+    }
+    let (start, all_day) = parse_time(property(event, "DTSTART")?, tz)?;
+    let end = match property(event, "DTEND").and_then(|p| parse_time(p, tz)) {
+        Some((end, _)) => end,
+        None => match value("DURATION").and_then(parse_duration) {
+            Some(duration) => start + duration,
+            None if all_day => start + Duration::days(1),
+            None => start,
+        },
+    };
+    let skipped = event
+        .properties
+        .iter()
+        .filter(|p| p.name == "EXDATE")
+        .flat_map(|p| {
+            p.value
+                .as_deref()
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|v| parse_value(v, p, tz))
+                .map(|(t, _)| t.date_naive())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    Some(Event {
+        uid: uid(event),
+        summary: value("SUMMARY")
+            .unwrap_or("Busy")
+            .replace("\\,", ",")
+            .replace("\\;", ";")
+            .replace("\\n", " "),
+        start,
+        end,
+        all_day,
+        rule: value("RRULE").and_then(Rule::parse),
+        skipped,
+    })
+}
+
+fn property<'a>(event: &'a IcalEvent, name: &str) -> Option<&'a Property> {
+    event.properties.iter().find(|p| p.name == name)
+}
+
+fn uid(event: &IcalEvent) -> String {
+    property(event, "UID")
+        .and_then(|p| p.value.clone())
+        .unwrap_or_default()
+}
+
+fn param<'a>(property: &'a Property, name: &str) -> Option<&'a str> {
+    property
+        .params
+        .iter()
+        .flatten()
+        .find(|(key, _)| key == name)
+        .and_then(|(_, values)| values.first())
+        .map(String::as_str)
+}
+
+/// Reads a DTSTART-style property into `tz`, and whether it is a whole day.
+fn parse_time(property: &Property, tz: Tz) -> Option<(DateTime<Tz>, bool)> {
+    parse_value(property.value.as_deref()?, property, tz)
+}
+
+/// Handles dates, UTC times, times in the property's `TZID` and floating
+/// times, which are taken to be in `tz`.
+fn parse_value(value: &str, property: &Property, tz: Tz) -> Option<(DateTime<Tz>, bool)> {
+    let value = value.trim();
+    if param(property, "VALUE") == Some("DATE") || value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        let midnight = tz.from_local_datetime(&date.and_hms_opt(0, 0, 0)?);
+        return Some((midnight.earliest()?, true));
+    }
+    if let Some(utc) = value.strip_suffix('Z') {
+        let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some((Utc.from_utc_datetime(&time).with_timezone(&tz), false));
+    }
+    let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    // Windows zone names such as "Pacific Standard Time" don't parse and are
+    // read as floating times.
+    let zone = param(property, "TZID")
+        .and_then(|name| name.parse::<Tz>().ok())
+        .unwrap_or(tz);
+    let local = zone.from_local_datetime(&time).earliest()?;
+    Some((local.with_timezone(&tz), false))
+}
+
+/// Parses an RFC 5545 duration such as `PT1H30M` or `P1D`.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim().trim_start_matches('+').strip_prefix('P')?;
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => {}
+            unit => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                total = total
+                    + match unit {
+                        'W' => Duration::weeks(n),
+                        'D' => Duration::days(n),
+                        'H' => Duration::hours(n),
+                        'M' => Duration::minutes(n),
+                        'S' => Duration::seconds(n),
+                        _ => return None,
+                    };
+            }
+        }
+    }
+    Some(total)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// The parts of an RRULE this tool understands. Rules using other parts,
+/// such as `BYSETPOS`, are approximated by the parts below.
+struct Rule {
+    frequency: Frequency,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<NaiveDate>,
+    /// Weekdays, with the week of the month for monthly rules such as `2MO`
+    /// or `-1FR`.
+    by_day: Vec<(Option<i32>, Weekday)>,
+}
+
+impl Rule {
+    fn parse(rule: &str) -> Option<Self> {
+        let mut frequency = None;
+        let mut interval = 1;
+        let mut count = None;
+        let mut until = None;
+        let mut by_day = Vec::new();
+        for part in rule.split(';') {
+            let (key, value) = part.split_once('=')?;
+            match key {
+                "FREQ" => {
+                    frequency = Some(match value {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return None,
+                    })
+                }
+                "INTERVAL" => interval = value.parse().ok().filter(|i| *i > 0)?,
+                "COUNT" => count = value.parse().ok(),
+                "UNTIL" => until = NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok(),
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        let split = day.len().checked_sub(2)?;
+                        let weekday = match &day[split..] {
+                            "MO" => Weekday::Mon,
+                            "TU" => Weekday::Tue,
+                            "WE" => Weekday::Wed,
+                            "TH" => Weekday::Thu,
+                            "FR" => Weekday::Fri,
+                            "SA" => Weekday::Sat,
+                            "SU" => Weekday::Sun,
+                            _ => return None,
+                        };
+                        by_day.push((day[..split].parse().ok(), weekday));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(Self {
+            frequency: frequency?,
+            interval,
+            count,
+            until,
+            by_day,
+        })
+    }
+
+    /// Whether the series starting on `first` has an occurrence on `day`.
+    fn occurs_on(&self, first: NaiveDate, day: NaiveDate) -> bool {
+        if day < first
+            || self.until.is_some_and(|until| day > until)
+            || (day - first).num_days() > MAX_RECURRENCE_DAYS
+        {
+            return false;
+        }
+        let Some(count) = self.count else {
+            return self.matches(first, day);
+        };
+        // COUNT limits occurrences, so they have to be counted from the start.
+        let mut seen = 0;
+        let mut current = first;
+        while current <= day {
+            if self.matches(first, current) {
+                seen += 1;
+                if seen > count {
+                    return false;
+                }
+                if current == day {
+                    return true;
+                }
+            }
+            current += Duration::days(1);
+        }
+        false
+    }
+
+    fn matches(&self, first: NaiveDate, day: NaiveDate) -> bool {
+        let interval = self.interval as i64;
+        let months = |d: NaiveDate| d.year() as i64 * 12 + d.month0() as i64;
+        match self.frequency {
+            Frequency::Daily => (day - first).num_days() % interval == 0,
+            Frequency::Weekly => {
+                let week_start =
+                    |d: NaiveDate| d - Duration::days(d.weekday().num_days_from_monday() as i64);
+                let weeks = (week_start(day) - week_start(first)).num_days() / 7;
+                weeks % interval == 0
+                    && if self.by_day.is_empty() {
+                        day.weekday() == first.weekday()
+                    } else {
+                        self.by_day
+                            .iter()
+                            .any(|(_, weekday)| *weekday == day.weekday())
+                    }
+            }
+            Frequency::Monthly => {
+                (months(day) - months(first)) % interval == 0
+                    && if self.by_day.is_empty() {
+                        day.day() == first.day()
+                    } else {
+                        self.by_day.iter().any(|(nth, weekday)| {
+                            *weekday == day.weekday() && nth_matches(*nth, day)
+                        })
+                    }
+            }
+            Frequency::Yearly => {
+                (day.year() - first.year()) as i64 % interval == 0
+                    && day.month() == first.month()
+                    && day.day() == first.day()
+            }
+        }
+    }
+}
+
+/// Whether `day` is the `nth` of its weekday in its month, counting from the
+/// end when `nth` is negative. No `nth` means every one.
+fn nth_matches(nth: Option<i32>, day: NaiveDate) -> bool {
+    match nth {
+        None => true,
+        Some(n) if n > 0 => (day.day() as i32 - 1) / 7 + 1 == n,
+        Some(n) => {
+            let from_end = (0..)
+                .map(|w| day + Duration::weeks(w))
+                .take_while(|d| d.month() == day.month())
+                .count() as i32;
+            from_end == -n
+        }
+    }
+}
+
+/// One event as it takes place on a given day.
+struct Slot<'a> {
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    event: &'a Event,
+}
+
+/// The events taking place on `day`, with recurring events placed on that
+/// day.
+fn events_on(events: &[Event], day: NaiveDate, tz: Tz) -> Vec<Slot<'_>> {
+    let day_start = midnight(day, tz);
+    let day_end = midnight(day + Duration::days(1), tz);
+    let mut found = Vec::new();
+    for event in events {
+        let (start, end) = match &event.rule {
+            Some(rule) => {
+                let first = event.start.date_naive();
+                if event.skipped.contains(&day) || !rule.occurs_on(first, day) {
+                    continue;
+                }
+                let start = tz
+                    .from_local_datetime(&day.and_time(event.start.time()))
+                    .earliest();
+                match start {
+                    Some(start) => (start, start + (event.end - event.start)),
+                    None => continue,
+                }
+            }
+            None => (event.start, event.end),
+        };
+        let overlaps = start < day_end && (end > day_start || start >= day_start);
+        if overlaps {
+            found.push(Slot { start, end, event });
+        }
+    }
+    found.sort_by_key(|slot| slot.start);
+    found
+}
+
+fn midnight(day: NaiveDate, tz: Tz) -> DateTime<Tz> {
+    tz.from_local_datetime(&day.and_time(NaiveTime::MIN))
+        .earliest()
+        .unwrap_or_else(|| {
+            Utc.from_utc_datetime(&day.and_time(NaiveTime::MIN))
+                .with_timezone(&tz)
+        })
+}
+
+/// Lists the day's events and the free time left in the working hours set
+/// by `calendar_work_hours`.
+fn day_report(events: &[Event], day: NaiveDate, tz: Tz) -> String {
+    let (work_start, work_end) = work_hours();
+    let found = events_on(events, day, tz);
+    let day_start = midnight(day, tz);
+    let day_end = midnight(day + Duration::days(1), tz);
+    let mut lines = vec![format!("{} ({tz}):", day.format("%A %-d %B %Y"))];
+
+    if found.is_empty() {
+        lines.push("Nothing on the calendar.".to_string());
+    } else {
+        lines.push("Busy:".to_string());
+        for slot in &found {
+            if slot.event.all_day {
+                lines.push(format!("- all day: {}", slot.event.summary));
+            } else {
+                lines.push(format!(
+                    "- {}–{} {}",
+                    slot.start.max(day_start).format("%H:%M"),
+                    if slot.end >= day_end {
+                        "24:00".to_string()
+                    } else {
+                        slot.end.format("%H:%M").to_string()
+                    },
+                    slot.event.summary
+                ));
+            }
+        }
+    }
+
+    let (Some(open), Some(close)) = (
+        tz.from_local_datetime(&day.and_time(work_start)).earliest(),
+        tz.from_local_datetime(&day.and_time(work_end)).earliest(),
+    ) else {
+        return lines.join("\n");
+    };
+    let mut free = Vec::new();
+    let mut cursor = open;
+    for slot in &found {
+        if slot.start > cursor && cursor < close {
+            free.push((cursor, slot.start.min(close)));
+        }
+        cursor = cursor.max(slot.end);
+    }
+    if cursor < close {
+        free.push((cursor, close));
+    }
+    lines.push(format!(
+        "Free during working hours ({}–{}):",
+        work_start.format("%H:%M"),
+        work_end.format("%H:%M")
+    ));
+    if free.is_empty() {
+        lines.push("- none".to_string());
+    }
+    for (start, end) in free {
+        lines.push(format!(
+            "- {}–{}",
+            start.format("%H:%M"),
+            end.format("%H:%M")
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Working hours from `calendar_work_hours`, such as `09:00-17:00`.
+fn work_hours() -> (NaiveTime, NaiveTime) {
+    let parse = |hours: &str| {
+        let (start, end) = hours.split_once('-')?;
+        Some((
+            NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?,
+            NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?,
+        ))
+    };
+    env::var("calendar_work_hours")
+        .ok()
+        .and_then(|hours| parse(&hours))
+        .filter(|(start, end)| start < end)
+        .or_else(|| parse(DEFAULT_WORK_HOURS))
+        .expect("valid default working hours")
+}
@@ -0,0 +1,94 @@
+use crate::tool;
+use crate::tools::http;
+use anyhow::{anyhow, bail};
+use serde_json::{json, Value};
+use std::env;
+
+const DEFAULT_RUN_TIMEOUT_MS: u64 = 3000;
+const DEFAULT_MEMORY_LIMIT_MB: i64 = 128;
+const COMPILE_TIMEOUT_MS: u64 = 10_000;
+/// Output past this many characters is cut short.
+const MAX_OUTPUT_CHARS: usize = 3000;
+
+tool! {
+    /// Run a small program in a sandbox and get its output back. Use this when the
+    /// user asks to run or test a snippet of code.
+    pub struct RunCodeTool as "runCode";
+
+    async fn run_code(
+        /// The programming language, such as "python", "javascript", "rust" or "go"
+        language: String,
+        /// The complete source code of the program
+        source: String,
+        /// Text to pass to the program on standard input
+        stdin: Option<String>,
+    ) -> anyhow::Result<String> {
+        let base = env::var("piston_url").map_err(|_| anyhow!("no code runner is configured"))?;
+        let run_timeout = setting("code_run_timeout_ms", DEFAULT_RUN_TIMEOUT_MS);
+        let memory_limit = setting("code_memory_limit_mb", DEFAULT_MEMORY_LIMIT_MB) * 1024 * 1024;
+        let body = json!({
+            "language": language.to_lowercase(),
+            "version": "*",
+            "files": [{ "content": source }],
+            "stdin": stdin.unwrap_or_default(),
+            "compile_timeout": COMPILE_TIMEOUT_MS,
+            "run_timeout": run_timeout,
+            "compile_memory_limit": memory_limit,
+            "run_memory_limit": memory_limit,
+        });
+
+        let url = format!("{}/api/v2/execute", base.trim_end_matches('/'));
+        let res = http::post_json(&url, &[], &body).await?;
+        let result: Value = res.json()?;
+        if !res.is_success() {
+            // Piston answers 400 for unknown languages, with a message to pass on.
+            match result["message"].as_str() {
+                Some(message) => return Ok(message.to_string()),
+                None => bail!("the code runner returned {}", res.status),
+            }
+        }
+
+        let mut report = vec![format!(
+            "Ran {} {}.",
+            result["language"].as_str().unwrap_or(&language),
+            result["version"].as_str().unwrap_or_default()
+        )];
+        if let Some(compile) = result.get("compile").filter(|c| c["code"] != json!(0)) {
+            report.push(format!("Compilation failed:\n{}", stage_output(compile)));
+            return Ok(report.join("\n"));
+        }
+        let run = &result["run"];
+        report.push(match run["signal"].as_str() {
+            // The sandbox kills programs that run out of time or memory.
+            Some(signal) => format!("The program was stopped with {signal}, likely by the time or memory limit."),
+            None => format!("Exit code {}.", run["code"]),
+        });
+        report.push(stage_output(run));
+        Ok(report.join("\n"))
+    }
+}
+
+/// Whether `piston_url` points at a Piston server to run code on.
+pub fn code_runner_configured() -> bool {
+    env::var("piston_url").is_ok()
+}
+
+fn setting<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// The interleaved stdout and stderr of a compile or run stage.
+fn stage_output(stage: &Value) -> String {
+    let output = stage["output"].as_str().unwrap_or_default();
+    if output.trim().is_empty() {
+        return "(no output)".to_string();
+    }
+    let mut output: String = output.chars().take(MAX_OUTPUT_CHARS).collect();
+    if output.len() < stage["output"].as_str().unwrap_or_default().len() {
+        output.push_str("\n[truncated]");
+    }
+    format!("```\n{output}\n```")
+}
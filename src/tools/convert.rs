@@ -0,0 +1,138 @@
+use crate::tool;
+use anyhow::{anyhow, bail};
+use Dimension::{Length, Mass, Temperature, Volume};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Length,
+    Mass,
+    Volume,
+    Temperature,
+}
+
+/// A unit and how many base units (metres, kilograms, litres) one of it is.
+/// Temperatures are converted through Celsius instead, see [`to_celsius`].
+struct Unit {
+    names: &'static [&'static str],
+    dimension: Dimension,
+    factor: f64,
+}
+
+impl Unit {
+    const fn new(names: &'static [&'static str], dimension: Dimension, factor: f64) -> Self {
+        Self {
+            names,
+            dimension,
+            factor,
+        }
+    }
+}
+
+const UNITS: &[Unit] = &[
+    Unit::new(&["mm", "millimeter", "millimetre"], Length, 0.001),
+    Unit::new(&["cm", "centimeter", "centimetre"], Length, 0.01),
+    Unit::new(&["m", "meter", "metre"], Length, 1.0),
+    Unit::new(&["km", "kilometer", "kilometre"], Length, 1000.0),
+    Unit::new(&["in", "inch", "inches"], Length, 0.0254),
+    Unit::new(&["ft", "foot", "feet"], Length, 0.3048),
+    Unit::new(&["yd", "yard"], Length, 0.9144),
+    Unit::new(&["mi", "mile"], Length, 1609.344),
+    Unit::new(&["nmi", "nautical mile"], Length, 1852.0),
+    Unit::new(&["mg", "milligram"], Mass, 0.000_001),
+    Unit::new(&["g", "gram"], Mass, 0.001),
+    Unit::new(&["kg", "kilogram", "kilo"], Mass, 1.0),
+    Unit::new(&["t", "tonne", "metric ton"], Mass, 1000.0),
+    Unit::new(&["oz", "ounce"], Mass, 0.028_349_523_125),
+    Unit::new(&["lb", "lbs", "pound"], Mass, 0.453_592_37),
+    Unit::new(&["st", "stone"], Mass, 6.350_293_18),
+    Unit::new(&["ml", "milliliter", "millilitre"], Volume, 0.001),
+    Unit::new(&["cl", "centiliter", "centilitre"], Volume, 0.01),
+    Unit::new(&["l", "liter", "litre"], Volume, 1.0),
+    Unit::new(&["m3", "cubic meter", "cubic metre"], Volume, 1000.0),
+    Unit::new(&["tsp", "teaspoon"], Volume, 0.004_928_921_593_75),
+    Unit::new(&["tbsp", "tablespoon"], Volume, 0.014_786_764_781_25),
+    Unit::new(&["fl oz", "fluid ounce"], Volume, 0.029_573_529_562_5),
+    Unit::new(&["cup"], Volume, 0.236_588_236_5),
+    Unit::new(&["pt", "pint"], Volume, 0.473_176_473),
+    Unit::new(&["qt", "quart"], Volume, 0.946_352_946),
+    Unit::new(&["gal", "gallon"], Volume, 3.785_411_784),
+    Unit::new(&["c", "°c", "celsius"], Temperature, 1.0),
+    Unit::new(&["f", "°f", "fahrenheit"], Temperature, 1.0),
+    Unit::new(&["k", "kelvin"], Temperature, 1.0),
+];
+
+tool! {
+    /// Convert a value between units of length, mass, volume or temperature. Use this
+    /// instead of doing the arithmetic yourself. US customary units are used for
+    /// cups, pints, quarts and gallons.
+    pub struct ConvertTool as "convertUnits";
+
+    async fn convert_units(
+        /// The amount to convert
+        value: f64,
+        /// The unit to convert from, such as "km", "lb", "cup" or "fahrenheit"
+        from_unit: String,
+        /// The unit to convert to
+        to_unit: String,
+    ) -> anyhow::Result<String> {
+        let from = find_unit(&from_unit)?;
+        let to = find_unit(&to_unit)?;
+        if from.dimension != to.dimension {
+            bail!("can't convert {:?} to {:?}", from.dimension, to.dimension);
+        }
+
+        let result = match from.dimension {
+            Temperature => from_celsius(to.names[0], to_celsius(from.names[0], value)),
+            _ => value * from.factor / to.factor,
+        };
+        Ok(format!("{value} {from_unit} = {} {to_unit}", round(result)))
+    }
+
+    examples {
+        "How many cups is 500 ml?" => { "value": 500, "from_unit": "ml", "to_unit": "cup" },
+        "350F in celsius" => { "value": 350, "from_unit": "fahrenheit", "to_unit": "celsius" },
+    }
+}
+
+fn find_unit(name: &str) -> anyhow::Result<&'static Unit> {
+    let lower = name.trim().to_lowercase();
+    let name = lower
+        .strip_prefix("degrees ")
+        .or_else(|| lower.strip_prefix("degree "))
+        .unwrap_or(&lower);
+    let singular = name.strip_suffix('s').unwrap_or(name);
+    UNITS
+        .iter()
+        .find(|unit| {
+            unit.names
+                .iter()
+                .any(|n| *n == name || *n == singular || n.replace(' ', "") == name)
+        })
+        .ok_or_else(|| anyhow!("unknown unit `{name}`"))
+}
+
+/// Converts from the temperature unit whose short name is `unit`.
+fn to_celsius(unit: &str, value: f64) -> f64 {
+    match unit {
+        "f" => (value - 32.0) * 5.0 / 9.0,
+        "k" => value - 273.15,
+        _ => value,
+    }
+}
+
+fn from_celsius(unit: &str, celsius: f64) -> f64 {
+    match unit {
+        "f" => celsius * 9.0 / 5.0 + 32.0,
+        "k" => celsius + 273.15,
+        _ => celsius,
+    }
+}
+
+/// Rounds to six significant digits so results don't show float noise.
+fn round(value: f64) -> f64 {
+    if value == 0.0 {
+        return 0.0;
+    }
+    let scale = 10f64.powi(5 - value.abs().log10().floor() as i32);
+    (value * scale).round() / scale
+}
@@ -0,0 +1,158 @@
+use crate::tools::scraper::{fetch, page_text};
+use crate::tools::{guard, policy, Summarizer, Tool, ToolContext};
+use anyhow::bail;
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+use url::Url;
+
+const DEFAULT_PAGES: usize = 5;
+const MAX_PAGES: usize = 10;
+
+/// Links to files that aren't worth reading as pages.
+const SKIPPED_EXTENSIONS: &[&str] = &[
+    ".png", ".jpg", ".jpeg", ".gif", ".svg", ".webp", ".ico", ".css", ".js", ".zip", ".gz", ".mp4",
+    ".mp3", ".xml",
+];
+
+static HREF: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)href\s*=\s*["']([^"'#]+)"#).expect("valid regex"));
+
+/// Reads a site by following its links breadth first, staying on the domain
+/// it starts from, and summarizes every page it visits.
+pub struct CrawlSiteTool {
+    summarizer: Summarizer,
+}
+
+impl CrawlSiteTool {
+    pub fn new(summarizer: Summarizer) -> Self {
+        Self { summarizer }
+    }
+}
+
+#[derive(Deserialize)]
+struct Arguments {
+    url: String,
+    max_pages: Option<usize>,
+    focus: Option<String>,
+}
+
+#[async_trait]
+impl Tool for CrawlSiteTool {
+    fn name(&self) -> &str {
+        "crawlSite"
+    }
+
+    fn description(&self) -> &str {
+        "Read several pages of a website, starting at the url passed to it and following \
+         links on the same domain, and return a summary of each page. Use this for questions \
+         about a whole site, such as what a company's docs say about pricing."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "url": {
+                    "type": "string",
+                    "description": "The page to start from",
+                },
+                "max_pages": {
+                    "type": "integer",
+                    "description": "How many pages to read, at most 10",
+                },
+                "focus": {
+                    "type": "string",
+                    "description": "What the user wants to know about the site, if anything in particular",
+                },
+            },
+            "required": ["url"],
+        })
+    }
+
+    async fn execute(&self, arguments: &str, context: &ToolContext) -> anyhow::Result<String> {
+        let cassette = &context.cassette;
+        let args: Arguments = serde_json::from_str(arguments)?;
+        let max_pages = args.max_pages.unwrap_or(DEFAULT_PAGES).clamp(1, MAX_PAGES);
+        let start = Url::parse(&args.url)?;
+        if !matches!(start.scheme(), "http" | "https") {
+            bail!("only http and https urls can be crawled");
+        }
+        if let Err(refusal) = policy::check(cassette, start.as_str()).await {
+            return Ok(refusal);
+        }
+
+        let mut queue = VecDeque::from([start.clone()]);
+        let mut seen = HashSet::from([start.to_string()]);
+        let mut sections = Vec::new();
+        while let Some(page) = queue.pop_front() {
+            if sections.len() == max_pages {
+                break;
+            }
+            // Fetching the page ourselves gives both its text and its links.
+            let document = fetch(cassette, page.as_str()).await.ok();
+            let text = match document.as_ref().map(|d| d.text(page.as_str())) {
+                Some(Ok(Some(text))) => Ok(text),
+                Some(Err(e)) => Err(e),
+                _ => page_text(cassette, page.as_str()).await,
+            };
+            let text = match text {
+                Ok(text) => text,
+                Err(e) => {
+                    log::warn!("Skipping {} while crawling: {}", page, e);
+                    continue;
+                }
+            };
+            // Summaries of one chunk each keep the cost per page bounded.
+            if let Some(summary) = self
+                .summarizer
+                .summarize(&text, args.focus.as_deref(), 1)
+                .await?
+            {
+                sections.push(format!("## {page}\n{summary}"));
+            }
+
+            let html = match &document {
+                Some(document) if document.is_html() => document.body_text(),
+                _ => continue,
+            };
+            for link in same_site_links(&html, &page, &start) {
+                if seen.insert(link.to_string())
+                    && policy::check(cassette, link.as_str()).await.is_ok()
+                {
+                    queue.push_back(link);
+                }
+            }
+        }
+
+        if sections.is_empty() {
+            return Ok(format!("Couldn't read any pages from {}", args.url));
+        }
+        Ok(guard::untrusted(&args.url, &sections.join("\n\n")))
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Some(Duration::from_secs(300))
+    }
+}
+
+/// The links in `html`, resolved against `page`, that stay on the host of
+/// `start`, without fragments.
+fn same_site_links(html: &str, page: &Url, start: &Url) -> Vec<Url> {
+    HREF.captures_iter(html)
+        .filter_map(|c| page.join(c[1].trim()).ok())
+        .filter(|link| link.host_str() == start.host_str())
+        .filter(|link| {
+            let path = link.path().to_ascii_lowercase();
+            !SKIPPED_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+        })
+        .map(|mut link| {
+            link.set_fragment(None);
+            link
+        })
+        .collect()
+}
@@ -0,0 +1,480 @@
+use crate::tool;
+use crate::tools::http;
+use anyhow::{anyhow, bail};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+use std::env;
+use url::Url;
+
+const DEFAULT_MAX_ROWS: usize = 50;
+/// Longer cell values are cut short so the table stays readable.
+const MAX_CELL_CHARS: usize = 40;
+
+/// Neon's hosts, the only ones a Postgres URL's credentials are sent to.
+const NEON_HOST_SUFFIX: &str = ".neon.tech";
+/// PlanetScale's hosts, the only ones a MySQL URL's credentials are sent to.
+const PLANETSCALE_HOST_SUFFIX: &str = ".psdb.cloud";
+
+/// Words that begin statements that write, lock or change settings, which
+/// can start a data-modifying CTE or subquery as well as a statement.
+const WRITE_STATEMENTS: &[&str] = &[
+    "insert", "update", "delete", "merge", "upsert", "replace", "drop", "alter", "create",
+    "truncate", "rename", "grant", "revoke", "copy", "call", "exec", "execute", "do", "lock",
+    "set", "reset", "vacuum", "analyze", "refresh", "comment", "load", "handler",
+];
+/// What can follow FOR in a SELECT that locks the rows it reads.
+const LOCKING_READS: &[&str] = &["update", "share", "no", "key"];
+
+tool! {
+    /// Run a read-only SQL query against the team's database and get the rows back
+    /// as a table. Only a single SELECT statement is allowed.
+    pub struct DatabaseTool as "queryDatabase";
+
+    async fn query_database(
+        /// A single SELECT statement
+        sql: String,
+    ) -> anyhow::Result<String> {
+        let url = Url::parse(
+            &env::var("DATABASE_URL").map_err(|_| anyhow!("no database is configured"))?,
+        )?;
+        // MySQL reads a backslash in a string as escaping the next character,
+        // while Postgres keeps it as it is.
+        if let Err(reason) = check_read_only(&sql, url.scheme() == "mysql") {
+            return Ok(format!("Refused to run the query: {reason}."));
+        }
+        let max_rows = max_rows();
+        // One row more than is shown tells whether there were more.
+        let limited = format!(
+            "SELECT * FROM ({}) AS q LIMIT {}",
+            sql.trim().trim_end_matches(';'),
+            max_rows + 1
+        );
+        let (columns, mut rows) = match url.scheme() {
+            "postgres" | "postgresql" => postgres_query(&url, &limited).await?,
+            "mysql" => mysql_query(&url, &limited).await?,
+            other => bail!("unsupported database type {other}"),
+        };
+
+        let truncated = rows.len() > max_rows;
+        rows.truncate(max_rows);
+        if rows.is_empty() {
+            return Ok("The query returned no rows.".to_string());
+        }
+        let mut table = render_table(&columns, &rows);
+        if truncated {
+            table.push_str(&format!("\nOnly the first {max_rows} rows are shown."));
+        }
+        Ok(table)
+    }
+}
+
+/// Whether `DATABASE_URL` is set.
+pub fn database_configured() -> bool {
+    env::var("DATABASE_URL").is_ok()
+}
+
+/// How many rows a query may return, from `database_max_rows`.
+fn max_rows() -> usize {
+    env::var("database_max_rows")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_ROWS)
+}
+
+/// Allows a single SELECT (or WITH ... SELECT) statement with no nested
+/// statement that writes, no INTO and no locking read. String literals and
+/// comments are ignored, as are columns and functions that share a
+/// keyword's name, so `replace(name, 'a', 'b')` and `t.comment` still run.
+/// The database is also asked for a read-only transaction, which is what
+/// actually keeps it from being written to.
+fn check_read_only(sql: &str, backslash_escapes: bool) -> Result<(), String> {
+    let code = strip_literals(sql, backslash_escapes);
+    let code = code.trim().trim_end_matches(';');
+    if code.contains(';') {
+        return Err("only one statement can be run".to_string());
+    }
+    let tokens = tokenize(code);
+    match tokens.first() {
+        Some(Token::Word(word)) if word == "select" || word == "with" => {}
+        _ => return Err("only SELECT statements are allowed".to_string()),
+    }
+    let refuse = |words: &str| {
+        Err(format!(
+            "\"{}\" is not allowed in a read-only query",
+            words.to_uppercase()
+        ))
+    };
+    for (i, token) in tokens.iter().enumerate() {
+        let Token::Word(word) = token else {
+            continue;
+        };
+        let previous = i.checked_sub(1).and_then(|i| tokens.get(i));
+        let next = tokens.get(i + 1);
+        if previous == Some(&Token::Symbol('.')) || next == Some(&Token::Symbol('(')) {
+            continue;
+        }
+        let starts_statement = matches!(previous, None | Some(Token::Symbol('(')));
+        if starts_statement && WRITE_STATEMENTS.contains(&word.as_str()) {
+            return refuse(word);
+        }
+        match (word.as_str(), next) {
+            ("into", _) => return refuse(word),
+            ("for", Some(Token::Word(next))) if LOCKING_READS.contains(&next.as_str()) => {
+                return refuse(&format!("{word} {next}"));
+            }
+            ("lock", Some(Token::Word(next))) if next == "in" => {
+                return refuse("lock in share mode");
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    /// A keyword or unquoted identifier, lowercased.
+    Word(String),
+    Symbol(char),
+}
+
+/// Splits SQL already stripped of literals into words and symbols.
+fn tokenize(code: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_alphanumeric() || c == '_' {
+            let mut word = c.to_ascii_lowercase().to_string();
+            while let Some(next) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                word.push(next.to_ascii_lowercase());
+            }
+            tokens.push(Token::Word(word));
+        } else if !c.is_whitespace() {
+            tokens.push(Token::Symbol(c));
+        }
+    }
+    tokens
+}
+
+/// Replaces quoted strings and identifiers with spaces and drops comments.
+/// `backslash_escapes` follows MySQL, where `\'` doesn't end a string.
+fn strip_literals(sql: &str, backslash_escapes: bool) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                // A doubled quote inside the literal is an escaped quote, and
+                // reads the same as closing and reopening it.
+                while let Some(inner) = chars.next() {
+                    if inner == c {
+                        break;
+                    }
+                    if inner == '\\' && backslash_escapes && c != '`' {
+                        chars.next();
+                    }
+                }
+                out.push(' ');
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for inner in chars.by_ref() {
+                    if inner == '\n' {
+                        break;
+                    }
+                }
+                out.push(' ');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for inner in chars.by_ref() {
+                    if previous == '*' && inner == '/' {
+                        break;
+                    }
+                    previous = inner;
+                }
+                out.push(' ');
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+type Rows = (Vec<String>, Vec<Vec<Option<String>>>);
+
+/// Runs a query through the Postgres HTTP endpoint served by Neon's proxy, in
+/// a read-only transaction. The connection string goes along in a header,
+/// so only Neon hosts are sent it.
+async fn postgres_query(url: &Url, sql: &str) -> anyhow::Result<Rows> {
+    let endpoint = format!(
+        "https://{}/sql",
+        provider_host(url, NEON_HOST_SUFFIX, "Neon")?
+    );
+    let body = json!({ "queries": [{ "query": sql, "params": [] }] });
+    let response = post(
+        &endpoint,
+        &[
+            ("Neon-Connection-String", url.as_str()),
+            ("Neon-Raw-Text-Output", "true"),
+            ("Neon-Array-Mode", "true"),
+            ("Neon-Batch-Read-Only", "true"),
+        ],
+        &body,
+    )
+    .await?;
+    let result = &response["results"][0];
+    let columns = result["fields"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|f| f["name"].as_str().unwrap_or_default().to_string())
+        .collect();
+    let rows = result["rows"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|row| {
+            row.as_array()
+                .into_iter()
+                .flatten()
+                .map(|v| v.as_str().map(str::to_string))
+                .collect()
+        })
+        .collect();
+    Ok((columns, rows))
+}
+
+/// Runs a query through PlanetScale's HTTP API, in a read-only transaction
+/// that is rolled back afterwards. Rows come as one base64 string of values
+/// along with their lengths.
+async fn mysql_query(url: &Url, sql: &str) -> anyhow::Result<Rows> {
+    let endpoint = format!(
+        "https://{}/psdb.v1alpha1.Database/Execute",
+        provider_host(url, PLANETSCALE_HOST_SUFFIX, "PlanetScale")?
+    );
+    let credentials = format!(
+        "{}:{}",
+        urlencoding::decode(url.username())?,
+        urlencoding::decode(url.password().unwrap_or_default())?
+    );
+    let authorization = format!("Basic {}", STANDARD.encode(credentials));
+    let headers = [("Authorization", authorization.as_str())];
+    // The session carries the transaction from one statement to the next.
+    let begun = planetscale_execute(
+        &endpoint,
+        &headers,
+        "START TRANSACTION READ ONLY",
+        &Value::Null,
+    )
+    .await?;
+    let response = planetscale_execute(&endpoint, &headers, sql, &begun["session"]).await;
+    let session = match &response {
+        Ok(response) => &response["session"],
+        Err(_) => &begun["session"],
+    };
+    if let Err(e) = planetscale_execute(&endpoint, &headers, "ROLLBACK", session).await {
+        log::warn!("Failed to end a read-only transaction: {}", e);
+    }
+    let response = response?;
+    let result = &response["result"];
+    let columns = result["fields"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|f| f["name"].as_str().unwrap_or_default().to_string())
+        .collect();
+    let mut rows = Vec::new();
+    for row in result["rows"].as_array().into_iter().flatten() {
+        let values = STANDARD.decode(row["values"].as_str().unwrap_or_default())?;
+        let mut offset = 0;
+        let mut cells = Vec::new();
+        for length in row["lengths"].as_array().into_iter().flatten() {
+            let length: i64 = length.as_str().unwrap_or("-1").parse()?;
+            if length < 0 {
+                cells.push(None);
+                continue;
+            }
+            let end = offset + length as usize;
+            let bytes = values
+                .get(offset..end)
+                .ok_or_else(|| anyhow!("the database sent a malformed row"))?;
+            cells.push(Some(String::from_utf8_lossy(bytes).into_owned()));
+            offset = end;
+        }
+        rows.push(cells);
+    }
+    Ok((columns, rows))
+}
+
+/// Runs one statement in PlanetScale's `session`, returning the response
+/// with the session to use next.
+async fn planetscale_execute(
+    endpoint: &str,
+    headers: &[(&str, &str)],
+    sql: &str,
+    session: &Value,
+) -> anyhow::Result<Value> {
+    let body = json!({ "query": sql, "session": session });
+    let response = post(endpoint, headers, &body).await?;
+    if let Some(error) = response["error"]["message"].as_str() {
+        bail!("the database returned an error: {error}");
+    }
+    Ok(response)
+}
+
+/// The host of `url`, which must be one of `provider`'s, ending in `suffix`.
+fn provider_host<'a>(url: &'a Url, suffix: &str, provider: &str) -> anyhow::Result<&'a str> {
+    let host = url.host_str().unwrap_or_default();
+    if !host.to_ascii_lowercase().ends_with(suffix) {
+        bail!("{host} is not a {provider} host, and the database can only be reached through {provider}");
+    }
+    Ok(host)
+}
+
+async fn post(url: &str, headers: &[(&str, &str)], body: &Value) -> anyhow::Result<Value> {
+    let res = http::post_json(url, headers, body).await?;
+    if !res.is_success() {
+        let message = res
+            .json::<Value>()
+            .ok()
+            .and_then(|v| v["message"].as_str().map(str::to_string))
+            .unwrap_or_default();
+        bail!("the database returned {}: {}", res.status, message);
+    }
+    res.json()
+}
+
+/// Renders rows as a Markdown table inside a code block, which Slack shows in
+/// a monospace font so the columns line up.
+fn render_table(columns: &[String], rows: &[Vec<Option<String>>]) -> String {
+    let cell = |value: &Option<String>| {
+        let value = value
+            .as_deref()
+            .unwrap_or("NULL")
+            .replace('\n', " ")
+            .replace('|', "\\|");
+        if value.chars().count() > MAX_CELL_CHARS {
+            value.chars().take(MAX_CELL_CHARS - 1).collect::<String>() + "…"
+        } else {
+            value
+        }
+    };
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(cell).collect())
+        .collect();
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .map(|v| v.chars().count())
+                .chain([name.chars().count(), 3])
+                .max()
+                .unwrap_or(3)
+        })
+        .collect();
+    let line = |cells: Vec<String>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        format!("| {} |", padded.join(" | "))
+    };
+
+    let mut lines = vec![line(columns.to_vec())];
+    lines.push(line(widths.iter().map(|w| "-".repeat(*w)).collect()));
+    for row in rows {
+        lines.push(line(row));
+    }
+    format!("```\n{}\n```", lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refusal(sql: &str) -> String {
+        check_read_only(sql, false).unwrap_err()
+    }
+
+    #[test]
+    fn allows_selects() {
+        assert_eq!(
+            check_read_only("SELECT id, name FROM users;", false),
+            Ok(())
+        );
+        assert_eq!(
+            check_read_only(
+                "WITH recent AS (SELECT * FROM orders) SELECT count(*) FROM recent",
+                false
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn allows_columns_and_functions_named_like_keywords() {
+        assert_eq!(
+            check_read_only(
+                "SELECT replace(p.title, 'a', 'b'), p.comment, p.set FROM posts p",
+                false
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn ignores_keywords_in_strings_and_comments() {
+        assert_eq!(
+            check_read_only(
+                "SELECT * FROM log WHERE action = 'delete' -- drop table\n/* update */",
+                false
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn refuses_other_statements() {
+        assert_eq!(
+            refusal("DELETE FROM users"),
+            "only SELECT statements are allowed"
+        );
+        assert_eq!(
+            refusal("SELECT 1; DROP TABLE users"),
+            "only one statement can be run"
+        );
+    }
+
+    #[test]
+    fn refuses_writes_inside_a_select() {
+        assert_eq!(
+            refusal("WITH gone AS (DELETE FROM users RETURNING *) SELECT * FROM gone"),
+            "\"DELETE\" is not allowed in a read-only query"
+        );
+        assert_eq!(
+            refusal("SELECT * INTO backup FROM users"),
+            "\"INTO\" is not allowed in a read-only query"
+        );
+        assert_eq!(
+            refusal("SELECT * FROM users FOR UPDATE"),
+            "\"FOR UPDATE\" is not allowed in a read-only query"
+        );
+    }
+
+    #[test]
+    fn follows_mysql_backslash_escapes() {
+        let sql = r"SELECT 'it\'s'; DROP TABLE users";
+        assert_eq!(
+            check_read_only(sql, true),
+            Err("only one statement can be run".to_string())
+        );
+        // In Postgres the backslash is kept, so the string ends before `s`.
+        assert!(check_read_only(r"SELECT 'a\' FROM t", false).is_ok());
+    }
+}
@@ -0,0 +1,177 @@
+use crate::tool;
+use crate::tools::http;
+use anyhow::{anyhow, bail};
+use hickory_proto::op::{Message, Query, ResponseCode};
+use hickory_proto::rr::{Name, RecordType};
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use url::Host;
+
+const DEFAULT_DOH_URL: &str = "https://cloudflare-dns.com/dns-query";
+const IANA_WHOIS: &str = "whois.iana.org";
+/// How many times a WHOIS server may refer to a more specific one.
+const MAX_REFERRALS: usize = 2;
+/// WHOIS replies past this many characters are cut short.
+const MAX_WHOIS_CHARS: usize = 3000;
+/// How long a WHOIS server gets to accept the connection, and then to
+/// answer.
+const WHOIS_TIMEOUT: Duration = Duration::from_secs(10);
+
+tool! {
+    /// Look up the DNS records of a domain, such as its A, AAAA, MX, TXT, NS or
+    /// CNAME records
+    pub struct DnsLookupTool as "dnsLookup";
+
+    async fn dns_lookup(
+        /// The domain name, such as "example.com"
+        domain: String,
+        /// The record type, A when left out
+        record_type: Option<String>,
+    ) -> anyhow::Result<String> {
+        let domain = normalize(&domain)?;
+        let record_type = record_type.unwrap_or("A".to_string()).to_uppercase();
+        let kind = RecordType::from_str(&record_type)
+            .map_err(|_| anyhow!("unknown record type {record_type}"))?;
+
+        let mut query = Message::new();
+        query
+            .set_recursion_desired(true)
+            .add_query(Query::query(Name::from_ascii(format!("{domain}."))?, kind));
+        let response = Message::from_vec(&resolve(query.to_vec()?).await?)?;
+
+        match response.response_code() {
+            ResponseCode::NoError => {}
+            ResponseCode::NXDomain => return Ok(format!("{domain} does not exist")),
+            code => bail!("the DNS server answered {code}"),
+        }
+        let records: Vec<String> = response
+            .answers()
+            .iter()
+            .filter_map(|record| {
+                record.data().map(|data| {
+                    format!(
+                        "{} {} {} {}",
+                        record.name(),
+                        record.ttl(),
+                        record.record_type(),
+                        data
+                    )
+                })
+            })
+            .collect();
+        if records.is_empty() {
+            return Ok(format!("{domain} has no {record_type} records"));
+        }
+        Ok(records.join("\n"))
+    }
+}
+
+tool! {
+    /// Get the WHOIS registration record of a domain: its registrar, creation and
+    /// expiry dates and name servers
+    pub struct WhoisTool as "whois";
+
+    async fn whois(
+        /// The domain name, such as "example.com"
+        domain: String,
+    ) -> anyhow::Result<String> {
+        let domain = normalize(&domain)?;
+        // IANA knows which server holds each top-level domain, and registries
+        // of thin domains such as .com point on to the registrar's server.
+        let mut server = IANA_WHOIS.to_string();
+        let mut reply = whois_query(&server, &domain).await?;
+        for _ in 0..MAX_REFERRALS {
+            match referral(&reply).filter(|next| !next.eq_ignore_ascii_case(&server)) {
+                Some(next) => {
+                    match whois_query(&next, &domain).await {
+                        Ok(next_reply) => reply = next_reply,
+                        Err(e) => {
+                            log::warn!("WHOIS referral to {} failed: {}", next, e);
+                            break;
+                        }
+                    }
+                    server = next;
+                }
+                None => break,
+            }
+        }
+
+        let text = clean_whois(&reply);
+        if text.is_empty() {
+            return Ok(format!("{server} has no record of {domain}"));
+        }
+        let mut shown: String = text.chars().take(MAX_WHOIS_CHARS).collect();
+        if shown.len() < text.len() {
+            shown.push_str("\n[truncated]");
+        }
+        Ok(format!("From {server}:\n{shown}"))
+    }
+}
+
+/// Turns internationalized names into their ASCII form, and rejects
+/// anything that isn't a domain name.
+fn normalize(domain: &str) -> anyhow::Result<String> {
+    let domain = domain.trim().trim_end_matches('.');
+    match Host::parse(domain) {
+        Ok(Host::Domain(domain)) if domain.contains('.') => Ok(domain),
+        _ => bail!("\"{domain}\" is not a domain name"),
+    }
+}
+
+/// Sends a DNS query over HTTPS to `dns_over_https_url`, since flows have
+/// no direct DNS access.
+async fn resolve(query: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let url = env::var("dns_over_https_url").unwrap_or(DEFAULT_DOH_URL.to_string());
+    let headers = [
+        ("Accept", "application/dns-message"),
+        ("Content-Type", "application/dns-message"),
+    ];
+    let res = http::send(http::Method::POST, &url, &headers, Some(query)).await?;
+    if !res.is_success() {
+        bail!("the DNS-over-HTTPS server returned {}", res.status);
+    }
+    Ok(res.body)
+}
+
+async fn whois_query(server: &str, domain: &str) -> anyhow::Result<String> {
+    let mut stream = timeout(WHOIS_TIMEOUT, TcpStream::connect((server, 43)))
+        .await
+        .map_err(|_| anyhow!("{server} didn't accept the connection in time"))??;
+    stream.write_all(format!("{domain}\r\n").as_bytes()).await?;
+    let mut reply = Vec::new();
+    timeout(WHOIS_TIMEOUT, stream.read_to_end(&mut reply))
+        .await
+        .map_err(|_| anyhow!("{server} didn't answer in time"))??;
+    Ok(String::from_utf8_lossy(&reply).into_owned())
+}
+
+/// The server named by a `refer:` (IANA) or `Registrar WHOIS Server:`
+/// (registry) line.
+fn referral(reply: &str) -> Option<String> {
+    reply.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+        let value = value
+            .strip_prefix("whois://")
+            .unwrap_or(value)
+            .trim_end_matches('/');
+        (matches!(key.as_str(), "refer" | "whois" | "registrar whois server") && !value.is_empty())
+            .then(|| value.to_string())
+    })
+}
+
+/// Drops comments, blank lines and the legal notice registries append.
+fn clean_whois(reply: &str) -> String {
+    reply
+        .lines()
+        .map(str::trim_end)
+        .take_while(|line| !line.starts_with(">>> Last update"))
+        .filter(|line| !line.is_empty() && !line.starts_with('%') && !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
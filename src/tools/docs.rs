@@ -0,0 +1,419 @@
+//! The team's own documents, for answers grounded in them: `!ingest` reads
+//! a page or an attached file, splits it into chunks, embeds them with
+//! `embedding_model`, and keeps them in the flow's store, or in a Qdrant
+//! collection when `qdrant_url` is set. `searchDocs` finds the chunks
+//! closest to a query.
+
+use super::http::{self, Method};
+use super::scraper::{page_text, Document};
+use super::{guard, policy, Cassette, Tool, ToolContext};
+use crate::analytics::fnv1a;
+use crate::chunking;
+use crate::client::ChatClient;
+use crate::store::{del, get, set};
+use anyhow::{anyhow, bail};
+use async_openai::types::{CreateEmbeddingRequestArgs, EmbeddingInput};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::env;
+
+const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+const DEFAULT_COLLECTION: &str = "docs";
+/// Lists what's in the flow's store, and how many chunks each has.
+const INDEX_KEY: &str = "docs:index";
+/// How long a chunk may be, about 400 tokens.
+const CHUNK_CHARS: usize = 1500;
+/// Longer documents are cut off, to bound the cost of embedding them.
+const MAX_CHUNKS: usize = 200;
+/// How many chunks are embedded per request.
+const EMBED_BATCH: usize = 64;
+const DEFAULT_RESULTS: usize = 4;
+const MAX_RESULTS: usize = 10;
+
+/// A piece of a document, with its embedding.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Chunk {
+    source: String,
+    text: String,
+    vector: Vec<f32>,
+}
+
+/// A chunk found for a query, and how close it is.
+#[derive(Debug, Clone)]
+struct Hit {
+    source: String,
+    text: String,
+    score: f32,
+}
+
+/// A document in the flow's store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Indexed {
+    source: String,
+    chunks: usize,
+}
+
+/// Where the chunks are kept.
+enum VectorStore {
+    /// The flow's store, searched by comparing the query with every chunk,
+    /// which suits a few hundred of them.
+    Flows,
+    Qdrant {
+        url: String,
+        api_key: Option<String>,
+        collection: String,
+    },
+}
+
+impl VectorStore {
+    /// Qdrant at `qdrant_url` with `QDRANT_API_KEY` and `docs_collection`,
+    /// or else the flow's store.
+    fn from_env() -> Self {
+        match env::var("qdrant_url").ok().filter(|v| !v.trim().is_empty()) {
+            Some(url) => VectorStore::Qdrant {
+                url: url.trim_end_matches('/').to_string(),
+                api_key: env::var("QDRANT_API_KEY").ok(),
+                collection: env::var("docs_collection")
+                    .unwrap_or_else(|_| DEFAULT_COLLECTION.to_string()),
+            },
+            None => VectorStore::Flows,
+        }
+    }
+
+    /// Keeps `chunks` as everything there is from `source`, dropping what
+    /// was ingested from it before.
+    async fn replace(&self, source: &str, chunks: &[Chunk]) -> anyhow::Result<()> {
+        match self {
+            VectorStore::Flows => {
+                let mut index = flows_index();
+                let previous = index
+                    .iter()
+                    .find(|doc| doc.source == source)
+                    .map_or(0, |doc| doc.chunks);
+                for (i, chunk) in chunks.iter().enumerate() {
+                    set(&chunk_key(source, i), json!(chunk), None);
+                }
+                for i in chunks.len()..previous {
+                    del(&chunk_key(source, i));
+                }
+                index.retain(|doc| doc.source != source);
+                index.push(Indexed {
+                    source: source.to_string(),
+                    chunks: chunks.len(),
+                });
+                set(INDEX_KEY, json!(index), None);
+                Ok(())
+            }
+            VectorStore::Qdrant { collection, .. } => {
+                let size = chunks.first().map_or(0, |chunk| chunk.vector.len());
+                let path = format!("/collections/{collection}");
+                if self.qdrant(Method::GET, &path, None).await.is_err() {
+                    self.qdrant(
+                        Method::PUT,
+                        &path,
+                        Some(json!({ "vectors": { "size": size, "distance": "Cosine" } })),
+                    )
+                    .await?;
+                }
+                self.qdrant(
+                    Method::POST,
+                    &format!("{path}/points/delete?wait=true"),
+                    Some(json!({
+                        "filter": { "must": [{ "key": "source", "match": { "value": source } }] },
+                    })),
+                )
+                .await?;
+                let points: Vec<Value> = chunks
+                    .iter()
+                    .enumerate()
+                    .map(|(i, chunk)| {
+                        json!({
+                            "id": fnv1a(chunk_key(source, i).as_bytes()),
+                            "vector": chunk.vector,
+                            "payload": { "source": chunk.source, "text": chunk.text },
+                        })
+                    })
+                    .collect();
+                self.qdrant(
+                    Method::PUT,
+                    &format!("{path}/points?wait=true"),
+                    Some(json!({ "points": points })),
+                )
+                .await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// The `limit` chunks closest to `vector`, closest first.
+    async fn search(&self, vector: &[f32], limit: usize) -> anyhow::Result<Vec<Hit>> {
+        match self {
+            VectorStore::Flows => {
+                let mut hits: Vec<Hit> = flows_index()
+                    .iter()
+                    .flat_map(|doc| (0..doc.chunks).map(|i| chunk_key(&doc.source, i)))
+                    .filter_map(|key| get(&key).and_then(|v| serde_json::from_value(v).ok()))
+                    .map(|chunk: Chunk| Hit {
+                        score: cosine(vector, &chunk.vector),
+                        source: chunk.source,
+                        text: chunk.text,
+                    })
+                    .collect();
+                hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+                hits.truncate(limit);
+                Ok(hits)
+            }
+            VectorStore::Qdrant { collection, .. } => {
+                let reply = self
+                    .qdrant(
+                        Method::POST,
+                        &format!("/collections/{collection}/points/search"),
+                        Some(json!({ "vector": vector, "limit": limit, "with_payload": true })),
+                    )
+                    .await?;
+                Ok(reply["result"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|point| Hit {
+                        source: point["payload"]["source"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                        text: point["payload"]["text"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                        score: point["score"].as_f64().unwrap_or_default() as f32,
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// Sends a request to Qdrant, returning its JSON reply.
+    async fn qdrant(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> anyhow::Result<Value> {
+        let VectorStore::Qdrant { url, api_key, .. } = self else {
+            bail!("Qdrant isn't configured");
+        };
+        let mut headers = vec![("Content-Type", "application/json")];
+        if let Some(key) = api_key {
+            headers.push(("api-key", key.as_str()));
+        }
+        let res = http::send(
+            method,
+            &format!("{url}{path}"),
+            &headers,
+            body.map(|body| body.to_string().into_bytes()),
+        )
+        .await?;
+        let reply: Value = res.json().unwrap_or_default();
+        if !res.is_success() {
+            bail!(
+                "Qdrant returned {}: {}",
+                res.status,
+                reply["status"]["error"]
+                    .as_str()
+                    .unwrap_or("no reason given")
+            );
+        }
+        Ok(reply)
+    }
+}
+
+/// Whether there's anything to search: a Qdrant collection is configured,
+/// or documents have been ingested into the flow's store.
+pub fn docs_configured() -> bool {
+    matches!(VectorStore::from_env(), VectorStore::Qdrant { .. }) || !flows_index().is_empty()
+}
+
+/// Ingests the page at `url`, and says how that went.
+pub async fn ingest_url(client: &ChatClient, url: &str) -> String {
+    let cassette = Cassette::from_env();
+    if let Err(refusal) = policy::check(&cassette, url).await {
+        return refusal;
+    }
+    let result = match page_text(&cassette, url).await {
+        Ok(text) => ingest(client, url, &text).await,
+        Err(e) => Err(e),
+    };
+    report(url, result)
+}
+
+/// Ingests the file `name`, of type `mimetype`, and says how that went.
+/// Text files, web pages and PDFs can be read.
+pub async fn ingest_file(client: &ChatClient, name: &str, mimetype: &str, body: Vec<u8>) -> String {
+    let document = Document {
+        content_type: mimetype.to_string(),
+        body,
+    };
+    let result = match document.text(name) {
+        Ok(Some(text)) => ingest(client, name, &text).await,
+        Ok(None) => Err(anyhow!("{mimetype} files can't be read")),
+        Err(e) => Err(e),
+    };
+    report(name, result)
+}
+
+fn report(source: &str, result: anyhow::Result<usize>) -> String {
+    match result {
+        Ok(chunks) => format!("Ingested {source} as {chunks} chunks."),
+        Err(e) => {
+            log::warn!("Failed to ingest {}: {}", source, e);
+            format!("Couldn't ingest {source}: {e}")
+        }
+    }
+}
+
+/// Splits `text` into chunks, embeds them and stores them as `source`,
+/// returning how many there were.
+async fn ingest(client: &ChatClient, source: &str, text: &str) -> anyhow::Result<usize> {
+    let mut pieces: Vec<String> = chunking::split(text, CHUNK_CHARS)
+        .into_iter()
+        .map(|piece| piece.trim().to_string())
+        .filter(|piece| !piece.is_empty())
+        .collect();
+    if pieces.is_empty() {
+        bail!("it has no text");
+    }
+    if pieces.len() > MAX_CHUNKS {
+        log::warn!(
+            "Keeping the first {} of {} chunks of {}",
+            MAX_CHUNKS,
+            pieces.len(),
+            source
+        );
+        pieces.truncate(MAX_CHUNKS);
+    }
+    let mut chunks = Vec::with_capacity(pieces.len());
+    for batch in pieces.chunks(EMBED_BATCH) {
+        let vectors = embed(client, batch.to_vec()).await?;
+        chunks.extend(batch.iter().zip(vectors).map(|(text, vector)| Chunk {
+            source: source.to_string(),
+            text: text.clone(),
+            vector,
+        }));
+    }
+    VectorStore::from_env().replace(source, &chunks).await?;
+    Ok(chunks.len())
+}
+
+/// The embeddings of `inputs`, in the same order.
+async fn embed(client: &ChatClient, inputs: Vec<String>) -> anyhow::Result<Vec<Vec<f32>>> {
+    let count = inputs.len();
+    let request = CreateEmbeddingRequestArgs::default()
+        .model(embedding_model())
+        .input(EmbeddingInput::StringArray(inputs))
+        .build()?;
+    let mut data = client.create_embeddings(request).await?.data;
+    if data.len() != count {
+        bail!("got {} embeddings for {} texts", data.len(), count);
+    }
+    data.sort_by_key(|embedding| embedding.index);
+    Ok(data
+        .into_iter()
+        .map(|embedding| embedding.embedding)
+        .collect())
+}
+
+fn embedding_model() -> String {
+    env::var("embedding_model").unwrap_or_else(|_| DEFAULT_EMBEDDING_MODEL.to_string())
+}
+
+fn flows_index() -> Vec<Indexed> {
+    get(INDEX_KEY)
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+fn chunk_key(source: &str, i: usize) -> String {
+    format!("docs:{:016x}:{i}", fnv1a(source.as_bytes()))
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norms =
+        a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+/// Searches the ingested documents.
+pub struct SearchDocsTool {
+    client: ChatClient,
+}
+
+impl SearchDocsTool {
+    pub fn new(client: ChatClient) -> Self {
+        Self { client }
+    }
+}
+
+#[derive(Deserialize)]
+struct Arguments {
+    query: String,
+    count: Option<usize>,
+}
+
+#[async_trait]
+impl Tool for SearchDocsTool {
+    fn name(&self) -> &str {
+        "searchDocs"
+    }
+
+    fn description(&self) -> &str {
+        "Search the team's own documents, such as handbooks, guides and specs, and return \
+         the passages that best match, each with its source. Prefer this over the web for \
+         questions about the team, its products or its processes"
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "What to look for, in a few words or a question",
+                },
+                "count": {
+                    "type": "integer",
+                    "description": "How many passages to return, at most 10",
+                },
+            },
+            "required": ["query"],
+        })
+    }
+
+    async fn execute(&self, arguments: &str, _context: &ToolContext) -> anyhow::Result<String> {
+        let args: Arguments = serde_json::from_str(arguments)?;
+        let count = args.count.unwrap_or(DEFAULT_RESULTS).clamp(1, MAX_RESULTS);
+        let vector = embed(&self.client, vec![args.query.clone()])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("the query couldn't be embedded"))?;
+        let hits = VectorStore::from_env().search(&vector, count).await?;
+        if hits.is_empty() {
+            return Ok(format!(
+                "Nothing in the documents matches \"{}\"",
+                args.query
+            ));
+        }
+        let passages: Vec<String> = hits
+            .iter()
+            .map(|hit| format!("Source: {}\n{}", hit.source, hit.text))
+            .collect();
+        Ok(guard::untrusted(
+            "the team's documents",
+            &passages.join("\n\n"),
+        ))
+    }
+}
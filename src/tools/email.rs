@@ -0,0 +1,145 @@
+use crate::tools::{http, Tool, ToolContext};
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::env;
+
+const SENDGRID_API: &str = "https://api.sendgrid.com/v3/mail/send";
+
+/// Sends an email through SendGrid, only to addresses on `email_allowlist`
+/// and only after the user has approved it.
+///
+/// Flows run as WebAssembly with HTTP as their only way out, so there is no
+/// SMTP support.
+pub struct SendEmailTool;
+
+impl SendEmailTool {
+    /// Whether SendGrid and a sender address are configured.
+    pub fn configured() -> bool {
+        env::var("SENDGRID_API_KEY").is_ok() && env::var("email_from").is_ok()
+    }
+}
+
+#[derive(Deserialize)]
+struct Email {
+    to: String,
+    subject: String,
+    body: String,
+}
+
+impl Email {
+    fn recipients(&self) -> Vec<String> {
+        self.to
+            .split([',', ';'])
+            .map(|address| address.trim().to_ascii_lowercase())
+            .filter(|address| !address.is_empty())
+            .collect()
+    }
+
+    /// Recipients not on `email_allowlist`, which lists addresses and whole
+    /// domains written as `@example.com`. An empty allowlist allows no one.
+    fn disallowed(&self) -> Vec<String> {
+        let allowlist: Vec<String> = env::var("email_allowlist")
+            .unwrap_or_default()
+            .split(',')
+            .map(|entry| entry.trim().to_ascii_lowercase())
+            .filter(|entry| !entry.is_empty())
+            .collect();
+        self.recipients()
+            .into_iter()
+            .filter(|address| {
+                !allowlist.iter().any(|entry| {
+                    address == entry
+                        || (entry.starts_with('@') && address.ends_with(entry.as_str()))
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Tool for SendEmailTool {
+    fn name(&self) -> &str {
+        "sendEmail"
+    }
+
+    fn description(&self) -> &str {
+        "Send a plain text email. Only some recipients are allowed, and the user is asked to \
+         approve the email first."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "to": {
+                    "type": "string",
+                    "description": "The recipient's email address, or several separated by commas",
+                },
+                "subject": {
+                    "type": "string",
+                    "description": "The subject line",
+                },
+                "body": {
+                    "type": "string",
+                    "description": "The message, as plain text",
+                },
+            },
+            "required": ["to", "subject", "body"],
+        })
+    }
+
+    fn confirmation(&self, arguments: &str) -> Option<String> {
+        let email: Email = serde_json::from_str(arguments).ok()?;
+        // Emails that can't be sent need no approval; running will refuse them.
+        if email.recipients().is_empty() || !email.disallowed().is_empty() {
+            return None;
+        }
+        Some(format!(
+            "Send this email to {}?\n*{}*\n{}",
+            email.recipients().join(", "),
+            email.subject,
+            email.body
+        ))
+    }
+
+    async fn execute(&self, arguments: &str, _context: &ToolContext) -> anyhow::Result<String> {
+        let email: Email = serde_json::from_str(arguments)?;
+        let recipients = email.recipients();
+        if recipients.is_empty() {
+            bail!("no recipient was given");
+        }
+        let disallowed = email.disallowed();
+        if !disallowed.is_empty() {
+            return Ok(format!(
+                "Not allowed to email {}: only addresses on the allowlist can be emailed.",
+                disallowed.join(", ")
+            ));
+        }
+
+        let from = env::var("email_from").map_err(|_| anyhow!("email_from is not set"))?;
+        let key =
+            env::var("SENDGRID_API_KEY").map_err(|_| anyhow!("SENDGRID_API_KEY is not set"))?;
+        let to: Vec<Value> = recipients.iter().map(|r| json!({ "email": r })).collect();
+        let body = json!({
+            "personalizations": [{ "to": to }],
+            "from": { "email": from },
+            "subject": email.subject,
+            "content": [{ "type": "text/plain", "value": email.body }],
+        });
+
+        let authorization = format!("Bearer {key}");
+        let res =
+            http::post_json(SENDGRID_API, &[("Authorization", &authorization)], &body).await?;
+        if !res.is_success() {
+            let message = res
+                .json::<Value>()
+                .ok()
+                .and_then(|v| v["errors"][0]["message"].as_str().map(str::to_string))
+                .unwrap_or_default();
+            bail!("SendGrid returned {}: {}", res.status, message);
+        }
+        Ok(format!("Sent the email to {}", recipients.join(", ")))
+    }
+}
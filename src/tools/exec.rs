@@ -0,0 +1,179 @@
+use crate::tool;
+use crate::tools::http;
+use anyhow::{anyhow, bail};
+use serde_json::{json, Value};
+use std::env;
+
+const MAX_ARGS: usize = 10;
+const MAX_ARG_CHARS: usize = 200;
+/// Output past this many characters is cut short.
+const MAX_OUTPUT_CHARS: usize = 3000;
+
+tool! {
+    /// Run an operations command such as "dig example.com MX" or
+    /// "curl -I https://example.com". Only a few commands are allowed, with plain
+    /// arguments and no extra options.
+    pub struct ExecTool as "exec";
+
+    async fn exec(
+        /// The command line to run
+        command: String,
+    ) -> anyhow::Result<String> {
+        let words = match check_command(&command, &allowlist()) {
+            Ok(words) => words,
+            Err(reason) => return Ok(format!("Refused to run `{command}`: {reason}.")),
+        };
+        let result = run_on_agent(&words).await?;
+
+        let output = [
+            result["stdout"].as_str().unwrap_or_default(),
+            result["stderr"].as_str().unwrap_or_default(),
+        ]
+        .join("\n");
+        let output = output.trim();
+        let mut shown: String = output.chars().take(MAX_OUTPUT_CHARS).collect();
+        if shown.len() < output.len() {
+            shown.push_str("\n[truncated]");
+        }
+        Ok(format!("Exit code {}.\n```\n{shown}\n```", result["code"]))
+    }
+}
+
+/// Whether an exec agent and at least one allowed command are configured.
+pub fn exec_configured() -> bool {
+    env::var("exec_agent_url").is_ok() && !allowlist().is_empty()
+}
+
+/// The allowed commands from `exec_allowlist`, such as `dig,whois,curl -I`.
+/// Each entry is a program and any options it must be run with.
+fn allowlist() -> Vec<Vec<String>> {
+    env::var("exec_allowlist")
+        .unwrap_or_default()
+        .split(',')
+        .map(|entry| {
+            entry
+                .split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+/// Splits `command` into words and checks them: they must start with an
+/// allowlisted entry, and the arguments after it may only use characters
+/// found in host names, addresses and web urls, and may not be options. Nothing
+/// is run through a shell, but the agent on the other end may not be as
+/// careful.
+fn check_command(command: &str, allowlist: &[Vec<String>]) -> Result<Vec<String>, String> {
+    let words: Vec<String> = command.split_whitespace().map(str::to_string).collect();
+    let entry = allowlist
+        .iter()
+        .filter(|entry| words.starts_with(entry))
+        .max_by_key(|entry| entry.len())
+        .ok_or_else(|| {
+            let allowed: Vec<String> = allowlist.iter().map(|entry| entry.join(" ")).collect();
+            format!("only these commands are allowed: {}", allowed.join(", "))
+        })?;
+
+    let args = &words[entry.len()..];
+    if args.len() > MAX_ARGS {
+        return Err(format!("at most {MAX_ARGS} arguments are allowed"));
+    }
+    for arg in args {
+        if arg.starts_with('-') {
+            return Err(format!("the option {arg} is not allowed"));
+        }
+        if arg.chars().count() > MAX_ARG_CHARS
+            || !arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "._:/@+=?%,~-".contains(c))
+        {
+            return Err(format!("the argument {arg} is not allowed"));
+        }
+        // Urls such as file:///etc/passwd would read the agent's own files.
+        if arg.contains("://") && !(arg.starts_with("http://") || arg.starts_with("https://")) {
+            return Err("only http and https urls are allowed".to_string());
+        }
+    }
+    Ok(words)
+}
+
+/// Flows can't start processes, so commands run on an agent reached at
+/// `exec_agent_url`. It takes `{"program", "args"}` and answers with
+/// `{"code", "stdout", "stderr"}`.
+async fn run_on_agent(words: &[String]) -> anyhow::Result<Value> {
+    let url = env::var("exec_agent_url").map_err(|_| anyhow!("no exec agent is configured"))?;
+    let body = json!({ "program": words[0], "args": words[1..] });
+    let authorization = env::var("EXEC_AGENT_TOKEN")
+        .ok()
+        .map(|token| format!("Bearer {token}"));
+    let mut headers = Vec::new();
+    if let Some(authorization) = &authorization {
+        headers.push(("Authorization", authorization.as_str()));
+    }
+    let res = http::post_json(&url, &headers, &body).await?;
+    if !res.is_success() {
+        bail!("the exec agent returned {}", res.status);
+    }
+    res.json()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed() -> Vec<Vec<String>> {
+        [vec!["dig"], vec!["curl", "-I"]]
+            .iter()
+            .map(|entry| entry.iter().map(|w| w.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn allows_listed_commands_with_plain_arguments() {
+        assert_eq!(
+            check_command("dig example.com MX", &allowed()),
+            Ok(vec!["dig".into(), "example.com".into(), "MX".into()])
+        );
+        assert_eq!(
+            check_command("curl -I https://example.com/a?b=c", &allowed()),
+            Ok(vec![
+                "curl".into(),
+                "-I".into(),
+                "https://example.com/a?b=c".into()
+            ])
+        );
+    }
+
+    #[test]
+    fn refuses_unlisted_commands() {
+        assert_eq!(
+            check_command("rm -rf /", &allowed()),
+            Err("only these commands are allowed: dig, curl -I".to_string())
+        );
+        // An entry's options must all be there.
+        assert!(check_command("curl https://example.com", &allowed()).is_err());
+    }
+
+    #[test]
+    fn refuses_extra_options() {
+        assert_eq!(
+            check_command("curl -I -o /tmp/x https://example.com", &allowed()),
+            Err("the option -o is not allowed".to_string())
+        );
+    }
+
+    #[test]
+    fn refuses_shell_characters_and_other_schemes() {
+        assert_eq!(
+            check_command("dig example.com;reboot", &allowed()),
+            Err("the argument example.com;reboot is not allowed".to_string())
+        );
+        assert!(check_command("dig $(id)", &allowed()).is_err());
+        assert_eq!(
+            check_command("curl -I file:///etc/passwd", &allowed()),
+            Err("only http and https urls are allowed".to_string())
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use crate::tool;
+use crate::tools::http::{self, Method};
+use crate::tools::{Tool, ToolContext};
+use anyhow::bail;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::env;
+use urlencoding::encode;
+
+const API_BASE: &str = "https://api.github.com";
+/// Issue bodies longer than this many characters are cut short.
+const MAX_BODY_CHARS: usize = 1500;
+const MAX_ISSUES: u32 = 20;
+
+tool! {
+    /// Get a GitHub repository's description, stars, forks, open issue count and
+    /// main language
+    pub struct RepoInfoTool as "getRepoInfo";
+
+    async fn get_repo_info(
+        /// The user or organization owning the repository
+        owner: String,
+        /// The repository name
+        repo: String,
+    ) -> anyhow::Result<String> {
+        let r = github_get(&format!("/repos/{}/{}", encode(&owner), encode(&repo))).await?;
+        Ok(format!(
+            "{}\n{}\nStars: {}, forks: {}, open issues and pull requests: {}\n\
+             Language: {}, license: {}, default branch: {}\nLast pushed: {}\n{}",
+            text(&r["full_name"]),
+            text(&r["description"]),
+            r["stargazers_count"],
+            r["forks_count"],
+            r["open_issues_count"],
+            text(&r["language"]),
+            text(&r["license"]["name"]),
+            text(&r["default_branch"]),
+            text(&r["pushed_at"]),
+            text(&r["html_url"]),
+        ))
+    }
+}
+
+tool! {
+    /// List the most recently updated open issues of a GitHub repository, optionally
+    /// only those with a given label
+    pub struct OpenIssuesTool as "listOpenIssues";
+
+    async fn list_open_issues(
+        /// The user or organization owning the repository
+        owner: String,
+        /// The repository name
+        repo: String,
+        /// Only list issues with this label
+        label: Option<String>,
+        /// How many issues to list, at most 20
+        count: Option<u32>,
+    ) -> anyhow::Result<String> {
+        let count = count.unwrap_or(10).clamp(1, MAX_ISSUES);
+        let mut path = format!(
+            "/repos/{}/{}/issues?state=open&sort=updated&per_page={count}",
+            encode(&owner),
+            encode(&repo)
+        );
+        if let Some(label) = &label {
+            path.push_str(&format!("&labels={}", encode(label)));
+        }
+
+        let issues = github_get(&path).await?;
+        let lines = issues
+            .as_array()
+            .into_iter()
+            .flatten()
+            // The issues endpoint includes pull requests.
+            .filter(|issue| issue.get("pull_request").is_none())
+            .map(|issue| {
+                format!(
+                    "#{} {} ({}) by {}",
+                    issue["number"],
+                    text(&issue["title"]),
+                    labels(issue),
+                    text(&issue["user"]["login"])
+                )
+            })
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return Ok(format!("No open issues in {owner}/{repo}"));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+tool! {
+    /// Get the title, state, labels and description of a GitHub issue or pull request
+    pub struct IssueTool as "getIssue";
+
+    async fn get_issue(
+        /// The user or organization owning the repository
+        owner: String,
+        /// The repository name
+        repo: String,
+        /// The issue number
+        number: u64,
+    ) -> anyhow::Result<String> {
+        let issue = github_get(&format!(
+            "/repos/{}/{}/issues/{number}",
+            encode(&owner),
+            encode(&repo)
+        ))
+        .await?;
+
+        let mut body = text(&issue["body"]);
+        if body.chars().count() > MAX_BODY_CHARS {
+            body = body.chars().take(MAX_BODY_CHARS).collect::<String>() + "\n[truncated]";
+        }
+        Ok(format!(
+            "#{number} {}\nState: {}, opened by {} on {}, {} comments\nLabels: {}\n{}\n\n{body}",
+            text(&issue["title"]),
+            text(&issue["state"]),
+            text(&issue["user"]["login"]),
+            text(&issue["created_at"]),
+            issue["comments"],
+            labels(&issue),
+            text(&issue["html_url"]),
+        ))
+    }
+}
+
+/// Files an issue, after the user has approved its title and body.
+pub struct CreateIssueTool;
+
+#[derive(Deserialize)]
+struct NewIssue {
+    owner: String,
+    repo: String,
+    title: String,
+    #[serde(default)]
+    body: String,
+}
+
+#[async_trait]
+impl Tool for CreateIssueTool {
+    fn name(&self) -> &str {
+        "createIssue"
+    }
+
+    fn description(&self) -> &str {
+        "File a new issue in a GitHub repository. The user is asked to approve it first."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "owner": {
+                    "type": "string",
+                    "description": "The user or organization owning the repository",
+                },
+                "repo": {
+                    "type": "string",
+                    "description": "The repository name",
+                },
+                "title": {
+                    "type": "string",
+                    "description": "A short summary of the issue",
+                },
+                "body": {
+                    "type": "string",
+                    "description": "The issue description, in Markdown",
+                },
+            },
+            "required": ["owner", "repo", "title"],
+        })
+    }
+
+    fn confirmation(&self, arguments: &str) -> Option<String> {
+        let issue: NewIssue = match serde_json::from_str(arguments) {
+            Ok(issue) => issue,
+            // Nothing can be filed from these arguments, so there's nothing to
+            // approve; running will report the error.
+            Err(_) => return None,
+        };
+        Some(format!(
+            "File this issue in {}/{}?\n*{}*\n{}",
+            issue.owner, issue.repo, issue.title, issue.body
+        ))
+    }
+
+    async fn execute(&self, arguments: &str, _context: &ToolContext) -> anyhow::Result<String> {
+        let issue: NewIssue = serde_json::from_str(arguments)?;
+        let created = github_request(
+            Method::POST,
+            &format!(
+                "/repos/{}/{}/issues",
+                encode(&issue.owner),
+                encode(&issue.repo)
+            ),
+            Some(json!({ "title": issue.title, "body": issue.body })),
+        )
+        .await?;
+        Ok(format!(
+            "Filed issue #{}: {}",
+            created["number"],
+            text(&created["html_url"])
+        ))
+    }
+}
+
+/// Calls the GitHub REST API, authenticating with `GITHUB_TOKEN` when it is
+/// set. Without one, only public repositories can be read and the rate limit
+/// is much lower.
+async fn github_get(path: &str) -> anyhow::Result<Value> {
+    github_request(Method::GET, path, None).await
+}
+
+async fn github_request(method: Method, path: &str, body: Option<Value>) -> anyhow::Result<Value> {
+    let token = env::var("GITHUB_TOKEN").ok();
+    if method != Method::GET && token.is_none() {
+        bail!("GITHUB_TOKEN is needed to change anything on GitHub");
+    }
+    let url = format!("{API_BASE}{path}");
+    let authorization = token.map(|token| format!("Bearer {token}"));
+    let mut headers = vec![("Accept", "application/vnd.github+json")];
+    if let Some(authorization) = &authorization {
+        headers.push(("Authorization", authorization));
+    }
+    let body = body.map(|b| {
+        headers.push(("Content-Type", "application/json"));
+        b.to_string().into_bytes()
+    });
+    let res = http::send(method, &url, &headers, body).await?;
+    if !res.is_success() {
+        let message = res
+            .json::<Value>()
+            .ok()
+            .and_then(|v| v["message"].as_str().map(str::to_string))
+            .unwrap_or_default();
+        bail!("GitHub returned {}: {}", res.status, message);
+    }
+    res.json()
+}
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or("none").to_string()
+}
+
+fn labels(issue: &Value) -> String {
+    let names = issue["labels"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|label| label["name"].as_str())
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        "no labels".to_string()
+    } else {
+        names.join(", ")
+    }
+}
@@ -0,0 +1,444 @@
+//! The HTTP client shared by the tools, with a user agent, timeouts, a
+//! redirect limit, a proxy and retries read from the environment.
+//!
+//! GETs go through the [`Cassette`] the tool was given: when recording,
+//! responses are also saved as JSON fixtures; when replaying, they're read
+//! back and the network isn't used, so tool output can be checked
+//! deterministically.
+//! Secrets in query strings are redacted before URLs are saved or matched.
+
+use crate::analytics::fnv1a;
+use crate::secrets::{MASK, SECRET_PARAMS};
+use anyhow::anyhow;
+use backoff::{backoff::Backoff, ExponentialBackoff, ExponentialBackoffBuilder};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use once_cell::sync::Lazy;
+use reqwest::{redirect, Client, Proxy};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use url::Url;
+
+pub(crate) use reqwest::Method;
+
+const DEFAULT_CASSETTE_DIR: &str = "fixtures/http";
+const DEFAULT_USER_AGENT: &str = "gpt-function-call-demo";
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+/// Retries of a failed GET or HEAD, after the first attempt.
+const DEFAULT_RETRIES: u32 = 2;
+
+/// A response, live or replayed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Response {
+    pub status: u16,
+    #[serde(default)]
+    pub content_type: String,
+    #[serde(with = "base64_body")]
+    pub body: Vec<u8>,
+    /// Where a redirect leads, for requests sent with [`send_unfollowed`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// The status's reason phrase, such as "Not Found".
+    pub fn reason(&self) -> &'static str {
+        reqwest::StatusCode::from_u16(self.status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or_default()
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> anyhow::Result<T> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+static CLIENT: Lazy<Client> = Lazy::new(|| {
+    build_client(true).unwrap_or_else(|e| {
+        log::warn!("Falling back to the default HTTP client: {}", e);
+        Client::new()
+    })
+});
+/// The same client, but leaving redirects to the caller.
+static UNFOLLOWED_CLIENT: Lazy<Client> = Lazy::new(|| {
+    build_client(false).unwrap_or_else(|e| {
+        log::warn!("Falling back to the default HTTP client: {}", e);
+        Client::builder()
+            .redirect(redirect::Policy::none())
+            .build()
+            .unwrap_or_default()
+    })
+});
+
+/// What a fixture file holds.
+#[derive(Serialize, Deserialize)]
+struct Recording {
+    url: String,
+    response: Response,
+}
+
+/// What GETs do with the network and the fixtures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Requests go to the network and nothing is saved.
+    Live,
+    /// Requests go to the network and responses are saved as fixtures.
+    Record,
+    /// Responses are read from the fixtures, without the network.
+    Replay,
+}
+
+/// How a tool's GETs are recorded or replayed, with the fixtures kept in
+/// `dir`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cassette {
+    pub mode: CassetteMode,
+    pub dir: PathBuf,
+}
+
+impl Cassette {
+    /// From `http_cassette`, `record` or `replay`, and `http_cassette_dir`.
+    pub fn from_env() -> Self {
+        let mode = match env::var("http_cassette").as_deref() {
+            Ok("record") => CassetteMode::Record,
+            Ok("replay") => CassetteMode::Replay,
+            _ => CassetteMode::Live,
+        };
+        let dir = env::var("http_cassette_dir").unwrap_or(DEFAULT_CASSETTE_DIR.to_string());
+        Self {
+            mode,
+            dir: PathBuf::from(dir),
+        }
+    }
+
+    /// Replays the fixtures in `dir`.
+    pub fn replay(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            mode: CassetteMode::Replay,
+            dir: dir.into(),
+        }
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.mode == CassetteMode::Replay
+    }
+}
+
+impl Default for Cassette {
+    fn default() -> Self {
+        Self {
+            mode: CassetteMode::Live,
+            dir: PathBuf::from(DEFAULT_CASSETTE_DIR),
+        }
+    }
+}
+
+/// GETs `url` with `headers`, or replays the recorded response.
+pub(crate) async fn get(
+    cassette: &Cassette,
+    url: &str,
+    headers: &[(&str, &str)],
+) -> anyhow::Result<Response> {
+    if cassette.is_replay() {
+        let path = fixture_path(&cassette.dir, url);
+        let json = fs::read_to_string(&path).map_err(|e| {
+            anyhow!(
+                "no recording of {} at {}: {}",
+                redact(url),
+                path.display(),
+                e
+            )
+        })?;
+        let recording: Recording = serde_json::from_str(&json)?;
+        return Ok(recording.response);
+    }
+
+    let response = send(Method::GET, url, headers, None).await?;
+    if cassette.mode == CassetteMode::Record {
+        if let Err(e) = record(&cassette.dir, url, &response) {
+            log::warn!("Failed to record {}: {}", redact(url), e);
+        }
+    }
+    Ok(response)
+}
+
+/// Saves `response` as the fixture for `url` in `dir`.
+pub(crate) fn record(dir: &Path, url: &str, response: &Response) -> anyhow::Result<()> {
+    let path = fixture_path(dir, url);
+    let recording = Recording {
+        url: redact(url),
+        response: response.clone(),
+    };
+    fs::create_dir_all(dir)?;
+    fs::write(&path, serde_json::to_string_pretty(&recording)?)?;
+    Ok(())
+}
+
+/// Sends a request with `headers` and `body`, always over the network.
+///
+/// GETs and HEADs that fail to connect, time out, or get a 429 or 5xx are
+/// retried `http_retries` times with exponential backoff. Other methods
+/// aren't, since they may have taken effect.
+pub(crate) async fn send(
+    method: Method,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<Vec<u8>>,
+) -> anyhow::Result<Response> {
+    let retries = if matches!(method, Method::GET | Method::HEAD) {
+        setting("http_retries", DEFAULT_RETRIES)
+    } else {
+        0
+    };
+    let mut backoff = retry_backoff();
+    let mut attempt = 0;
+    loop {
+        let result = send_once(&CLIENT, method.clone(), url, headers, body.clone()).await;
+        let retryable = match &result {
+            Ok(res) => res.status == 429 || res.status >= 500,
+            Err(e) => e
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|e| e.is_timeout() || e.is_request()),
+        };
+        if !retryable || attempt >= retries {
+            return result;
+        }
+        let delay = backoff.next_backoff().unwrap_or(Duration::from_secs(1));
+        log::warn!(
+            "{} {} attempt {} failed, retrying in {:?}",
+            method,
+            redact(url),
+            attempt + 1,
+            delay
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Sends a request once, without retrying, allowing it `timeout` instead of
+/// `http_timeout_secs`, for uploads the API takes a while to answer.
+pub(crate) async fn send_timed(
+    method: Method,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Vec<u8>,
+    timeout: Duration,
+) -> anyhow::Result<Response> {
+    let mut request = CLIENT.request(method, url).timeout(timeout);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    read(request.body(body)).await
+}
+
+/// Sends a request once, without following redirects or retrying, for
+/// callers that follow redirects a hop at a time.
+pub(crate) async fn send_unfollowed(
+    method: Method,
+    url: &str,
+    headers: &[(&str, &str)],
+) -> anyhow::Result<Response> {
+    send_once(&UNFOLLOWED_CLIENT, method, url, headers, None).await
+}
+
+async fn send_once(
+    client: &Client,
+    method: Method,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<Vec<u8>>,
+) -> anyhow::Result<Response> {
+    let mut request = client.request(method, url);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    if let Some(body) = body {
+        request = request.body(body);
+    }
+    read(request).await
+}
+
+async fn read(request: reqwest::RequestBuilder) -> anyhow::Result<Response> {
+    // The URL may carry an API key, so it's kept out of the error.
+    let res = request.send().await.map_err(reqwest::Error::without_url)?;
+    let status = res.status().as_u16();
+    let content_type = res
+        .headers()
+        .get("Content-Type")
+        .and_then(|t| t.to_str().ok())
+        .map(|t| t.to_ascii_lowercase())
+        .unwrap_or_default();
+    let location = res
+        .headers()
+        .get("Location")
+        .and_then(|l| l.to_str().ok())
+        .map(str::to_string);
+    let body = res.bytes().await?.to_vec();
+    Ok(Response {
+        status,
+        content_type,
+        body,
+        location,
+    })
+}
+
+/// POSTs `body` as JSON.
+pub(crate) async fn post_json(
+    url: &str,
+    headers: &[(&str, &str)],
+    body: &serde_json::Value,
+) -> anyhow::Result<Response> {
+    let mut headers = headers.to_vec();
+    headers.push(("Content-Type", "application/json"));
+    send(
+        Method::POST,
+        url,
+        &headers,
+        Some(body.to_string().into_bytes()),
+    )
+    .await
+}
+
+/// The client, from `http_user_agent`, `http_timeout_secs`,
+/// `http_connect_timeout_secs` and `http_max_redirects`. Requests go
+/// through `http_proxy_url` when it is set, and otherwise through the usual
+/// `HTTPS_PROXY` and `HTTP_PROXY`; hosts in `NO_PROXY` are reached directly.
+/// Without `follow`, no redirects are followed.
+fn build_client(follow: bool) -> anyhow::Result<Client> {
+    let user_agent = env::var("http_user_agent").unwrap_or_else(|_| DEFAULT_USER_AGENT.to_string());
+    let timeout = setting("http_timeout_secs", DEFAULT_TIMEOUT_SECS);
+    let connect_timeout = setting("http_connect_timeout_secs", DEFAULT_CONNECT_TIMEOUT_SECS);
+    let redirects = match setting("http_max_redirects", DEFAULT_MAX_REDIRECTS) {
+        _ if !follow => redirect::Policy::none(),
+        0 => redirect::Policy::none(),
+        max => redirect::Policy::limited(max),
+    };
+    let mut builder = Client::builder()
+        .user_agent(user_agent)
+        .timeout(Duration::from_secs(timeout))
+        .connect_timeout(Duration::from_secs(connect_timeout))
+        .redirect(redirects)
+        .no_proxy();
+    let proxies = match env::var("http_proxy_url")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+    {
+        Some(proxy) => vec![(None, proxy)],
+        None => [("https", "HTTPS_PROXY"), ("http", "HTTP_PROXY")]
+            .into_iter()
+            .filter_map(|(scheme, var)| proxy_var(var).map(|proxy| (Some(scheme), proxy)))
+            .collect(),
+    };
+    for (scheme, proxy) in proxies {
+        let proxy = Url::parse(&proxy)?;
+        let bypass = no_proxy_hosts();
+        builder = builder.proxy(Proxy::custom(move |url| {
+            let host = url.host_str().unwrap_or_default();
+            let bypassed = bypass.iter().any(|domain| {
+                domain == "*" || host == domain || host.ends_with(&format!(".{domain}"))
+            });
+            let applies = scheme.is_none_or(|scheme| url.scheme() == scheme);
+            (applies && !bypassed).then(|| proxy.clone())
+        }));
+    }
+    Ok(builder.build()?)
+}
+
+fn retry_backoff() -> ExponentialBackoff {
+    ExponentialBackoffBuilder::new()
+        .with_initial_interval(Duration::from_millis(500))
+        .with_max_interval(Duration::from_secs(5))
+        .with_max_elapsed_time(None)
+        .build()
+}
+
+fn setting<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+/// The domains in `NO_PROXY` that are reached directly.
+fn no_proxy_hosts() -> Vec<String> {
+    proxy_var("NO_PROXY")
+        .unwrap_or_default()
+        .split(',')
+        .map(|d| d.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|d| !d.is_empty())
+        .collect()
+}
+
+/// `name`, or its lowercase spelling, when set.
+fn proxy_var(name: &str) -> Option<String> {
+    env::var(name)
+        .or_else(|_| env::var(name.to_ascii_lowercase()))
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+}
+
+/// Replays the fixtures kept with the crate, for tests of the tools that
+/// fetch over HTTP, which `cargo test` runs from the crate's directory.
+#[cfg(test)]
+pub(crate) fn replay_fixtures() -> Cassette {
+    Cassette::replay(DEFAULT_CASSETTE_DIR)
+}
+
+/// Where the recording of `url` is kept in `dir`, named by its host and a
+/// hash of the redacted URL.
+fn fixture_path(dir: &Path, url: &str) -> PathBuf {
+    let redacted = redact(url);
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or("unknown".to_string());
+    dir.join(format!("{host}-{:016x}.json", fnv1a(redacted.as_bytes())))
+}
+
+/// `url` with the values of [`SECRET_PARAMS`] replaced.
+fn redact(url: &str) -> String {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    let pairs: Vec<(String, String)> = parsed
+        .query_pairs()
+        .map(|(name, value)| {
+            let value = if SECRET_PARAMS.contains(&name.to_ascii_lowercase().as_str()) {
+                MASK.to_string()
+            } else {
+                value.into_owned()
+            };
+            (name.into_owned(), value)
+        })
+        .collect();
+    if !pairs.is_empty() {
+        parsed.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    parsed.to_string()
+}
+
+/// Bodies are saved as base64, since pages and PDFs needn't be UTF-8.
+mod base64_body {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(body: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(body))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,147 @@
+use crate::client::ChatClient;
+use crate::tools::{Tool, ToolContext};
+use anyhow::{anyhow, bail};
+use async_openai::types::{CreateImageRequestArgs, Image, ImageModel, ImageSize, ResponseFormat};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::env;
+use std::time::Duration;
+
+const DEFAULT_IMAGE_MODEL: &str = "dall-e-3";
+
+/// Draws an image for a prompt and posts it to the conversation's channel,
+/// or hands back a link to it when `image_delivery` is `url`.
+pub struct ImageTool {
+    client: ChatClient,
+}
+
+impl ImageTool {
+    pub fn new(client: ChatClient) -> Self {
+        Self { client }
+    }
+}
+
+#[derive(Deserialize)]
+struct Arguments {
+    prompt: String,
+    size: Option<String>,
+}
+
+#[async_trait]
+impl Tool for ImageTool {
+    fn name(&self) -> &str {
+        "generateImage"
+    }
+
+    fn description(&self) -> &str {
+        "Draw an image, such as an illustration or a diagram, from a description and show it \
+         to the user"
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "prompt": {
+                    "type": "string",
+                    "description": "A detailed description of the image",
+                },
+                "size": {
+                    "type": "string",
+                    "enum": ["1024x1024", "1792x1024", "1024x1792"],
+                    "description": "Square, landscape or portrait. Square when left out",
+                },
+            },
+            "required": ["prompt"],
+        })
+    }
+
+    async fn execute(&self, arguments: &str, context: &ToolContext) -> anyhow::Result<String> {
+        let args: Arguments = serde_json::from_str(arguments)?;
+        let size = match args.size.as_deref() {
+            None | Some("1024x1024") => ImageSize::S1024x1024,
+            Some("1792x1024") => ImageSize::S1792x1024,
+            Some("1024x1792") => ImageSize::S1024x1792,
+            Some(other) => bail!("unsupported image size {other}"),
+        };
+        // Outside a flow there's no channel to post the image to.
+        let upload = cfg!(feature = "flows") && env::var("image_delivery").as_deref() != Ok("url");
+        let model = match env::var("image_model")
+            .unwrap_or(DEFAULT_IMAGE_MODEL.to_string())
+            .as_str()
+        {
+            "dall-e-2" => ImageModel::DallE2,
+            "dall-e-3" => ImageModel::DallE3,
+            other => ImageModel::Other(other.to_string()),
+        };
+        let request = CreateImageRequestArgs::default()
+            .prompt(&args.prompt)
+            .model(model)
+            .size(size)
+            .response_format(if upload {
+                ResponseFormat::B64Json
+            } else {
+                ResponseFormat::Url
+            })
+            .user(&context.user)
+            .build()?;
+
+        let response = self.client.create_image(request).await?;
+        let image = response
+            .data
+            .first()
+            .ok_or_else(|| anyhow!("no image was generated"))?;
+        match image.as_ref() {
+            Image::B64Json {
+                b64_json,
+                revised_prompt,
+            } => {
+                let bytes = STANDARD.decode(b64_json.as_bytes())?;
+                post_image(context, bytes).await?;
+                Ok(describe(
+                    "Posted the image to the channel.",
+                    revised_prompt.as_deref(),
+                ))
+            }
+            Image::Url {
+                url,
+                revised_prompt,
+            } => Ok(describe(
+                &format!("The image is at {url} (the link expires after an hour)."),
+                revised_prompt.as_deref(),
+            )),
+        }
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Some(Duration::from_secs(120))
+    }
+}
+
+/// Models that rewrite prompts say how, which helps explain the result.
+fn describe(outcome: &str, revised_prompt: Option<&str>) -> String {
+    match revised_prompt {
+        Some(prompt) => format!("{outcome} It was drawn from this prompt: {prompt}"),
+        None => outcome.to_string(),
+    }
+}
+
+#[cfg(feature = "flows")]
+async fn post_image(context: &ToolContext, png: Vec<u8>) -> anyhow::Result<()> {
+    slack_flows::upload_file(
+        &context.workspace,
+        &context.channel,
+        "image.png",
+        "png",
+        png,
+    )
+    .await;
+    Ok(())
+}
+
+#[cfg(not(feature = "flows"))]
+async fn post_image(_context: &ToolContext, _png: Vec<u8>) -> anyhow::Result<()> {
+    bail!("images can only be posted to a channel from a flow")
+}
@@ -0,0 +1,274 @@
+use serde_json::{json, Value};
+
+/// Declares a [`Tool`](crate::tools::Tool) from an async function.
+///
+/// The doc comment on the struct becomes the tool description, each argument
+/// becomes a parameter whose JSON schema is derived from its type through
+/// [`ToolParam`], and doc comments on arguments become parameter descriptions.
+/// `Option<T>` arguments are left out of `required`. A leading argument
+/// marked `#[context]` receives the [`ToolContext`](crate::tools::ToolContext)
+/// instead of a model-supplied value. An optional `examples` block after the
+/// function pairs requests with the arguments they should be answered with.
+///
+/// ```ignore
+/// tool! {
+///     /// Get weather forecast for the city passed to it
+///     pub struct WeatherTool as "getWeather";
+///
+///     pub async fn get_weather(
+///         /// The city specified by the user
+///         city: String,
+///     ) -> String {
+///         format!("Sunny in {city}")
+///     }
+///
+///     examples {
+///         "Do I need an umbrella in Paris?" => { "city": "Paris" },
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! tool {
+    (
+        @impl
+        $(#[doc = $doc:literal])*
+        $vis:vis struct $tool:ident as $name:literal;
+        examples [$($request:literal => $example:tt),*];
+        ($ctx:ident) $call:expr;
+        $( $(#[doc = $pdoc:literal])* $arg:ident : $ty:ty ),*
+    ) => {
+        $(#[doc = $doc])*
+        $vis struct $tool;
+
+        #[$crate::tools::__private::async_trait]
+        impl $crate::tools::Tool for $tool {
+            fn name(&self) -> &str {
+                $name
+            }
+
+            fn description(&self) -> &str {
+                static DESCRIPTION: $crate::tools::__private::Lazy<String> =
+                    $crate::tools::__private::Lazy::new(|| {
+                        $crate::tools::__private::doc_text(&[$($doc),*])
+                    });
+                &DESCRIPTION
+            }
+
+            #[allow(unused_mut)]
+            fn parameters(&self) -> $crate::tools::__private::Value {
+                let mut params = $crate::tools::__private::Parameters::default();
+                $(
+                    params.add::<$ty>(
+                        stringify!($arg),
+                        &$crate::tools::__private::doc_text(&[$($pdoc),*]),
+                    );
+                )*
+                params.into_schema()
+            }
+
+            fn examples(&self) -> Vec<$crate::tools::ToolExample> {
+                vec![$(
+                    $crate::tools::ToolExample::new(
+                        $request,
+                        $crate::tools::__private::json!($example),
+                    )
+                ),*]
+            }
+
+            #[allow(unused_variables, unused_mut)]
+            async fn execute(
+                &self,
+                arguments: &str,
+                $ctx: &$crate::tools::ToolContext,
+            ) -> $crate::tools::__private::anyhow::Result<String> {
+                let mut args = $crate::tools::__private::parse_arguments(arguments)?;
+                $(
+                    let $arg = $crate::tools::__private::take_argument::<$ty>(
+                        &mut args,
+                        stringify!($arg),
+                    )?;
+                )*
+                $crate::tools::ToolOutput::into_output($call.await)
+            }
+        }
+    };
+    (
+        $(#[doc = $doc:literal])*
+        $vis:vis struct $tool:ident as $name:literal;
+
+        $fvis:vis async fn $func:ident(
+            #[context] $ctx:ident : &$cty:ty,
+            $( $(#[doc = $pdoc:literal])* $arg:ident : $ty:ty ),* $(,)?
+        ) -> $ret:ty $body:block
+
+        $(examples { $($request:literal => $example:tt),* $(,)? })?
+    ) => {
+        $fvis async fn $func($ctx: &$cty, $($arg: $ty),*) -> $ret $body
+
+        $crate::tool! {
+            @impl
+            $(#[doc = $doc])*
+            $vis struct $tool as $name;
+            examples [$($($request => $example),*)?];
+            ($ctx) $func($ctx, $($arg),*);
+            $( $(#[doc = $pdoc])* $arg: $ty ),*
+        }
+    };
+    (
+        $(#[doc = $doc:literal])*
+        $vis:vis struct $tool:ident as $name:literal;
+
+        $fvis:vis async fn $func:ident(
+            $( $(#[doc = $pdoc:literal])* $arg:ident : $ty:ty ),* $(,)?
+        ) -> $ret:ty $body:block
+
+        $(examples { $($request:literal => $example:tt),* $(,)? })?
+    ) => {
+        $fvis async fn $func($($arg: $ty),*) -> $ret $body
+
+        $crate::tool! {
+            @impl
+            $(#[doc = $doc])*
+            $vis struct $tool as $name;
+            examples [$($($request => $example),*)?];
+            (context) $func($($arg),*);
+            $( $(#[doc = $pdoc])* $arg: $ty ),*
+        }
+    };
+}
+
+/// Maps a Rust type to the JSON schema of a tool parameter.
+pub trait ToolParam: serde::de::DeserializeOwned {
+    fn schema() -> Value;
+
+    fn required() -> bool {
+        true
+    }
+}
+
+impl ToolParam for String {
+    fn schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl ToolParam for bool {
+    fn schema() -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+macro_rules! impl_tool_param {
+    ($json_type:literal => $($ty:ty),*) => {
+        $(
+            impl ToolParam for $ty {
+                fn schema() -> Value {
+                    json!({ "type": $json_type })
+                }
+            }
+        )*
+    };
+}
+
+impl_tool_param!("integer" => i8, i16, i32, i64, u8, u16, u32, u64, usize);
+impl_tool_param!("number" => f32, f64);
+
+impl<T: ToolParam> ToolParam for Option<T> {
+    fn schema() -> Value {
+        T::schema()
+    }
+
+    fn required() -> bool {
+        false
+    }
+}
+
+impl<T: ToolParam> ToolParam for Vec<T> {
+    fn schema() -> Value {
+        json!({ "type": "array", "items": T::schema() })
+    }
+}
+
+/// Converts the return value of a [`tool!`] function into a tool result.
+pub trait ToolOutput {
+    fn into_output(self) -> anyhow::Result<String>;
+}
+
+impl ToolOutput for String {
+    fn into_output(self) -> anyhow::Result<String> {
+        Ok(self)
+    }
+}
+
+impl<E: Into<anyhow::Error>> ToolOutput for Result<String, E> {
+    fn into_output(self) -> anyhow::Result<String> {
+        self.map_err(Into::into)
+    }
+}
+
+#[doc(hidden)]
+pub mod __private {
+    use super::ToolParam;
+    use serde_json::Map;
+
+    pub use anyhow;
+    pub use async_trait::async_trait;
+    pub use once_cell::sync::Lazy;
+    pub use serde_json::{json, Value};
+
+    /// Joins the lines of a doc comment into a single sentence.
+    pub fn doc_text(lines: &[&str]) -> String {
+        lines
+            .iter()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[derive(Default)]
+    pub struct Parameters {
+        properties: Map<String, Value>,
+        required: Vec<String>,
+    }
+
+    impl Parameters {
+        pub fn add<T: ToolParam>(&mut self, name: &str, description: &str) {
+            let mut schema = T::schema();
+            if !description.is_empty() {
+                schema["description"] = json!(description);
+            }
+            self.properties.insert(name.to_string(), schema);
+            if T::required() {
+                self.required.push(name.to_string());
+            }
+        }
+
+        pub fn into_schema(self) -> Value {
+            json!({
+                "type": "object",
+                "properties": self.properties,
+                "required": self.required,
+            })
+        }
+    }
+
+    pub fn parse_arguments(arguments: &str) -> anyhow::Result<Map<String, Value>> {
+        if arguments.trim().is_empty() {
+            return Ok(Map::new());
+        }
+        Ok(serde_json::from_str(arguments)?)
+    }
+
+    pub fn take_argument<T: ToolParam>(
+        args: &mut Map<String, Value>,
+        name: &str,
+    ) -> anyhow::Result<T> {
+        match args.remove(name) {
+            Some(value) => serde_json::from_value(value)
+                .map_err(|e| anyhow::anyhow!("invalid argument `{name}`: {e}")),
+            None if T::required() => Err(anyhow::anyhow!("missing argument `{name}`")),
+            None => Ok(serde_json::from_value(Value::Null)?),
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use crate::client::ChatClient;
+use crate::core::session::SessionKey;
+use crate::response_format;
+use crate::status::StatusSink;
+use async_openai::types::{
+    ChatCompletionFunctionsArgs, ChatCompletionTool, ChatCompletionToolArgs, ChatCompletionToolType,
+};
+use async_trait::async_trait;
+use jsonschema::JSONSchema;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+mod calendar;
+mod code;
+mod convert;
+mod crawl;
+mod database;
+mod dns;
+mod docs;
+mod email;
+mod exec;
+mod github;
+mod guard;
+pub(crate) mod http;
+mod image;
+mod macros;
+mod policy;
+mod prefs;
+mod reminder;
+mod scraper;
+mod search;
+mod summarize;
+mod time;
+mod tracker;
+mod translate;
+mod uptime;
+mod weather;
+
+pub use calendar::{calendar_configured, CalendarTool};
+pub use code::{code_runner_configured, RunCodeTool};
+pub use convert::ConvertTool;
+pub use crawl::CrawlSiteTool;
+pub use database::{database_configured, DatabaseTool};
+pub use dns::{DnsLookupTool, WhoisTool};
+pub use docs::{docs_configured, ingest_file, ingest_url, SearchDocsTool};
+pub use email::SendEmailTool;
+pub use exec::{exec_configured, ExecTool};
+pub use github::{CreateIssueTool, IssueTool, OpenIssuesTool, RepoInfoTool};
+pub(crate) use guard::is_untrusted;
+pub use http::{Cassette, CassetteMode};
+pub use image::ImageTool;
+#[doc(hidden)]
+pub use macros::__private;
+pub use macros::{ToolOutput, ToolParam};
+pub use prefs::{GetHomeCityTool, HomeCityTool, LanguageTool, SpeechTool, UnitsTool};
+#[cfg(feature = "flows")]
+pub use reminder::post_due_reminders;
+pub use reminder::ReminderTool;
+pub use scraper::ScraperTool;
+pub use search::{SearchProvider, SearchTool};
+pub use summarize::{SummarizeUrlTool, Summarizer};
+pub use time::TimeOfDayTool;
+pub use tracker::{TicketSearchTool, TicketTool, TrackerProvider};
+pub use translate::TranslateTool;
+pub use uptime::CheckUrlTool;
+pub use weather::{check_weather, AirQualityTool, ForecastTool, SunTimesTool, WeatherTool};
+
+/// Tools that post to Slack, left out on other platforms.
+pub const SLACK_ONLY_TOOLS: &[&str] = &["setReminder", "generateImage", "setSpokenAnswers"];
+
+/// Tools that change something beyond the conversation, which only users
+/// allowed by `write_users` or `write_usergroup` may use.
+pub const WRITE_TOOLS: &[&str] = &[
+    "sendEmail",
+    "createIssue",
+    "exec",
+    "runCode",
+    "setReminder",
+    "setChannelLanguage",
+];
+
+/// Who a tool is running for.
+#[derive(Clone, Default)]
+pub struct ToolContext {
+    pub workspace: String,
+    pub channel: String,
+    pub user: String,
+    /// The conversation the tools are called from, which holds requests for
+    /// confirmation.
+    pub session: SessionKey,
+    /// Where to post messages to the user while the conversation is running.
+    pub status: Option<Arc<dyn StatusSink>>,
+    /// Whether the tools' GETs are recorded or replayed.
+    pub cassette: Cassette,
+}
+
+impl ToolContext {
+    pub fn new(workspace: &str, channel: &str, user: &str) -> Self {
+        Self {
+            workspace: workspace.to_string(),
+            channel: channel.to_string(),
+            user: user.to_string(),
+            session: SessionKey::new(channel, user),
+            status: None,
+            cassette: Cassette::from_env(),
+        }
+    }
+
+    pub fn in_session(mut self, session: SessionKey) -> Self {
+        self.session = session;
+        self
+    }
+
+    pub fn with_status(mut self, status: Arc<dyn StatusSink>) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn with_cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = cassette;
+        self
+    }
+}
+
+/// A request a tool answers, and the arguments it should be called with.
+#[derive(Debug, Clone)]
+pub struct ToolExample {
+    pub request: String,
+    pub arguments: Value,
+}
+
+impl ToolExample {
+    pub fn new(request: &str, arguments: Value) -> Self {
+        Self {
+            request: request.to_string(),
+            arguments,
+        }
+    }
+}
+
+/// Returned by a tool that can't go on without asking the user, such as
+/// which of several places they mean. The call is held and `param` is
+/// filled in with their answer.
+#[derive(Debug, thiserror::Error)]
+#[error("{question}")]
+pub struct NeedsInput {
+    pub param: String,
+    pub question: String,
+}
+
+impl NeedsInput {
+    pub fn new(param: &str, question: impl Into<String>) -> Self {
+        Self {
+            param: param.to_string(),
+            question: question.into(),
+        }
+    }
+}
+
+/// A function the model can call.
+///
+/// Implementors describe themselves with a name, a description and a JSON
+/// schema for their parameters, and run with the raw JSON arguments string
+/// produced by the model and the context of the conversation.
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    fn parameters(&self) -> Value;
+
+    async fn execute(&self, arguments: &str, context: &ToolContext) -> anyhow::Result<String>;
+
+    /// For tools with side effects, the question to ask the user before
+    /// running with `arguments`. The call is held until they approve it.
+    fn confirmation(&self, _arguments: &str) -> Option<String> {
+        None
+    }
+
+    /// How long the tool may run, overriding the configured default.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Requests this tool answers, shown to the model when `tool_examples`
+    /// is on so smaller models pick the right tool.
+    fn examples(&self) -> Vec<ToolExample> {
+        Vec::new()
+    }
+
+    /// Checks `arguments` against [`parameters`](Self::parameters),
+    /// describing what's wrong so the model can correct its call.
+    fn check_arguments(&self, arguments: &str) -> Result<(), String> {
+        let mut value: Value = if arguments.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str(arguments)
+                .map_err(|e| format!("the arguments aren't JSON: {e}"))?
+        };
+        // Models pass null for optional parameters they leave out.
+        if let Some(object) = value.as_object_mut() {
+            object.retain(|_, v| !v.is_null());
+        }
+        match JSONSchema::compile(&self.parameters()) {
+            Ok(schema) => response_format::check(&schema, &value),
+            Err(e) => {
+                log::warn!("The {} tool has an invalid schema: {}", self.name(), e);
+                Ok(())
+            }
+        }
+    }
+
+    /// The first required parameter that `arguments`, a JSON object, leaves
+    /// out or empty. Only the user can say what it should be.
+    fn missing_argument(&self, arguments: &str) -> Option<String> {
+        let value: Value = if arguments.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str(arguments).ok()?
+        };
+        let object = value.as_object()?;
+        self.parameters()["required"]
+            .as_array()?
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|param| match object.get(*param) {
+                None | Some(Value::Null) => true,
+                Some(Value::String(s)) => s.trim().is_empty(),
+                Some(_) => false,
+            })
+            .map(str::to_string)
+    }
+
+    fn schema(&self) -> ChatCompletionTool {
+        ChatCompletionToolArgs::default()
+            .r#type(ChatCompletionToolType::Function)
+            .function(
+                ChatCompletionFunctionsArgs::default()
+                    .name(self.name())
+                    .description(self.description())
+                    .parameters(self.parameters())
+                    .build()
+                    .expect("Failed to build function"),
+            )
+            .build()
+            .expect("Failed to build tool")
+    }
+}
+
+/// Maps tool names to their implementations.
+///
+/// Tools are kept in registration order so the list sent to the model is
+/// stable between requests.
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: Vec<Box<dyn Tool>>,
+    index: HashMap<String, usize>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry holding every built-in tool, except those needing a service
+    /// that isn't configured: web search, ticket lookup, the calendar, email, the
+    /// database, the code runner and command execution. The tools that call
+    /// the model themselves, such as summarizing and translating, use
+    /// `client` and `model`.
+    pub fn with_builtin_tools(client: &ChatClient, model: &str) -> Self {
+        let mut registry = Self::new();
+        registry.register(WeatherTool);
+        registry.register(ForecastTool);
+        registry.register(AirQualityTool);
+        registry.register(SunTimesTool);
+        registry.register(UnitsTool);
+        registry.register(SpeechTool);
+        registry.register(LanguageTool);
+        registry.register(HomeCityTool);
+        registry.register(GetHomeCityTool);
+        registry.register(ScraperTool);
+        let summarizer = Summarizer::new(client.clone(), model);
+        registry.register(SummarizeUrlTool::new(summarizer.clone()));
+        registry.register(CrawlSiteTool::new(summarizer));
+        registry.register(TranslateTool::new(client.clone(), model));
+        if docs_configured() {
+            registry.register(SearchDocsTool::new(client.clone()));
+        }
+        registry.register(ImageTool::new(client.clone()));
+        registry.register(TimeOfDayTool);
+        registry.register(ReminderTool);
+        registry.register(ConvertTool);
+        registry.register(RepoInfoTool);
+        registry.register(OpenIssuesTool);
+        registry.register(IssueTool);
+        registry.register(CreateIssueTool);
+        registry.register(DnsLookupTool);
+        registry.register(WhoisTool);
+        registry.register(CheckUrlTool);
+        if SearchProvider::from_env().is_some() {
+            registry.register(SearchTool);
+        }
+        if TrackerProvider::from_env().is_some() {
+            registry.register(TicketTool);
+            registry.register(TicketSearchTool);
+        }
+        if calendar_configured() {
+            registry.register(CalendarTool);
+        }
+        if SendEmailTool::configured() {
+            registry.register(SendEmailTool);
+        }
+        if database_configured() {
+            registry.register(DatabaseTool);
+        }
+        if code_runner_configured() {
+            registry.register(RunCodeTool);
+        }
+        if exec_configured() {
+            registry.register(ExecTool);
+        }
+        registry
+    }
+
+    /// Adds a tool, replacing any previously registered tool with the same name.
+    pub fn register<T: Tool + 'static>(&mut self, tool: T) {
+        self.insert(Box::new(tool));
+    }
+
+    /// Adds every tool in `other`, replacing tools with the same names.
+    pub fn extend(&mut self, other: ToolRegistry) {
+        for tool in other.tools {
+            self.insert(tool);
+        }
+    }
+
+    fn insert(&mut self, tool: Box<dyn Tool>) {
+        let name = tool.name().to_string();
+        match self.index.get(&name) {
+            Some(&i) => self.tools[i] = tool,
+            None => {
+                self.index.insert(name, self.tools.len());
+                self.tools.push(tool);
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Tool> {
+        self.index.get(name).map(|&i| self.tools[i].as_ref())
+    }
+
+    /// The tool `name` refers to: the tool of that name ignoring case, or else
+    /// the only one whose name contains it, so `weather` finds `getWeather`.
+    pub fn find(&self, name: &str) -> Option<&dyn Tool> {
+        let name = name.to_lowercase();
+        if let Some(tool) = self.tools.iter().find(|t| t.name().to_lowercase() == name) {
+            return Some(tool.as_ref());
+        }
+        let mut matches = self
+            .tools
+            .iter()
+            .filter(|t| t.name().to_lowercase().contains(&name));
+        match (matches.next(), matches.next()) {
+            (Some(tool), None) => Some(tool.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// The names of the tools not in `allowed`.
+    pub fn names_outside(&self, allowed: &BTreeSet<String>) -> BTreeSet<String> {
+        self.names()
+            .filter(|name| !allowed.contains(*name))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// The names of the tools, in registration order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.tools.iter().map(|tool| tool.name())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// The tool definitions to pass in a chat completion request.
+    pub fn schemas(&self) -> Vec<ChatCompletionTool> {
+        self.tools.iter().map(|tool| tool.schema()).collect()
+    }
+}
@@ -0,0 +1,260 @@
+use crate::tools::{http, Cassette};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use url::Url;
+
+/// The name matched against `User-agent` lines in robots.txt.
+const USER_AGENT: &str = "gpt-function-call-demo";
+
+/// Parsed robots.txt files, keyed by origin.
+static ROBOTS: Lazy<Mutex<HashMap<String, Robots>>> = Lazy::new(Default::default);
+
+/// Checks whether the bot may read `url`: the domain must pass
+/// [`check_domain`] and the site's robots.txt must allow it. The error is a
+/// refusal to hand to the model. robots.txt is fetched through `cassette`.
+pub(crate) async fn check(cassette: &Cassette, url: &str) -> Result<(), String> {
+    check_domain(url)?;
+    let Ok(parsed) = Url::parse(url) else {
+        return Err(refusal(url, "it is not a valid url"));
+    };
+    let origin = parsed.origin().ascii_serialization();
+    let mut path = parsed.path().to_string();
+    if let Some(query) = parsed.query() {
+        path = format!("{path}?{query}");
+    }
+    let cached = ROBOTS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&origin)
+        .map(|robots| robots.allows(&path));
+    let allowed = match cached {
+        Some(allowed) => allowed,
+        None => {
+            let robots = fetch_robots(cassette, &origin).await;
+            let allowed = robots.allows(&path);
+            ROBOTS
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(origin, robots);
+            allowed
+        }
+    };
+    if !allowed {
+        return Err(refusal(url, "the site's robots.txt disallows it"));
+    }
+    Ok(())
+}
+
+/// Checks only that `url`'s domain passes the `scraper_allowlist` and
+/// `scraper_blocklist` settings, for requests that don't read the page,
+/// which robots.txt has no say over.
+pub(crate) fn check_domain(url: &str) -> Result<(), String> {
+    let Ok(parsed) = Url::parse(url) else {
+        return Err(refusal(url, "it is not a valid url"));
+    };
+    let host = parsed.host_str().unwrap_or_default().to_ascii_lowercase();
+    if let Some(blocked) = domain_list("scraper_blocklist")
+        .iter()
+        .find(|domain| in_domain(&host, domain))
+    {
+        return Err(refusal(url, &format!("{blocked} is blocked")));
+    }
+    let allowlist = domain_list("scraper_allowlist");
+    if !allowlist.is_empty() && !allowlist.iter().any(|domain| in_domain(&host, domain)) {
+        return Err(refusal(url, &format!("{host} is not on the allowlist")));
+    }
+    Ok(())
+}
+
+fn refusal(url: &str, reason: &str) -> String {
+    format!("Not allowed to read {url}: {reason}.")
+}
+
+fn domain_list(key: &str) -> Vec<String> {
+    env::var(key)
+        .unwrap_or_default()
+        .split(',')
+        .map(|d| d.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|d| !d.is_empty())
+        .collect()
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+fn in_domain(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{domain}"))
+}
+
+/// The rules of a robots.txt that apply to this bot.
+#[derive(Default)]
+struct Robots {
+    /// `(allow, path pattern)` pairs.
+    rules: Vec<(bool, String)>,
+}
+
+impl Robots {
+    /// The longest matching rule decides, and `Allow` wins a tie.
+    fn allows(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| rule_matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .is_none_or(|(allow, _)| *allow)
+    }
+
+    /// Uses the group naming this bot if there is one, and the `*` group
+    /// otherwise.
+    fn parse(body: &str) -> Self {
+        let mut specific = Vec::new();
+        let mut wildcard = Vec::new();
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+                None => continue,
+            };
+            match key.as_str() {
+                "user-agent" => {
+                    // Consecutive User-agent lines share the rules that follow.
+                    if in_rules {
+                        agents.clear();
+                        in_rules = false;
+                    }
+                    agents.push(value.to_ascii_lowercase());
+                }
+                "allow" | "disallow" => {
+                    in_rules = true;
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let rule = (key == "allow", value.to_string());
+                    if agents
+                        .iter()
+                        .any(|a| a != "*" && USER_AGENT.contains(a.as_str()))
+                    {
+                        specific.push(rule.clone());
+                    }
+                    if agents.iter().any(|a| a == "*") {
+                        wildcard.push(rule);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Self {
+            rules: if specific.is_empty() {
+                wildcard
+            } else {
+                specific
+            },
+        }
+    }
+}
+
+/// A missing or unreadable robots.txt allows everything.
+async fn fetch_robots(cassette: &Cassette, origin: &str) -> Robots {
+    match http::get(cassette, &format!("{origin}/robots.txt"), &[]).await {
+        Ok(res) if res.is_success() => Robots::parse(&String::from_utf8_lossy(&res.body)),
+        _ => Robots::default(),
+    }
+}
+
+/// Matches a robots.txt path pattern, where `*` stands for any run of
+/// characters and a trailing `$` anchors the end.
+fn rule_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let mut rest = match path.strip_prefix(parts.next().unwrap_or_default()) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_match_prefixes_wildcards_and_anchors() {
+        assert!(rule_matches("/private", "/private/notes"));
+        assert!(!rule_matches("/private", "/public"));
+        assert!(rule_matches("/*.pdf$", "/docs/report.pdf"));
+        assert!(!rule_matches("/*.pdf$", "/docs/report.pdf?download=1"));
+        assert!(rule_matches("/search*q=", "/search?lang=en&q=rust"));
+        assert!(rule_matches("/exact$", "/exact"));
+        assert!(!rule_matches("/exact$", "/exact/more"));
+    }
+
+    #[test]
+    fn the_longest_rule_wins_and_allow_wins_a_tie() {
+        let robots = Robots::parse(
+            "User-agent: *\n\
+             Disallow: /docs/\n\
+             Allow: /docs/public/\n\
+             Disallow: /tie\n\
+             Allow: /tie\n",
+        );
+        assert!(!robots.allows("/docs/secret"));
+        assert!(robots.allows("/docs/public/page"));
+        assert!(robots.allows("/tie"));
+        assert!(robots.allows("/elsewhere"));
+    }
+
+    #[test]
+    fn a_group_naming_the_bot_replaces_the_wildcard_group() {
+        let robots = Robots::parse(
+            "# comments and unknown lines are skipped\n\
+             User-agent: *\n\
+             Disallow: /\n\
+             \n\
+             User-agent: other-bot\n\
+             User-agent: GPT-Function-Call-Demo\n\
+             Disallow: /admin # not for bots\n\
+             Sitemap: https://example.org/sitemap.xml\n",
+        );
+        assert!(robots.allows("/"));
+        assert!(!robots.allows("/admin/users"));
+    }
+
+    #[test]
+    fn rules_after_a_group_start_a_new_one() {
+        let robots = Robots::parse(
+            "User-agent: gpt-function-call-demo\n\
+             Disallow: /mine\n\
+             User-agent: other-bot\n\
+             Disallow: /theirs\n",
+        );
+        assert!(!robots.allows("/mine"));
+        assert!(robots.allows("/theirs"));
+    }
+
+    #[test]
+    fn an_empty_disallow_allows_everything() {
+        let robots = Robots::parse("User-agent: *\nDisallow:\n");
+        assert!(robots.allows("/anything"));
+    }
+
+    #[test]
+    fn subdomains_are_in_their_parent_domain() {
+        assert!(in_domain("example.org", "example.org"));
+        assert!(in_domain("docs.example.org", "example.org"));
+        assert!(!in_domain("badexample.org", "example.org"));
+    }
+}
@@ -0,0 +1,100 @@
+use crate::prefs::{ChannelPrefs, Prefs, Units};
+use crate::tool;
+use crate::tools::ToolContext;
+
+tool! {
+    /// Remember whether the user wants weather reported in metric (°C, km/h) or
+    /// imperial (°F, mph) units
+    pub struct UnitsTool as "setUnits";
+
+    async fn set_units(
+        #[context] context: &ToolContext,
+        /// The units the user prefers
+        units: Units,
+    ) -> String {
+        let mut prefs = Prefs::load(&context.user);
+        prefs.units = units;
+        prefs.save(&context.user);
+        format!("From now on the weather is reported in {} units.", units.as_str())
+    }
+}
+
+tool! {
+    /// Turn spoken answers on or off. When on, every answer is also posted as an
+    /// audio file the user can play
+    pub struct SpeechTool as "setSpokenAnswers";
+
+    async fn set_spoken_answers(
+        #[context] context: &ToolContext,
+        /// Whether answers should be read out
+        enabled: bool,
+    ) -> String {
+        let mut prefs = Prefs::load(&context.user);
+        prefs.speech = enabled;
+        prefs.save(&context.user);
+        if enabled {
+            "From now on answers are also posted as audio.".to_string()
+        } else {
+            "Answers are no longer posted as audio.".to_string()
+        }
+    }
+}
+
+tool! {
+    /// Remember the user's home city, such as when they say "my city is Berlin", so
+    /// weather questions that don't name a city are about it. Leave the city out to
+    /// forget it
+    pub struct HomeCityTool as "setHomeCity";
+
+    async fn set_home_city(
+        #[context] context: &ToolContext,
+        /// The city, optionally qualified as "City, State, Country"
+        city: Option<String>,
+    ) -> String {
+        let mut prefs = Prefs::load(&context.user);
+        prefs.home_city = city.map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+        prefs.save(&context.user);
+        match &prefs.home_city {
+            Some(city) => format!("From now on weather questions without a city are about {city}."),
+            None => "The home city is forgotten.".to_string(),
+        }
+    }
+
+    examples {
+        "my city is Berlin" => { "city": "Berlin" },
+    }
+}
+
+tool! {
+    /// Get the home city the user saved, if any
+    pub struct GetHomeCityTool as "getHomeCity";
+
+    async fn get_home_city(
+        #[context] context: &ToolContext,
+    ) -> String {
+        match Prefs::load(&context.user).home_city {
+            Some(city) => format!("The user's home city is {city}."),
+            None => "The user hasn't saved a home city.".to_string(),
+        }
+    }
+}
+
+tool! {
+    /// Set the language the bot replies in on this channel, or clear it to reply in
+    /// whatever language the user writes in
+    pub struct LanguageTool as "setChannelLanguage";
+
+    async fn set_channel_language(
+        #[context] context: &ToolContext,
+        /// The language, such as "German" or "Japanese". Leave out to clear it
+        language: Option<String>,
+    ) -> String {
+        let mut prefs = ChannelPrefs::load(&context.channel);
+        prefs.language = language.map(|l| l.trim().to_string()).filter(|l| !l.is_empty());
+        prefs.save(&context.channel);
+        match &prefs.language {
+            Some(language) => format!("From now on the bot replies in {language} on this channel."),
+            None => "The bot now replies in the language it is written to in.".to_string(),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::store::{get, set};
+use crate::tool;
+use crate::tools::ToolContext;
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+const REMINDERS_KEY: &str = "reminders";
+
+/// A message to post back to the channel it was requested from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Reminder {
+    /// Unix timestamp, in seconds.
+    due: i64,
+    workspace: String,
+    channel: String,
+    user: String,
+    text: String,
+}
+
+tool! {
+    /// Remind the user of something later by posting a message in this channel.
+    /// Reminders are checked once per scheduled run, so they may arrive a little late.
+    pub struct ReminderTool as "setReminder";
+
+    async fn set_reminder(
+        #[context] context: &ToolContext,
+        /// When to remind, either relative like "in 2 hours 30 minutes" or an
+        /// absolute UTC time like "2024-01-31 09:00"
+        when: String,
+        /// What to remind the user about
+        text: String,
+    ) -> anyhow::Result<String> {
+        let now = Utc::now();
+        let due = parse_when(&when, now)
+            .ok_or_else(|| anyhow!("can't understand the reminder time \"{when}\""))?;
+        if due <= now {
+            return Ok(format!("{} is in the past, so no reminder was set.", format_due(due)));
+        }
+
+        let mut reminders = load_reminders();
+        reminders.push(Reminder {
+            due: due.timestamp(),
+            workspace: context.workspace.clone(),
+            channel: context.channel.clone(),
+            user: context.user.clone(),
+            text,
+        });
+        save_reminders(&reminders)?;
+        Ok(format!("Reminder set for {}.", format_due(due)))
+    }
+}
+
+/// Posts every reminder that has come due and forgets it.
+#[cfg(feature = "flows")]
+pub async fn post_due_reminders() {
+    let now = Utc::now().timestamp();
+    let (due, pending): (Vec<_>, Vec<_>) = load_reminders().into_iter().partition(|r| r.due <= now);
+    if due.is_empty() {
+        return;
+    }
+    if let Err(e) = save_reminders(&pending) {
+        log::error!("Failed to save reminders: {}", e);
+        return;
+    }
+
+    for reminder in due {
+        slack_flows::send_message_to_channel(
+            &reminder.workspace,
+            &reminder.channel,
+            format!("<@{}> Reminder: {}", reminder.user, reminder.text),
+        )
+        .await;
+    }
+}
+
+fn load_reminders() -> Vec<Reminder> {
+    get(REMINDERS_KEY)
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+fn save_reminders(reminders: &[Reminder]) -> anyhow::Result<()> {
+    set(REMINDERS_KEY, serde_json::to_value(reminders)?, None);
+    Ok(())
+}
+
+fn format_due(due: DateTime<Utc>) -> String {
+    due.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+/// Parses an RFC 3339 time, a `YYYY-MM-DD HH:MM` UTC time, or a relative
+/// time made of number and unit pairs such as `in 1 hour 30 minutes`.
+fn parse_when(when: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let when = when.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(when) {
+        return Some(time.with_timezone(&Utc));
+    }
+    if let Ok(time) = NaiveDateTime::parse_from_str(when, "%Y-%m-%d %H:%M") {
+        return Some(Utc.from_utc_datetime(&time));
+    }
+
+    let relative = when.strip_prefix("in ").unwrap_or(when);
+    // Split "2h30m" into "2 h 30 m" so the pairs can be read off in order.
+    let mut spaced = String::new();
+    let mut prev_digit = None;
+    for c in relative.chars() {
+        let digit = c.is_ascii_digit();
+        if prev_digit.is_some_and(|prev| prev != digit) {
+            spaced.push(' ');
+        }
+        spaced.push(c);
+        prev_digit = Some(digit);
+    }
+
+    let mut words = spaced
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|w| !w.is_empty() && *w != "and");
+    let mut total = Duration::zero();
+    let mut matched = false;
+    while let Some(amount) = words.next() {
+        let amount: i64 = amount.parse().ok()?;
+        let unit = words.next()?.to_ascii_lowercase();
+        let unit = match unit.len() {
+            1 => unit.as_str(),
+            _ => unit.trim_end_matches('s'),
+        };
+        total = total
+            + match unit {
+                "s" | "sec" | "second" => Duration::seconds(amount),
+                "m" | "min" | "minute" => Duration::minutes(amount),
+                "h" | "hr" | "hour" => Duration::hours(amount),
+                "d" | "day" => Duration::days(amount),
+                "w" | "week" => Duration::weeks(amount),
+                _ => return None,
+            };
+        matched = true;
+    }
+    matched.then(|| now + total)
+}
@@ -0,0 +1,282 @@
+use crate::history::estimate_tokens;
+use crate::tool;
+use crate::tools::{guard, http, policy, Cassette, ToolContext};
+use anyhow::{anyhow, bail};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::env;
+
+const DEFAULT_MAX_TOKENS: usize = 1500;
+/// Pages larger than this are not read.
+const MAX_BYTES: usize = 10 * 1024 * 1024;
+const TRUNCATED: &str = "[truncated]";
+
+/// Elements that hold no page content, each matched with its closing tag.
+static NON_CONTENT: Lazy<Vec<Regex>> = Lazy::new(|| {
+    ["script", "style", "noscript", "svg", "nav", "footer"]
+        .iter()
+        .map(|tag| Regex::new(&format!(r"(?is)<{tag}\b.*?</{tag}\s*>")).expect("valid regex"))
+        .collect()
+});
+static MARKDOWN_LINK: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"!?\[([^\]]*)\]\([^)]*\)").expect("valid regex"));
+
+/// Short lines that are navigation or banner chrome rather than content.
+const BOILERPLATE: &[&str] = &[
+    "skip to content",
+    "skip to main content",
+    "skip navigation",
+    "toggle navigation",
+    "menu",
+    "main menu",
+    "search",
+    "close",
+    "share",
+    "sign in",
+    "sign up",
+    "log in",
+    "login",
+    "subscribe",
+    "accept",
+    "accept all",
+    "accept cookies",
+    "back to top",
+    "privacy policy",
+    "terms of service",
+    "cookie policy",
+    "all rights reserved",
+];
+
+tool! {
+    /// Get the content of the webpage from the url passed to it as Markdown, or the
+    /// text of a PDF
+    pub struct ScraperTool as "scraper";
+
+    async fn scraper(
+        #[context] context: &ToolContext,
+        /// The url from which to fetch the content
+        url: String,
+    ) -> String {
+        if let Err(refusal) = policy::check(&context.cassette, &url).await {
+            return refusal;
+        }
+        match page_text(&context.cassette, &url).await {
+            Err(e) => {
+                log::warn!("Failed to get {}: {}", url, e);
+                "failed to get webpage".to_string()
+            }
+
+            Ok(txt) => guard::untrusted(&url, &truncate(&clean_text(&txt), max_tokens())),
+        }
+    }
+
+    examples {
+        "What does https://example.com/pricing say about the free plan?" =>
+            { "url": "https://example.com/pricing" },
+    }
+}
+
+/// The content of the page at `url`: Markdown for HTML pages, and the
+/// extracted text for PDFs. Pages that can't be fetched directly go through
+/// the flows scraper service instead, unless the cassette is replaying.
+pub(crate) async fn page_text(cassette: &Cassette, url: &str) -> anyhow::Result<String> {
+    match fetch(cassette, url).await {
+        Ok(document) => {
+            if let Some(text) = document.text(url)? {
+                return Ok(text);
+            }
+        }
+        Err(e) if cassette.is_replay() => return Err(e),
+        Err(e) => log::info!("Falling back to the scraper service for {}: {}", url, e),
+    }
+    scraper_service(url).await
+}
+
+#[cfg(feature = "flows")]
+async fn scraper_service(url: &str) -> anyhow::Result<String> {
+    web_scraper_flows::get_page_text(url)
+        .await
+        .map_err(|e| anyhow!(e))
+}
+
+#[cfg(not(feature = "flows"))]
+async fn scraper_service(url: &str) -> anyhow::Result<String> {
+    Err(anyhow!(
+        "couldn't read {url}, and the scraper service is only there in a flow"
+    ))
+}
+
+/// A downloaded page.
+pub(crate) struct Document {
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+impl Document {
+    /// Whether the server says this is a PDF, or `url` names a `.pdf` file.
+    pub fn is_pdf(&self, url: &str) -> bool {
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+        self.content_type.starts_with("application/pdf")
+            || path.to_ascii_lowercase().ends_with(".pdf")
+    }
+
+    pub fn is_html(&self) -> bool {
+        self.content_type.starts_with("text/html")
+            || self.content_type.starts_with("application/xhtml")
+    }
+
+    pub fn body_text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+
+    /// The readable content, or `None` when the type isn't understood.
+    pub fn text(&self, url: &str) -> anyhow::Result<Option<String>> {
+        if self.is_pdf(url) {
+            return Ok(Some(pdf_extract::extract_text_from_mem(&self.body)?));
+        }
+        if self.is_html() {
+            return Ok(Some(html_to_markdown(&self.body_text())));
+        }
+        if self.content_type.starts_with("text/") {
+            return Ok(Some(self.body_text().into_owned()));
+        }
+        Ok(None)
+    }
+}
+
+pub(crate) async fn fetch(cassette: &Cassette, url: &str) -> anyhow::Result<Document> {
+    let res = http::get(cassette, url, &[]).await?;
+    if !res.is_success() {
+        bail!("server returned {}", res.status);
+    }
+    if res.body.len() > MAX_BYTES {
+        bail!("page is larger than {} MB", MAX_BYTES / 1024 / 1024);
+    }
+    Ok(Document {
+        content_type: res.content_type,
+        body: res.body,
+    })
+}
+
+/// Converts HTML to Markdown, keeping headings, lists and links but not
+/// scripts or styles.
+fn html_to_markdown(html: &str) -> String {
+    let mut html = Cow::Borrowed(html);
+    for element in NON_CONTENT.iter() {
+        if let Cow::Owned(stripped) = element.replace_all(&html, "") {
+            html = Cow::Owned(stripped);
+        }
+    }
+    html2md::parse_html(&html)
+}
+
+/// How much page text the scraper may return, from `scraper_max_tokens`.
+fn max_tokens() -> usize {
+    env::var("scraper_max_tokens")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_TOKENS)
+}
+
+/// Drops navigation chrome, script leftovers and repeated lines from
+/// extracted page text, and collapses its whitespace.
+pub(crate) fn clean_text(text: &str) -> String {
+    let mut seen = HashSet::new();
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() || is_boilerplate(&line) || looks_like_code(&line) {
+            continue;
+        }
+        // Headers and footers repeat the same links on every page section.
+        if !seen.insert(line.to_lowercase()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+fn is_boilerplate(line: &str) -> bool {
+    let lower = MARKDOWN_LINK.replace_all(line, "$1").to_lowercase();
+    let lower = lower.trim_matches(|c: char| !c.is_alphanumeric());
+    lower.is_empty() || BOILERPLATE.contains(&lower)
+}
+
+/// Whether `line` is dense with the punctuation of JavaScript and CSS.
+/// Brackets and parentheses aren't counted, since Markdown links use them.
+fn looks_like_code(line: &str) -> bool {
+    let symbols = line.chars().filter(|c| "{};=<>".contains(*c)).count();
+    symbols * 20 > line.chars().count()
+}
+
+/// Keeps whole lines of `text` up to about `max_tokens`, marking the cut.
+fn truncate(text: &str, max_tokens: usize) -> String {
+    let mut out = String::new();
+    let mut tokens = 0;
+    for line in text.lines() {
+        let line_tokens = estimate_tokens(line) + 1;
+        if tokens + line_tokens > max_tokens {
+            if out.is_empty() {
+                // A single huge line is cut mid-line rather than dropped.
+                out.extend(line.chars().take(max_tokens * 4));
+                out.push('\n');
+            }
+            out.push_str(TRUNCATED);
+            return out;
+        }
+        out.push_str(line);
+        out.push('\n');
+        tokens += line_tokens;
+    }
+    out.truncate(out.trim_end().len());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::Tool;
+
+    async fn scrape(cassette: Cassette, url: &str) -> String {
+        ScraperTool
+            .execute(
+                &serde_json::json!({ "url": url }).to_string(),
+                &ToolContext::new("test", "test", "alice").with_cassette(cassette),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn formats_a_replayed_page() {
+        let out = scrape(http::replay_fixtures(), "https://example.com/").await;
+        assert_eq!(
+            out,
+            guard::untrusted(
+                "https://example.com/",
+                "Example Domain\n\
+                 This domain is for use in illustrative examples in documents. You may use \
+                 this domain in literature without prior coordination or asking for permission.\n\
+                 [More information...](https://www.iana.org/domains/example)"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn follows_the_replayed_robots_txt() {
+        // Saved the way a recording would be, so the rule is the test's own.
+        let cassette = Cassette::replay("target/test-fixtures/robots");
+        let robots = http::Response {
+            status: 200,
+            content_type: "text/plain".to_string(),
+            body: b"User-agent: *\nDisallow: /private/\n".to_vec(),
+            location: None,
+        };
+        http::record(&cassette.dir, "https://example.org/robots.txt", &robots).unwrap();
+        let out = scrape(cassette, "https://example.org/private/notes").await;
+        assert!(out.starts_with("Not allowed to read"), "{out}");
+    }
+}
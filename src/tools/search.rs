@@ -0,0 +1,155 @@
+use crate::tool;
+use crate::tools::{http, Cassette, ToolContext};
+use anyhow::{anyhow, bail};
+use serde_json::Value;
+use std::env;
+use urlencoding::encode;
+
+const MAX_RESULTS: u32 = 10;
+
+/// The search API behind `searchWeb`, picked with `SEARCH_PROVIDER`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchProvider {
+    Brave,
+    Bing,
+    SerpApi,
+}
+
+impl SearchProvider {
+    /// The configured provider, or `None` when `SEARCH_API_KEY` is unset.
+    pub fn from_env() -> Option<Self> {
+        env::var("SEARCH_API_KEY").ok()?;
+        match env::var("SEARCH_PROVIDER")
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "bing" => Some(SearchProvider::Bing),
+            "serpapi" => Some(SearchProvider::SerpApi),
+            _ => Some(SearchProvider::Brave),
+        }
+    }
+}
+
+struct SearchResult {
+    title: String,
+    url: String,
+    snippet: String,
+}
+
+tool! {
+    /// Search the web and return the top results with their titles, snippets and URLs.
+    /// Use the scraper tool on a result URL to read the full page.
+    pub struct SearchTool as "searchWeb";
+
+    async fn search_web(
+        #[context] context: &ToolContext,
+        /// What to search for
+        query: String,
+        /// How many results to return, at most 10
+        count: Option<u32>,
+    ) -> anyhow::Result<String> {
+        let provider =
+            SearchProvider::from_env().ok_or_else(|| anyhow!("web search is not configured"))?;
+        let api_key = env::var("SEARCH_API_KEY")?;
+        let count = count.unwrap_or(5).clamp(1, MAX_RESULTS);
+        let results = search(&context.cassette, provider, &api_key, &query, count).await?;
+        Ok(format_results(&query, &results))
+    }
+
+    examples {
+        "Who won the last Tour de France?" => { "query": "Tour de France winner" },
+    }
+}
+
+fn format_results(query: &str, results: &[SearchResult]) -> String {
+    if results.is_empty() {
+        return format!("No results for \"{query}\"");
+    }
+    results
+        .iter()
+        .enumerate()
+        .map(|(i, r)| format!("{}. {}\n   {}\n   {}", i + 1, r.title, r.url, r.snippet))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+async fn search(
+    cassette: &Cassette,
+    provider: SearchProvider,
+    api_key: &str,
+    query: &str,
+    count: u32,
+) -> anyhow::Result<Vec<SearchResult>> {
+    let query = encode(query);
+
+    let (url, header) = match provider {
+        SearchProvider::Brave => (
+            format!("https://api.search.brave.com/res/v1/web/search?q={query}&count={count}"),
+            Some(("X-Subscription-Token", api_key)),
+        ),
+        SearchProvider::Bing => (
+            format!("https://api.bing.microsoft.com/v7.0/search?q={query}&count={count}"),
+            Some(("Ocp-Apim-Subscription-Key", api_key)),
+        ),
+        SearchProvider::SerpApi => (
+            format!(
+                "https://serpapi.com/search.json?engine=google&q={query}&num={count}&api_key={}",
+                encode(api_key)
+            ),
+            None,
+        ),
+    };
+
+    let mut headers = vec![("Accept", "application/json")];
+    headers.extend(header);
+    let res = http::get(cassette, &url, &headers).await?;
+    if !res.is_success() {
+        bail!("search API returned {}", res.status);
+    }
+
+    let body: Value = res.json()?;
+    let (list, title, url, snippet) = match provider {
+        SearchProvider::Brave => ("/web/results", "title", "url", "description"),
+        SearchProvider::Bing => ("/webPages/value", "name", "url", "snippet"),
+        SearchProvider::SerpApi => ("/organic_results", "title", "link", "snippet"),
+    };
+    let field = |item: &Value, key: &str| item[key].as_str().unwrap_or_default().to_string();
+
+    Ok(body
+        .pointer(list)
+        .and_then(Value::as_array)
+        .map(|items| {
+            items
+                .iter()
+                .take(count as usize)
+                .map(|item| SearchResult {
+                    title: field(item, title),
+                    url: field(item, url),
+                    snippet: field(item, snippet),
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn formats_replayed_results() {
+        let cassette = http::replay_fixtures();
+        let results = search(&cassette, SearchProvider::Brave, "test", "rust", 2)
+            .await
+            .unwrap();
+        let out = format_results("rust", &results);
+        assert_eq!(
+            out,
+            "1. Rust Programming Language\n   https://www.rust-lang.org/\n   \
+             A language empowering everyone to build reliable and efficient software.\n\
+             2. The Rust Programming Language - The Rust Programming Language\n   \
+             https://doc.rust-lang.org/book/\n   The Rust book, an introductory book about Rust."
+        );
+    }
+}
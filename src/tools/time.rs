@@ -0,0 +1,50 @@
+use crate::tool;
+use chrono::prelude::*;
+use chrono_tz::Tz;
+use std::fmt::Display;
+
+tool! {
+    /// Get the time of day, in the server's timezone unless another one is given.
+    pub struct TimeOfDayTool as "getTimeOfDay";
+
+    async fn get_time_of_day(
+        /// An IANA timezone name such as "Asia/Tokyo"
+        timezone: Option<String>,
+        /// A locale such as "ja_JP" or "fr_FR" to format the date and time for
+        locale: Option<String>,
+    ) -> String {
+        let locale = locale.and_then(|l| Locale::try_from(l.replace('-', "_").as_str()).ok());
+        match timezone {
+            Some(name) => match name.parse::<Tz>() {
+                Ok(tz) => format!(
+                    "{} in {name}",
+                    format_time(&Utc::now().with_timezone(&tz), locale)
+                ),
+                Err(_) => format!(
+                    "Unknown timezone \"{name}\". Use an IANA name such as \"Europe/Paris\"."
+                ),
+            },
+            None => format_time(&Local::now(), locale),
+        }
+    }
+
+    examples {
+        "What time is it in Tokyo?" => { "timezone": "Asia/Tokyo" },
+    }
+}
+
+/// Formats `now` for `locale`, or as a 12-hour clock time when there is none.
+fn format_time<T: TimeZone>(now: &DateTime<T>, locale: Option<Locale>) -> String
+where
+    T::Offset: Display,
+{
+    match locale {
+        Some(locale) => now.format_localized("%c %Z", locale).to_string(),
+        None => format!(
+            "{:02}:{:02} {}",
+            now.hour12().1,
+            now.minute(),
+            if now.hour12().0 { "p.m." } else { "a.m." }
+        ),
+    }
+}
@@ -0,0 +1,244 @@
+use crate::tool;
+use crate::tools::http::{self, Method};
+use anyhow::{anyhow, bail};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+use std::env;
+use urlencoding::encode;
+
+const LINEAR_API: &str = "https://api.linear.app/graphql";
+const MAX_RESULTS: u32 = 20;
+/// Ticket descriptions longer than this many characters are cut short.
+const MAX_DESCRIPTION_CHARS: usize = 1500;
+
+/// The issue tracker behind `getTicket` and `searchTickets`, picked with
+/// `TRACKER_PROVIDER`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackerProvider {
+    Jira,
+    Linear,
+}
+
+impl TrackerProvider {
+    /// The configured tracker, or `None` when `TRACKER_TOKEN` is unset.
+    pub fn from_env() -> Option<Self> {
+        env::var("TRACKER_TOKEN").ok()?;
+        match env::var("TRACKER_PROVIDER")
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "linear" => Some(TrackerProvider::Linear),
+            _ => Some(TrackerProvider::Jira),
+        }
+    }
+}
+
+struct Ticket {
+    key: String,
+    title: String,
+    status: String,
+    assignee: String,
+    url: String,
+    description: String,
+}
+
+impl Ticket {
+    fn summary_line(&self) -> String {
+        format!(
+            "{} {} [{}] assigned to {}",
+            self.key, self.title, self.status, self.assignee
+        )
+    }
+}
+
+tool! {
+    /// Get the status, assignee and description of a ticket in the team's issue
+    /// tracker, by its key such as "PROJ-123"
+    pub struct TicketTool as "getTicket";
+
+    async fn get_ticket(
+        /// The ticket key
+        key: String,
+    ) -> anyhow::Result<String> {
+        let provider =
+            TrackerProvider::from_env().ok_or_else(|| anyhow!("no issue tracker is configured"))?;
+        let ticket = match provider {
+            TrackerProvider::Jira => jira_ticket(&key).await?,
+            TrackerProvider::Linear => linear_ticket(&key).await?,
+        };
+        let mut description = ticket.description.clone();
+        if description.chars().count() > MAX_DESCRIPTION_CHARS {
+            description = description.chars().take(MAX_DESCRIPTION_CHARS).collect::<String>()
+                + "\n[truncated]";
+        }
+        Ok(format!("{}\n{}\n\n{description}", ticket.summary_line(), ticket.url))
+    }
+}
+
+tool! {
+    /// Search the team's issue tracker for tickets. Jira accepts JQL, and plain words
+    /// search ticket text in either tracker.
+    pub struct TicketSearchTool as "searchTickets";
+
+    async fn search_tickets(
+        /// JQL such as "project = PROJ AND status = Open", or words to search for
+        query: String,
+        /// How many tickets to return, at most 20
+        count: Option<u32>,
+    ) -> anyhow::Result<String> {
+        let provider =
+            TrackerProvider::from_env().ok_or_else(|| anyhow!("no issue tracker is configured"))?;
+        let count = count.unwrap_or(10).clamp(1, MAX_RESULTS);
+        let tickets = match provider {
+            TrackerProvider::Jira => jira_search(&query, count).await?,
+            TrackerProvider::Linear => linear_search(&query, count).await?,
+        };
+        if tickets.is_empty() {
+            return Ok(format!("No tickets match \"{query}\""));
+        }
+        Ok(tickets
+            .iter()
+            .map(Ticket::summary_line)
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+/// Jira Cloud authenticates with `TRACKER_EMAIL` and an API token, and Jira
+/// Server with a bearer token when no email is set.
+async fn jira_get(path: &str) -> anyhow::Result<Value> {
+    let base =
+        env::var("TRACKER_BASE_URL").map_err(|_| anyhow!("TRACKER_BASE_URL is needed for Jira"))?;
+    let token = env::var("TRACKER_TOKEN")?;
+    let authorization = match env::var("TRACKER_EMAIL") {
+        Ok(email) => format!("Basic {}", STANDARD.encode(format!("{email}:{token}"))),
+        Err(_) => format!("Bearer {token}"),
+    };
+    let url = format!("{}{path}", base.trim_end_matches('/'));
+    send(Method::GET, &url, &authorization, None).await
+}
+
+async fn jira_ticket(key: &str) -> anyhow::Result<Ticket> {
+    let issue = jira_get(&format!(
+        "/rest/api/2/issue/{}?fields=summary,status,assignee,description",
+        encode(key)
+    ))
+    .await?;
+    Ok(jira_to_ticket(&issue))
+}
+
+async fn jira_search(query: &str, count: u32) -> anyhow::Result<Vec<Ticket>> {
+    let looks_like_jql = [" = ", "=", " ~ ", " in ", "order by"]
+        .iter()
+        .any(|op| query.to_lowercase().contains(op));
+    let jql = if looks_like_jql {
+        query.to_string()
+    } else {
+        format!(
+            "text ~ \"{}\" ORDER BY updated DESC",
+            query.replace('"', "\\\"")
+        )
+    };
+    let result = jira_get(&format!(
+        "/rest/api/2/search?jql={}&maxResults={count}&fields=summary,status,assignee",
+        encode(&jql)
+    ))
+    .await?;
+    Ok(result["issues"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(jira_to_ticket)
+        .collect())
+}
+
+fn jira_to_ticket(issue: &Value) -> Ticket {
+    let fields = &issue["fields"];
+    let key = text(&issue["key"]);
+    let base = env::var("TRACKER_BASE_URL").unwrap_or_default();
+    Ticket {
+        url: format!("{}/browse/{key}", base.trim_end_matches('/')),
+        key,
+        title: text(&fields["summary"]),
+        status: text(&fields["status"]["name"]),
+        assignee: text(&fields["assignee"]["displayName"]),
+        description: text(&fields["description"]),
+    }
+}
+
+/// Linear's GraphQL API, at `TRACKER_BASE_URL` when set.
+async fn linear_query(query: &str, variables: Value) -> anyhow::Result<Value> {
+    let url = env::var("TRACKER_BASE_URL").unwrap_or(LINEAR_API.to_string());
+    let token = env::var("TRACKER_TOKEN")?;
+    let body = json!({ "query": query, "variables": variables });
+    let response = send(Method::POST, &url, &token, Some(body)).await?;
+    if let Some(error) = response["errors"][0]["message"].as_str() {
+        bail!("Linear returned an error: {error}");
+    }
+    Ok(response["data"].clone())
+}
+
+const LINEAR_FIELDS: &str = "identifier title url description state { name } assignee { name }";
+
+async fn linear_ticket(key: &str) -> anyhow::Result<Ticket> {
+    let data = linear_query(
+        &format!("query($id: String!) {{ issue(id: $id) {{ {LINEAR_FIELDS} }} }}"),
+        json!({ "id": key }),
+    )
+    .await?;
+    Ok(linear_to_ticket(&data["issue"]))
+}
+
+async fn linear_search(query: &str, count: u32) -> anyhow::Result<Vec<Ticket>> {
+    let data = linear_query(
+        &format!(
+            "query($term: String!, $first: Int) {{ searchIssues(term: $term, first: $first) \
+             {{ nodes {{ {LINEAR_FIELDS} }} }} }}"
+        ),
+        json!({ "term": query, "first": count }),
+    )
+    .await?;
+    Ok(data["searchIssues"]["nodes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(linear_to_ticket)
+        .collect())
+}
+
+fn linear_to_ticket(issue: &Value) -> Ticket {
+    Ticket {
+        key: text(&issue["identifier"]),
+        title: text(&issue["title"]),
+        status: text(&issue["state"]["name"]),
+        assignee: text(&issue["assignee"]["name"]),
+        url: text(&issue["url"]),
+        description: text(&issue["description"]),
+    }
+}
+
+async fn send(
+    method: Method,
+    url: &str,
+    authorization: &str,
+    body: Option<Value>,
+) -> anyhow::Result<Value> {
+    let mut headers = vec![
+        ("Accept", "application/json"),
+        ("Authorization", authorization),
+    ];
+    let body = body.map(|b| {
+        headers.push(("Content-Type", "application/json"));
+        b.to_string().into_bytes()
+    });
+    let res = http::send(method, url, &headers, body).await?;
+    if !res.is_success() {
+        bail!("issue tracker returned {}", res.status);
+    }
+    res.json()
+}
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or("none").to_string()
+}
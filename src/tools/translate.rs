@@ -0,0 +1,96 @@
+use crate::client::ChatClient;
+use crate::history::estimate_tokens;
+use crate::tools::{Tool, ToolContext};
+use anyhow::{anyhow, bail};
+use async_openai::types::{
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+    CreateChatCompletionRequestArgs,
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Longer texts have to be translated in parts.
+const MAX_TEXT_TOKENS: usize = 3000;
+
+/// Translates text with the chat model.
+pub struct TranslateTool {
+    client: ChatClient,
+    model: String,
+}
+
+impl TranslateTool {
+    pub fn new(client: ChatClient, model: &str) -> Self {
+        Self {
+            client,
+            model: model.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Arguments {
+    text: String,
+    target_lang: String,
+}
+
+#[async_trait]
+impl Tool for TranslateTool {
+    fn name(&self) -> &str {
+        "translate"
+    }
+
+    fn description(&self) -> &str {
+        "Translate text into another language"
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "text": {
+                    "type": "string",
+                    "description": "The text to translate",
+                },
+                "target_lang": {
+                    "type": "string",
+                    "description": "The language to translate into, as a name or code, \
+                                    such as \"French\" or \"ja\"",
+                },
+            },
+            "required": ["text", "target_lang"],
+        })
+    }
+
+    async fn execute(&self, arguments: &str, _context: &ToolContext) -> anyhow::Result<String> {
+        let args: Arguments = serde_json::from_str(arguments)?;
+        let tokens = estimate_tokens(&args.text);
+        if tokens > MAX_TEXT_TOKENS {
+            bail!("the text is too long to translate at once; translate it in parts");
+        }
+        let target = args.target_lang.trim();
+        let request = CreateChatCompletionRequestArgs::default()
+            // Translations can take more tokens than the original.
+            .max_tokens((tokens * 2 + 100) as u16)
+            .model(&self.model)
+            .messages(vec![
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content(format!(
+                        "Translate the user's text into {target}. Reply with the translation \
+                         only. Keep the formatting, names, numbers and links as they are."
+                    ))
+                    .build()?
+                    .into(),
+                ChatCompletionRequestUserMessageArgs::default()
+                    .content(args.text)
+                    .build()?
+                    .into(),
+            ])
+            .build()?;
+        let chat = self.client.create_chat(request).await?;
+        chat.choices
+            .first()
+            .and_then(|c| c.message.content.clone())
+            .ok_or_else(|| anyhow!("the model returned no translation"))
+    }
+}
@@ -0,0 +1,330 @@
+use crate::tool;
+use crate::tools::http::{self, Method, Response};
+use crate::tools::policy;
+use anyhow::{anyhow, bail};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use url::Url;
+
+const MAX_REDIRECTS: usize = 10;
+/// How long the server gets to accept the connection, and then to send its
+/// certificate.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// The most handshake data read while looking for the certificate.
+const MAX_HANDSHAKE_BYTES: usize = 64 * 1024;
+
+tool! {
+    /// Check whether a website is up: its status code, response time, redirects and
+    /// when its TLS certificate expires
+    pub struct CheckUrlTool as "checkUrl";
+
+    async fn check_url(
+        /// The url to check
+        url: String,
+    ) -> anyhow::Result<String> {
+        let mut current = Url::parse(&url)?;
+        if !matches!(current.scheme(), "http" | "https") {
+            bail!("only http and https urls can be checked");
+        }
+        if let Err(refusal) = policy::check_domain(current.as_str()) {
+            return Ok(refusal);
+        }
+
+        let mut lines = Vec::new();
+        let mut total = Duration::ZERO;
+        let mut final_status = None;
+        for _ in 0..=MAX_REDIRECTS {
+            let started = Instant::now();
+            let res = match head_or_get(current.as_str()).await {
+                Ok(res) => res,
+                Err(e) => {
+                    lines.push(format!("{current} → couldn't connect: {e}"));
+                    break;
+                }
+            };
+            let elapsed = started.elapsed();
+            total += elapsed;
+            lines.push(format!(
+                "{current} → {} {} ({} ms)",
+                res.status,
+                res.reason(),
+                elapsed.as_millis()
+            ));
+            final_status = Some(res.status);
+
+            let location = res.location.filter(|_| (300..400).contains(&res.status));
+            let Some(next) = location.and_then(|l| current.join(&l).ok()) else {
+                break;
+            };
+            // A redirect can lead anywhere, so where it leads is checked too.
+            if let Err(refusal) = policy::check_domain(next.as_str()) {
+                lines.push(format!("Stopped following the redirects. {refusal}"));
+                break;
+            }
+            current = next;
+        }
+
+        let verdict = match final_status {
+            Some(status) if (200..400).contains(&status) => "The site is up.",
+            Some(_) => "The site answered with an error.",
+            None => "The site is down.",
+        };
+        lines.push(format!("{verdict} Total time: {} ms.", total.as_millis()));
+        if current.scheme() == "https" {
+            let host = current.host_str().unwrap_or_default();
+            let port = current.port_or_known_default().unwrap_or(443);
+            lines.push(match certificate(host, port).await {
+                Ok(cert) => cert.describe(host),
+                Err(e) => format!("Couldn't read the TLS certificate of {host}: {e}."),
+            });
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Sends a HEAD request, falling back to GET for servers that don't allow it.
+async fn head_or_get(url: &str) -> anyhow::Result<Response> {
+    let res = http::send_unfollowed(Method::HEAD, url, &[]).await?;
+    if matches!(res.status, 405 | 501) {
+        return http::send_unfollowed(Method::GET, url, &[]).await;
+    }
+    Ok(res)
+}
+
+/// What a server's leaf certificate says about itself.
+struct Certificate {
+    subject: Option<String>,
+    issuer: Option<String>,
+    not_after: NaiveDateTime,
+}
+
+impl Certificate {
+    fn describe(&self, host: &str) -> String {
+        let days = (self.not_after - Utc::now().naive_utc()).num_days();
+        let when = if days < 0 {
+            format!("expired {} days ago", -days)
+        } else {
+            format!("expires in {days} days")
+        };
+        format!(
+            "The TLS certificate for {} issued by {} {when}, on {} UTC.",
+            self.subject.as_deref().unwrap_or(host),
+            self.issuer.as_deref().unwrap_or("an unknown issuer"),
+            self.not_after.format("%Y-%m-%d %H:%M")
+        )
+    }
+}
+
+/// Reads the certificate `host` presents by starting a TLS 1.2 handshake.
+/// The HTTP client's TLS runs outside the flow and doesn't expose the
+/// certificate, while TLS 1.2 still sends it unencrypted. Servers that only
+/// speak TLS 1.3 refuse the handshake.
+async fn certificate(host: &str, port: u16) -> anyhow::Result<Certificate> {
+    let mut stream = timeout(HANDSHAKE_TIMEOUT, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| anyhow!("the connection timed out"))??;
+    stream.write_all(&client_hello(host)).await?;
+    timeout(HANDSHAKE_TIMEOUT, read_certificate(&mut stream))
+        .await
+        .map_err(|_| anyhow!("the handshake timed out"))?
+}
+
+async fn read_certificate(stream: &mut TcpStream) -> anyhow::Result<Certificate> {
+    let mut handshake = Vec::new();
+    let mut read = 0;
+    loop {
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).await?;
+        let length = u16::from_be_bytes([header[3], header[4]]) as usize;
+        let mut payload = vec![0u8; length];
+        stream.read_exact(&mut payload).await?;
+        read += length;
+        match header[0] {
+            // Alert, most likely because TLS 1.2 isn't offered.
+            0x15 => bail!("the server refused a TLS 1.2 handshake"),
+            0x16 => handshake.extend_from_slice(&payload),
+            _ => {}
+        }
+
+        let mut messages = handshake.as_slice();
+        while messages.len() >= 4 {
+            let length = u24(&messages[1..4]);
+            let Some(body) = messages.get(4..4 + length) else {
+                break;
+            };
+            match messages[0] {
+                11 => return parse_certificate(leaf_certificate(body)?),
+                // ServerHelloDone without a certificate.
+                14 => bail!("the server sent no certificate"),
+                _ => messages = &messages[4 + length..],
+            }
+        }
+        if read > MAX_HANDSHAKE_BYTES {
+            bail!("the handshake was too long");
+        }
+    }
+}
+
+fn client_hello(host: &str) -> Vec<u8> {
+    // The random only has to differ between connections, as this handshake
+    // never completes.
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let random: Vec<u8> = (0..32u32)
+        .map(|i| (seed.rotate_left(i * 7) as u8) ^ (i as u8))
+        .collect();
+    let ciphers: &[u16] = &[
+        0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9, 0xcca8, 0xc013, 0xc014, 0x009c, 0x009d, 0x002f,
+        0x0035,
+    ];
+    let signatures: &[u16] = &[
+        0x0403, 0x0804, 0x0401, 0x0503, 0x0805, 0x0501, 0x0806, 0x0601, 0x0201,
+    ];
+    let groups: &[u16] = &[0x001d, 0x0017, 0x0018];
+
+    let mut sni = Vec::new();
+    sni.extend(u16_bytes(host.len() + 3));
+    sni.push(0);
+    sni.extend(u16_bytes(host.len()));
+    sni.extend(host.as_bytes());
+    let mut extensions = Vec::new();
+    for (kind, data) in [
+        (0x0000, sni),
+        (0x000a, prefixed_list(groups)),
+        (0x000b, vec![1, 0]),
+        (0x000d, prefixed_list(signatures)),
+    ] {
+        extensions.extend(u16_bytes(kind));
+        extensions.extend(u16_bytes(data.len()));
+        extensions.extend(data);
+    }
+
+    let mut hello = vec![0x03, 0x03];
+    hello.extend(random);
+    hello.push(0);
+    hello.extend(prefixed_list(ciphers));
+    hello.extend([1, 0]);
+    hello.extend(u16_bytes(extensions.len()));
+    hello.extend(extensions);
+
+    let mut handshake = vec![1];
+    handshake.extend(&(hello.len() as u32).to_be_bytes()[1..]);
+    handshake.extend(hello);
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend(u16_bytes(handshake.len()));
+    record.extend(handshake);
+    record
+}
+
+fn u16_bytes(n: usize) -> [u8; 2] {
+    (n as u16).to_be_bytes()
+}
+
+fn u24(bytes: &[u8]) -> usize {
+    (bytes[0] as usize) << 16 | (bytes[1] as usize) << 8 | bytes[2] as usize
+}
+
+fn prefixed_list(items: &[u16]) -> Vec<u8> {
+    let mut out = u16_bytes(items.len() * 2).to_vec();
+    for item in items {
+        out.extend(item.to_be_bytes());
+    }
+    out
+}
+
+/// The first certificate of a Certificate handshake message, which is the
+/// server's own.
+fn leaf_certificate(body: &[u8]) -> anyhow::Result<&[u8]> {
+    let length = body
+        .get(3..6)
+        .map(u24)
+        .ok_or_else(|| anyhow!("the certificate message is empty"))?;
+    body.get(6..6 + length)
+        .ok_or_else(|| anyhow!("the certificate message is cut short"))
+}
+
+/// Splits one DER element off `input`, as its tag, contents and the rest.
+fn der(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *input.first()?;
+    let first = *input.get(1)? as usize;
+    let (length, header) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let count = first & 0x7f;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let length = input
+            .get(2..2 + count)?
+            .iter()
+            .fold(0usize, |n, b| n << 8 | *b as usize);
+        (length, 2 + count)
+    };
+    let content = input.get(header..header + length)?;
+    Some((tag, content, &input[header + length..]))
+}
+
+fn parse_certificate(der_bytes: &[u8]) -> anyhow::Result<Certificate> {
+    parse_tbs(der_bytes).ok_or_else(|| anyhow!("the certificate couldn't be parsed"))
+}
+
+fn parse_tbs(der_bytes: &[u8]) -> Option<Certificate> {
+    let (_, certificate, _) = der(der_bytes)?;
+    let (_, tbs, _) = der(certificate)?;
+    let mut rest = tbs;
+    // The version is an optional explicit [0] field.
+    if rest.first() == Some(&0xa0) {
+        rest = der(rest)?.2;
+    }
+    let (_, _serial, rest) = der(rest)?;
+    let (_, _signature, rest) = der(rest)?;
+    let (_, issuer, rest) = der(rest)?;
+    let (_, validity, rest) = der(rest)?;
+    let (_, subject, _) = der(rest)?;
+    let (_, _not_before, validity) = der(validity)?;
+    let (tag, not_after, _) = der(validity)?;
+
+    Some(Certificate {
+        subject: name_attribute(subject, &[0x55, 0x04, 0x03]),
+        // The organization reads better than the issuing CA's name.
+        issuer: name_attribute(issuer, &[0x55, 0x04, 0x0a])
+            .or_else(|| name_attribute(issuer, &[0x55, 0x04, 0x03])),
+        not_after: parse_asn1_time(tag, not_after)?,
+    })
+}
+
+/// The value of the attribute with object identifier `oid` in an X.501 name.
+fn name_attribute(name: &[u8], oid: &[u8]) -> Option<String> {
+    let mut sets = name;
+    while let Some((_, set, rest)) = der(sets) {
+        sets = rest;
+        let (_, pair, _) = der(set)?;
+        let (_, id, value) = der(pair)?;
+        if id == oid {
+            let (_, value, _) = der(value)?;
+            return Some(String::from_utf8_lossy(value).into_owned());
+        }
+    }
+    None
+}
+
+/// Reads a UTCTime (two-digit year, before 2050) or GeneralizedTime.
+fn parse_asn1_time(tag: u8, value: &[u8]) -> Option<NaiveDateTime> {
+    let text = std::str::from_utf8(value).ok()?.trim_end_matches('Z');
+    let text = match tag {
+        0x17 => {
+            let year: u32 = text.get(..2)?.parse().ok()?;
+            format!("{}{text}", if year < 50 { "20" } else { "19" })
+        }
+        0x18 => text.to_string(),
+        _ => return None,
+    };
+    let date = NaiveDate::parse_from_str(text.get(..8)?, "%Y%m%d").ok()?;
+    let time = chrono::NaiveTime::parse_from_str(text.get(8..14)?, "%H%M%S").ok()?;
+    Some(date.and_time(time))
+}
@@ -0,0 +1,503 @@
+use crate::prefs::{Prefs, Units};
+use crate::store::{get, set, Expire, ExpireKind};
+use crate::tool;
+use crate::tools::{http, Cassette, NeedsInput, ToolContext};
+use anyhow::bail;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::env;
+use std::fmt;
+
+mod open_meteo;
+mod openweathermap;
+mod sun;
+mod weatherapi;
+
+use open_meteo::OpenMeteo;
+use openweathermap::OpenWeatherMap;
+pub use sun::SunTimesTool;
+use weatherapi::WeatherApi;
+
+tool! {
+    /// Get weather forecast for the city passed to it. If several places share the
+    /// name, the candidates are returned so the user can say which one they mean.
+    pub struct WeatherTool as "getWeather";
+
+    async fn get_weather(
+        #[context] context: &ToolContext,
+        /// The city specified by the user, optionally qualified as "City, State, Country".
+        /// Leave out when the user names no city, to use their home city
+        city: Option<String>,
+        /// Metric or imperial. Leave unset to use the units the user prefers
+        units: Option<Units>,
+    ) -> anyhow::Result<String> {
+        let prefs = Prefs::load(&context.user);
+        let units = units.unwrap_or(prefs.units);
+        let city = city_or_home(city, &prefs)?;
+        Ok(weather(provider(&context.cassette).as_ref(), &city, units).await?)
+    }
+
+    examples {
+        "Do I need an umbrella in Paris today?" => { "city": "Paris" },
+        "How warm is it in Portland, Oregon in fahrenheit?" =>
+            { "city": "Portland, OR, US", "units": "imperial" },
+    }
+}
+
+/// Today's weather in `city` from `provider`.
+async fn weather(
+    provider: &dyn WeatherProvider,
+    city: &str,
+    units: Units,
+) -> Result<String, NeedsInput> {
+    let place = locate(provider, city).await?;
+    Ok(match provider.current(&place, units).await {
+        Ok(w) => format!(
+            r#"
+Today in {}
+{}
+Low temperature: {} {temp_unit},
+High temperature: {} {temp_unit},
+Wind Speed: {} {wind_unit}"#,
+            place,
+            w.conditions,
+            w.temp_min as i32,
+            w.temp_max as i32,
+            w.wind.round() as i32,
+            temp_unit = temp_unit(units),
+            wind_unit = wind_unit(units),
+        ),
+        Err(e) => {
+            log::warn!(
+                "{} failed to get the weather for {}: {}",
+                provider.name(),
+                place,
+                e
+            );
+            format!("Couldn't get the weather for {place}")
+        }
+    })
+}
+
+/// How many matches to ask geocoding APIs for.
+const MAX_CANDIDATES: u32 = 5;
+/// How long weather answers are reused unless `weather_cache_minutes` says
+/// otherwise.
+const DEFAULT_CACHE_MINUTES: i64 = 10;
+
+/// A service that geocodes places and reports their weather.
+///
+/// Temperatures come in the requested units, wind speeds in km/h or mph, and
+/// precipitation always in mm.
+#[async_trait]
+pub trait WeatherProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// The places matching `city`, best first.
+    async fn locate(&self, city: &str) -> anyhow::Result<Vec<Place>>;
+
+    /// Today's conditions at `place`.
+    async fn current(&self, place: &Place, units: Units) -> anyhow::Result<Conditions>;
+
+    /// A summary of each of the next `days` days at `place`.
+    async fn forecast(
+        &self,
+        place: &Place,
+        days: usize,
+        units: Units,
+    ) -> anyhow::Result<Vec<DaySummary>>;
+
+    /// The air quality and UV index at `place`. Providers without air
+    /// quality data of their own get it from Open-Meteo, which needs no key.
+    async fn air_quality(&self, place: &Place) -> anyhow::Result<AirQuality>;
+
+    /// Makes an uncached request, to check that the service and its key work.
+    async fn check(&self) -> anyhow::Result<()>;
+}
+
+/// The provider picked with `weather_provider`: `openweathermap` (with
+/// `API_KEY`), `open-meteo` (no key needed) or `weatherapi` (with
+/// `WEATHERAPI_KEY`). Without a choice, OpenWeatherMap is used when
+/// `API_KEY` is set, and Open-Meteo otherwise. Its requests go through
+/// `cassette`.
+pub fn provider(cassette: &Cassette) -> Box<dyn WeatherProvider> {
+    let cassette = cassette.clone();
+    let owm_key = env::var("API_KEY").ok();
+    let choice = env::var("weather_provider").unwrap_or_default();
+    match choice.trim().to_ascii_lowercase().as_str() {
+        "" => match owm_key {
+            Some(key) => Box::new(OpenWeatherMap::new(key, cassette)),
+            None => Box::new(OpenMeteo::new(cassette)),
+        },
+        "openweathermap" | "owm" => match owm_key {
+            Some(key) => Box::new(OpenWeatherMap::new(key, cassette)),
+            None => {
+                log::warn!("API_KEY isn't set, using Open-Meteo instead of OpenWeatherMap");
+                Box::new(OpenMeteo::new(cassette))
+            }
+        },
+        "weatherapi" => match env::var("WEATHERAPI_KEY") {
+            Ok(key) => Box::new(WeatherApi::new(key, cassette)),
+            Err(_) => {
+                log::warn!("WEATHERAPI_KEY isn't set, using Open-Meteo instead of WeatherAPI");
+                Box::new(OpenMeteo::new(cassette))
+            }
+        },
+        "open-meteo" | "openmeteo" => Box::new(OpenMeteo::new(cassette)),
+        other => {
+            log::warn!("Unknown weather_provider {}, using Open-Meteo", other);
+            Box::new(OpenMeteo::new(cassette))
+        }
+    }
+}
+
+/// A geocoded place.
+#[derive(Debug, Clone)]
+pub struct Place {
+    pub name: String,
+    pub state: Option<String>,
+    pub country: String,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.state {
+            Some(state) => write!(f, "{}, {}, {}", self.name, state, self.country),
+            None => write!(f, "{}, {}", self.name, self.country),
+        }
+    }
+}
+
+/// Today's weather at a place.
+#[derive(Debug, Clone)]
+pub struct Conditions {
+    /// A word or two, such as "Rain".
+    pub conditions: String,
+    pub temp_min: f64,
+    pub temp_max: f64,
+    pub wind: f64,
+}
+
+/// One day of a forecast.
+#[derive(Debug, Clone)]
+pub struct DaySummary {
+    /// As YYYY-MM-DD.
+    pub date: String,
+    pub temp_min: f64,
+    pub temp_max: f64,
+    pub precipitation: f64,
+    pub wind_max: f64,
+}
+
+/// The scale an air quality index is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AqiScale {
+    /// The US EPA's 0 to 500 index.
+    Us,
+    /// The US EPA's six bands, numbered 1 to 6.
+    UsEpaBand,
+    /// OpenWeatherMap's 1 to 5 index.
+    OpenWeatherMap,
+}
+
+/// The air at a place right now. Each reading is missing when the provider
+/// doesn't have it.
+#[derive(Debug, Clone)]
+pub struct AirQuality {
+    pub aqi: Option<(f64, AqiScale)>,
+    /// Fine particles, in µg/m³.
+    pub pm2_5: Option<f64>,
+    pub uv_index: Option<f64>,
+}
+
+impl AqiScale {
+    fn category(self, aqi: f64) -> &'static str {
+        const US_BANDS: [&str; 6] = [
+            "Good",
+            "Moderate",
+            "Unhealthy for sensitive groups",
+            "Unhealthy",
+            "Very unhealthy",
+            "Hazardous",
+        ];
+        const OWM_BANDS: [&str; 5] = ["Good", "Fair", "Moderate", "Poor", "Very poor"];
+        let band = |bands: &'static [&'static str], n: f64| {
+            bands[(n.max(1.0) as usize).min(bands.len()) - 1]
+        };
+        match self {
+            AqiScale::Us => match aqi.round() as u32 {
+                0..=50 => US_BANDS[0],
+                51..=100 => US_BANDS[1],
+                101..=150 => US_BANDS[2],
+                151..=200 => US_BANDS[3],
+                201..=300 => US_BANDS[4],
+                _ => US_BANDS[5],
+            },
+            AqiScale::UsEpaBand => band(&US_BANDS, aqi),
+            AqiScale::OpenWeatherMap => band(&OWM_BANDS, aqi),
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            AqiScale::Us => "US AQI, 0 to 500",
+            AqiScale::UsEpaBand => "US EPA band, 1 to 6",
+            AqiScale::OpenWeatherMap => "OpenWeatherMap index, 1 to 5",
+        }
+    }
+}
+
+/// The WHO's exposure categories for a UV index.
+fn uv_category(uv: f64) -> &'static str {
+    match uv.round() as u32 {
+        0..=2 => "Low",
+        3..=5 => "Moderate",
+        6 | 7 => "High",
+        8..=10 => "Very high",
+        _ => "Extreme",
+    }
+}
+
+/// `city`, or the user's home city when they didn't name one. Otherwise the
+/// user is asked which city they mean.
+fn city_or_home(city: Option<String>, prefs: &Prefs) -> Result<String, NeedsInput> {
+    city.filter(|c| !c.trim().is_empty())
+        .or_else(|| prefs.home_city.clone())
+        .ok_or_else(|| {
+            NeedsInput::new(
+                "city",
+                "Which city do you mean? You can also say \"my city is ...\" to save one \
+                 for next time.",
+            )
+        })
+}
+
+/// Resolves `city` to a single place. When the name is unknown or matches
+/// several places, the user is asked which place they mean.
+async fn locate(provider: &dyn WeatherProvider, city: &str) -> Result<Place, NeedsInput> {
+    let mut places = provider.locate(city).await.unwrap_or_else(|e| {
+        log::warn!("{} failed to look up {}: {}", provider.name(), city, e);
+        Vec::new()
+    });
+    // Geocoders sometimes return the same place twice with slightly
+    // different coordinates.
+    places.dedup_by(|a, b| a.to_string() == b.to_string());
+
+    // A qualified name such as "Springfield, IL, US" is taken at its word.
+    if places.len() > 1 && !city.contains(',') {
+        let candidates = places
+            .iter()
+            .enumerate()
+            .map(|(i, p)| format!("{}. {}", i + 1, p))
+            .collect::<Vec<_>>()
+            .join("\n");
+        return Err(NeedsInput::new(
+            "city",
+            format!(
+                "Several places are called \"{city}\":\n{candidates}\n\
+                 Which one do you mean? Give its full name as listed."
+            ),
+        ));
+    }
+    places.into_iter().next().ok_or_else(|| {
+        NeedsInput::new(
+            "city",
+            format!("No place called \"{city}\" was found. Which city do you mean?"),
+        )
+    })
+}
+
+/// Checks that the configured weather provider answers, and says which one
+/// it is.
+pub async fn check_weather() -> anyhow::Result<String> {
+    let provider = provider(&Cassette::from_env());
+    provider.check().await?;
+    Ok(format!("{} answers", provider.name()))
+}
+
+/// GETs JSON from `url`, failing on error statuses.
+async fn get_json(cassette: &Cassette, provider: &str, url: &str) -> anyhow::Result<Value> {
+    let res = http::get(cassette, url, &[]).await?;
+    match res.status {
+        401 | 403 => bail!("{provider} rejected the API key"),
+        status if !res.is_success() => bail!("{provider} returned {status}"),
+        _ => res.json(),
+    }
+}
+
+/// Like [`get_json`], but answers are kept for `weather_cache_minutes` under
+/// `path`, which leaves out any API key, so bursts of questions about the
+/// same place stay within free tiers' limits. With caching off, the store
+/// isn't touched.
+async fn cached_json(
+    cassette: &Cassette,
+    provider: &str,
+    path: &str,
+    url: &str,
+) -> anyhow::Result<Value> {
+    let minutes = cache_minutes();
+    if minutes <= 0 {
+        return get_json(cassette, provider, url).await;
+    }
+    let cache_key = format!("weather_cache:{provider}:{path}");
+    if let Some(cached) = get(&cache_key) {
+        return Ok(cached);
+    }
+    let body = get_json(cassette, provider, url).await?;
+    let expire = Expire {
+        kind: ExpireKind::Ex,
+        value: minutes * 60,
+    };
+    set(&cache_key, body.clone(), Some(expire));
+    Ok(body)
+}
+
+fn cache_minutes() -> i64 {
+    env::var("weather_cache_minutes")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_CACHE_MINUTES)
+}
+
+fn temp_unit(units: Units) -> &'static str {
+    match units {
+        Units::Metric => "°C",
+        Units::Imperial => "°F",
+    }
+}
+
+fn wind_unit(units: Units) -> &'static str {
+    match units {
+        Units::Metric => "km/h",
+        Units::Imperial => "mph",
+    }
+}
+
+/// Converts a precipitation amount from mm for display.
+fn precipitation(mm: f64, units: Units) -> (f64, &'static str) {
+    match units {
+        Units::Metric => (mm, "mm"),
+        Units::Imperial => (mm / 25.4, "in"),
+    }
+}
+
+tool! {
+    /// Get the weather forecast for the next few days in the city passed to it. If
+    /// several places share the name, the candidates are returned so the user can
+    /// say which one they mean.
+    pub struct ForecastTool as "getForecast";
+
+    async fn get_forecast(
+        #[context] context: &ToolContext,
+        /// The city specified by the user, optionally qualified as "City, State, Country".
+        /// Leave out when the user names no city, to use their home city
+        city: Option<String>,
+        /// How many days to forecast, from 1 to 5
+        days: Option<u32>,
+        /// Metric or imperial. Leave unset to use the units the user prefers
+        units: Option<Units>,
+    ) -> anyhow::Result<String> {
+        let prefs = Prefs::load(&context.user);
+        let units = units.unwrap_or(prefs.units);
+        let city = city_or_home(city, &prefs)?;
+        let days = days.unwrap_or(3).clamp(1, 5) as usize;
+        let provider = provider(&context.cassette);
+        let place = locate(provider.as_ref(), &city).await?;
+        Ok(match provider.forecast(&place, days, units).await {
+            Ok(forecast) => format_forecast(&place, &forecast, units),
+            Err(e) => {
+                log::warn!("{} failed to get the forecast for {}: {}", provider.name(), place, e);
+                format!("Couldn't get the forecast for {place}")
+            }
+        })
+    }
+
+    examples {
+        "What's the weather in Berlin over the weekend?" => { "city": "Berlin", "days": 3 },
+    }
+}
+
+tool! {
+    /// Get the air quality in the city passed to it: its air quality index, fine
+    /// particle (PM2.5) level and UV index. If several places share the name, the
+    /// candidates are returned so the user can say which one they mean.
+    pub struct AirQualityTool as "getAirQuality";
+
+    async fn get_air_quality(
+        #[context] context: &ToolContext,
+        /// The city specified by the user, optionally qualified as "City, State, Country".
+        /// Leave out when the user names no city, to use their home city
+        city: Option<String>,
+    ) -> anyhow::Result<String> {
+        let city = city_or_home(city, &Prefs::load(&context.user))?;
+        let provider = provider(&context.cassette);
+        let place = locate(provider.as_ref(), &city).await?;
+        Ok(match provider.air_quality(&place).await {
+            Ok(air) => format_air_quality(&place, &air),
+            Err(e) => {
+                log::warn!("{} failed to get the air quality for {}: {}", provider.name(), place, e);
+                format!("Couldn't get the air quality for {place}")
+            }
+        })
+    }
+
+    examples {
+        "Is the air OK for a run in Delhi?" => { "city": "Delhi" },
+        "Do I need sunscreen in Sydney today?" => { "city": "Sydney" },
+    }
+}
+
+fn format_air_quality(place: &Place, air: &AirQuality) -> String {
+    let unknown = "unknown".to_string();
+    let aqi = air.aqi.map_or(unknown.clone(), |(aqi, scale)| {
+        format!("{aqi:.0} ({}, {})", scale.category(aqi), scale.describe())
+    });
+    let pm2_5 = air
+        .pm2_5
+        .map_or(unknown.clone(), |pm| format!("{pm:.1} µg/m³"));
+    let uv = air
+        .uv_index
+        .map_or(unknown, |uv| format!("{uv:.1} ({})", uv_category(uv)));
+    format!("Air quality in {place}\nAQI: {aqi}\nPM2.5: {pm2_5}\nUV index: {uv}")
+}
+
+fn format_forecast(place: &Place, days: &[DaySummary], units: Units) -> String {
+    let temp_unit = temp_unit(units);
+    let wind_unit = wind_unit(units);
+    let mut table = format!(
+        "Forecast for {place}\n```\n{:<10}  {:>5}  {:>5}  {:>7}  {:>9}\n",
+        "Date", "Low", "High", "Precip", "Wind"
+    );
+    for day in days {
+        let (precip, precip_unit) = precipitation(day.precipitation, units);
+        table.push_str(&format!(
+            "{:<10}  {:>3}{temp_unit}  {:>3}{temp_unit}  {:>4.1} {precip_unit:<2}  {:>4} {wind_unit:<4}\n",
+            day.date,
+            day.temp_min.round() as i32,
+            day.temp_max.round() as i32,
+            precip,
+            day.wind_max.round() as i32,
+        ));
+    }
+    table.push_str("```");
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn formats_replayed_weather() {
+        crate::store::use_memory_for_thread();
+        let provider = OpenMeteo::new(http::replay_fixtures());
+        let out = weather(&provider, "Tokyo", Units::Metric).await.unwrap();
+        assert_eq!(
+            out,
+            "\nToday in Tokyo, Tokyo, Japan\nClouds\nLow temperature: 14 °C,\n\
+             High temperature: 22 °C,\nWind Speed: 12 km/h"
+        );
+    }
+}
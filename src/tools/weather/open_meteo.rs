@@ -0,0 +1,244 @@
+use super::{
+    cached_json, get_json, AirQuality, AqiScale, Conditions, DaySummary, Place, WeatherProvider,
+    MAX_CANDIDATES,
+};
+use crate::prefs::Units;
+use crate::tools::Cassette;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde::Deserialize;
+use urlencoding::encode;
+
+const GEOCODING_API: &str = "https://geocoding-api.open-meteo.com/v1/search";
+const FORECAST_API: &str = "https://api.open-meteo.com/v1/forecast";
+const AIR_QUALITY_API: &str = "https://air-quality-api.open-meteo.com/v1/air-quality";
+const NAME: &str = "Open-Meteo";
+
+/// open-meteo.com, which needs no API key.
+pub struct OpenMeteo {
+    cassette: Cassette,
+}
+
+#[derive(Deserialize)]
+struct Geocoding {
+    #[serde(default)]
+    results: Vec<OmPlace>,
+}
+
+#[derive(Deserialize)]
+struct OmPlace {
+    name: String,
+    admin1: Option<String>,
+    #[serde(default)]
+    country: String,
+    #[serde(default)]
+    country_code: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+impl OmPlace {
+    /// Whether a qualifier such as "IL" or "US" from "Springfield, IL, US"
+    /// names this place's state or country.
+    fn matches(&self, qualifier: &str) -> bool {
+        let qualifier = qualifier.to_lowercase();
+        self.country_code.to_lowercase() == qualifier
+            || [self.admin1.as_deref().unwrap_or_default(), &self.country]
+                .iter()
+                .any(|name| name.to_lowercase().starts_with(&qualifier))
+    }
+}
+
+#[derive(Deserialize)]
+struct Forecast {
+    current: Current,
+    daily: Daily,
+}
+
+#[derive(Deserialize)]
+struct Current {
+    weather_code: u32,
+    wind_speed_10m: f64,
+}
+
+/// Parallel arrays, one entry per day.
+#[derive(Deserialize)]
+struct Daily {
+    time: Vec<String>,
+    temperature_2m_min: Vec<f64>,
+    temperature_2m_max: Vec<f64>,
+    precipitation_sum: Vec<Option<f64>>,
+    wind_speed_10m_max: Vec<f64>,
+}
+
+impl OpenMeteo {
+    pub fn new(cassette: Cassette) -> Self {
+        Self { cassette }
+    }
+
+    async fn get_forecast(
+        &self,
+        place: &Place,
+        days: usize,
+        units: Units,
+    ) -> anyhow::Result<Forecast> {
+        let mut path = format!(
+            "?latitude={}&longitude={}&current=weather_code,wind_speed_10m\
+             &daily=temperature_2m_min,temperature_2m_max,precipitation_sum,wind_speed_10m_max\
+             &timezone=auto&forecast_days={days}",
+            place.lat, place.lon
+        );
+        if units == Units::Imperial {
+            path.push_str("&temperature_unit=fahrenheit&wind_speed_unit=mph");
+        }
+        let url = format!("{FORECAST_API}{path}");
+        Ok(serde_json::from_value(
+            cached_json(&self.cassette, NAME, &url, &url).await?,
+        )?)
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for OpenMeteo {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    async fn locate(&self, city: &str) -> anyhow::Result<Vec<Place>> {
+        // The API only searches names, so "Springfield, IL, US" is searched
+        // as "Springfield" and narrowed down by the rest.
+        let mut parts = city.split(',').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        let qualifiers: Vec<&str> = parts.filter(|q| !q.is_empty()).collect();
+        let url = format!(
+            "{GEOCODING_API}?name={}&count={MAX_CANDIDATES}&language=en&format=json",
+            encode(name)
+        );
+        let geocoding: Geocoding =
+            serde_json::from_value(cached_json(&self.cassette, NAME, &url, &url).await?)?;
+        Ok(geocoding
+            .results
+            .into_iter()
+            .filter(|p| qualifiers.iter().all(|q| p.matches(q)))
+            .map(|p| Place {
+                name: p.name,
+                state: p.admin1,
+                country: p.country,
+                lat: p.latitude,
+                lon: p.longitude,
+            })
+            .collect())
+    }
+
+    async fn current(&self, place: &Place, units: Units) -> anyhow::Result<Conditions> {
+        let forecast = self.get_forecast(place, 1, units).await?;
+        let today = days(&forecast.daily)
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("{NAME} sent no forecast for today"))?;
+        Ok(Conditions {
+            conditions: describe(forecast.current.weather_code).to_string(),
+            temp_min: today.temp_min,
+            temp_max: today.temp_max,
+            wind: forecast.current.wind_speed_10m,
+        })
+    }
+
+    async fn forecast(
+        &self,
+        place: &Place,
+        days: usize,
+        units: Units,
+    ) -> anyhow::Result<Vec<DaySummary>> {
+        let forecast = self.get_forecast(place, days, units).await?;
+        Ok(self::days(&forecast.daily))
+    }
+
+    async fn air_quality(&self, place: &Place) -> anyhow::Result<AirQuality> {
+        air_quality(&self.cassette, place).await
+    }
+
+    async fn check(&self) -> anyhow::Result<()> {
+        let url = format!("{GEOCODING_API}?name=London&count=1&format=json");
+        get_json(&self.cassette, NAME, &url).await.map(|_| ())
+    }
+}
+
+#[derive(Deserialize)]
+struct AirQualityResult {
+    current: AirQualityNow,
+}
+
+#[derive(Deserialize)]
+struct AirQualityNow {
+    us_aqi: Option<f64>,
+    pm2_5: Option<f64>,
+    uv_index: Option<f64>,
+}
+
+/// The air at `place` from Open-Meteo's air quality API, which also serves
+/// providers without air quality data of their own.
+pub(super) async fn air_quality(cassette: &Cassette, place: &Place) -> anyhow::Result<AirQuality> {
+    let url = format!(
+        "{AIR_QUALITY_API}?latitude={}&longitude={}&current=us_aqi,pm2_5,uv_index",
+        place.lat, place.lon
+    );
+    let result: AirQualityResult =
+        serde_json::from_value(cached_json(cassette, NAME, &url, &url).await?)?;
+    let now = result.current;
+    Ok(AirQuality {
+        aqi: now.us_aqi.map(|aqi| (aqi, AqiScale::Us)),
+        pm2_5: now.pm2_5,
+        uv_index: now.uv_index,
+    })
+}
+
+#[derive(Deserialize)]
+struct TimezoneResult {
+    timezone: String,
+}
+
+/// The IANA name of the timezone `place` keeps.
+pub(super) async fn timezone(cassette: &Cassette, place: &Place) -> anyhow::Result<String> {
+    let url = format!(
+        "{FORECAST_API}?latitude={}&longitude={}&timezone=auto&forecast_days=1",
+        place.lat, place.lon
+    );
+    let result: TimezoneResult =
+        serde_json::from_value(cached_json(cassette, NAME, &url, &url).await?)?;
+    Ok(result.timezone)
+}
+
+fn days(daily: &Daily) -> Vec<DaySummary> {
+    daily
+        .time
+        .iter()
+        .enumerate()
+        .map(|(i, date)| DaySummary {
+            date: date.clone(),
+            temp_min: daily.temperature_2m_min.get(i).copied().unwrap_or_default(),
+            temp_max: daily.temperature_2m_max.get(i).copied().unwrap_or_default(),
+            precipitation: daily
+                .precipitation_sum
+                .get(i)
+                .copied()
+                .flatten()
+                .unwrap_or_default(),
+            wind_max: daily.wind_speed_10m_max.get(i).copied().unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Names a WMO weather code in the words OpenWeatherMap uses.
+fn describe(code: u32) -> &'static str {
+    match code {
+        0 => "Clear",
+        1..=3 => "Clouds",
+        45 | 48 => "Fog",
+        51..=57 => "Drizzle",
+        61..=67 | 80..=82 => "Rain",
+        71..=77 | 85 | 86 => "Snow",
+        95..=99 => "Thunderstorm",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,249 @@
+use super::{
+    cached_json, get_json, open_meteo, AirQuality, AqiScale, Conditions, DaySummary, Place,
+    WeatherProvider, MAX_CANDIDATES,
+};
+use crate::prefs::Units;
+use crate::tools::Cassette;
+use async_trait::async_trait;
+use chrono::DateTime;
+use serde::{de::DeserializeOwned, Deserialize};
+use urlencoding::encode;
+
+const API_BASE: &str = "https://api.openweathermap.org";
+const NAME: &str = "OpenWeatherMap";
+
+/// openweathermap.org, keyed by `API_KEY`.
+pub struct OpenWeatherMap {
+    api_key: String,
+    cassette: Cassette,
+}
+
+impl OpenWeatherMap {
+    pub fn new(api_key: String, cassette: Cassette) -> Self {
+        Self { api_key, cassette }
+    }
+
+    /// Queries `path`, which must already carry a query string.
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        let url = format!("{API_BASE}/{path}&appid={}", self.api_key);
+        Ok(serde_json::from_value(
+            cached_json(&self.cassette, NAME, path, &url).await?,
+        )?)
+    }
+}
+
+/// A match from the geocoding API.
+#[derive(Deserialize)]
+struct OwmPlace {
+    name: String,
+    state: Option<String>,
+    country: String,
+    lat: f64,
+    lon: f64,
+}
+
+#[derive(Deserialize)]
+struct ApiResult {
+    weather: Vec<Weather>,
+    main: Main,
+    wind: Wind,
+}
+
+#[derive(Deserialize)]
+struct Weather {
+    main: String,
+}
+
+#[derive(Deserialize)]
+struct Main {
+    temp_max: f64,
+    temp_min: f64,
+}
+
+#[derive(Deserialize)]
+struct Wind {
+    speed: f64,
+}
+
+#[derive(Deserialize)]
+struct ForecastResult {
+    list: Vec<ForecastEntry>,
+    city: ForecastCity,
+}
+
+#[derive(Deserialize)]
+struct ForecastCity {
+    /// The place's offset from UTC, in seconds.
+    #[serde(default)]
+    timezone: i64,
+}
+
+/// One three-hour slot of the forecast.
+#[derive(Deserialize)]
+struct ForecastEntry {
+    /// When the slot starts, in Unix seconds.
+    dt: i64,
+    main: Main,
+    wind: Wind,
+    rain: Option<Precipitation>,
+    snow: Option<Precipitation>,
+}
+
+#[derive(Deserialize)]
+struct Precipitation {
+    #[serde(rename = "3h", default)]
+    three_hours: f64,
+}
+
+#[derive(Deserialize)]
+struct Pollution {
+    list: Vec<PollutionEntry>,
+}
+
+#[derive(Deserialize)]
+struct PollutionEntry {
+    main: PollutionIndex,
+    components: Components,
+}
+
+#[derive(Deserialize)]
+struct PollutionIndex {
+    aqi: f64,
+}
+
+#[derive(Deserialize)]
+struct Components {
+    pm2_5: Option<f64>,
+}
+
+#[async_trait]
+impl WeatherProvider for OpenWeatherMap {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    async fn locate(&self, city: &str) -> anyhow::Result<Vec<Place>> {
+        let places: Vec<OwmPlace> = self
+            .get(&format!(
+                "geo/1.0/direct?q={}&limit={MAX_CANDIDATES}",
+                encode(city)
+            ))
+            .await?;
+        Ok(places
+            .into_iter()
+            .map(|p| Place {
+                name: p.name,
+                state: p.state,
+                country: p.country,
+                lat: p.lat,
+                lon: p.lon,
+            })
+            .collect())
+    }
+
+    async fn current(&self, place: &Place, units: Units) -> anyhow::Result<Conditions> {
+        let w: ApiResult = self.get(&data_path("weather", place, units)).await?;
+        Ok(Conditions {
+            conditions: w
+                .weather
+                .first()
+                .map_or("Unknown".to_string(), |w| w.main.clone()),
+            temp_min: w.main.temp_min,
+            temp_max: w.main.temp_max,
+            wind: wind_speed(w.wind.speed, units),
+        })
+    }
+
+    async fn forecast(
+        &self,
+        place: &Place,
+        days: usize,
+        units: Units,
+    ) -> anyhow::Result<Vec<DaySummary>> {
+        let forecast: ForecastResult = self.get(&data_path("forecast", place, units)).await?;
+        Ok(daily_summaries(&forecast, days, units))
+    }
+
+    /// The air pollution API has no UV index, so that comes from Open-Meteo.
+    async fn air_quality(&self, place: &Place) -> anyhow::Result<AirQuality> {
+        let pollution: Pollution = self
+            .get(&format!(
+                "data/2.5/air_pollution?lat={}&lon={}",
+                place.lat, place.lon
+            ))
+            .await?;
+        let now = pollution.list.first();
+        let uv_index = match open_meteo::air_quality(&self.cassette, place).await {
+            Ok(air) => air.uv_index,
+            Err(e) => {
+                log::warn!("Failed to get the UV index for {}: {}", place, e);
+                None
+            }
+        };
+        Ok(AirQuality {
+            aqi: now.map(|n| (n.main.aqi, AqiScale::OpenWeatherMap)),
+            pm2_5: now.and_then(|n| n.components.pm2_5),
+            uv_index,
+        })
+    }
+
+    async fn check(&self) -> anyhow::Result<()> {
+        let url = format!(
+            "{API_BASE}/geo/1.0/direct?q=London&limit=1&appid={}",
+            self.api_key
+        );
+        get_json(&self.cassette, NAME, &url).await.map(|_| ())
+    }
+}
+
+/// The path of a data endpoint for `place`.
+fn data_path(endpoint: &str, place: &Place, units: Units) -> String {
+    format!(
+        "data/2.5/{endpoint}?lat={}&lon={}&units={}",
+        place.lat,
+        place.lon,
+        units.as_str()
+    )
+}
+
+/// Metric wind speeds arrive in m/s.
+fn wind_speed(speed: f64, units: Units) -> f64 {
+    match units {
+        Units::Metric => speed * 3.6,
+        Units::Imperial => speed,
+    }
+}
+
+/// Sums up the slots of each day, counting days in the place's own
+/// timezone rather than UTC.
+fn daily_summaries(forecast: &ForecastResult, days: usize, units: Units) -> Vec<DaySummary> {
+    let mut summaries: Vec<DaySummary> = Vec::new();
+    for entry in &forecast.list {
+        let full = summaries.len() == days;
+        let Some(local) = DateTime::from_timestamp(entry.dt + forecast.city.timezone, 0) else {
+            continue;
+        };
+        let date = local.format("%Y-%m-%d").to_string();
+        let precipitation = entry.rain.as_ref().map_or(0.0, |p| p.three_hours)
+            + entry.snow.as_ref().map_or(0.0, |p| p.three_hours);
+        let wind = wind_speed(entry.wind.speed, units);
+
+        match summaries.last_mut() {
+            Some(day) if day.date == date => {
+                day.temp_min = day.temp_min.min(entry.main.temp_min);
+                day.temp_max = day.temp_max.max(entry.main.temp_max);
+                day.precipitation += precipitation;
+                day.wind_max = day.wind_max.max(wind);
+            }
+            _ if full => break,
+            _ => summaries.push(DaySummary {
+                date,
+                temp_min: entry.main.temp_min,
+                temp_max: entry.main.temp_max,
+                precipitation,
+                wind_max: wind,
+            }),
+        }
+    }
+    summaries
+}
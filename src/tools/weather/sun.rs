@@ -0,0 +1,158 @@
+use super::{city_or_home, locate, open_meteo, provider, Place};
+use crate::prefs::Prefs;
+use crate::tool;
+use crate::tools::{Cassette, ToolContext};
+use chrono::{prelude::*, Duration};
+use chrono_tz::Tz;
+use std::f64::consts::PI;
+
+/// Julian date of the J2000 epoch, 2000-01-01 12:00 UTC.
+const J2000: f64 = 2_451_545.0;
+/// Julian date of the Unix epoch.
+const UNIX_EPOCH_JD: f64 = 2_440_587.5;
+/// The sun's altitude at sunrise and sunset, allowing for refraction and
+/// the size of its disc.
+const HORIZON_DEGREES: f64 = -0.833;
+/// The tilt of the Earth's axis.
+const OBLIQUITY_DEGREES: f64 = 23.4397;
+
+tool! {
+    /// Get the sunrise, sunset, solar noon and day length in the city passed to it
+    /// on a given day. If several places share the name, the candidates are
+    /// returned so the user can say which one they mean.
+    pub struct SunTimesTool as "getSunTimes";
+
+    async fn get_sun_times(
+        #[context] context: &ToolContext,
+        /// The city specified by the user, optionally qualified as "City, State, Country".
+        /// Leave out when the user names no city, to use their home city
+        city: Option<String>,
+        /// The day, as YYYY-MM-DD, "today" or "tomorrow". Today when left out
+        date: Option<String>,
+        /// An IANA timezone name such as "Asia/Tokyo" to show times in. The
+        /// city's own timezone when left out
+        timezone: Option<String>,
+    ) -> anyhow::Result<String> {
+        let city = city_or_home(city, &Prefs::load(&context.user))?;
+        let provider = provider(&context.cassette);
+        let place = locate(provider.as_ref(), &city).await?;
+        let tz = match timezone {
+            Some(name) => match name.parse::<Tz>() {
+                Ok(tz) => tz,
+                Err(_) => {
+                    return Ok(format!(
+                        "Unknown timezone \"{name}\". Use an IANA name such as \"Europe/Paris\"."
+                    ))
+                }
+            },
+            None => local_timezone(&context.cassette, &place).await,
+        };
+        let today = Utc::now().with_timezone(&tz).date_naive();
+        let day = match date.as_deref().map(|d| d.trim().to_lowercase()).as_deref() {
+            None | Some("today") => today,
+            Some("tomorrow") => today + Duration::days(1),
+            Some(other) => match NaiveDate::parse_from_str(other, "%Y-%m-%d") {
+                Ok(day) => day,
+                Err(_) => return Ok(format!("\"{other}\" is not a date like 2024-03-01")),
+            },
+        };
+        Ok(format_sun_times(&place, day, tz))
+    }
+
+    examples {
+        "When does the sun set in Reykjavik tomorrow?" =>
+            { "city": "Reykjavik", "date": "tomorrow" },
+        "How long is the day in Oslo on the solstice?" =>
+            { "city": "Oslo", "date": "2024-06-20" },
+    }
+}
+
+/// The timezone `place` keeps, from Open-Meteo, or UTC when it can't be
+/// found.
+async fn local_timezone(cassette: &Cassette, place: &Place) -> Tz {
+    match open_meteo::timezone(cassette, place).await {
+        Ok(name) => name.parse().unwrap_or(Tz::UTC),
+        Err(e) => {
+            log::warn!("Failed to find the timezone of {}: {}", place, e);
+            Tz::UTC
+        }
+    }
+}
+
+/// What the sun does on a day.
+enum SunDay {
+    /// Unix seconds.
+    RisesAndSets { rise: f64, noon: f64, set: f64 },
+    /// Polar day.
+    NeverSets { noon: f64 },
+    /// Polar night.
+    NeverRises { noon: f64 },
+}
+
+/// Works out the sun's times at `lat`, `lon` (degrees, east positive) on
+/// `day` with the sunrise equation, which is good to a minute or so away
+/// from the poles.
+fn sun_day(lat: f64, lon: f64, day: NaiveDate) -> SunDay {
+    let radians = |degrees: f64| degrees * PI / 180.0;
+    let degrees = |radians: f64| radians * 180.0 / PI;
+
+    let days_since_epoch = day.signed_duration_since(NaiveDate::default()).num_days() as f64;
+    // Days from J2000 to noon UTC on `day`, plus a leap second correction,
+    // then shifted to the mean solar noon at `lon`.
+    let n = (UNIX_EPOCH_JD + days_since_epoch + 0.5 - J2000).round();
+    let mean_noon = n + 0.0008 - lon / 360.0;
+    let anomaly = (357.5291 + 0.985_600_28 * mean_noon).rem_euclid(360.0);
+    let m = radians(anomaly);
+    let center = 1.9148 * m.sin() + 0.02 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+    let longitude = radians((anomaly + center + 180.0 + 102.9372).rem_euclid(360.0));
+    let transit = J2000 + mean_noon + 0.0053 * m.sin() - 0.0069 * (2.0 * longitude).sin();
+    let declination = (longitude.sin() * radians(OBLIQUITY_DEGREES).sin()).asin();
+
+    let phi = radians(lat);
+    let cos_hour_angle = (radians(HORIZON_DEGREES).sin() - phi.sin() * declination.sin())
+        / (phi.cos() * declination.cos());
+    let unix = |julian: f64| (julian - UNIX_EPOCH_JD) * 86_400.0;
+    let noon = unix(transit);
+    if cos_hour_angle < -1.0 {
+        return SunDay::NeverSets { noon };
+    }
+    if cos_hour_angle > 1.0 {
+        return SunDay::NeverRises { noon };
+    }
+    let half_day = degrees(cos_hour_angle.acos()) / 360.0;
+    SunDay::RisesAndSets {
+        rise: unix(transit - half_day),
+        noon,
+        set: unix(transit + half_day),
+    }
+}
+
+fn format_sun_times(place: &Place, day: NaiveDate, tz: Tz) -> String {
+    let clock = |unix: f64| {
+        DateTime::from_timestamp(unix.round() as i64, 0)
+            .map(|t| t.with_timezone(&tz).format("%H:%M").to_string())
+            .unwrap_or_default()
+    };
+    let heading = format!("Sun times in {place} on {day} ({tz})");
+    match sun_day(place.lat, place.lon, day) {
+        SunDay::RisesAndSets { rise, noon, set } => {
+            let minutes = ((set - rise) / 60.0).round() as i64;
+            format!(
+                "{heading}\nSunrise: {}\nSunset: {}\nSolar noon: {}\nDay length: {} h {} min",
+                clock(rise),
+                clock(set),
+                clock(noon),
+                minutes / 60,
+                minutes % 60
+            )
+        }
+        SunDay::NeverSets { noon } => format!(
+            "{heading}\nThe sun doesn't set that day.\nSolar noon: {}\nDay length: 24 h",
+            clock(noon)
+        ),
+        SunDay::NeverRises { noon } => format!(
+            "{heading}\nThe sun doesn't rise that day.\nSolar noon: {}\nDay length: 0 h",
+            clock(noon)
+        ),
+    }
+}
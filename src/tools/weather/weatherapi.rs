@@ -0,0 +1,203 @@
+use super::{
+    cached_json, get_json, AirQuality, AqiScale, Conditions, DaySummary, Place, WeatherProvider,
+};
+use crate::prefs::Units;
+use crate::tools::Cassette;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize};
+use urlencoding::encode;
+
+const API_BASE: &str = "https://api.weatherapi.com/v1";
+const NAME: &str = "WeatherAPI";
+
+/// weatherapi.com, keyed by `WEATHERAPI_KEY`.
+pub struct WeatherApi {
+    api_key: String,
+    cassette: Cassette,
+}
+
+impl WeatherApi {
+    pub fn new(api_key: String, cassette: Cassette) -> Self {
+        Self { api_key, cassette }
+    }
+
+    /// Queries `path`, which must already carry a query string.
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        let url = format!("{API_BASE}/{path}&key={}", self.api_key);
+        Ok(serde_json::from_value(
+            cached_json(&self.cassette, NAME, path, &url).await?,
+        )?)
+    }
+
+    async fn get_forecast(&self, place: &Place, days: usize) -> anyhow::Result<ForecastResult> {
+        self.get(&format!(
+            "forecast.json?q={},{}&days={days}",
+            place.lat, place.lon
+        ))
+        .await
+    }
+}
+
+#[derive(Deserialize)]
+struct WaPlace {
+    name: String,
+    #[serde(default)]
+    region: String,
+    country: String,
+    lat: f64,
+    lon: f64,
+}
+
+#[derive(Deserialize)]
+struct ForecastResult {
+    current: Current,
+    forecast: ForecastDays,
+}
+
+#[derive(Deserialize)]
+struct Current {
+    condition: Condition,
+    wind_kph: f64,
+    wind_mph: f64,
+}
+
+#[derive(Deserialize)]
+struct Condition {
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct CurrentResult {
+    current: CurrentAir,
+}
+
+#[derive(Deserialize)]
+struct CurrentAir {
+    uv: Option<f64>,
+    air_quality: Option<Pollution>,
+}
+
+#[derive(Deserialize)]
+struct Pollution {
+    pm2_5: Option<f64>,
+    #[serde(rename = "us-epa-index")]
+    us_epa_index: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct ForecastDays {
+    forecastday: Vec<ForecastDay>,
+}
+
+#[derive(Deserialize)]
+struct ForecastDay {
+    date: String,
+    day: Day,
+}
+
+#[derive(Deserialize)]
+struct Day {
+    mintemp_c: f64,
+    maxtemp_c: f64,
+    mintemp_f: f64,
+    maxtemp_f: f64,
+    totalprecip_mm: f64,
+    maxwind_kph: f64,
+    maxwind_mph: f64,
+}
+
+impl Day {
+    fn summary(&self, date: &str, units: Units) -> DaySummary {
+        let (temp_min, temp_max, wind_max) = match units {
+            Units::Metric => (self.mintemp_c, self.maxtemp_c, self.maxwind_kph),
+            Units::Imperial => (self.mintemp_f, self.maxtemp_f, self.maxwind_mph),
+        };
+        DaySummary {
+            date: date.to_string(),
+            temp_min,
+            temp_max,
+            precipitation: self.totalprecip_mm,
+            wind_max,
+        }
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for WeatherApi {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    async fn locate(&self, city: &str) -> anyhow::Result<Vec<Place>> {
+        let places: Vec<WaPlace> = self.get(&format!("search.json?q={}", encode(city))).await?;
+        Ok(places
+            .into_iter()
+            .map(|p| Place {
+                name: p.name,
+                state: Some(p.region).filter(|r| !r.is_empty()),
+                country: p.country,
+                lat: p.lat,
+                lon: p.lon,
+            })
+            .collect())
+    }
+
+    async fn current(&self, place: &Place, units: Units) -> anyhow::Result<Conditions> {
+        let result = self.get_forecast(place, 1).await?;
+        let today = result
+            .forecast
+            .forecastday
+            .first()
+            .ok_or_else(|| anyhow!("{NAME} sent no forecast for today"))?;
+        let today = today.day.summary(&today.date, units);
+        Ok(Conditions {
+            conditions: result.current.condition.text,
+            temp_min: today.temp_min,
+            temp_max: today.temp_max,
+            wind: match units {
+                Units::Metric => result.current.wind_kph,
+                Units::Imperial => result.current.wind_mph,
+            },
+        })
+    }
+
+    async fn forecast(
+        &self,
+        place: &Place,
+        days: usize,
+        units: Units,
+    ) -> anyhow::Result<Vec<DaySummary>> {
+        let result = self.get_forecast(place, days).await?;
+        Ok(result
+            .forecast
+            .forecastday
+            .iter()
+            .take(days)
+            .map(|d| d.day.summary(&d.date, units))
+            .collect())
+    }
+
+    async fn air_quality(&self, place: &Place) -> anyhow::Result<AirQuality> {
+        let result: CurrentResult = self
+            .get(&format!(
+                "current.json?q={},{}&aqi=yes",
+                place.lat, place.lon
+            ))
+            .await?;
+        let air = result.current.air_quality;
+        Ok(AirQuality {
+            aqi: air
+                .as_ref()
+                .and_then(|a| a.us_epa_index)
+                .map(|band| (band, AqiScale::UsEpaBand)),
+            pm2_5: air.and_then(|a| a.pm2_5),
+            uv_index: result.current.uv,
+        })
+    }
+
+    async fn check(&self) -> anyhow::Result<()> {
+        let url = format!("{API_BASE}/search.json?q=London&key={}", self.api_key);
+        get_json(&self.cassette, NAME, &url).await.map(|_| ())
+    }
+}
@@ -0,0 +1,157 @@
+//! Structured logs of chat completion requests.
+//!
+//! Each completion is logged as one line of `key=value` pairs, and at the
+//! `full` level followed by the tool call arguments and the answer. Lines go
+//! to the [`DebugSink`].
+
+use crate::debug::DebugSink;
+use async_openai::error::OpenAIError;
+use async_openai::types::{CreateChatCompletionRequest, CreateChatCompletionResponse};
+use log::Level;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The longest answer logged at the `full` level.
+const MAX_CONTENT_CHARS: usize = 1000;
+
+/// How much of each completion is logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum TraceLevel {
+    Off,
+    /// One line per request: its ID, model, token counts, finish reason and
+    /// the tools called.
+    #[default]
+    Summary,
+    /// Also the tool call arguments and the answer.
+    Full,
+}
+
+impl FromStr for TraceLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(TraceLevel::Off),
+            "summary" => Ok(TraceLevel::Summary),
+            "full" => Ok(TraceLevel::Full),
+            other => Err(format!("unknown trace level: {other}")),
+        }
+    }
+}
+
+/// How completions are traced, read from `trace_level` and `debug_sink`.
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    pub level: TraceLevel,
+    pub sink: DebugSink,
+}
+
+impl Trace {
+    pub fn from_env() -> Self {
+        let level = match env::var("trace_level") {
+            Ok(value) => value.parse().unwrap_or_else(|e| {
+                log::warn!("Ignoring trace_level: {}", e);
+                TraceLevel::default()
+            }),
+            Err(_) => TraceLevel::default(),
+        };
+        Self {
+            level,
+            sink: DebugSink::from_env(),
+        }
+    }
+
+    /// Logs a completion that took `elapsed`.
+    pub async fn completion(
+        &self,
+        request: &CreateChatCompletionRequest,
+        response: &CreateChatCompletionResponse,
+        elapsed: Duration,
+    ) {
+        if self.level == TraceLevel::Off {
+            return;
+        }
+        let choice = response.choices.first();
+        let calls = choice
+            .and_then(|c| c.message.tool_calls.as_ref())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let mut lines = vec![format!(
+            "chat id={} model={} messages={} tools={} prompt_tokens={} completion_tokens={} \
+             finish={} tool_calls=[{}] ms={}",
+            response.id,
+            response.model,
+            request.messages.len(),
+            request.tools.as_ref().map_or(0, Vec::len),
+            response.usage.as_ref().map_or(0, |u| u.prompt_tokens),
+            response.usage.as_ref().map_or(0, |u| u.completion_tokens),
+            choice
+                .and_then(|c| c.finish_reason)
+                .map(|reason| format!("{reason:?}").to_lowercase())
+                .unwrap_or("none".to_string()),
+            calls
+                .iter()
+                .map(|call| call.function.name.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+            elapsed.as_millis()
+        )];
+        if self.level == TraceLevel::Full {
+            for call in calls {
+                lines.push(format!(
+                    "chat id={} call={} arguments={}",
+                    response.id, call.function.name, call.function.arguments
+                ));
+            }
+            if let Some(content) = choice.and_then(|c| c.message.content.as_deref()) {
+                lines.push(format!(
+                    "chat id={} content={:?}",
+                    response.id,
+                    content.chars().take(MAX_CONTENT_CHARS).collect::<String>()
+                ));
+            }
+        }
+        self.sink.emit(Level::Info, &lines.join("\n")).await;
+    }
+
+    /// Logs a completion request that failed after `elapsed`.
+    pub async fn failure(
+        &self,
+        request: &CreateChatCompletionRequest,
+        err: &OpenAIError,
+        elapsed: Duration,
+    ) {
+        if self.level == TraceLevel::Off {
+            return;
+        }
+        let line = format!(
+            "chat failed model={} messages={} ms={} error={:?}",
+            request.model,
+            request.messages.len(),
+            elapsed.as_millis(),
+            err.to_string()
+        );
+        self.sink.emit(Level::Warn, &line).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_parse_in_any_case() {
+        assert_eq!("off".parse(), Ok(TraceLevel::Off));
+        assert_eq!(" Summary ".parse(), Ok(TraceLevel::Summary));
+        assert_eq!("FULL".parse(), Ok(TraceLevel::Full));
+    }
+
+    #[test]
+    fn unknown_levels_are_refused() {
+        assert_eq!(
+            "verbose".parse::<TraceLevel>(),
+            Err("unknown trace level: verbose".to_string())
+        );
+    }
+}
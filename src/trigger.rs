@@ -0,0 +1,143 @@
+use crate::config;
+use regex::{Regex, RegexBuilder};
+use std::env;
+
+const DEFAULT_TRIGGER_WORD: &str = "tool_calls";
+
+/// What a message has to start with, or match, to start a chat.
+#[derive(Debug, Clone)]
+pub enum Trigger {
+    /// A word at the start of the message.
+    Prefix { word: String, case_sensitive: bool },
+    /// A regular expression anywhere in the message.
+    Pattern(Regex),
+}
+
+impl Trigger {
+    /// Reads `trigger_pattern`, falling back to `trigger_word` when it's
+    /// unset or invalid. Both ignore case unless `trigger_case_sensitive` is
+    /// `true`.
+    pub fn from_env() -> Self {
+        let case_sensitive = env::var("trigger_case_sensitive").as_deref() == Ok("true");
+        if let Ok(pattern) = env::var("trigger_pattern") {
+            match RegexBuilder::new(&pattern)
+                .case_insensitive(!case_sensitive)
+                .build()
+            {
+                Ok(regex) => return Trigger::Pattern(regex),
+                Err(e) => log::warn!("Ignoring invalid trigger_pattern {}: {}", pattern, e),
+            }
+        }
+        Trigger::Prefix {
+            word: env::var("trigger_word").unwrap_or(DEFAULT_TRIGGER_WORD.to_string()),
+            case_sensitive,
+        }
+    }
+
+    /// The message without the trigger when it starts a chat.
+    pub fn strip(&self, text: &str) -> Option<String> {
+        let rest = match self {
+            Trigger::Prefix {
+                word,
+                case_sensitive,
+            } => {
+                let text = text.trim_start();
+                let head = text.get(..word.len())?;
+                let matches = if *case_sensitive {
+                    head == word
+                } else {
+                    head.eq_ignore_ascii_case(word)
+                };
+                let rest = &text[word.len()..];
+                // "tool_calls: ..." triggers, "tool_callsite" doesn't.
+                if !matches || rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+                    return None;
+                }
+                rest.trim_start_matches([':', ',']).to_string()
+            }
+            Trigger::Pattern(regex) => {
+                let found = regex.find(text)?;
+                format!("{}{}", &text[..found.start()], &text[found.end()..])
+            }
+        };
+        Some(rest.trim().to_string())
+    }
+    /// What to answer for a message that starts a chat: the message without
+    /// the trigger, or all of it when it reads `tool_calls <tool>: ...`, as
+    /// the default trigger is the start of that and `force_tool` needs it.
+    pub fn input(&self, text: &str) -> Option<String> {
+        let rest = self.strip(text)?;
+        Some(match config::parse_forced_tool(text) {
+            Some(_) => text.trim().to_string(),
+            None => rest,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix(word: &str, case_sensitive: bool) -> Trigger {
+        Trigger::Prefix {
+            word: word.to_string(),
+            case_sensitive,
+        }
+    }
+
+    #[test]
+    fn prefix_strips_the_word_and_separator() {
+        let trigger = prefix("tool_calls", false);
+        assert_eq!(
+            trigger
+                .strip("  tool_calls: what's the weather?")
+                .as_deref(),
+            Some("what's the weather?")
+        );
+        assert_eq!(trigger.strip("Tool_Calls, hello").as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn prefix_only_matches_a_whole_word_at_the_start() {
+        let trigger = prefix("tool_calls", false);
+        assert_eq!(trigger.strip("tool_callsite is down"), None);
+        assert_eq!(trigger.strip("tool_calls_v2 hello"), None);
+        assert_eq!(trigger.strip("please tool_calls hello"), None);
+        assert_eq!(trigger.strip("tool"), None);
+    }
+
+    #[test]
+    fn case_sensitive_prefix_needs_the_exact_word() {
+        let trigger = prefix("Bot", true);
+        assert_eq!(trigger.strip("Bot hi").as_deref(), Some("hi"));
+        assert_eq!(trigger.strip("bot hi"), None);
+    }
+
+    #[test]
+    fn pattern_removes_the_match_anywhere() {
+        let trigger = Trigger::Pattern(
+            RegexBuilder::new(r"@helper\b")
+                .case_insensitive(true)
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(
+            trigger.strip("hey @Helper what time is it").as_deref(),
+            Some("hey  what time is it")
+        );
+        assert_eq!(trigger.strip("no mention here"), None);
+    }
+
+    #[test]
+    fn input_keeps_a_forced_tool_call_whole() {
+        let trigger = prefix("tool_calls", false);
+        assert_eq!(
+            trigger.input("tool_calls weather: Paris").as_deref(),
+            Some("tool_calls weather: Paris")
+        );
+        assert_eq!(
+            trigger.input("tool_calls: hello there").as_deref(),
+            Some("hello there")
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use crate::store::{get, set, Expire, ExpireKind};
+use async_openai::types::CompletionUsage;
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::Mutex;
+
+/// Dollars per million prompt and completion tokens, matched by model name
+/// prefix, longest first. `token_prices` adds to or overrides these.
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o-mini", 0.15, 0.6),
+    ("gpt-4o", 5.0, 15.0),
+    ("gpt-4-turbo", 10.0, 30.0),
+    ("gpt-4-1106", 10.0, 30.0),
+    ("gpt-4-0125", 10.0, 30.0),
+    ("gpt-4-32k", 60.0, 120.0),
+    ("gpt-4", 30.0, 60.0),
+    ("gpt-3.5-turbo-1106", 1.0, 2.0),
+    ("gpt-3.5-turbo", 0.5, 1.5),
+];
+
+/// Who the completions made while handling the current event are billed to.
+/// A flow handles one Slack event per run, so this is set once in the
+/// handler and covers the tools' requests as well.
+static SCOPE: Lazy<Mutex<Option<(String, String)>>> = Lazy::new(|| Mutex::new(None));
+
+/// Token counts and their estimated cost.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Totals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// In dollars, `0` for models without a known price.
+    pub cost: f64,
+}
+
+impl Totals {
+    pub fn tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    fn add(&mut self, other: &Totals) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost += other.cost;
+    }
+}
+
+/// What a channel has used, stored under `usage:<channel>`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChannelUsage {
+    /// The day counting started, as `YYYY-MM-DD`.
+    pub since: String,
+    pub total: Totals,
+    /// Per Slack user.
+    pub users: BTreeMap<String, Totals>,
+}
+
+impl ChannelUsage {
+    pub fn load(channel: &str) -> Self {
+        get(&usage_key(channel))
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_else(|| ChannelUsage {
+                since: Utc::now().format("%Y-%m-%d").to_string(),
+                ..Default::default()
+            })
+    }
+
+    fn save(&self, channel: &str) {
+        match serde_json::to_value(self) {
+            Ok(value) => set(&usage_key(channel), value, None),
+            Err(e) => log::warn!("Failed to save usage for {}: {}", channel, e),
+        }
+    }
+
+    /// A summary for posting to Slack, heaviest users first.
+    pub fn report(&self, channel: &str) -> String {
+        if self.total.requests == 0 {
+            return format!("Nothing has been used on {channel} yet.");
+        }
+        let mut lines = vec![format!(
+            "Usage on {channel} since {}: {}",
+            self.since,
+            describe(&self.total)
+        )];
+        let mut users: Vec<_> = self.users.iter().collect();
+        users.sort_by_key(|(_, totals)| Reverse(totals.tokens()));
+        for (user, totals) in users {
+            lines.push(format!("• <@{user}>: {}", describe(totals)));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Bills the completions that follow to `user` in `channel`.
+pub fn set_scope(channel: &str, user: &str) {
+    if let Ok(mut scope) = SCOPE.lock() {
+        *scope = Some((channel.to_string(), user.to_string()));
+    }
+}
+
+/// Adds a completion's usage to the day's and month's totals and to those
+/// of the current scope. Usage outside a scope, such as while posting
+/// reminders, isn't tracked.
+pub fn record(model: &str, usage: &CompletionUsage) {
+    let Some((channel, user)) = SCOPE.lock().ok().and_then(|scope| scope.clone()) else {
+        return;
+    };
+    let totals = Totals {
+        requests: 1,
+        prompt_tokens: usage.prompt_tokens as u64,
+        completion_tokens: usage.completion_tokens as u64,
+        cost: cost(model, usage),
+    };
+    for (key, keep_days) in [(day_key(), 2), (month_key(), 32)] {
+        let mut period: Totals = get(&key)
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default();
+        period.add(&totals);
+        if let Ok(value) = serde_json::to_value(period) {
+            let expire = Expire {
+                kind: ExpireKind::Ex,
+                value: keep_days * 24 * 60 * 60,
+            };
+            set(&key, value, Some(expire));
+        }
+    }
+
+    let mut channel_usage = ChannelUsage::load(&channel);
+    channel_usage.total.add(&totals);
+    channel_usage.users.entry(user).or_default().add(&totals);
+    channel_usage.save(&channel);
+}
+
+/// The estimated dollar cost of `usage` on `model`.
+fn cost(model: &str, usage: &CompletionUsage) -> f64 {
+    match price(model) {
+        Some((prompt, completion)) => {
+            (usage.prompt_tokens as f64 * prompt + usage.completion_tokens as f64 * completion)
+                / 1_000_000.0
+        }
+        None => 0.0,
+    }
+}
+
+/// Per million prompt and completion tokens. `token_prices` is read as
+/// `model=prompt:completion` pairs separated by commas, e.g.
+/// `llama3=0:0,gpt-4o=2.5:10`.
+fn price(model: &str) -> Option<(f64, f64)> {
+    let configured = env::var("token_prices").unwrap_or_default();
+    let mut prices: Vec<(String, f64, f64)> = configured
+        .split(',')
+        .filter_map(|pair| {
+            let (name, price) = pair.split_once('=')?;
+            let (prompt, completion) = price.split_once(':')?;
+            Some((
+                name.trim().to_string(),
+                prompt.trim().parse().ok()?,
+                completion.trim().parse().ok()?,
+            ))
+        })
+        .chain(PRICES.iter().map(|(n, p, c)| (n.to_string(), *p, *c)))
+        .collect();
+    // Configured prices come first, so a stable sort keeps them ahead of
+    // built-in ones of the same length.
+    prices.sort_by_key(|(name, _, _)| Reverse(name.len()));
+    prices
+        .into_iter()
+        .find(|(name, _, _)| model.starts_with(name.as_str()))
+        .map(|(_, prompt, completion)| (prompt, completion))
+}
+
+/// Limits on the tokens the whole deployment may use, read from
+/// `DAILY_TOKEN_BUDGET` and `MONTHLY_TOKEN_BUDGET`. Days and months are UTC.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Budget {
+    pub daily: Option<u64>,
+    pub monthly: Option<u64>,
+}
+
+impl Budget {
+    pub fn from_env() -> Self {
+        let limit = |key| env::var(key).ok().and_then(|v| v.trim().parse().ok());
+        Self {
+            daily: limit("DAILY_TOKEN_BUDGET"),
+            monthly: limit("MONTHLY_TOKEN_BUDGET"),
+        }
+    }
+
+    /// What to tell users once a limit is reached, or `None` while there's
+    /// budget left or an admin has lifted the limits for today.
+    pub fn exhausted(&self) -> Option<String> {
+        if get(&override_key()).is_some() {
+            return None;
+        }
+        let today = Utc::now().date_naive();
+        if self
+            .daily
+            .is_some_and(|limit| period_tokens(&day_key()) >= limit)
+        {
+            return Some(
+                "Sorry, today's token budget is exhausted. It resets at midnight UTC.".to_string(),
+            );
+        }
+        if self
+            .monthly
+            .is_some_and(|limit| period_tokens(&month_key()) >= limit)
+        {
+            return Some(format!(
+                "Sorry, this month's token budget is exhausted. It resets on {} UTC.",
+                next_month(today).format("%B %-d")
+            ));
+        }
+        None
+    }
+
+    /// A summary of what has been used against each limit.
+    pub fn report(&self) -> String {
+        let line = |name: &str, key: String, limit: Option<u64>| {
+            let used = period_tokens(&key);
+            match limit {
+                Some(limit) => format!("{name}: {used} of {limit} tokens"),
+                None => format!("{name}: {used} tokens, no limit"),
+            }
+        };
+        let mut lines = vec![
+            line("Today", day_key(), self.daily),
+            line("This month", month_key(), self.monthly),
+        ];
+        if get(&override_key()).is_some() {
+            lines.push("The limits are lifted until midnight UTC.".to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+/// Lets everyone past the budget until midnight UTC.
+pub fn override_budget() {
+    let now = Utc::now();
+    let midnight = (now.date_naive() + Duration::days(1))
+        .and_hms_opt(0, 0, 0)
+        .map(|t| t.and_utc().timestamp())
+        .unwrap_or(now.timestamp() + 24 * 60 * 60);
+    set(
+        &override_key(),
+        serde_json::json!(true),
+        Some(Expire {
+            kind: ExpireKind::ExAt,
+            value: midnight,
+        }),
+    );
+}
+
+fn period_tokens(key: &str) -> u64 {
+    get(key)
+        .and_then(|v| serde_json::from_value::<Totals>(v).ok())
+        .map(|t| t.tokens())
+        .unwrap_or_default()
+}
+
+fn next_month(date: NaiveDate) -> NaiveDate {
+    let (year, month) = match date.month() {
+        12 => (date.year() + 1, 1),
+        month => (date.year(), month + 1),
+    };
+    NaiveDate::from_ymd_opt(year, month, 1).unwrap_or(date)
+}
+
+fn day_key() -> String {
+    format!("usage_day:{}", Utc::now().format("%Y-%m-%d"))
+}
+
+fn month_key() -> String {
+    format!("usage_month:{}", Utc::now().format("%Y-%m"))
+}
+
+fn override_key() -> String {
+    format!("budget_override:{}", Utc::now().format("%Y-%m-%d"))
+}
+
+fn describe(totals: &Totals) -> String {
+    format!(
+        "{} tokens ({} prompt, {} completion) in {} requests, about ${:.2}",
+        totals.tokens(),
+        totals.prompt_tokens,
+        totals.completion_tokens,
+        totals.requests,
+        totals.cost
+    )
+}
+
+fn usage_key(channel: &str) -> String {
+    format!("usage:{channel}")
+}